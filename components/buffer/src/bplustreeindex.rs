@@ -0,0 +1,230 @@
+use crate::index::{entry_key, entry_range, stored_bounds, Index};
+use std::ops::Bound::{self, Included, Unbounded};
+use std::sync::{Arc, Mutex};
+use storage_engine::bplustree::BPlusTree;
+
+pub const DEFAULT_ORDER: usize = 64;
+
+type KeyTree = BPlusTree<Vec<u8>, (u64, usize)>;
+
+#[derive(Debug, Clone)]
+pub struct BPlusTreeIndex {
+    tree: Arc<Mutex<KeyTree>>, // encoded key → (page_id, offset)
+    unique: bool,
+}
+
+impl Default for BPlusTreeIndex {
+    fn default() -> Self {
+        Self::new(DEFAULT_ORDER)
+    }
+}
+
+impl BPlusTreeIndex {
+    pub fn new(order: usize) -> Self {
+        Self {
+            tree: Arc::new(Mutex::new(BPlusTree::new_empty(order))),
+            unique: true,
+        }
+    }
+
+    // An index that keeps one entry per (key, location).
+    pub fn new_non_unique(order: usize) -> Self {
+        Self {
+            unique: false,
+            ..Self::new(order)
+        }
+    }
+}
+
+impl Index for BPlusTreeIndex {
+    fn insert(&self, key: Vec<u8>, page_id: u64, offset: usize) -> bool {
+        let key = if self.unique {
+            key
+        } else {
+            entry_key(key, page_id, offset)
+        };
+        let mut guard = self.tree.lock().unwrap();
+        if guard.find(&key).is_some() {
+            return false;
+        }
+        guard.insert(key, (page_id, offset));
+        true
+    }
+
+    fn delete(&self, key: &[u8], page_id: u64, offset: usize) -> bool {
+        let mut guard = self.tree.lock().unwrap();
+        if !self.unique {
+            return guard
+                .remove(&entry_key(key.to_vec(), page_id, offset))
+                .is_some();
+        }
+        let key = key.to_vec();
+        if guard.find(&key) != Some(&(page_id, offset)) {
+            return false;
+        }
+        guard.remove(&key).is_some()
+    }
+
+    fn find(&self, key: &[u8]) -> Option<(u64, usize)> {
+        if !self.unique {
+            return self.range_query(key, key).first().copied();
+        }
+        self.tree.lock().unwrap().find(&key.to_vec()).copied()
+    }
+
+    fn range_query(&self, start: &[u8], end: &[u8]) -> Vec<(u64, usize)> {
+        let (start, end) = if self.unique {
+            (start.to_vec(), end.to_vec())
+        } else {
+            entry_range(start, end)
+        };
+        self.tree
+            .lock()
+            .unwrap()
+            .range(Included(&start), Included(&end))
+            .map(|(_, loc)| *loc)
+            .collect()
+    }
+
+    fn is_ordered(&self) -> bool {
+        true
+    }
+
+    fn range_entries(
+        &self,
+        start: Bound<&[u8]>,
+        end: Bound<&[u8]>,
+    ) -> Vec<(Vec<u8>, (u64, usize))> {
+        let (start, end) = stored_bounds(self.unique, start, end);
+        self.tree
+            .lock()
+            .unwrap()
+            .range(start.as_ref(), end.as_ref())
+            .map(|(key, loc)| (key.clone(), *loc))
+            .collect()
+    }
+
+    fn next_entry(&self, key: &[u8]) -> Option<(Vec<u8>, (u64, usize))> {
+        let (start, _) = stored_bounds(self.unique, Bound::Excluded(key), Unbounded);
+        self.tree
+            .lock()
+            .unwrap()
+            .range(start.as_ref(), Unbounded)
+            .next()
+            .map(|(key, loc)| (key.clone(), *loc))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::index_key::{encode_u64, KeyColumn, KeySchema, SortOrder};
+    use crate::query_types::{TypeId, Value};
+
+    #[test]
+    fn test_composite_key_range_query() {
+        // (lastname ASC, age DESC)
+        let key_schema = KeySchema::new(vec![
+            KeyColumn {
+                column_idx: 0,
+                type_id: TypeId::VARCHAR,
+                order: SortOrder::Asc,
+            },
+            KeyColumn {
+                column_idx: 1,
+                type_id: TypeId::SMALLINT,
+                order: SortOrder::Desc,
+            },
+        ]);
+        let key = |name: &str, age: i16| {
+            key_schema
+                .encode_values(&[Value::Varchar(name.to_string()), Value::SmallInt(age)])
+                .unwrap()
+        };
+        let index = BPlusTreeIndex::new(3);
+        let rows = [
+            ("codd", 30),
+            ("gray", 41),
+            ("codd", 60),
+            ("hopper", 22),
+            ("codd", 45),
+        ];
+        for (i, (name, age)) in rows.iter().enumerate() {
+            index.insert(key(name, *age), i as u64, 0);
+        }
+        assert_eq!(index.find(&key("gray", 41)), Some((1, 0)));
+        // every codd, oldest first
+        let codds = index.range_query(&key("codd", i16::MAX), &key("codd", i16::MIN));
+        assert_eq!(codds, vec![(2, 0), (4, 0), (0, 0)]);
+    }
+
+    #[test]
+    fn test_non_unique_keys_across_leaves() {
+        let index = BPlusTreeIndex::new_non_unique(3);
+        let key = |lastname: &str| {
+            KeySchema::new(vec![KeyColumn {
+                column_idx: 0,
+                type_id: TypeId::VARCHAR,
+                order: SortOrder::Asc,
+            }])
+            .encode_values(&[Value::Varchar(lastname.to_string())])
+            .unwrap()
+        };
+        for page in 0..20u64 {
+            let lastname = if page % 2 == 0 { "codd" } else { "gray" };
+            assert!(index.insert(key(lastname), page, 1));
+        }
+        assert!(index.insert(key("co"), 99, 0));
+        let codds = index.range_query(&key("codd"), &key("codd"));
+        assert_eq!(
+            codds,
+            (0..20).step_by(2).map(|p| (p, 1)).collect::<Vec<_>>()
+        );
+        assert_eq!(index.range_query(&key("a"), &key("z")).len(), 21);
+        assert!(index.delete(&key("codd"), 4, 1));
+        assert!(!index.delete(&key("gray"), 4, 1));
+        assert_eq!(index.range_query(&key("codd"), &key("codd")).len(), 9);
+        assert_eq!(index.range_query(&key("gray"), &key("gray")).len(), 10);
+    }
+
+    #[test]
+    fn test_entries_are_read_with_their_keys_in_order() {
+        use std::ops::Bound::Excluded;
+
+        let unique = BPlusTreeIndex::new(4);
+        let non_unique = BPlusTreeIndex::new_non_unique(4);
+        for i in [3u64, 1, 7, 5] {
+            assert!(Index::insert(&unique, encode_u64(i), i, 0));
+            assert!(Index::insert(&non_unique, encode_u64(i), i, 0));
+            assert!(Index::insert(&non_unique, encode_u64(i), i, 1));
+        }
+        let locations = |entries: Vec<(Vec<u8>, (u64, usize))>| {
+            entries.into_iter().map(|(_, loc)| loc).collect::<Vec<_>>()
+        };
+        assert!(unique.is_ordered());
+        let (three, six) = (encode_u64(3), encode_u64(6));
+        assert_eq!(
+            locations(unique.range_entries(Included(&three[..]), Included(&six[..]))),
+            vec![(3, 0), (5, 0)]
+        );
+        assert_eq!(
+            locations(non_unique.range_entries(Excluded(&three[..]), Unbounded)),
+            vec![(5, 0), (5, 1), (7, 0), (7, 1)]
+        );
+        assert_eq!(
+            locations(non_unique.range_entries(Unbounded, Included(&three[..]))),
+            vec![(1, 0), (1, 1), (3, 0), (3, 1)]
+        );
+        // a unique index stores the key as it is, a non-unique one with the location after it
+        let (key, loc) = unique.next_entry(&three).unwrap();
+        assert_eq!((key, loc), (encode_u64(5), (5, 0)));
+        let (key, loc) = non_unique.next_entry(&three).unwrap();
+        assert_eq!(loc, (5, 0));
+        assert!(key.starts_with(&encode_u64(5)) && key.len() > encode_u64(5).len());
+        assert_eq!(
+            non_unique.next_entry(&six).map(|(_, loc)| loc),
+            Some((7, 0))
+        );
+        assert_eq!(unique.next_entry(&encode_u64(7)), None);
+    }
+}
@@ -1,13 +1,11 @@
 use crate::frameheader::FrameHeader;
-use crate::page_guard::{ReadPageGuard, WritePageGuard};
-#[allow(unused)]
-use crate::query_types::{get_demo_table_heap_with_n_page_m_tuples_each, TableHeap};
-use common::types::PageId;
-use std::collections::HashMap;
+use crate::page_guard::{PinnedFrame, ReadPageGuard, WritePageGuard};
+use common::types::{PageId, TxnResult};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::{Arc, Mutex, RwLock};
-use std::time::{SystemTime, UNIX_EPOCH};
-use storage_engine::disk_manager::DiskManager;
+use std::sync::{Arc, Mutex};
+use storage_engine::disk_manager::{DiskManager, PAGE_SIZE};
 use storage_engine::disk_scheduler::DiskScheduler;
 
 type FrameId = usize;
@@ -38,7 +36,6 @@ type FrameId = usize;
 #[derive(Debug, Default, Clone, PartialEq)]
 struct LRUNode {
     history: Vec<usize>,
-    k_: usize,
     fid: FrameId,
     is_evictable: bool,
 }
@@ -48,132 +45,74 @@ impl LRUNode {
     pub fn new(frame_id: FrameId) -> Self {
         Self {
             history: vec![],
-            k_: 0,
             fid: frame_id,
             is_evictable: false,
         }
     }
 }
 
-#[allow(dead_code)]
+// Timestamps are a logical clock bumped on every access, each node keeps its last k of them.
 #[derive(Clone, Debug, Default)]
-struct LRUKReplacer {
+pub(crate) struct LRUKReplacer {
     node_store: HashMap<FrameId, LRUNode>,
     current_timestamp: usize,
-    current_size: usize,
-    replacer_size: usize,
     k_: usize,
-    latch: Arc<Mutex<LRUNode>>,
-    k_b_d_value: usize,
 }
 
-#[allow(dead_code)]
+#[allow(non_snake_case)]
 impl LRUKReplacer {
-    #[allow(dead_code, unused)]
-    fn new(k_b_d: usize) -> Self {
+    pub(crate) fn new(k_b_d: usize) -> Self {
         Self {
             node_store: HashMap::new(),
             current_timestamp: 0,
-            current_size: 0,
-            replacer_size: 0,
-            k_: 0,
-            latch: Arc::new(Mutex::new(LRUNode {
-                history: vec![0; 5],
-                k_: 0,
-                fid: 0,
-                is_evictable: false,
-            })),
-            k_b_d_value: k_b_d,
+            k_: k_b_d.max(1),
         }
     }
 
-    #[allow(non_snake_case)]
-    fn Evict(&mut self) -> Option<FrameId> {
-        // find Node[n].frame_id = \A n \in Nodes: Node[n].k_b_d = MAXIMUM({Node[n].k_b_d})
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("Time went backwards")
-            .as_nanos() as usize;
-
-        let mut evictable = self
+    // Evicts the evictable frame with the largest backward k-distance. Frames with fewer than
+    // k accesses have an infinite distance and go first, oldest first access first.
+    pub(crate) fn Evict(&mut self) -> Option<FrameId> {
+        let victim = self
             .node_store
             .values()
             .filter(|node| node.is_evictable)
-            .cloned()
-            .collect::<Vec<LRUNode>>();
-
-        // [A,B,C,D]
-        for i in 0..evictable.len() - 1 {
-            // IF Nodes[n].HISTORY.len() < 2 THEN Nodes[n].k_b_d = INFINITY
-            // TODO: try the following: evitable.iter().fold(0, |k, &x| { k + x })
-
-            // evictable: [A,B,C,D]
-            let (left, right) = evictable.split_at_mut(i + 1); // (left: [A], [B,C,D])
-            if left.last().unwrap().k_ == right.first().unwrap().k_
-                && left.last().unwrap().history.len() >= self.k_b_d_value
-                && right.first().unwrap().history.len() >= self.k_b_d_value
-            {
-                if left.last().unwrap().history[self.k_b_d_value]
-                    < right.first().unwrap().history[self.k_b_d_value]
-                {
-                    if let Some(node) = self.node_store.remove(&left.last().unwrap().fid) {
-                        return Some(node.fid);
-                    } else {
-                        let node = self.node_store.remove(&left.last().unwrap().fid);
-                        return Some(node.unwrap().fid);
-                    }
+            .min_by_key(|node| {
+                if node.history.len() < self.k_ {
+                    (0, node.history.first().copied().unwrap_or(0))
+                } else {
+                    (1, node.history[node.history.len() - self.k_])
                 }
-                break;
-            }
-            if (now - left.last().unwrap().k_) > (now - right.first().unwrap().k_) {
-                // if A.k_ > B.k_ {
-                std::mem::swap(&mut left.last().unwrap(), &mut right.first().unwrap());
-                // [B, A, C, D]
-            }
-        }
-        let victim_id = evictable.last().unwrap().fid;
-        if let Some(evictee) = self.node_store.remove(&victim_id) {
-            Some(evictee.fid)
-        } else {
-            None
-        }
+            })?
+            .fid;
+        self.node_store.remove(&victim);
+        Some(victim)
     }
 
-    #[allow(non_snake_case)]
-    fn RecordAccess(&mut self, frame_id_t: FrameId) {
-        if let Some((_, node)) = self
+    pub(crate) fn RecordAccess(&mut self, frame_id_t: FrameId) {
+        self.current_timestamp += 1;
+        let node = self
             .node_store
-            .iter_mut()
-            .find(|(_, node)| node.fid == frame_id_t)
-        {
-            let now = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .expect("Time went backwards")
-                .as_nanos();
-            node.history.push(now as usize);
-            node.k_ = now as usize;
+            .entry(frame_id_t)
+            .or_insert_with(|| LRUNode::new(frame_id_t));
+        node.history.push(self.current_timestamp);
+        if node.history.len() > self.k_ {
+            node.history.remove(0);
         }
     }
 
-    #[allow(non_snake_case)]
-    fn Remove(&mut self, frame_id_t: FrameId) {
-        self.node_store
-            .get_mut(&frame_id_t)
-            .unwrap()
-            .history
-            .clear();
+    pub(crate) fn Remove(&mut self, frame_id_t: FrameId) {
+        self.node_store.remove(&frame_id_t);
     }
 
-    #[allow(non_snake_case)]
-    fn SetEvictable(&mut self, frame_id_t: FrameId, set_evictable: bool) {
+    pub(crate) fn SetEvictable(&mut self, frame_id_t: FrameId, set_evictable: bool) {
         if let Some(x) = self.node_store.get_mut(&frame_id_t) {
             x.is_evictable = set_evictable;
         }
     }
 
-    #[allow(non_snake_case)]
-    fn Size(&self) -> usize {
-        self.node_store.len()
+    #[allow(dead_code)]
+    pub(crate) fn Size(&self) -> usize {
+        self.node_store.values().filter(|n| n.is_evictable).count()
     }
 }
 
@@ -191,17 +130,20 @@ impl LRUKReplacer {
 //   void FlushAllPages();
 //   auto GetPinCount(page_id_t page_id) -> std::optional<size_t>;
 
-#[allow(dead_code)]
+static NEXT_FILE_ID: AtomicUsize = AtomicUsize::new(0);
+
+// The page table latch is the pool latch: it guards the page table, the free lists and the
+// replacer, and is always taken before a page latch.
 pub struct BufferPoolManager {
-    num_frames: usize,
+    num_frames: AtomicUsize, // pages handed out by new_page and not deleted yet
     next_page: AtomicUsize,
-    atomic_counter: AtomicUsize,
-    frames: Vec<FrameHeader>,
+    frames: Vec<Arc<FrameHeader>>,
     page_table: Arc<Mutex<HashMap<PageId, FrameId>>>,
-    free_frames: Vec<[u8; 4096]>,
-    replacer: Box<LRUKReplacer>,
+    free_frames: Mutex<Vec<FrameId>>,
+    free_pages: Mutex<Vec<PageId>>,
+    replacer: Arc<Mutex<LRUKReplacer>>,
     disk_scheduler: DiskScheduler,
-    pub table_heap: Arc<Mutex<TableHeap>>,
+    temp_file: Option<PathBuf>,
 }
 
 //                     +-----------------------------+
@@ -216,98 +158,269 @@ pub struct BufferPoolManager {
 // | HTAP Storage (KestrelDB)  |        |  Real-Time Streaming Engine (OSS)   |
 // |  - Buffer Pool Manager    |        |  - Kafka / Redpanda                 |
 // |  - LRU Cache Optimization |        |  - AI-enhanced queries              |
-// +---------------------------+        +-------------------------------------+
 
-#[allow(unused)]
 impl BufferPoolManager {
+    // A pool backed by a fresh scratch file in the temp directory, removed again on drop.
     pub fn new(capacity: usize, k_b_d: usize) -> Self {
-        let new_file = file_system::file::File::create("disk_file.dat").unwrap();
+        let file_name = format!(
+            "disk_file_{}_{}.dat",
+            std::process::id(),
+            NEXT_FILE_ID.fetch_add(1, Ordering::SeqCst)
+        );
+        let new_file = file_system::file::File::open_or_create(&file_name).unwrap();
+        new_file.set_len(0).unwrap();
+        Self::with_file(
+            new_file,
+            0,
+            capacity,
+            k_b_d,
+            Some(std::env::temp_dir().join(file_name)),
+        )
+    }
+
+    // A pool over a database file that is kept, created if it does not exist yet. Dirty pages
+    // are written back when the pool is dropped.
+    pub fn open<P: AsRef<Path>>(path: P, capacity: usize, k_b_d: usize) -> Result<Self, String> {
+        let path = path.as_ref();
+        let file = file_system::file::File::open_or_create(path)
+            .map_err(|e| format!("could not open {}: {}", path.display(), e))?;
+        let len = file.metadata().map_err(|e| e.to_string())?.len() as usize;
+        Ok(Self::with_file(
+            file,
+            len.div_ceil(PAGE_SIZE),
+            capacity,
+            k_b_d,
+            None,
+        ))
+    }
+
+    fn with_file(
+        file: file_system::file::File,
+        pages: usize,
+        capacity: usize,
+        k_b_d: usize,
+        temp_file: Option<PathBuf>,
+    ) -> Self {
         // allocate all in-memory frames upfront
-        let mut frames: Vec<FrameHeader> = Vec::new();
-        let mut free_frames = Vec::with_capacity(capacity);
-        for _ in 0..capacity {
-            frames.push(FrameHeader::default());
-            free_frames.push([0; 4096]);
-        }
+        let frames = (0..capacity)
+            .map(|frame_id| Arc::new(FrameHeader::new(frame_id)))
+            .collect();
 
         Self {
-            num_frames: 0,
-            next_page: AtomicUsize::new(0),
-            atomic_counter: AtomicUsize::new(0),
+            num_frames: AtomicUsize::new(pages),
+            next_page: AtomicUsize::new(pages),
             frames,
             page_table: Arc::new(Mutex::new(HashMap::with_capacity(capacity))),
-            free_frames,
-            replacer: Box::new(LRUKReplacer::new(k_b_d)),
-            disk_scheduler: DiskScheduler::new(DiskManager::new(new_file)),
-            table_heap: Arc::new(Mutex::new(TableHeap::new(capacity))),
+            free_frames: Mutex::new((0..capacity).rev().collect()),
+            free_pages: Mutex::new(vec![]),
+            replacer: Arc::new(Mutex::new(LRUKReplacer::new(k_b_d))),
+            disk_scheduler: DiskScheduler::new(DiskManager::new(file)),
+            temp_file,
+        }
+    }
+
+    // Number of page ids handed out so far, deleted ones included.
+    pub fn page_count(&self) -> usize {
+        self.next_page.load(Ordering::SeqCst)
+    }
+
+    // Allocates a zeroed page, reusing deleted page ids first. Returns None if every frame is
+    // pinned, as the new page has to be brought into the pool.
+    pub fn new_page(&self) -> Option<PageId> {
+        let page_id = self
+            .free_pages
+            .lock()
+            .unwrap()
+            .pop()
+            .unwrap_or_else(|| self.next_page.fetch_add(1, Ordering::SeqCst));
+        match self.pin_page(page_id, true) {
+            Some(_) => {
+                self.num_frames.fetch_add(1, Ordering::SeqCst);
+                Some(page_id)
+            }
+            None => {
+                self.free_pages.lock().unwrap().push(page_id);
+                None
+            }
         }
     }
 
-    pub fn new_page(&mut self) -> Option<FrameId> {
-        // acquire mutex
-        let mut guard = self.page_table.lock().unwrap();
-        self.num_frames += 1;
-        self.atomic_counter.fetch_add(1, Ordering::SeqCst);
-        self.frames.pop();
-        let frame = FrameHeader::default();
-        self.frames.push(frame);
-        self.free_frames.pop();
-        // self.table_heap.add_table_page(frame); //TODO: should this keep track of the pages in memory?
-        guard.insert(self.num_frames, self.num_frames)
+    // The free list only lives in memory. After opening a file the owner of the pages passes
+    // every page it still reaches, all other pages are freed for reuse.
+    pub fn free_unreachable_pages(&self, reachable: &HashSet<PageId>) {
+        let unreachable = (0..self.page_count())
+            .filter(|page_id| !reachable.contains(page_id))
+            .collect::<Vec<PageId>>();
+        for page_id in unreachable {
+            self.delete_page(page_id);
+        }
     }
 
     pub fn get_buffer_manager_size(&self) -> usize {
         self.frames.len()
     }
 
-    pub fn delete_page(&mut self, page_id: PageId) -> bool {
-        let mut guard = self.page_table.lock().unwrap();
-        self.num_frames -= 1;
-        self.atomic_counter.fetch_sub(1, Ordering::SeqCst);
-        self.frames.remove(page_id);
-        self.frames.push(FrameHeader::default());
-        self.free_frames.push([0; 4096]);
-        guard.remove(&page_id);
+    // Frees the page id for reuse. Fails if the page is pinned or not allocated.
+    pub fn delete_page(&self, page_id: PageId) -> bool {
+        let mut page_table = self.page_table.lock().unwrap();
+        let mut free_pages = self.free_pages.lock().unwrap();
+        if page_id >= self.next_page.load(Ordering::SeqCst) || free_pages.contains(&page_id) {
+            return false;
+        }
+        if let Some(&frame_id) = page_table.get(&page_id) {
+            let frame = &self.frames[frame_id];
+            if frame.get_pin_count() > 0 {
+                return false;
+            }
+            page_table.remove(&page_id);
+            frame.set_dirty(false);
+            self.replacer.lock().unwrap().Remove(frame_id);
+            self.free_frames.lock().unwrap().push(frame_id);
+        }
+        free_pages.push(page_id);
+        self.num_frames.fetch_sub(1, Ordering::SeqCst);
         true
     }
 
-    fn check_write_page(&self, frame_id: FrameId, data: Vec<u8>) -> Result<bool, String> {
-        let writer = Arc::new(RwLock::new(WritePageGuard::new()));
-        let result = match writer.write() {
-            Ok(mut guard) => {
-                if let Some(val) = guard.write_page_data(frame_id, data) {
-                    // TODO: persist the frame_id from the write in meta data
-                    let frame_id = val;
-                    Ok(true)
+    // Pins the page into a frame, reading it from disk (or zeroing it when `fresh`) if it is
+    // not in the pool yet. The victim frame is written back first if it is dirty.
+    fn pin_page(&self, page_id: PageId, fresh: bool) -> Option<PinnedFrame> {
+        let mut page_table = self.page_table.lock().unwrap();
+        let mut replacer = self.replacer.lock().unwrap();
+        let frame_id = match page_table.get(&page_id) {
+            Some(&frame_id) => frame_id,
+            None => {
+                let frame_id = match self.free_frames.lock().unwrap().pop() {
+                    Some(frame_id) => frame_id,
+                    None => {
+                        let frame_id = replacer.Evict()?;
+                        page_table.remove(&self.frames[frame_id].get_page_id());
+                        frame_id
+                    }
+                };
+                let frame = &self.frames[frame_id];
+                // unpinned frames are not latched by anyone, this does not block
+                let mut data = frame.data.write();
+                if frame.is_dirty() {
+                    if let TxnResult::Err(()) = self
+                        .disk_scheduler
+                        .write_page(frame.get_page_id(), &data[..])
+                    {
+                        panic!("failed to write back page {}", frame.get_page_id());
+                    }
+                }
+                if fresh {
+                    data.fill(0);
                 } else {
-                    // TODO: when this fails to write to mem then make new_page and retry write
-                    Ok(false)
+                    self.disk_scheduler
+                        .read_page(page_id, &mut data[..])
+                        .expect("failed to read page");
                 }
+                frame.set_dirty(fresh);
+                frame.set_page_id(page_id);
+                page_table.insert(page_id, frame_id);
+                frame_id
             }
-            Err(_) => Err("failed to get the guard and write data".to_string()),
         };
-        result
+        self.frames[frame_id].pin();
+        replacer.RecordAccess(frame_id);
+        replacer.SetEvictable(frame_id, false);
+        Some(PinnedFrame {
+            page_id,
+            frame: Arc::clone(&self.frames[frame_id]),
+            page_table: Arc::clone(&self.page_table),
+            replacer: Arc::clone(&self.replacer),
+        })
     }
 
-    fn check_read_page(&self, frame_id: FrameId) -> Result<bool, String> {
-        let reader = Arc::new(RwLock::new(ReadPageGuard::new()));
-        let result = match reader.read() {
-            Ok(mut guard) => {
-                guard.read_page_data(frame_id);
-                Ok(true)
-            }
-            Err(_) => Err("failed to get the guard".to_string()),
+    // None if the page cannot be brought in because every frame is pinned.
+    pub fn checked_read_page(&self, page_id: PageId) -> Option<ReadPageGuard> {
+        self.pin_page(page_id, false).map(ReadPageGuard::new)
+    }
+
+    pub fn checked_write_page(&self, page_id: PageId) -> Option<WritePageGuard> {
+        self.pin_page(page_id, false).map(WritePageGuard::new)
+    }
+
+    pub fn read_page(&self, page_id: PageId) -> ReadPageGuard {
+        self.checked_read_page(page_id)
+            .unwrap_or_else(|| panic!("no free frame to read page {}", page_id))
+    }
+
+    pub fn write_page(&self, page_id: PageId) -> WritePageGuard {
+        self.checked_write_page(page_id)
+            .unwrap_or_else(|| panic!("no free frame to write page {}", page_id))
+    }
+
+    // Writes the page to disk if it is in the pool. Returns false if it is not.
+    pub fn flush_page(&self, page_id: PageId) -> bool {
+        if !self.page_table.lock().unwrap().contains_key(&page_id) {
+            return false;
+        }
+        let Some(pin) = self.pin_page(page_id, false) else {
+            return false;
         };
-        result
+        let frame = Arc::clone(&pin.frame);
+        let guard = ReadPageGuard::new(pin);
+        let written = self.disk_scheduler.write_page(page_id, &guard[..]);
+        frame.set_dirty(false);
+        written == TxnResult::Ok(PAGE_SIZE)
     }
 
-    fn check_page_exists_in_buffer(&self, data: Vec<u8>) -> bool {
-        true
-        // let frame = self.frames.iter().filter(|&x| x.data == data).collect::<[FrameHeader]>();
+    pub fn flush_all_pages(&self) {
+        let page_ids = self
+            .page_table
+            .lock()
+            .unwrap()
+            .keys()
+            .copied()
+            .collect::<Vec<PageId>>();
+        for page_id in page_ids {
+            self.flush_page(page_id);
+        }
     }
 
-    pub fn set_table_heap(&mut self, table_heap: TableHeap) {
-        self.table_heap = Arc::new(Mutex::new(table_heap));
+    pub fn get_pin_count(&self, page_id: PageId) -> Option<u64> {
+        let page_table = self.page_table.lock().unwrap();
+        page_table
+            .get(&page_id)
+            .map(|frame_id| self.frames[*frame_id].get_pin_count())
+    }
+
+    #[allow(dead_code)]
+    fn check_write_page(&self, page_id: PageId, data: Vec<u8>) -> Result<bool, String> {
+        if data.len() > PAGE_SIZE {
+            return Ok(false);
+        }
+        let mut guard = self
+            .checked_write_page(page_id)
+            .ok_or_else(|| "failed to get the guard and write data".to_string())?;
+        guard[..data.len()].copy_from_slice(&data);
+        Ok(true)
+    }
+
+    #[allow(dead_code)]
+    fn check_read_page(&self, page_id: PageId) -> Result<bool, String> {
+        match self.checked_read_page(page_id) {
+            Some(_guard) => Ok(true),
+            None => Err("failed to get the guard".to_string()),
+        }
+    }
+
+    #[allow(dead_code)]
+    fn check_page_exists_in_buffer(&self, page_id: PageId) -> bool {
+        self.page_table.lock().unwrap().contains_key(&page_id)
+    }
+}
+
+impl Drop for BufferPoolManager {
+    fn drop(&mut self) {
+        match &self.temp_file {
+            Some(path) => {
+                let _ = std::fs::remove_file(path);
+            }
+            None => self.flush_all_pages(),
+        }
     }
 }
 
@@ -315,6 +428,7 @@ impl BufferPoolManager {
 mod test {
 
     use super::*;
+    use crate::query_types::get_demo_table_heap_with_n_tuples;
 
     #[test]
     fn test_cache_eviction() {
@@ -339,7 +453,7 @@ mod test {
 
     #[test]
     fn test_bpm_new_page() {
-        let mut bpm = BufferPoolManager::new(10, 2);
+        let bpm = BufferPoolManager::new(10, 2);
         let _frame_id_0 = bpm.new_page();
         let _frame_id_1 = bpm.new_page();
         assert_eq!(bpm.get_buffer_manager_size(), 10);
@@ -347,7 +461,7 @@ mod test {
         let successful_delete = bpm.delete_page(0);
         assert_eq!(bpm.get_buffer_manager_size(), 10);
         assert!(successful_delete);
-        assert_eq!(bpm.num_frames, 1);
+        assert_eq!(bpm.num_frames.load(Ordering::SeqCst), 1);
     }
 
     #[test]
@@ -414,18 +528,139 @@ mod test {
 
     #[test]
     fn test_bpm_page_heap_and_pages_in_writes_and_reads() {
-        let mut bpm = BufferPoolManager::new(10, 2);
-        let table_heap = get_demo_table_heap_with_n_page_m_tuples_each(5, 20);
-        bpm.set_table_heap(table_heap);
+        let bpm = Arc::new(BufferPoolManager::new(10, 2));
+        let mut table_heap = get_demo_table_heap_with_n_tuples(Arc::clone(&bpm), 100);
         // have data in the table heap.
-        bpm.table_heap.lock().unwrap().create_index();
+        table_heap.create_index();
     }
 
     #[test]
     fn test_bpm_create_table_heap() {
-        let mut bpm = BufferPoolManager::new(10, 2);
+        let bpm = BufferPoolManager::new(10, 2);
 
         // bpm.table_heap.add_table_page(page);
         bpm.new_page();
     }
+
+    #[test]
+    fn test_replacer_evicts_by_backward_k_distance() {
+        let mut lru = LRUKReplacer::new(2);
+        // frame 0: two old accesses, frame 1: two recent ones, frame 2: a single access
+        for frame_id in [0, 0, 1, 2, 1] {
+            lru.RecordAccess(frame_id);
+        }
+        for frame_id in 0..3 {
+            lru.SetEvictable(frame_id, true);
+        }
+        assert_eq!(lru.Size(), 3);
+        assert_eq!(lru.Evict(), Some(2));
+        assert_eq!(lru.Evict(), Some(0));
+        lru.SetEvictable(1, false);
+        assert_eq!(lru.Evict(), None);
+        assert_eq!(lru.Size(), 0);
+    }
+
+    #[test]
+    fn test_pages_survive_eviction() {
+        let bpm = BufferPoolManager::new(3, 2);
+        let page_ids = (0..10).map(|_| bpm.new_page().unwrap()).collect::<Vec<_>>();
+        for page_id in page_ids.iter() {
+            bpm.write_page(*page_id)[..8].copy_from_slice(&(*page_id as u64).to_be_bytes());
+        }
+        for page_id in page_ids.iter().rev() {
+            let guard = bpm.read_page(*page_id);
+            assert_eq!(guard[..8], (*page_id as u64).to_be_bytes());
+            assert_eq!(guard.page_id(), *page_id);
+        }
+        // a deleted page id is handed out again, zeroed
+        assert!(bpm.delete_page(page_ids[4]));
+        assert!(!bpm.delete_page(page_ids[4]));
+        assert_eq!(bpm.new_page(), Some(page_ids[4]));
+        assert_eq!(bpm.read_page(page_ids[4])[..8], [0; 8]);
+    }
+
+    #[test]
+    fn test_open_keeps_pages_across_restarts() {
+        let path = std::env::temp_dir().join(format!("bpm_reopen_{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        {
+            let bpm = BufferPoolManager::open(&path, 2, 2).unwrap();
+            for i in 0..5u8 {
+                let page_id = bpm.new_page().unwrap();
+                bpm.write_page(page_id)[0] = i + 1;
+            }
+        }
+        let bpm = BufferPoolManager::open(&path, 2, 2).unwrap();
+        assert_eq!(bpm.page_count(), 5);
+        assert!((0..5u8).all(|i| bpm.read_page(i as PageId)[0] == i + 1));
+        assert_eq!(bpm.new_page(), Some(5));
+        drop(bpm);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_free_unreachable_pages() {
+        let bpm = BufferPoolManager::new(4, 2);
+        for _ in 0..6 {
+            bpm.new_page().unwrap();
+        }
+        bpm.free_unreachable_pages(&HashSet::from([0, 2, 5]));
+        let mut reused = (0..3).map(|_| bpm.new_page().unwrap()).collect::<Vec<_>>();
+        reused.sort();
+        assert_eq!(reused, vec![1, 3, 4]);
+        assert_eq!(bpm.new_page(), Some(6));
+    }
+
+    #[test]
+    fn test_pinned_pages_are_not_evicted() {
+        let bpm = BufferPoolManager::new(2, 2);
+        let (a, b, c) = (
+            bpm.new_page().unwrap(),
+            bpm.new_page().unwrap(),
+            bpm.new_page().unwrap(),
+        );
+        let guard_a = bpm.read_page(a);
+        let guard_b = bpm.write_page(b);
+        assert_eq!(bpm.get_pin_count(a), Some(1));
+        assert!(bpm.checked_read_page(c).is_none());
+        assert!(bpm.new_page().is_none());
+        assert!(!bpm.delete_page(a));
+        drop(guard_b);
+        assert_eq!(bpm.get_pin_count(b), Some(0));
+        assert!(bpm.checked_read_page(c).is_some());
+        // b was evicted to make room for c, a stays pinned
+        assert_eq!(bpm.get_pin_count(b), None);
+        assert_eq!(bpm.get_pin_count(a), Some(1));
+        drop(guard_a);
+        assert!(bpm.flush_page(a));
+        assert!(!bpm.flush_page(b));
+    }
+
+    #[test]
+    fn test_concurrent_readers_and_writers() {
+        let bpm = Arc::new(BufferPoolManager::new(4, 2));
+        let page_ids = (0..8).map(|_| bpm.new_page().unwrap()).collect::<Vec<_>>();
+        let handles = (0..4)
+            .map(|t| {
+                let bpm = Arc::clone(&bpm);
+                let page_ids = page_ids.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..50 {
+                        for page_id in page_ids.iter() {
+                            let mut guard = bpm.write_page(*page_id);
+                            let count = u64::from_be_bytes(guard[..8].try_into().unwrap());
+                            guard[..8].copy_from_slice(&(count + 1).to_be_bytes());
+                            drop(guard);
+                            let _ = bpm.read_page(page_ids[t]);
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        handles.into_iter().for_each(|h| h.join().unwrap());
+        for page_id in page_ids {
+            let guard = bpm.read_page(page_id);
+            assert_eq!(u64::from_be_bytes(guard[..8].try_into().unwrap()), 200);
+        }
+    }
 }
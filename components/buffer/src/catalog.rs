@@ -1,26 +1,278 @@
-use std::cell::RefCell;
-use std::sync::atomic::Ordering;
-use std::sync::{Arc, Mutex};
-use std::{collections::HashMap, sync::atomic::AtomicU32};
+use std::collections::{HashMap, HashSet};
+use std::ops::Bound::{self, Excluded, Included, Unbounded};
+use std::path::Path;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, RwLock};
+use std::time::{Duration, SystemTime};
 
+use crate::bplustreeindex::{BPlusTreeIndex, DEFAULT_ORDER};
 use crate::bufferpoolmanager::BufferPoolManager;
-use crate::query_types::{Schema, TableHeap};
+use crate::errors::{ConstraintViolation, DmlError};
+use crate::expression::{Expr, Functions};
+use crate::extendiblehashindex::{ExtendibleHashIndex, DEFAULT_BUCKET_MAX_SIZE};
+use crate::index::Index;
+use crate::index_key::{KeySchema, SortOrder};
+use crate::query_types::{Column, Identity, Schema, Tuple, TypeId, Value};
+use crate::sequence::{Sequence, SequenceOptions};
+use crate::session::DEFAULT_USER;
 use crate::skiplistindex::SkipListIndex;
-use common::transaction::Transaction;
-// use skiplist::SkipMap;
-#[allow(dead_code)]
-enum IndexType {
+use crate::statistics::{AutoAnalyze, TableStatistics};
+use crate::system_catalog::{
+    ConstraintRow, IndexRow, SchemaRow, SequenceRow, SystemCatalog, TableRow,
+};
+use crate::table_heap::TableHeap;
+use common::transaction::{IsolationLevel, Transaction, TransactionState, TxnId, TXN_START_ID};
+use common::types::{PageId, RecordId};
+use concurrency_manager::deadlock_detector::{DeadlockDetector, DEFAULT_DEADLOCK_INTERVAL};
+use concurrency_manager::lock_manager::{LockError, LockManager, LockMode, LockTarget};
+use mvcc::transaction_manager::TransactionManager;
+
+mod constraints;
+mod dml;
+
+use dml::{LockedTable, LockedTables};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IndexType {
     BPlusTreeIndex,
     SkipListIndex,
+    ExtendibleHashIndex,
+}
+/// One change of `ALTER TABLE`.
+#[derive(Clone, Debug)]
+pub enum AlterTable {
+    AddColumn(Column),
+    DropColumn(String),
+    RenameColumn {
+        column: String,
+        new_name: String,
+    },
+    RenameTo(String),
+    AlterColumnType {
+        column: String,
+        type_id: TypeId,
+        length: u32,
+    },
+    SetNotNull(String),
+    DropNotNull(String),
+    SetDefault {
+        column: String,
+        default: Option<Expr>,
+    },
+    AddConstraint(TableConstraint),
+    DropConstraint(String),
+}
+
+/// What happens to the rows referencing a deleted row.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReferentialAction {
+    Restrict,
+    Cascade,
+    SetNull,
+}
+
+/// The point a transaction reading an earlier state of the database goes back to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AsOf {
+    Time(SystemTime),
+    CommitTs(u64),
+}
+
+/// What `select_for_update` does with a row another transaction holds locked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RowLockWait {
+    // wait for it, up to the transaction's lock timeout
+    Wait,
+    // fail, as FOR UPDATE NOWAIT
+    NoWait,
+    // leave it out, as FOR UPDATE SKIP LOCKED
+    SkipLocked,
+}
+
+/// A table constraint as it is declared, e.g. by `ALTER TABLE ... ADD CONSTRAINT`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TableConstraint {
+    Check {
+        name: String,
+        expr: Expr,
+    },
+    // the referenced columns need a unique index over exactly these columns, which is how
+    // referenced rows are found
+    ForeignKey {
+        name: String,
+        columns: Vec<String>,
+        ref_table: String,
+        ref_columns: Vec<String>,
+        on_delete: ReferentialAction,
+    },
+}
+
+// Foreign keys refer to columns by id, which stays the same when a column is renamed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ForeignKey {
+    pub columns: Vec<u32>,
+    pub ref_table_id: TableId,
+    pub ref_columns: Vec<u32>,
+    pub on_delete: ReferentialAction,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConstraintKind {
+    Check(Expr),
+    ForeignKey(ForeignKey),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConstraintInfo {
+    constraint_id: ConstraintId,
+    constraint_name: String,
+    kind: ConstraintKind,
+}
+
+impl ConstraintInfo {
+    pub(crate) fn new(
+        constraint_id: ConstraintId,
+        constraint_name: String,
+        kind: ConstraintKind,
+    ) -> Self {
+        Self {
+            constraint_id,
+            constraint_name,
+            kind,
+        }
+    }
+
+    pub fn get_constraint_name(&self) -> &str {
+        &self.constraint_name
+    }
+
+    pub fn get_kind(&self) -> &ConstraintKind {
+        &self.kind
+    }
+
+    pub(crate) fn get_constraint_id(&self) -> ConstraintId {
+        self.constraint_id
+    }
 }
+
+#[allow(dead_code)]
+type SchemaName = String;
 #[allow(dead_code)]
 type TableName = String;
 #[allow(dead_code)]
 type IndexName = String;
 #[allow(dead_code)]
+type SchemaId = u32;
+#[allow(dead_code)]
 type TableId = u32;
 #[allow(dead_code)]
 type IndexId = u32;
+#[allow(dead_code)]
+type ConstraintId = u32;
+#[allow(dead_code)]
+type SequenceName = String;
+#[allow(dead_code)]
+type SequenceId = u32;
+
+const DEFAULT_POOL_SIZE: usize = 64;
+// transactions that finish between two garbage collections
+const GC_INTERVAL: u64 = 64;
+
+/// Schema of the tables whose name is not qualified, created with every database.
+pub const PUBLIC_SCHEMA: &str = "public";
+
+/// Splits "schema.table" into its schema and table name; a name without a schema is in the
+/// public schema. Schema names are case-insensitive, table names are not.
+pub fn split_name(name: &str) -> (&str, &str) {
+    name.split_once('.').unwrap_or((PUBLIC_SCHEMA, name))
+}
+
+#[derive(Clone, Debug)]
+pub struct SchemaInfo {
+    schema_name: SchemaName,
+    schema_id: SchemaId,
+    owner: String,
+}
+
+impl SchemaInfo {
+    pub fn get_schema_name(&self) -> &str {
+        &self.schema_name
+    }
+
+    pub fn get_schema_id(&self) -> SchemaId {
+        self.schema_id
+    }
+
+    pub fn get_owner(&self) -> &str {
+        &self.owner
+    }
+}
+
+// Unlike the other entries, a sequence is not replaced when its state changes: every statement
+// taking values from it shares the one Sequence.
+#[derive(Debug)]
+pub struct SequenceInfo {
+    sequence_name: SequenceName,
+    sequence_id: SequenceId,
+    schema_id: SchemaId,
+    schema_name: SchemaName,
+    // the identity column the sequence belongs to, as table id and column id
+    owned_by: Option<(TableId, u32)>,
+    // its row in sys_sequences, which is updated in place as blocks of values are reserved
+    rid: RecordId,
+    sequence: Sequence,
+}
+
+impl SequenceInfo {
+    pub fn get_sequence_name(&self) -> &str {
+        &self.sequence_name
+    }
+
+    pub fn get_schema_name(&self) -> &str {
+        &self.schema_name
+    }
+
+    // "schema.sequence"
+    pub fn get_qualified_name(&self) -> String {
+        format!("{}.{}", self.schema_name, self.sequence_name)
+    }
+
+    pub fn get_sequence_id(&self) -> SequenceId {
+        self.sequence_id
+    }
+
+    pub fn get_options(&self) -> &SequenceOptions {
+        self.sequence.get_options()
+    }
+
+    pub fn is_identity(&self) -> bool {
+        self.owned_by.is_some()
+    }
+}
+
+// The sequence of an identity column counts up from 1 to the largest value of the column's type.
+fn identity_options(column: &Column) -> Result<SequenceOptions, String> {
+    let max_value = match column.get_type() {
+        TypeId::TINYINT => i8::MAX as i64,
+        TypeId::SMALLINT => i16::MAX as i64,
+        TypeId::INTEGER => i32::MAX as i64,
+        TypeId::BIGINT => i64::MAX,
+        other => {
+            return Err(format!(
+                "identity column {} must be of an integer type, not {:?}",
+                column.get_name(),
+                other
+            ))
+        }
+    };
+    if column.get_default().is_some() {
+        return Err(format!(
+            "both default and identity specified for column {}",
+            column.get_name()
+        ));
+    }
+    Ok(SequenceOptions::new(1).with_max_value(max_value))
+}
 
 #[allow(dead_code)]
 #[derive(Debug)]
@@ -29,116 +281,3881 @@ pub struct TableInfo {
     schema: Schema, // perhaps put on the heap?
     table_heap: Arc<Mutex<TableHeap>>,
     table_id: TableId,
+    schema_id: SchemaId,
+    schema_name: SchemaName,
+    constraints: Vec<ConstraintInfo>,
+    // rows committed transactions inserted, updated or deleted since the table was last
+    // analyzed
+    modifications: Arc<AtomicU64>,
 }
 
 impl Clone for TableInfo {
     fn clone(&self) -> Self {
         Self {
             table_name: self.table_name.clone(),
+            schema_id: self.schema_id,
+            schema_name: self.schema_name.clone(),
             schema: self.schema.clone(),
             table_heap: self.table_heap.clone(),
-            table_id: self.table_id.clone(),
+            table_id: self.table_id,
+            constraints: self.constraints.clone(),
+            modifications: Arc::clone(&self.modifications),
         }
     }
 }
 #[allow(dead_code)]
 impl TableInfo {
-    fn new(table_name: String, schema: Schema, table_heap: TableHeap, table_id: TableId) -> Self {
+    fn new(
+        schema_info: &SchemaInfo,
+        table_name: String,
+        schema: Schema,
+        table_heap: TableHeap,
+        table_id: TableId,
+    ) -> Self {
         Self {
             table_name,
             schema,
             table_heap: Arc::new(Mutex::new(table_heap)),
             table_id,
+            schema_id: schema_info.schema_id,
+            schema_name: schema_info.schema_name.clone(),
+            constraints: vec![],
+            modifications: Arc::new(AtomicU64::new(0)),
         }
     }
+
+    pub fn get_table_name(&self) -> &str {
+        &self.table_name
+    }
+
+    pub fn get_schema_name(&self) -> &str {
+        &self.schema_name
+    }
+
+    // "schema.table"
+    pub fn get_qualified_name(&self) -> String {
+        format!("{}.{}", self.schema_name, self.table_name)
+    }
+
+    pub fn get_schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    pub fn get_table_heap(&self) -> Arc<Mutex<TableHeap>> {
+        Arc::clone(&self.table_heap)
+    }
+
+    pub fn get_table_id(&self) -> TableId {
+        self.table_id
+    }
+
+    pub fn get_constraints(&self) -> &[ConstraintInfo] {
+        &self.constraints
+    }
+
+    fn column_id(&self, column: &str) -> Result<u32, String> {
+        self.schema
+            .get_col_idx(column)
+            .map(|idx| self.schema.get_column(idx).unwrap().get_column_id())
+            .ok_or_else(|| format!("column {} does not exist", column))
+    }
 }
 
 #[allow(dead_code)]
-struct IndexInfo {
-    schema: Schema,
+#[derive(Clone, Debug)]
+pub struct IndexInfo {
+    key_schema: KeySchema,
     index_name: String,
-    index: Box<SkipListIndex>,
+    index: Arc<dyn Index>,
     index_id: IndexId,
+    table_id: TableId,
     table_name: TableName,
     index_key_size: i32,
+    is_unique: bool,
     is_primary_key: bool,
     index_type: IndexType,
+    header_page_id: Option<PageId>,
 }
 
-#[derive(Default)]
-#[allow(dead_code)]
-struct LockManager {}
+impl IndexInfo {
+    pub fn is_unique(&self) -> bool {
+        self.is_unique
+    }
+
+    pub fn is_primary_key(&self) -> bool {
+        self.is_primary_key
+    }
+
+    // Key under which `tuple` is stored in this index. Unique indexes do not store keys with a
+    // NULL part, as NULLs never compare equal to each other.
+    fn entry_key(&self, tuple: &Tuple) -> Result<Option<Vec<u8>>, String> {
+        if self.is_unique && self.key_schema.has_null(tuple) {
+            return Ok(None);
+        }
+        self.key_schema.encode_tuple(tuple).map(Some)
+    }
+
+    // Key under which a version of a row is stored, None if there is no such version.
+    fn version_key(&self, version: &Option<Tuple>) -> Result<Option<Vec<u8>>, String> {
+        Ok(version
+            .as_ref()
+            .map(|t| self.entry_key(t))
+            .transpose()?
+            .flatten())
+    }
+
+    // Adds the tuple to the index, failing with a constraint violation if a unique key is taken.
+    // An entry the row already has is left as it is, None is returned as for a NULL key.
+    fn insert_entry(
+        &self,
+        tuple: &Tuple,
+        rid: RecordId,
+        schema: &Schema,
+    ) -> Result<Option<Vec<u8>>, DmlError> {
+        if self.is_primary_key && self.key_schema.has_null(tuple) {
+            let column = self
+                .key_schema
+                .get_columns()
+                .iter()
+                .find(|c| tuple.get_value(c.column_idx).is_none_or(|v| v.is_null()))
+                .unwrap();
+            return Err(ConstraintViolation::NotNull {
+                column: schema
+                    .get_column(column.column_idx)
+                    .unwrap()
+                    .get_name()
+                    .to_string(),
+            }
+            .into());
+        }
+        let Some(key) = self.entry_key(tuple)? else {
+            return Ok(None);
+        };
+        let (page_id, slot) = rid.into();
+        if !self.index.insert(key.clone(), page_id, slot) {
+            // kept for the version the row's writer wrote over
+            if self
+                .index
+                .range_query(&key, &key)
+                .contains(&(page_id, slot))
+            {
+                return Ok(None);
+            }
+            if !self.is_unique {
+                return Err(format!("index {} has no room for the key", self.index_name).into());
+            }
+            return Err(ConstraintViolation::Unique {
+                index_name: self.index_name.clone(),
+                key: self.key_schema.key_values(tuple),
+            }
+            .into());
+        }
+        Ok(Some(key))
+    }
+
+    fn delete_entry(&self, key: &[u8], rid: RecordId) -> bool {
+        let (page_id, slot) = rid.into();
+        self.index.delete(key, page_id, slot)
+    }
+
+    pub fn get_index(&self) -> &dyn Index {
+        self.index.as_ref()
+    }
+
+    pub fn get_key_schema(&self) -> &KeySchema {
+        &self.key_schema
+    }
+
+    pub fn get_index_type(&self) -> IndexType {
+        self.index_type
+    }
+
+    pub fn get_index_name(&self) -> &str {
+        &self.index_name
+    }
+
+    pub fn get_table_name(&self) -> &str {
+        &self.table_name
+    }
+
+    pub fn get_index_id(&self) -> IndexId {
+        self.index_id
+    }
+
+    // Whether a foreign key referencing `column_ids` of the table can look its keys up here.
+    fn covers(&self, schema: &Schema, column_ids: &[u32]) -> bool {
+        self.is_unique
+            && self.key_schema.get_columns().len() == column_ids.len()
+            && self
+                .key_schema
+                .get_columns()
+                .iter()
+                .zip(column_ids)
+                .all(|(c, id)| schema.get_column(c.column_idx).unwrap().get_column_id() == *id)
+    }
+}
 
 #[derive(Default)]
 #[allow(dead_code)]
 struct LogManager {}
 
+// The catalog's entries, guarded by one latch. Entries are never changed in place: DDL builds a
+// new TableInfo or IndexInfo and swaps it in, so whoever still holds the old Arc keeps a
+// consistent view of the table.
+#[derive(Clone, Default)]
+struct Entries {
+    schemas: HashMap<SchemaId, Arc<SchemaInfo>>,
+    schema_names: HashMap<SchemaName, SchemaId>,
+    tables: HashMap<TableId, Arc<TableInfo>>,
+    table_names: HashMap<(SchemaId, TableName), TableId>,
+    indexes: HashMap<IndexId, Arc<IndexInfo>>,
+    index_names: HashMap<TableId, HashMap<IndexName, IndexId>>,
+    sequences: HashMap<SequenceId, Arc<SequenceInfo>>,
+    sequence_names: HashMap<(SchemaId, SequenceName), SequenceId>,
+    statistics: HashMap<TableId, Arc<TableStatistics>>,
+}
+
+impl Entries {
+    fn schema(&self, schema_name: &str) -> Option<Arc<SchemaInfo>> {
+        self.schema_names
+            .get(&schema_name.to_lowercase())
+            .map(|schema_id| Arc::clone(&self.schemas[schema_id]))
+    }
+
+    fn table(&self, table_name: &str) -> Option<Arc<TableInfo>> {
+        let (schema_name, table_name) = split_name(table_name);
+        let schema_id = self.schema_names.get(&schema_name.to_lowercase())?;
+        self.table_names
+            .get(&(*schema_id, table_name.to_string()))
+            .map(|table_id| Arc::clone(&self.tables[table_id]))
+    }
+
+    fn sequence(&self, sequence_name: &str) -> Option<Arc<SequenceInfo>> {
+        let sequence_name = sequence_name.to_lowercase();
+        let (schema_name, sequence_name) = split_name(&sequence_name);
+        let schema_id = self.schema_names.get(schema_name)?;
+        self.sequence_names
+            .get(&(*schema_id, sequence_name.to_string()))
+            .map(|sequence_id| Arc::clone(&self.sequences[sequence_id]))
+    }
+
+    // Sequences of the table's identity columns, or of the one with `column_id`.
+    fn owned_sequences(&self, table_id: TableId, column_id: Option<u32>) -> Vec<Arc<SequenceInfo>> {
+        self.sequences
+            .values()
+            .filter(|sequence_info| {
+                sequence_info.owned_by.is_some_and(|(owner, column)| {
+                    owner == table_id && column_id.is_none_or(|id| id == column)
+                })
+            })
+            .cloned()
+            .collect()
+    }
+
+    fn table_indexes(&self, table_id: TableId) -> Vec<Arc<IndexInfo>> {
+        self.index_names
+            .get(&table_id)
+            .map(|names| {
+                names
+                    .values()
+                    .map(|index_id| Arc::clone(&self.indexes[index_id]))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn insert_schema(&mut self, schema_info: Arc<SchemaInfo>) {
+        self.schema_names
+            .insert(schema_info.schema_name.clone(), schema_info.schema_id);
+        self.schemas.insert(schema_info.schema_id, schema_info);
+    }
+
+    fn insert_table(&mut self, table_info: Arc<TableInfo>) {
+        self.table_names.insert(
+            (table_info.schema_id, table_info.table_name.clone()),
+            table_info.table_id,
+        );
+        self.index_names.entry(table_info.table_id).or_default();
+        self.tables.insert(table_info.table_id, table_info);
+    }
+
+    // Takes the table out together with its indexes and statistics.
+    fn remove_table(&mut self, table_info: &TableInfo) {
+        self.table_names
+            .remove(&(table_info.schema_id, table_info.table_name.clone()));
+        self.tables.remove(&table_info.table_id);
+        self.statistics.remove(&table_info.table_id);
+        for index_id in self
+            .index_names
+            .remove(&table_info.table_id)
+            .unwrap_or_default()
+            .into_values()
+        {
+            self.indexes.remove(&index_id);
+        }
+    }
+
+    fn insert_sequence(&mut self, sequence_info: Arc<SequenceInfo>) {
+        self.sequence_names.insert(
+            (sequence_info.schema_id, sequence_info.sequence_name.clone()),
+            sequence_info.sequence_id,
+        );
+        self.sequences
+            .insert(sequence_info.sequence_id, sequence_info);
+    }
+
+    fn remove_sequence(&mut self, sequence_info: &SequenceInfo) {
+        self.sequence_names
+            .remove(&(sequence_info.schema_id, sequence_info.sequence_name.clone()));
+        self.sequences.remove(&sequence_info.sequence_id);
+    }
+
+    fn insert_index(&mut self, index_info: Arc<IndexInfo>) {
+        self.index_names
+            .entry(index_info.table_id)
+            .or_default()
+            .insert(index_info.index_name.clone(), index_info.index_id);
+        self.indexes.insert(index_info.index_id, index_info);
+    }
+
+    // Tables with a foreign key to the table, itself included if it references itself.
+    fn referencing(&self, table_id: TableId) -> Vec<Arc<TableInfo>> {
+        let mut tables = self
+            .tables
+            .values()
+            .filter(|t| t.foreign_keys().any(|(_, fk)| fk.ref_table_id == table_id))
+            .cloned()
+            .collect::<Vec<Arc<TableInfo>>>();
+        tables.sort_by_key(|t| t.table_id);
+        tables
+    }
+
+    // The table and every table connected to it through foreign keys, in either direction,
+    // ordered by table id. A statement changing rows of the table may have to read or change
+    // rows of any of them.
+    fn foreign_key_group(&self, table_id: TableId) -> Vec<Arc<TableInfo>> {
+        let mut group = vec![Arc::clone(&self.tables[&table_id])];
+        let mut next = 0;
+        while next < group.len() {
+            let table_info = Arc::clone(&group[next]);
+            next += 1;
+            let neighbours = table_info
+                .foreign_keys()
+                .filter_map(|(_, fk)| self.tables.get(&fk.ref_table_id).cloned())
+                .chain(self.referencing(table_info.table_id))
+                .collect::<Vec<Arc<TableInfo>>>();
+            for neighbour in neighbours {
+                if !group.iter().any(|t| t.table_id == neighbour.table_id) {
+                    group.push(neighbour);
+                }
+            }
+        }
+        group.sort_by_key(|t| t.table_id);
+        group
+    }
+}
+
+// Pages created by DDL, freed if its transaction aborts.
+enum Storage {
+    Heap(Arc<Mutex<TableHeap>>),
+    Index(Arc<dyn Index>),
+}
+
+impl Storage {
+    fn page_ids(&self) -> Vec<PageId> {
+        match self {
+            Storage::Heap(table_heap) => table_heap.lock().unwrap().page_ids(),
+            Storage::Index(index) => index.page_ids(),
+        }
+    }
+}
+
+// A table whose heap DDL dropped or replaced, with the indexes it had then, or only indexes
+// dropped from a table that stays. Freed once the transaction commits; until then the rows it
+// wrote there are rolled back through them if it aborts.
+struct Retired {
+    table_info: Arc<TableInfo>,
+    indexes: Vec<Arc<IndexInfo>>,
+    with_heap: bool,
+}
+
+impl Retired {
+    fn page_ids(&self) -> Vec<PageId> {
+        let mut page_ids = match self.with_heap {
+            true => self.table_info.table_heap.lock().unwrap().page_ids(),
+            false => vec![],
+        };
+        for index_info in self.indexes.iter() {
+            page_ids.extend(index_info.index.page_ids());
+        }
+        page_ids
+    }
+}
+
+// What the DDL of a transaction that has not ended changed.
+#[derive(Default)]
+struct PendingDdl {
+    // the entries as they were before its first DDL statement, as other transactions see them
+    entries: Option<Arc<Entries>>,
+    // rows of the system tables written, by system table
+    writes: Vec<(usize, RecordId)>,
+    created: Vec<Storage>,
+    retired: Vec<Retired>,
+}
+
+// Held by a DDL statement for as long as it runs: the ddl latch, with the system rows the
+// statement writes recorded for its transaction.
+struct DdlGuard<'a> {
+    catalog: &'a Catalog,
+    txn_id: TxnId,
+    _latch: MutexGuard<'a, ()>,
+}
+
+impl DdlGuard<'_> {
+    fn pending<R>(&self, f: impl FnOnce(&mut PendingDdl) -> R) -> R {
+        let mut pending_ddl = self.catalog.pending_ddl.lock().unwrap();
+        f(pending_ddl.entry(self.txn_id).or_default())
+    }
+
+    fn created(&self, storage: Storage) {
+        self.pending(|pending| pending.created.push(storage));
+    }
+
+    fn retired(&self, table_info: &Arc<TableInfo>, indexes: Vec<Arc<IndexInfo>>, with_heap: bool) {
+        self.pending(|pending| {
+            pending.retired.push(Retired {
+                table_info: Arc::clone(table_info),
+                indexes,
+                with_heap,
+            })
+        });
+    }
+}
+
+impl Drop for DdlGuard<'_> {
+    fn drop(&mut self) {
+        let writes = self.catalog.system.end_journal();
+        self.pending(|pending| pending.writes.extend(writes));
+    }
+}
+
+// The catalog is shared between threads as is. Lookups take the entries latch for as long as it
+// takes to clone an Arc. DDL statements run one at a time under the `ddl` latch and hold the
+// heap latch of the table they change while they swap its entries; DML holds the same heap latch
+// while it reads the entries, so it never sees a table without the index being built on it.
+// Latch order: ddl, table heap, entries.
+//
+// DDL is part of the transaction it runs in and undone if it aborts. A transaction running DDL
+// holds the catalog lock until it ends, so the DDL of one transaction is never interleaved with
+// another's, and the DDL's table locks, so writers of a table it changed wait until it ends.
+// Scans of other transactions see the tables as they were before its DDL; lookups outside of a
+// transaction see them as they are, as do the schemas and sequences it changed.
 #[allow(dead_code)]
 pub struct Catalog {
-    pub bpm: BufferPoolManager,
+    pub bpm: Arc<BufferPoolManager>,
+    system: SystemCatalog,
     logm: LogManager,
-    lockm: LockManager,
-    tables: HashMap<TableId, RefCell<TableInfo>>,
-    table_names: HashMap<TableName, TableId>,
+    lockm: Arc<LockManager>,
+    // breaks the deadlocks of transactions waiting for each other's locks, unless turned off
+    deadlock_detector: Mutex<Option<DeadlockDetector>>,
+    txn_manager: TransactionManager<Tuple>,
+    // rows deleted by committed transactions with the commit timestamp, freed by the garbage
+    // collector once every transaction sees them deleted
+    #[allow(clippy::type_complexity)]
+    deleted: Mutex<Vec<(u64, Arc<TableInfo>, RecordId)>>,
+    finished: AtomicU64,
+    ddl: Mutex<()>,
+    entries: RwLock<Entries>,
+    pending_ddl: Mutex<HashMap<TxnId, PendingDdl>>,
+    schema_next_id: AtomicU32,
     table_next_id: AtomicU32,
-    indexes: HashMap<IndexId, Box<IndexInfo>>,
-    index_names: HashMap<TableName, HashMap<IndexName, IndexId>>,
     index_next_id: AtomicU32,
+    constraint_next_id: AtomicU32,
+    sequence_next_id: AtomicU32,
+    auto_analyze: Mutex<Option<AutoAnalyze>>,
+}
+
+impl Default for Catalog {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 #[allow(dead_code)]
 impl Catalog {
+    // A catalog over a scratch database that is gone once the catalog is dropped.
     pub fn new() -> Self {
-        Self {
-            bpm: BufferPoolManager::new(0, 0),
+        Self::with_bpm(Arc::new(BufferPoolManager::new(DEFAULT_POOL_SIZE, 2)))
+            .expect("could not bootstrap the system tables")
+    }
+
+    /// Opens the database file at `path`, creating it if needed, and loads the tables and
+    /// indexes recorded in its system tables.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        Self::with_bpm(Arc::new(BufferPoolManager::open(
+            path,
+            DEFAULT_POOL_SIZE,
+            2,
+        )?))
+    }
+
+    fn with_bpm(bpm: Arc<BufferPoolManager>) -> Result<Self, String> {
+        let lockm = Arc::new(LockManager::new());
+        let catalog = Self {
+            system: SystemCatalog::open(Arc::clone(&bpm))?,
+            bpm,
             logm: LogManager::default(),
-            lockm: LockManager::default(),
-            tables: HashMap::new(),
-            table_names: HashMap::new(),
+            deadlock_detector: Mutex::new(Some(DeadlockDetector::start(
+                Arc::clone(&lockm),
+                DEFAULT_DEADLOCK_INTERVAL,
+            ))),
+            lockm,
+            txn_manager: TransactionManager::new(),
+            deleted: Mutex::new(vec![]),
+            finished: AtomicU64::new(0),
+            ddl: Mutex::new(()),
+            entries: RwLock::new(Entries::default()),
+            pending_ddl: Mutex::new(HashMap::new()),
+            schema_next_id: AtomicU32::new(0),
             table_next_id: AtomicU32::new(0),
-            indexes: HashMap::new(),
-            index_names: HashMap::new(),
             index_next_id: AtomicU32::new(0),
+            constraint_next_id: AtomicU32::new(0),
+            sequence_next_id: AtomicU32::new(0),
+            auto_analyze: Mutex::new(None),
+        };
+        let schemas = catalog.system.load_schemas()?;
+        if schemas.is_empty() {
+            let txn = catalog.begin(IsolationLevel::default());
+            catalog.create_schema(&txn, PUBLIC_SCHEMA, DEFAULT_USER)?;
+            catalog.commit(&txn).map_err(|e| e.to_string())?;
+        }
+        for row in schemas {
+            catalog.register_schema(SchemaInfo {
+                schema_name: row.schema_name,
+                schema_id: row.schema_id,
+                owner: row.owner,
+            });
+        }
+        // tables with rows of transactions that did not end before the database was closed
+        let mut rolled_back = HashSet::new();
+        for row in catalog.system.load_tables()? {
+            // a table of a schema whose drop did not finish
+            let Some(schema_info) = catalog.get_schema_by_id(row.schema_id) else {
+                catalog.system.delete_table(row.table_id)?;
+                continue;
+            };
+            let table_heap = TableHeap::open(Arc::clone(&catalog.bpm), row.first_page_id);
+            if table_heap.roll_back_unfinished()? > 0 {
+                rolled_back.insert(row.table_id);
+            }
+            catalog.register_table(TableInfo::new(
+                &schema_info,
+                row.table_name,
+                row.schema,
+                table_heap,
+                row.table_id,
+            ));
+        }
+        for row in catalog.system.load_indexes()? {
+            // the index of a table whose drop did not finish
+            let Some(table_info) = catalog.get_table_by_id(row.table_id) else {
+                catalog.system.delete_index(row.index_id)?;
+                continue;
+            };
+            let rebuild = rolled_back.contains(&row.table_id);
+            let index_info = catalog.open_index(&table_info, row, rebuild)?;
+            catalog.register_index(index_info);
         }
+        let mut constraints: HashMap<TableId, Vec<ConstraintInfo>> = HashMap::new();
+        for row in catalog.system.load_constraints()? {
+            let constraint = row.constraint;
+            // the constraint of a table whose drop did not finish, or referencing one
+            let dangling = match &constraint.kind {
+                ConstraintKind::ForeignKey(fk) => {
+                    catalog.get_table_by_id(fk.ref_table_id).is_none()
+                }
+                ConstraintKind::Check(_) => false,
+            };
+            if dangling || catalog.get_table_by_id(row.table_id).is_none() {
+                catalog.system.delete_constraint(constraint.constraint_id)?;
+                continue;
+            }
+            catalog
+                .constraint_next_id
+                .fetch_max(constraint.constraint_id + 1, Ordering::SeqCst);
+            constraints
+                .entry(row.table_id)
+                .or_default()
+                .push(constraint);
+        }
+        for (table_id, mut constraints) in constraints {
+            constraints.sort_by_key(|c| c.constraint_id);
+            let table_info = catalog.get_table_by_id(table_id).unwrap();
+            catalog
+                .entries
+                .write()
+                .unwrap()
+                .insert_table(Arc::new(TableInfo {
+                    constraints,
+                    ..TableInfo::clone(&table_info)
+                }));
+        }
+        for (rid, row) in catalog.system.load_sequences()? {
+            // the sequence of a schema or an identity column whose drop did not finish
+            let owner_is_gone = row.owned_by.is_some_and(|(table_id, column_id)| {
+                catalog
+                    .get_table_by_id(table_id)
+                    .is_none_or(|t| t.schema.get_col_idx_by_id(column_id).is_none())
+            });
+            let schema_info = catalog.get_schema_by_id(row.schema_id);
+            let Some(schema_info) = schema_info.filter(|_| !owner_is_gone) else {
+                catalog.system.delete_sequence(rid)?;
+                continue;
+            };
+            catalog.register_sequence(SequenceInfo {
+                sequence_name: row.sequence_name,
+                sequence_id: row.sequence_id,
+                schema_id: schema_info.schema_id,
+                schema_name: schema_info.schema_name.clone(),
+                owned_by: row.owned_by,
+                rid,
+                sequence: Sequence::new(row.options, row.state),
+            });
+        }
+        for (table_id, statistics) in catalog.system.load_statistics()? {
+            // the statistics of a table whose drop did not finish
+            if catalog.get_table_by_id(table_id).is_none() {
+                catalog.system.delete_statistics(table_id);
+                continue;
+            }
+            catalog
+                .entries
+                .write()
+                .unwrap()
+                .statistics
+                .insert(table_id, Arc::new(statistics));
+        }
+        // pages of objects dropped in an earlier run can be reused
+        let mut reachable = catalog.system.page_ids();
+        let entries = catalog.entries.read().unwrap();
+        for table_info in entries.tables.values() {
+            reachable.extend(table_info.table_heap.lock().unwrap().page_ids());
+        }
+        for index_info in entries.indexes.values() {
+            reachable.extend(index_info.index.page_ids());
+        }
+        drop(entries);
+        catalog
+            .bpm
+            .free_unreachable_pages(&reachable.into_iter().collect());
+        Ok(catalog)
     }
 
-    pub fn create_table(
-        &mut self,
-        _trxn: Transaction,
-        table_name: TableName,
-        schema: Schema,
-        create_table: bool,
-    ) -> TableInfo {
-        let mut table_heap: Option<TableHeap> = None;
-        if create_table {
-            table_heap = Some(TableHeap::new(1));
+    fn register_schema(&self, schema_info: SchemaInfo) -> Arc<SchemaInfo> {
+        self.schema_next_id
+            .fetch_max(schema_info.schema_id + 1, Ordering::SeqCst);
+        let schema_info = Arc::new(schema_info);
+        self.entries
+            .write()
+            .unwrap()
+            .insert_schema(Arc::clone(&schema_info));
+        schema_info
+    }
+
+    fn register_table(&self, table_info: TableInfo) -> Arc<TableInfo> {
+        self.table_next_id
+            .fetch_max(table_info.table_id + 1, Ordering::SeqCst);
+        let table_info = Arc::new(table_info);
+        self.entries
+            .write()
+            .unwrap()
+            .insert_table(Arc::clone(&table_info));
+        table_info
+    }
+
+    fn register_sequence(&self, sequence_info: SequenceInfo) -> Arc<SequenceInfo> {
+        self.sequence_next_id
+            .fetch_max(sequence_info.sequence_id + 1, Ordering::SeqCst);
+        let sequence_info = Arc::new(sequence_info);
+        self.entries
+            .write()
+            .unwrap()
+            .insert_sequence(Arc::clone(&sequence_info));
+        sequence_info
+    }
+
+    fn register_index(&self, index_info: IndexInfo) -> Arc<IndexInfo> {
+        self.index_next_id
+            .fetch_max(index_info.index_id + 1, Ordering::SeqCst);
+        let index_info = Arc::new(index_info);
+        self.entries
+            .write()
+            .unwrap()
+            .insert_index(Arc::clone(&index_info));
+        index_info
+    }
+
+    /// Starts a transaction. Every statement runs in one, and what it changed is visible to
+    /// others once the transaction commits.
+    pub fn begin(&self, isolation_level: IsolationLevel) -> Arc<Transaction> {
+        self.txn_manager.begin(isolation_level)
+    }
+
+    /// Begins a read-only transaction reading the database as it was at `as_of`, which may be
+    /// as far back as the version retention window or the oldest running transaction goes.
+    /// Versions are kept in memory only, the database as it was before startup is not kept.
+    pub fn begin_as_of(&self, as_of: AsOf) -> Result<Arc<Transaction>, String> {
+        let read_ts = match as_of {
+            AsOf::Time(time) => self.txn_manager.get_commit_ts_at(time)?,
+            AsOf::CommitTs(commit_ts) => commit_ts,
+        };
+        self.txn_manager.begin_as_of(read_ts)
+    }
+
+    /// Keeps the versions committed within `retention` of now, so they can be read with
+    /// `begin_as_of`. Rows deleted by mistake can be read back from there.
+    pub fn set_version_retention(&self, retention: Duration) {
+        self.txn_manager.set_retention(retention);
+    }
+
+    pub fn get_version_retention(&self) -> Duration {
+        self.txn_manager.get_retention()
+    }
+
+    /// Looks for transactions waiting for each other's locks every `interval`, or never. One
+    /// transaction of each deadlock fails with a deadlock error, and can only be aborted.
+    pub fn set_deadlock_interval(&self, interval: Option<Duration>) {
+        let mut deadlock_detector = self.deadlock_detector.lock().unwrap();
+        // stops the running detector first
+        *deadlock_detector = None;
+        *deadlock_detector =
+            interval.map(|interval| DeadlockDetector::start(Arc::clone(&self.lockm), interval));
+    }
+
+    pub fn get_deadlock_interval(&self) -> Option<Duration> {
+        let deadlock_detector = self.deadlock_detector.lock().unwrap();
+        deadlock_detector.as_ref().map(|d| d.get_interval())
+    }
+
+    pub fn get_last_commit_ts(&self) -> u64 {
+        self.txn_manager.get_last_commit_ts()
+    }
+
+    /// Called before each statement `txn` runs, a READ COMMITTED transaction takes a new
+    /// snapshot.
+    pub fn begin_statement(&self, txn: &Transaction) {
+        self.txn_manager.begin_statement(txn);
+    }
+
+    /// Commits the transaction, returning its commit timestamp. Transactions beginning later
+    /// see what it wrote. A serializable transaction fails with a serialization failure if it
+    /// cannot be serialized with the transactions that committed while it ran; it can only be
+    /// aborted then, and retried.
+    pub fn commit(&self, txn: &Transaction) -> Result<u64, DmlError> {
+        txn.check_running()?;
+        let (commit_ts, deleted) = self.with_written(txn, |tables, rows| {
+            // taken while the rows are still the transaction's own
+            let mut written = vec![];
+            for (table_id, rid) in rows {
+                let table = &tables.tables[table_id];
+                if let Some(versions) = table.written_versions(txn, *rid)? {
+                    written.push((table, *rid, versions));
+                }
+            }
+            let commit_ts = self
+                .txn_manager
+                .commit(txn)
+                .map_err(|e| match txn.get_state() {
+                    TransactionState::TAINTED => DmlError::SerializationFailure(e),
+                    _ => DmlError::Invalid(e),
+                })?;
+            let mut deleted = vec![];
+            for (table, rid, (before, now)) in written {
+                table.commit_row(rid, &before, &now)?;
+                table.info.modifications.fetch_add(1, Ordering::Relaxed);
+                if now.is_none() {
+                    deleted.push((Arc::clone(&table.info), rid));
+                }
+            }
+            Ok::<_, DmlError>((commit_ts, deleted))
+        })?;
+        self.deleted.lock().unwrap().extend(
+            deleted
+                .into_iter()
+                .map(|(table_info, rid)| (commit_ts, table_info, rid)),
+        );
+        let pending = self.pending_ddl.lock().unwrap().remove(&txn.get_id());
+        if let Some(pending) = pending {
+            let _ddl = self.ddl.lock().unwrap();
+            self.system.commit_writes(&pending.writes);
+            for retired in pending.retired {
+                self.free_pages(retired.page_ids());
+            }
         }
-        let table_name = RefCell::new(table_name);
-        let table_id = self.table_next_id.fetch_add(1, Ordering::SeqCst);
-        let table_info = RefCell::new(TableInfo::new(
-            table_name.borrow().parse().unwrap(),
-            schema,
-            table_heap.unwrap(),
-            table_id,
-        ));
-        self.tables.insert(table_id, table_info.clone());
-        self.table_names
-            .insert(table_name.borrow().parse().unwrap(), table_id);
-        self.index_names
-            .entry(table_name.borrow().parse().unwrap())
-            .or_insert_with(|| HashMap::new());
-        self.bpm.table_heap = table_info.borrow().clone().table_heap;
-        // .insert(IndexName, IndexId);
-        table_info.into_inner()
+        self.lockm.unlock_all(txn);
+        self.finish_transaction();
+        self.auto_analyze(txn.get_write_set().into_keys());
+        Ok(commit_ts)
     }
 
-    pub fn get_table(&self, _table_name: Option<String>) -> Vec<String> {
-        let keys: Vec<String> = self.table_names.keys().cloned().collect();
-        keys
+    /// Aborts the transaction, putting back every row it wrote as it was before, and the
+    /// catalog as it was before its DDL.
+    pub fn abort(&self, txn: &Transaction) -> Result<(), String> {
+        if matches!(
+            txn.get_state(),
+            TransactionState::COMMITTED | TransactionState::ABORTED
+        ) {
+            // refused, it finished already
+            return self.txn_manager.abort(txn);
+        }
+        // others go on seeing the entries as they were before until they are put back
+        let pending = self
+            .pending_ddl
+            .lock()
+            .unwrap()
+            .get_mut(&txn.get_id())
+            .map(|pending| PendingDdl {
+                entries: pending.entries.clone(),
+                ..std::mem::take(pending)
+            });
+        let retired = pending.as_ref().map_or(&[][..], |pending| &pending.retired);
+        let write_set = txn.get_write_set();
+        // rows written to heaps its DDL dropped or replaced since go back through those
+        let mut elsewhere = HashSet::new();
+        for gone in retired.iter().filter(|gone| gone.with_heap) {
+            let table_info = &gone.table_info;
+            let heap = table_info.table_heap.lock().unwrap();
+            let page_ids = heap.page_ids().into_iter().collect::<HashSet<PageId>>();
+            let rids = write_set
+                .get(&table_info.table_id)
+                .into_iter()
+                .flatten()
+                .filter(|rid| page_ids.contains(&rid.page_id))
+                .copied()
+                .collect::<Vec<RecordId>>();
+            let table = LockedTable {
+                info: Arc::clone(table_info),
+                indexes: Self::with_dropped_indexes(gone.indexes.clone(), table_info, retired),
+                heap: &heap,
+                txn_manager: &self.txn_manager,
+                lock_manager: &self.lockm,
+            };
+            Self::roll_back_rows(txn, &table, &rids).map_err(|e| e.to_string())?;
+            elsewhere.extend(page_ids);
+        }
+        self.with_written(txn, |tables, rows| {
+            for (table_id, locked) in tables.tables.iter() {
+                let table = LockedTable {
+                    info: Arc::clone(&locked.info),
+                    indexes: Self::with_dropped_indexes(
+                        locked.indexes.clone(),
+                        &locked.info,
+                        retired,
+                    ),
+                    heap: locked.heap,
+                    txn_manager: locked.txn_manager,
+                    lock_manager: locked.lock_manager,
+                };
+                let rids = rows
+                    .iter()
+                    .filter(|(id, rid)| id == table_id && !elsewhere.contains(&rid.page_id))
+                    .map(|(_, rid)| *rid)
+                    .collect::<Vec<RecordId>>();
+                Self::roll_back_rows(txn, &table, &rids)?;
+            }
+            Ok::<(), DmlError>(())
+        })
+        .map_err(|e| e.to_string())?;
+        if let Some(pending) = pending {
+            self.undo_ddl(txn, pending)?;
+        }
+        self.txn_manager.abort(txn)?;
+        self.lockm.unlock_all(txn);
+        self.finish_transaction();
+        Ok(())
     }
-}
 
-// // Update the internal tracking mechanisms
-// tables_.emplace(table_oid, meta);
-// table_names_.emplace(table_name, table_oid);
-// index_names_.emplace(table_name, std::unordered_map<std::string, index_oid_t>{});
+    fn roll_back_rows(
+        txn: &Transaction,
+        table: &LockedTable,
+        rids: &[RecordId],
+    ) -> Result<(), DmlError> {
+        for rid in rids {
+            table.abort_row(txn, *rid)?;
+        }
+        for rid in rids {
+            table.restore_row(txn, *rid)?;
+        }
+        Ok(())
+    }
+
+    // `indexes` of the table, and those the aborting transaction dropped from its heap, which
+    // may hold entries of rows it wrote before.
+    fn with_dropped_indexes(
+        mut indexes: Vec<Arc<IndexInfo>>,
+        table_info: &TableInfo,
+        retired: &[Retired],
+    ) -> Vec<Arc<IndexInfo>> {
+        for retired in retired.iter().filter(|retired| !retired.with_heap) {
+            if Arc::ptr_eq(&retired.table_info.table_heap, &table_info.table_heap) {
+                indexes.extend(retired.indexes.iter().cloned());
+            }
+        }
+        indexes
+    }
+
+    // Puts the catalog back as it was before the aborting transaction's DDL and frees the pages
+    // it created.
+    fn undo_ddl(&self, txn: &Transaction, pending: PendingDdl) -> Result<(), String> {
+        let _ddl = self.ddl.lock().unwrap();
+        self.system.roll_back_writes(&pending.writes)?;
+        if let Some(entries) = pending.entries {
+            let mut entries = Arc::unwrap_or_clone(entries);
+            let mut current = self.entries.write().unwrap();
+            // statistics collected since are kept for the tables that stay
+            for (table_id, statistics) in current.statistics.drain() {
+                if entries.tables.contains_key(&table_id) {
+                    entries.statistics.insert(table_id, statistics);
+                }
+            }
+            *current = entries;
+        }
+        self.pending_ddl.lock().unwrap().remove(&txn.get_id());
+        for storage in pending.created {
+            self.free_pages(storage.page_ids());
+        }
+        Ok(())
+    }
+
+    fn finish_transaction(&self) {
+        if self.finished.fetch_add(1, Ordering::Relaxed) % GC_INTERVAL == GC_INTERVAL - 1 {
+            self.garbage_collect();
+        }
+    }
+
+    /// Frees what no transaction can read any more: the versions rows had before, and the rows
+    /// whose delete every transaction sees, which leave the heap for good. What committed
+    /// inside the version retention window is kept. Runs every GC_INTERVAL transactions as
+    /// well.
+    pub fn garbage_collect(&self) {
+        let watermark = self.txn_manager.get_gc_horizon();
+        let settled = {
+            let mut deleted = self.deleted.lock().unwrap();
+            let (settled, pending) = std::mem::take(&mut *deleted)
+                .into_iter()
+                .partition(|(commit_ts, _, _)| *commit_ts <= watermark);
+            *deleted = pending;
+            settled
+        };
+        for (_, table_info, rid) in settled {
+            let table_heap = table_info.table_heap.lock().unwrap();
+            // the rows of a heap dropped or truncated since went with it
+            if self
+                .get_table_by_id(table_info.table_id)
+                .is_some_and(|t| Arc::ptr_eq(&t.table_heap, &table_info.table_heap))
+            {
+                table_heap.apply_delete(rid);
+            }
+        }
+        self.txn_manager.garbage_collect();
+    }
+
+    // Runs `f` with the heaps of the tables `txn` wrote to latched, handing it the rows it wrote
+    // in tables that were not dropped since.
+    fn with_written<R>(
+        &self,
+        txn: &Transaction,
+        f: impl FnOnce(&LockedTables, &[(TableId, RecordId)]) -> R,
+    ) -> R {
+        let write_set = txn.get_write_set();
+        let tables = write_set
+            .keys()
+            .filter_map(|table_id| self.get_table_by_id(*table_id))
+            .collect::<Vec<Arc<TableInfo>>>();
+        self.with_locked(tables, |tables| {
+            let rows = write_set
+                .iter()
+                .filter(|(table_id, _)| tables.tables.contains_key(table_id))
+                .flat_map(|(table_id, rids)| rids.iter().map(|rid| (*table_id, *rid)))
+                .collect::<Vec<(TableId, RecordId)>>();
+            f(tables, &rows)
+        })
+    }
+
+    /// Creates an empty schema owned by `owner`. Schema names are kept in lower case.
+    pub fn create_schema(
+        &self,
+        txn: &Transaction,
+        schema_name: &str,
+        owner: &str,
+    ) -> Result<Arc<SchemaInfo>, String> {
+        self.lock_catalog(txn)?;
+        let _ddl = self.ddl_latch(txn);
+        let schema_name = schema_name.to_lowercase();
+        if schema_name.is_empty() || schema_name.contains('.') {
+            return Err(format!("invalid schema name {}", schema_name));
+        }
+        if self.get_schema(&schema_name).is_some() {
+            return Err(format!("schema {} already exists", schema_name));
+        }
+        let schema_id = self.schema_next_id.fetch_add(1, Ordering::SeqCst);
+        self.system.insert_schema(&SchemaRow {
+            schema_id,
+            schema_name: schema_name.clone(),
+            owner: owner.to_string(),
+        })?;
+        Ok(self.register_schema(SchemaInfo {
+            schema_name,
+            schema_id,
+            owner: owner.to_string(),
+        }))
+    }
+
+    /// Drops the schema. Its tables and sequences depend on it, without `cascade` a schema that
+    /// still has either is refused, with it they are dropped, the tables together with their
+    /// indexes. The tables dropped are X locked first, as by `drop_table`.
+    pub fn drop_schema(
+        &self,
+        txn: &Transaction,
+        schema_name: &str,
+        cascade: bool,
+    ) -> Result<(), String> {
+        self.lock_catalog(txn)?;
+        let schema_table_ids = || {
+            self.get_schema(schema_name).map_or(vec![], |schema_info| {
+                self.entries
+                    .read()
+                    .unwrap()
+                    .tables
+                    .values()
+                    .filter(|table_info| table_info.schema_id == schema_info.schema_id)
+                    .map(|table_info| table_info.table_id)
+                    .collect::<Vec<TableId>>()
+            })
+        };
+        // a table created in the schema while waiting for the locks is locked on the next round
+        let ddl = loop {
+            if cascade {
+                for table_id in schema_table_ids() {
+                    self.lockm
+                        .lock_table(txn, LockMode::Exclusive, table_id)
+                        .map_err(|e| e.to_string())?;
+                }
+            }
+            let ddl = self.ddl_latch(txn);
+            if !cascade
+                || schema_table_ids().into_iter().all(|table_id| {
+                    self.lockm.held_mode(txn, LockTarget::Table(table_id))
+                        == Some(LockMode::Exclusive)
+                })
+            {
+                break ddl;
+            }
+        };
+        let schema_info = self
+            .get_schema(schema_name)
+            .ok_or_else(|| format!("schema {} does not exist", schema_name))?;
+        let tables = self
+            .entries
+            .read()
+            .unwrap()
+            .tables
+            .values()
+            .filter(|table_info| table_info.schema_id == schema_info.schema_id)
+            .cloned()
+            .collect::<Vec<Arc<TableInfo>>>();
+        // those of identity columns go with their tables
+        let sequences = self
+            .entries
+            .read()
+            .unwrap()
+            .sequences
+            .values()
+            .filter(|s| s.schema_id == schema_info.schema_id && !s.is_identity())
+            .cloned()
+            .collect::<Vec<Arc<SequenceInfo>>>();
+        if (!tables.is_empty() || !sequences.is_empty()) && !cascade {
+            let mut table_names = tables
+                .iter()
+                .map(|table_info| table_info.table_name.as_str())
+                .collect::<Vec<&str>>();
+            table_names.sort();
+            let mut sequence_names = sequences
+                .iter()
+                .map(|sequence_info| sequence_info.sequence_name.as_str())
+                .collect::<Vec<&str>>();
+            sequence_names.sort();
+            let mut dependents = vec![];
+            if !table_names.is_empty() {
+                dependents.push(format!("tables {}", table_names.join(", ")));
+            }
+            if !sequence_names.is_empty() {
+                dependents.push(format!("sequences {}", sequence_names.join(", ")));
+            }
+            return Err(format!(
+                "cannot drop schema {} because {} depend on it",
+                schema_info.schema_name,
+                dependents.join(" and ")
+            ));
+        }
+        // the tables are unreachable from here on, whatever happens to the rest of the drop
+        self.system.delete_schema(schema_info.schema_id)?;
+        let mut entries = self.entries.write().unwrap();
+        entries.schema_names.remove(&schema_info.schema_name);
+        entries.schemas.remove(&schema_info.schema_id);
+        drop(entries);
+        for table_info in tables {
+            // as it is now, a table dropped before may have had a foreign key to it
+            let table_info = self.get_table_by_id(table_info.table_id).unwrap();
+            self.drop_referencing_constraints(&table_info)?;
+            self.remove_table(&ddl, &table_info)?;
+        }
+        for sequence_info in sequences {
+            self.remove_sequence(&sequence_info)?;
+        }
+        Ok(())
+    }
+
+    pub fn create_table(
+        &self,
+        txn: &Transaction,
+        table_name: TableName,
+        schema: Schema,
+        create_table: bool,
+    ) -> Result<Arc<TableInfo>, String> {
+        self.lock_catalog(txn)?;
+        let ddl = self.ddl_latch(txn);
+        let (schema_name, name) = split_name(&table_name);
+        let schema_info = self
+            .get_schema(schema_name)
+            .ok_or_else(|| format!("schema {} does not exist", schema_name))?;
+        if name.is_empty() || name.contains('.') {
+            return Err(format!("invalid table name {}", table_name));
+        }
+        if self.get_table(&table_name).is_some() {
+            return Err(format!("table {} already exists", table_name));
+        }
+        let table_name = name.to_string();
+        let mut table_heap: Option<TableHeap> = None;
+        if create_table {
+            table_heap = Some(TableHeap::new(Arc::clone(&self.bpm))?);
+        }
+        let table_heap = table_heap.unwrap();
+        self.flush_pages(table_heap.page_ids());
+        let table_id = self.table_next_id.fetch_add(1, Ordering::SeqCst);
+        // others wait for the transaction to end before they read or write the table
+        self.lockm
+            .lock_table(txn, LockMode::Exclusive, table_id)
+            .map_err(|e| e.to_string())?;
+        let mut sequences = vec![];
+        let result = self
+            .identity_defaults(&schema_info, &table_name, table_id, &schema, &mut sequences)
+            .and_then(|schema| {
+                self.system.insert_table(&TableRow {
+                    table_id,
+                    schema_id: schema_info.schema_id,
+                    table_name: table_name.clone(),
+                    first_page_id: table_heap.get_first_page_id(),
+                    schema: schema.clone(),
+                })?;
+                Ok(schema)
+            });
+        let schema = match result {
+            Ok(schema) => schema,
+            Err(e) => {
+                for sequence_info in sequences {
+                    self.remove_sequence(&sequence_info)?;
+                }
+                return Err(e);
+            }
+        };
+        let table_info = self.register_table(TableInfo::new(
+            &schema_info,
+            table_name,
+            schema,
+            table_heap,
+            table_id,
+        ));
+        ddl.created(Storage::Heap(table_info.get_table_heap()));
+        Ok(table_info)
+    }
+
+    // The schema with the identity columns declared in it taking their default from a new
+    // sequence each, "table_column_seq" in the table's schema. The sequences are added to
+    // `created`.
+    fn identity_defaults(
+        &self,
+        schema_info: &SchemaInfo,
+        table_name: &str,
+        table_id: TableId,
+        schema: &Schema,
+        created: &mut Vec<Arc<SequenceInfo>>,
+    ) -> Result<Schema, String> {
+        let mut columns = schema.get_columns().to_vec();
+        for column in columns.iter_mut() {
+            if column.get_identity().is_none() || column.get_default().is_some() {
+                continue;
+            }
+            let sequence_info = self.new_sequence(
+                &format!(
+                    "{}.{}_{}_seq",
+                    schema_info.schema_name,
+                    table_name,
+                    column.get_name()
+                ),
+                identity_options(column)?,
+                Some((table_id, column.get_column_id())),
+            )?;
+            let nextval = Expr::Function {
+                name: "nextval".to_string(),
+                args: vec![Expr::Literal(Value::Varchar(
+                    sequence_info.get_qualified_name(),
+                ))],
+            };
+            *column = column.clone().with_default(nextval);
+            created.push(sequence_info);
+        }
+        Ok(Schema::with_versions(
+            columns,
+            schema.get_versions().to_vec(),
+        ))
+    }
+
+    /// Creates a sequence, "schema.sequence" or one of the public schema. Sequence names are
+    /// kept in lower case.
+    pub fn create_sequence(
+        &self,
+        txn: &Transaction,
+        sequence_name: &str,
+        options: SequenceOptions,
+    ) -> Result<Arc<SequenceInfo>, String> {
+        self.lock_catalog(txn)?;
+        let _ddl = self.ddl_latch(txn);
+        self.new_sequence(sequence_name, options, None)
+    }
+
+    // Called with the ddl latch held.
+    fn new_sequence(
+        &self,
+        sequence_name: &str,
+        options: SequenceOptions,
+        owned_by: Option<(TableId, u32)>,
+    ) -> Result<Arc<SequenceInfo>, String> {
+        options.validate()?;
+        let sequence_name = sequence_name.to_lowercase();
+        let (schema_name, name) = split_name(&sequence_name);
+        let schema_info = self
+            .get_schema(schema_name)
+            .ok_or_else(|| format!("schema {} does not exist", schema_name))?;
+        if name.is_empty() || name.contains('.') {
+            return Err(format!("invalid sequence name {}", sequence_name));
+        }
+        if self.get_sequence(&sequence_name).is_some() {
+            return Err(format!("sequence {} already exists", sequence_name));
+        }
+        let sequence_id = self.sequence_next_id.fetch_add(1, Ordering::SeqCst);
+        let state = Sequence::initial_state(&options);
+        let rid = self.system.insert_sequence(&SequenceRow {
+            sequence_id,
+            schema_id: schema_info.schema_id,
+            sequence_name: name.to_string(),
+            options: options.clone(),
+            state,
+            owned_by,
+        })?;
+        Ok(self.register_sequence(SequenceInfo {
+            sequence_name: name.to_string(),
+            sequence_id,
+            schema_id: schema_info.schema_id,
+            schema_name: schema_info.schema_name.clone(),
+            owned_by,
+            rid,
+            sequence: Sequence::new(options, state),
+        }))
+    }
+
+    /// Drops the sequence. The sequence of an identity column is dropped with the column.
+    pub fn drop_sequence(&self, txn: &Transaction, sequence_name: &str) -> Result<(), String> {
+        self.lock_catalog(txn)?;
+        let _ddl = self.ddl_latch(txn);
+        let sequence_info = self
+            .get_sequence(sequence_name)
+            .ok_or_else(|| format!("sequence {} does not exist", sequence_name))?;
+        if let Some((table_id, column_id)) = sequence_info.owned_by {
+            let table_info = self.get_table_by_id(table_id).unwrap();
+            let schema = &table_info.schema;
+            let column = schema
+                .get_col_idx_by_id(column_id)
+                .and_then(|idx| schema.get_column(idx))
+                .unwrap();
+            return Err(format!(
+                "cannot drop sequence {} because column {} of table {} uses it",
+                sequence_name,
+                column.get_name(),
+                table_info.get_qualified_name()
+            ));
+        }
+        self.remove_sequence(&sequence_info)
+    }
+
+    fn remove_sequence(&self, sequence_info: &SequenceInfo) -> Result<(), String> {
+        self.system.delete_sequence(sequence_info.rid)?;
+        self.entries.write().unwrap().remove_sequence(sequence_info);
+        Ok(())
+    }
+
+    /// The sequence's next value. Only taking the first value of a block of `cache` values
+    /// writes to the database file.
+    pub fn nextval(&self, sequence_info: &SequenceInfo) -> Result<i64, String> {
+        sequence_info
+            .sequence
+            .nextval(&sequence_info.get_qualified_name(), |state| {
+                self.system.set_sequence_state(sequence_info.rid, state)
+            })
+    }
+
+    /// Sets the sequence's current value, `nextval` returns `value` itself next unless
+    /// `is_called`.
+    pub fn setval(
+        &self,
+        sequence_info: &SequenceInfo,
+        value: i64,
+        is_called: bool,
+    ) -> Result<(), String> {
+        sequence_info.sequence.setval(
+            &sequence_info.get_qualified_name(),
+            value,
+            is_called,
+            |state| self.system.set_sequence_state(sequence_info.rid, state),
+        )
+    }
+
+    // A new, empty index. The header page id is set for indexes that keep their pages in the
+    // pool.
+    fn new_index(
+        bpm: &Arc<BufferPoolManager>,
+        index_type: IndexType,
+        is_unique: bool,
+    ) -> Result<(Arc<dyn Index>, Option<PageId>), String> {
+        let index: Arc<dyn Index> = match (index_type, is_unique) {
+            (IndexType::BPlusTreeIndex, true) => Arc::new(BPlusTreeIndex::default()),
+            (IndexType::BPlusTreeIndex, false) => {
+                Arc::new(BPlusTreeIndex::new_non_unique(DEFAULT_ORDER))
+            }
+            (IndexType::SkipListIndex, true) => Arc::new(SkipListIndex::new()),
+            (IndexType::SkipListIndex, false) => Arc::new(SkipListIndex::new_non_unique()),
+            (IndexType::ExtendibleHashIndex, _) => {
+                let index = if is_unique {
+                    ExtendibleHashIndex::new(Arc::clone(bpm), DEFAULT_BUCKET_MAX_SIZE)?
+                } else {
+                    ExtendibleHashIndex::new_non_unique(Arc::clone(bpm), DEFAULT_BUCKET_MAX_SIZE)?
+                };
+                let header_page_id = index.get_header_page_id();
+                return Ok((Arc::new(index), Some(header_page_id)));
+            }
+        };
+        Ok((index, None))
+    }
+
+    // Brings back an index recorded in sys_indexes. In-memory indexes are rebuilt from the
+    // table, a hash index is reattached to its pages unless `rebuild`: rows were rolled back on
+    // startup, and its pages may still hold their entries.
+    fn open_index(
+        &self,
+        table_info: &TableInfo,
+        row: IndexRow,
+        rebuild: bool,
+    ) -> Result<IndexInfo, String> {
+        let key_schema = KeySchema::from_column_names(&table_info.schema, &row.key_columns)?;
+        let (index, header_page_id): (Arc<dyn Index>, _) = match row.header_page_id {
+            Some(header_page_id) if !rebuild => (
+                Arc::new(ExtendibleHashIndex::open(
+                    Arc::clone(&self.bpm),
+                    header_page_id,
+                    row.is_unique,
+                )),
+                Some(header_page_id),
+            ),
+            _ => {
+                let (index, header_page_id) =
+                    Self::new_index(&self.bpm, row.index_type, row.is_unique)?;
+                table_info.table_heap.lock().unwrap().build_index(
+                    &table_info.schema,
+                    &key_schema,
+                    index.as_ref(),
+                    row.is_unique,
+                )?;
+                // the old pages are freed as unreachable
+                if let Some(header_page_id) = header_page_id {
+                    self.flush_pages(index.page_ids());
+                    self.system
+                        .set_header_page_id(row.index_id, header_page_id)?;
+                }
+                (index, header_page_id)
+            }
+        };
+        Ok(IndexInfo {
+            index_key_size: key_schema.key_size(&table_info.schema) as i32,
+            key_schema,
+            index_name: row.index_name,
+            index,
+            index_id: row.index_id,
+            table_id: table_info.table_id,
+            table_name: table_info.table_name.clone(),
+            is_unique: row.is_unique,
+            is_primary_key: row.is_primary_key,
+            index_type: row.index_type,
+            header_page_id,
+        })
+    }
+
+    /// Builds an index over `key_columns` of an existing table, e.g. for
+    /// `CREATE INDEX idx ON t (lastname, salary DESC)`. Waits for an S lock on the table first,
+    /// the transactions writing its rows have to end, readers go on.
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_index(
+        &self,
+        txn: &Transaction,
+        index_name: IndexName,
+        table_name: TableName,
+        key_columns: Vec<(String, SortOrder)>,
+        index_type: IndexType,
+        is_unique: bool,
+        is_primary_key: bool,
+    ) -> Result<Arc<IndexInfo>, String> {
+        let (ddl, table_info) = self.lock_for_ddl(txn, &table_name, LockMode::Shared)?;
+        if self.get_index(&table_name, &index_name).is_some() {
+            return Err(format!(
+                "index {} already exists on {}",
+                index_name, table_name
+            ));
+        }
+        if is_primary_key
+            && self
+                .get_table_indexes(&table_name)
+                .iter()
+                .any(|index_info| index_info.is_primary_key)
+        {
+            return Err(format!("table {} already has a primary key", table_name));
+        }
+        let key_schema = KeySchema::from_column_names(&table_info.schema, &key_columns)?;
+        let is_unique = is_unique || is_primary_key;
+        let (index, header_page_id) = Self::new_index(&self.bpm, index_type, is_unique)?;
+        // writers wait until the index is registered, none of their rows are missed
+        let table_heap = table_info.table_heap.lock().unwrap();
+        if is_primary_key
+            && table_heap.iter().any(|(_, tuple)| {
+                table_info
+                    .schema
+                    .upgrade(tuple)
+                    .is_ok_and(|tuple| key_schema.has_null(&tuple))
+            })
+        {
+            return Err(format!(
+                "primary key columns of {} contain NULL values",
+                table_name
+            ));
+        }
+        table_heap
+            .build_index(&table_info.schema, &key_schema, index.as_ref(), is_unique)
+            .map_err(|e| format!("could not create unique index {}: {}", index_name, e))?;
+
+        let index_id = self.index_next_id.fetch_add(1, Ordering::SeqCst);
+        // stored under the schema's column names, which is how they are resolved on startup
+        let key_columns = key_schema
+            .get_columns()
+            .iter()
+            .map(|c| {
+                let column = table_info.schema.get_column(c.column_idx).unwrap();
+                (column.get_name().to_string(), c.order)
+            })
+            .collect();
+        if header_page_id.is_some() {
+            self.flush_pages(index.page_ids());
+        }
+        self.system.insert_index(&IndexRow {
+            index_id,
+            table_id: table_info.table_id,
+            index_name: index_name.clone(),
+            key_columns,
+            index_type,
+            is_unique,
+            is_primary_key,
+            header_page_id,
+        })?;
+        ddl.created(Storage::Index(Arc::clone(&index)));
+        Ok(self.register_index(IndexInfo {
+            index_key_size: key_schema.key_size(&table_info.schema) as i32,
+            key_schema,
+            index_name,
+            index,
+            index_id,
+            table_id: table_info.table_id,
+            table_name: table_info.table_name.clone(),
+            is_unique,
+            is_primary_key,
+            index_type,
+            header_page_id,
+        }))
+    }
+
+    // Takes the catalog lock for a DDL statement of `txn`, waiting for the transaction holding
+    // it to end.
+    fn lock_catalog(&self, txn: &Transaction) -> Result<(), String> {
+        txn.check_writable()?;
+        self.lockm.lock_catalog(txn).map_err(|e| e.to_string())
+    }
+
+    // Takes the ddl latch for a DDL statement of `txn`. The entries are kept as they are before
+    // the transaction's first one, to be put back if it aborts.
+    fn ddl_latch(&self, txn: &Transaction) -> DdlGuard<'_> {
+        let latch = self.ddl.lock().unwrap();
+        let entries = Arc::new(self.entries.read().unwrap().clone());
+        self.pending_ddl
+            .lock()
+            .unwrap()
+            .entry(txn.get_id())
+            .or_default()
+            .entries
+            .get_or_insert(entries);
+        self.system.begin_journal();
+        DdlGuard {
+            catalog: self,
+            txn_id: txn.get_id(),
+            _latch: latch,
+        }
+    }
+
+    // Locks the table in `mode` until the transaction ends, waiting for the transactions holding
+    // conflicting locks, and looks it up again in case it was dropped meanwhile.
+    fn lock_table_named(
+        &self,
+        txn: &Transaction,
+        table_name: &str,
+        mode: LockMode,
+    ) -> Result<TableId, String> {
+        let table_id = self
+            .get_table(table_name)
+            .ok_or_else(|| format!("table {} does not exist", table_name))?
+            .table_id;
+        self.lockm
+            .lock_table(txn, mode, table_id)
+            .map_err(|e| e.to_string())?;
+        Ok(table_id)
+    }
+
+    // Takes the catalog lock and the lock on the table a DDL statement works on, then the ddl
+    // latch. The latch is not held while waiting, and the table is looked up again under it in
+    // case it changed meanwhile.
+    fn lock_for_ddl(
+        &self,
+        txn: &Transaction,
+        table_name: &str,
+        mode: LockMode,
+    ) -> Result<(DdlGuard<'_>, Arc<TableInfo>), String> {
+        self.lock_catalog(txn)?;
+        let table_id = self.lock_table_named(txn, table_name, mode)?;
+        let ddl = self.ddl_latch(txn);
+        let table_info = self
+            .get_table(table_name)
+            .filter(|table_info| table_info.table_id == table_id)
+            .ok_or_else(|| format!("table {} does not exist", table_name))?;
+        Ok((ddl, table_info))
+    }
+
+    fn free_pages(&self, page_ids: Vec<PageId>) {
+        for page_id in page_ids {
+            self.bpm.delete_page(page_id);
+        }
+    }
+
+    // Writes the pages of a new heap or index before the catalog row pointing at them. They
+    // hold no rows of running transactions: DDL holds the table's S or X lock, and startup
+    // runs none.
+    fn flush_pages(&self, page_ids: Vec<PageId>) {
+        for page_id in page_ids {
+            self.bpm.flush_page(page_id);
+        }
+    }
+
+    /// Drops the table and frees its pages. The table's indexes and the foreign keys of other
+    /// tables referencing it depend on it, without `cascade` a table that still has either is
+    /// refused, with it they are dropped too. Waits for an X lock on the table first.
+    pub fn drop_table(
+        &self,
+        txn: &Transaction,
+        table_name: &str,
+        cascade: bool,
+    ) -> Result<(), String> {
+        let (ddl, table_info) = self.lock_for_ddl(txn, table_name, LockMode::Exclusive)?;
+        let indexes = self.get_table_indexes(table_name);
+        let referencing = self.referencing_constraints(&table_info);
+        if (!indexes.is_empty() || !referencing.is_empty()) && !cascade {
+            let mut index_names = indexes
+                .iter()
+                .map(|index_info| index_info.index_name.as_str())
+                .collect::<Vec<&str>>();
+            index_names.sort();
+            let mut dependents = vec![];
+            if !index_names.is_empty() {
+                dependents.push(format!("indexes {}", index_names.join(", ")));
+            }
+            if !referencing.is_empty() {
+                dependents.push(format!("constraints {}", referencing.join(", ")));
+            }
+            return Err(format!(
+                "cannot drop table {} because {} depend on it",
+                table_name,
+                dependents.join(" and ")
+            ));
+        }
+        self.drop_referencing_constraints(&table_info)?;
+        self.remove_table(&ddl, &table_info)
+    }
+
+    // Deletes the table, its indexes, constraints, statistics and the sequences of its identity
+    // columns from the catalog. Their pages are freed once the transaction commits.
+    fn remove_table(&self, ddl: &DdlGuard, table_info: &Arc<TableInfo>) -> Result<(), String> {
+        let entries = self.entries.read().unwrap();
+        let indexes = entries.table_indexes(table_info.table_id);
+        let sequences = entries.owned_sequences(table_info.table_id, None);
+        drop(entries);
+        self.system.delete_table(table_info.table_id)?;
+        for index_info in indexes.iter() {
+            self.system.delete_index(index_info.index_id)?;
+        }
+        for constraint in table_info.constraints.iter() {
+            self.system.delete_constraint(constraint.constraint_id)?;
+        }
+        for sequence_info in sequences {
+            self.remove_sequence(&sequence_info)?;
+        }
+        self.system.delete_statistics(table_info.table_id);
+        let table_heap = table_info.table_heap.lock().unwrap();
+        self.entries.write().unwrap().remove_table(table_info);
+        drop(table_heap);
+        ddl.retired(table_info, indexes, true);
+        Ok(())
+    }
+
+    pub fn drop_index(
+        &self,
+        txn: &Transaction,
+        table_name: &str,
+        index_name: &str,
+    ) -> Result<(), String> {
+        let (ddl, table_info) = self.lock_for_ddl(txn, table_name, LockMode::Exclusive)?;
+        let index_info = self
+            .get_index(table_name, index_name)
+            .ok_or_else(|| format!("index {} does not exist on {}", index_name, table_name))?;
+        // a foreign key needs a unique index to find the keys it references
+        let indexes = self.get_table_indexes(table_name);
+        let referencing = self
+            .entries
+            .read()
+            .unwrap()
+            .referencing(table_info.table_id);
+        for child in referencing {
+            for (constraint, fk) in child.foreign_keys() {
+                let covers = |i: &IndexInfo| i.covers(&table_info.schema, &fk.ref_columns);
+                if fk.ref_table_id == table_info.table_id
+                    && covers(&index_info)
+                    && !indexes
+                        .iter()
+                        .any(|i| i.index_id != index_info.index_id && covers(i))
+                {
+                    return Err(format!(
+                        "cannot drop index {} because constraint {} on {} depends on it",
+                        index_name,
+                        constraint.constraint_name,
+                        child.get_qualified_name()
+                    ));
+                }
+            }
+        }
+        self.system.delete_index(index_info.index_id)?;
+        let table_heap = table_info.table_heap.lock().unwrap();
+        let mut entries = self.entries.write().unwrap();
+        entries
+            .index_names
+            .get_mut(&table_info.table_id)
+            .unwrap()
+            .remove(index_name);
+        entries.indexes.remove(&index_info.index_id);
+        drop(entries);
+        drop(table_heap);
+        ddl.retired(&table_info, vec![index_info], false);
+        Ok(())
+    }
+
+    /// Removes every row of the table. The table and its indexes start over on new pages and
+    /// the old ones are freed once the transaction commits. Waits for an X lock on the table
+    /// first, the transactions writing its rows have to end before they are gone.
+    pub fn truncate_table(&self, txn: &Transaction, table_name: &str) -> Result<(), String> {
+        let (ddl, table_info) = self.lock_for_ddl(txn, table_name, LockMode::Exclusive)?;
+        let referencing = self.referencing_constraints(&table_info);
+        if !referencing.is_empty() {
+            return Err(format!(
+                "cannot truncate table {} because constraints {} reference it",
+                table_name,
+                referencing.join(", ")
+            ));
+        }
+        let table_heap = table_info.table_heap.lock().unwrap();
+        let new_heap = TableHeap::new(Arc::clone(&self.bpm))?;
+        self.flush_pages(new_heap.page_ids());
+        if let Err(e) = self
+            .system
+            .set_first_page_id(table_info.table_id, new_heap.get_first_page_id())
+        {
+            self.free_pages(new_heap.page_ids());
+            return Err(e);
+        }
+        // a new heap, the old one stays as it is in case the transaction aborts
+        let new_table_info = Arc::new(TableInfo {
+            table_heap: Arc::new(Mutex::new(new_heap)),
+            ..TableInfo::clone(&table_info)
+        });
+        ddl.created(Storage::Heap(new_table_info.get_table_heap()));
+        let indexes = self.get_table_indexes(table_name);
+        let mut new_indexes = vec![];
+        for index_info in indexes.iter() {
+            let (index, header_page_id) =
+                Self::new_index(&self.bpm, index_info.index_type, index_info.is_unique)?;
+            ddl.created(Storage::Index(Arc::clone(&index)));
+            if let Some(header_page_id) = header_page_id {
+                self.flush_pages(index.page_ids());
+                self.system
+                    .set_header_page_id(index_info.index_id, header_page_id)?;
+            }
+            new_indexes.push(Arc::new(IndexInfo {
+                index,
+                header_page_id,
+                ..IndexInfo::clone(index_info)
+            }));
+        }
+        let mut entries = self.entries.write().unwrap();
+        entries.insert_table(new_table_info);
+        for index_info in new_indexes {
+            entries.insert_index(index_info);
+        }
+        drop(entries);
+        drop(table_heap);
+        ddl.retired(&table_info, indexes, true);
+        Ok(())
+    }
+
+    /// Collects statistics of the table's rows, or of every table's without a name, replacing
+    /// those collected before. Waits for an S lock on each table first, the transactions writing
+    /// its rows have to end.
+    pub fn analyze(&self, txn: &Transaction, table_name: Option<&str>) -> Result<(), String> {
+        let tables = match table_name {
+            Some(table_name) => {
+                txn.check_writable()?;
+                let table_id = self.lock_table_named(txn, table_name, LockMode::Shared)?;
+                let table_info = self
+                    .get_table_by_id(table_id)
+                    .ok_or_else(|| format!("table {} does not exist", table_name))?;
+                vec![table_info]
+            }
+            None => {
+                txn.check_writable()?;
+                let mut table_ids = self
+                    .entries
+                    .read()
+                    .unwrap()
+                    .tables
+                    .keys()
+                    .copied()
+                    .collect::<Vec<TableId>>();
+                table_ids.sort();
+                for table_id in table_ids.iter() {
+                    self.lockm
+                        .lock_table(txn, LockMode::Shared, *table_id)
+                        .map_err(|e| e.to_string())?;
+                }
+                // those dropped while waiting are gone
+                table_ids
+                    .into_iter()
+                    .filter_map(|table_id| self.get_table_by_id(table_id))
+                    .collect::<Vec<Arc<TableInfo>>>()
+            }
+        };
+        for table_info in tables {
+            self.analyze_table(&table_info)?;
+        }
+        Ok(())
+    }
+
+    // Collects the statistics of the committed rows through a snapshot of its own, latching one
+    // page at a time, and takes the ddl latch only to store them.
+    fn analyze_table(&self, table_info: &Arc<TableInfo>) -> Result<(), String> {
+        // rows changed from here on count towards the next auto-analyze
+        table_info.modifications.store(0, Ordering::Relaxed);
+        let snapshot = self
+            .txn_manager
+            .begin_as_of(self.txn_manager.get_last_commit_ts())?;
+        let mut failed = None;
+        let rows = self
+            .scan_table(&snapshot, Arc::clone(table_info))
+            .map_while(|row| match row {
+                Ok((_, tuple)) => Some(tuple),
+                Err(e) => {
+                    failed = Some(e.to_string());
+                    None
+                }
+            });
+        let page_count = table_info.table_heap.lock().unwrap().page_ids().len() as u64;
+        let statistics = TableStatistics::collect(&table_info.schema, rows, page_count);
+        let _ = self.commit(&snapshot);
+        if let Some(e) = failed {
+            return Err(e);
+        }
+        let statistics = statistics?;
+        let _ddl = self.ddl.lock().unwrap();
+        // dropped or truncated while it was read
+        if !self
+            .get_table_by_id(table_info.table_id)
+            .is_some_and(|t| Arc::ptr_eq(&t.table_heap, &table_info.table_heap))
+        {
+            return Ok(());
+        }
+        self.system
+            .set_statistics(table_info.table_id, &statistics)?;
+        self.entries
+            .write()
+            .unwrap()
+            .statistics
+            .insert(table_info.table_id, Arc::new(statistics));
+        Ok(())
+    }
+
+    /// Turns analyzing tables once enough of their rows changed on or off. It is off unless set.
+    pub fn set_auto_analyze(&self, auto_analyze: Option<AutoAnalyze>) {
+        *self.auto_analyze.lock().unwrap() = auto_analyze;
+    }
+
+    // Analyzes the tables a transaction wrote to once it committed, if auto-analyze is on and
+    // enough of their rows changed since they were last analyzed. The commit went through even
+    // if this fails.
+    fn auto_analyze(&self, table_ids: impl Iterator<Item = TableId>) {
+        let Some(auto_analyze) = *self.auto_analyze.lock().unwrap() else {
+            return;
+        };
+        for table_id in table_ids {
+            let Some(table_info) = self.get_table_by_id(table_id) else {
+                continue;
+            };
+            let row_count = self
+                .entries
+                .read()
+                .unwrap()
+                .statistics
+                .get(&table_id)
+                .map_or(0, |statistics| statistics.row_count);
+            if auto_analyze.is_due(table_info.modifications.load(Ordering::Relaxed), row_count) {
+                let _ = self.analyze_table(&table_info);
+            }
+        }
+    }
+
+    /// The statistics of the table as of its last ANALYZE, if it was analyzed.
+    pub fn get_statistics(&self, table_name: &str) -> Option<Arc<TableStatistics>> {
+        let entries = self.entries.read().unwrap();
+        let table_info = entries.table(table_name)?;
+        entries.statistics.get(&table_info.table_id).cloned()
+    }
+
+    /// The schema named `schema_name`, matched case-insensitively, None if it does not exist.
+    pub fn get_schema(&self, schema_name: &str) -> Option<Arc<SchemaInfo>> {
+        self.entries.read().unwrap().schema(schema_name)
+    }
+
+    pub fn get_schema_by_id(&self, schema_id: SchemaId) -> Option<Arc<SchemaInfo>> {
+        self.entries
+            .read()
+            .unwrap()
+            .schemas
+            .get(&schema_id)
+            .cloned()
+    }
+
+    pub fn get_schema_names(&self) -> Vec<SchemaName> {
+        let mut schema_names = self
+            .entries
+            .read()
+            .unwrap()
+            .schema_names
+            .keys()
+            .cloned()
+            .collect::<Vec<SchemaName>>();
+        schema_names.sort();
+        schema_names
+    }
+
+    /// The table named `table_name`, "schema.table" or a table of the public schema, as of the
+    /// last DDL statement that changed it.
+    pub fn get_table(&self, table_name: &str) -> Option<Arc<TableInfo>> {
+        self.entries.read().unwrap().table(table_name)
+    }
+
+    // The table as `txn` reads it, DDL of another transaction that has not ended is not seen.
+    fn get_table_for(&self, txn: &Transaction, table_name: &str) -> Option<Arc<TableInfo>> {
+        let before = self
+            .pending_ddl
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(txn_id, _)| **txn_id != txn.get_id())
+            .and_then(|(_, pending)| pending.entries.clone());
+        match before {
+            Some(entries) => entries.table(table_name),
+            None => self.get_table(table_name),
+        }
+    }
+
+    pub fn get_table_by_id(&self, table_id: TableId) -> Option<Arc<TableInfo>> {
+        self.entries.read().unwrap().tables.get(&table_id).cloned()
+    }
+
+    /// The sequence named `sequence_name`, "schema.sequence" or a sequence of the public schema.
+    pub fn get_sequence(&self, sequence_name: &str) -> Option<Arc<SequenceInfo>> {
+        self.entries.read().unwrap().sequence(sequence_name)
+    }
+
+    /// Qualified names of all sequences, "schema.sequence".
+    pub fn get_sequence_names(&self) -> Vec<SequenceName> {
+        let mut sequence_names = self
+            .entries
+            .read()
+            .unwrap()
+            .sequences
+            .values()
+            .map(|sequence_info| sequence_info.get_qualified_name())
+            .collect::<Vec<SequenceName>>();
+        sequence_names.sort();
+        sequence_names
+    }
+
+    pub fn get_index(&self, table_name: &str, index_name: &str) -> Option<Arc<IndexInfo>> {
+        let entries = self.entries.read().unwrap();
+        let table_id = entries.table(table_name)?.table_id;
+        let index_id = entries.index_names.get(&table_id)?.get(index_name)?;
+        entries.indexes.get(index_id).cloned()
+    }
+
+    pub fn get_table_indexes(&self, table_name: &str) -> Vec<Arc<IndexInfo>> {
+        let entries = self.entries.read().unwrap();
+        entries
+            .table(table_name)
+            .map(|table_info| entries.table_indexes(table_info.table_id))
+            .unwrap_or_default()
+    }
+
+    /// Qualified names of all tables, "schema.table".
+    pub fn get_table_names(&self) -> Vec<TableName> {
+        let mut table_names = self
+            .entries
+            .read()
+            .unwrap()
+            .tables
+            .values()
+            .map(|table_info| table_info.get_qualified_name())
+            .collect::<Vec<TableName>>();
+        table_names.sort();
+        table_names
+    }
+
+    /// Changes the table's definition. Adding, dropping, renaming or retyping a column does not
+    /// touch the stored rows, they are upgraded to the new schema version as they are read.
+    /// Columns used by an index or a constraint cannot be dropped or change their type, and the
+    /// stored rows have to satisfy a constraint being added. Waits for an X lock on the table
+    /// first.
+    pub fn alter_table(
+        &self,
+        txn: &Transaction,
+        table_name: &str,
+        action: AlterTable,
+    ) -> Result<(), String> {
+        self.lock_catalog(txn)?;
+        // a foreign key being added reads the referenced rows, which have to stay as they are
+        if let AlterTable::AddConstraint(TableConstraint::ForeignKey { ref_table, .. }) = &action {
+            if let Some(ref_info) = self.get_table(ref_table) {
+                self.lockm
+                    .lock_table(txn, LockMode::Shared, ref_info.table_id)
+                    .map_err(|e| e.to_string())?;
+            }
+        }
+        let (_ddl, table_info) = self.lock_for_ddl(txn, table_name, LockMode::Exclusive)?;
+        let action = match action {
+            AlterTable::AddConstraint(TableConstraint::ForeignKey {
+                name,
+                columns,
+                ref_table,
+                ref_columns,
+                on_delete,
+            }) => {
+                return self.add_foreign_key(
+                    &table_info,
+                    name,
+                    &columns,
+                    &ref_table,
+                    &ref_columns,
+                    on_delete,
+                )
+            }
+            action => action,
+        };
+        let table_heap = table_info.table_heap.lock().unwrap();
+        let indexes = self.get_table_indexes(table_name);
+        let schema = &table_info.schema;
+        let key_columns = |index_info: &IndexInfo| {
+            index_info
+                .key_schema
+                .get_columns()
+                .iter()
+                .map(|c| {
+                    let column = schema.get_column(c.column_idx).unwrap();
+                    (column.get_name().to_string(), c.order)
+                })
+                .collect::<Vec<(String, SortOrder)>>()
+        };
+        let refuse_indexed = |column: &str| match indexes.iter().find(|index_info| {
+            key_columns(index_info)
+                .iter()
+                .any(|(name, _)| name.eq_ignore_ascii_case(column))
+        }) {
+            Some(index_info) => Err(format!(
+                "column {} is used by index {}",
+                column, index_info.index_name
+            )),
+            None => Ok(()),
+        };
+        let refuse_constrained = |column: &str| {
+            let column_id = table_info.column_id(column)?;
+            match table_info.constraints.iter().find(|c| match &c.kind {
+                ConstraintKind::Check(expr) => expr.references(column),
+                ConstraintKind::ForeignKey(fk) => fk.columns.contains(&column_id),
+            }) {
+                Some(constraint) => Err(format!(
+                    "column {} is used by constraint {}",
+                    column, constraint.constraint_name
+                )),
+                None => Ok(()),
+            }
+        };
+        let refuse_identity = |column: &str| {
+            let idx = schema
+                .get_col_idx(column)
+                .ok_or_else(|| format!("column {} does not exist", column))?;
+            match schema.get_column(idx).unwrap().get_identity() {
+                Some(_) => Err(format!(
+                    "column {} of table {} is an identity column",
+                    column, table_name
+                )),
+                None => Ok(()),
+            }
+        };
+        let mut constraints = table_info.constraints.clone();
+        // whether the stored rows have to be checked against the new definition
+        let mut check_rows = false;
+        // sequences created for a new identity column and dropped with a column
+        let mut created = vec![];
+        let mut dropped = vec![];
+        let (new_schema, renamed) = match action {
+            AlterTable::RenameTo(new_name) => {
+                return self.rename_table(&table_info, &indexes, new_name)
+            }
+            AlterTable::AddColumn(column) => {
+                check_rows = true;
+                if column.get_identity().is_some() {
+                    identity_options(&column)?;
+                }
+                let new_schema = schema.add_column(column)?;
+                let schema_info = self.get_schema_by_id(table_info.schema_id).unwrap();
+                let new_schema = self.identity_defaults(
+                    &schema_info,
+                    &table_info.table_name,
+                    table_info.table_id,
+                    &new_schema,
+                    &mut created,
+                )?;
+                (new_schema, None)
+            }
+            AlterTable::DropColumn(column) => {
+                refuse_indexed(&column)?;
+                refuse_constrained(&column)?;
+                dropped = self
+                    .entries
+                    .read()
+                    .unwrap()
+                    .owned_sequences(table_info.table_id, Some(table_info.column_id(&column)?));
+                (schema.drop_column(&column)?, None)
+            }
+            AlterTable::RenameColumn { column, new_name } => {
+                let new_schema = schema.rename_column(&column, &new_name)?;
+                for constraint in constraints.iter_mut() {
+                    if let ConstraintKind::Check(expr) = &mut constraint.kind {
+                        *expr = expr.rename_column(&column, &new_name);
+                    }
+                }
+                (new_schema, Some((column, new_name)))
+            }
+            AlterTable::AlterColumnType {
+                column,
+                type_id,
+                length,
+            } => {
+                refuse_indexed(&column)?;
+                refuse_constrained(&column)?;
+                // every stored value has to survive the cast it gets when it is read
+                check_rows = true;
+                (schema.alter_column_type(&column, type_id, length)?, None)
+            }
+            AlterTable::SetNotNull(column) => {
+                check_rows = true;
+                (schema.set_not_null(&column, true)?, None)
+            }
+            AlterTable::DropNotNull(column) => {
+                refuse_identity(&column)?;
+                (schema.set_not_null(&column, false)?, None)
+            }
+            AlterTable::SetDefault { column, default } => {
+                refuse_identity(&column)?;
+                (schema.set_default(&column, default)?, None)
+            }
+            AlterTable::AddConstraint(TableConstraint::Check { name, expr }) => {
+                if table_info.constraint(&name).is_some() {
+                    return Err(format!(
+                        "constraint {} already exists on {}",
+                        name, table_name
+                    ));
+                }
+                if let Some(column) = expr
+                    .columns()
+                    .iter()
+                    .find(|c| schema.get_col_idx(c).is_none())
+                {
+                    return Err(format!("column {} does not exist", column));
+                }
+                let constraint_id = self.constraint_next_id.fetch_add(1, Ordering::SeqCst);
+                constraints.push(ConstraintInfo::new(
+                    constraint_id,
+                    name,
+                    ConstraintKind::Check(expr),
+                ));
+                check_rows = true;
+                (schema.clone(), None)
+            }
+            AlterTable::AddConstraint(TableConstraint::ForeignKey { .. }) => unreachable!(),
+            AlterTable::DropConstraint(name) => {
+                if table_info.constraint(&name).is_none() {
+                    return Err(format!(
+                        "constraint {} does not exist on {}",
+                        name, table_name
+                    ));
+                }
+                constraints.retain(|c| c.constraint_name != name);
+                (schema.clone(), None)
+            }
+        };
+        let new_table_info = TableInfo {
+            schema: new_schema,
+            constraints,
+            ..TableInfo::clone(&table_info)
+        };
+        let result = self.alter_table_info(
+            &table_info,
+            new_table_info,
+            &table_heap,
+            &indexes,
+            check_rows,
+            renamed,
+        );
+        for sequence_info in if result.is_ok() { dropped } else { created } {
+            self.remove_sequence(&sequence_info)?;
+        }
+        result
+    }
+
+    // Checks the stored rows against the table's new definition if `check_rows`, then makes it
+    // durable and swaps it in together with the indexes of the table.
+    fn alter_table_info(
+        &self,
+        table_info: &TableInfo,
+        new_table_info: TableInfo,
+        table_heap: &TableHeap,
+        indexes: &[Arc<IndexInfo>],
+        check_rows: bool,
+        renamed: Option<(String, String)>,
+    ) -> Result<(), String> {
+        let schema = &table_info.schema;
+        let key_columns = |index_info: &IndexInfo| {
+            index_info
+                .key_schema
+                .get_columns()
+                .iter()
+                .map(|c| {
+                    let column = schema.get_column(c.column_idx).unwrap();
+                    (column.get_name().to_string(), c.order)
+                })
+                .collect::<Vec<(String, SortOrder)>>()
+        };
+        if check_rows {
+            for (_, tuple) in table_heap.iter() {
+                let tuple = new_table_info.schema.upgrade(tuple)?;
+                new_table_info
+                    .check_row(&tuple)
+                    .map_err(|e| new_table_info.existing_row_error(e))?;
+            }
+        }
+
+        // positions of the key columns shift when a column is dropped, names when one is renamed
+        let mut new_indexes = vec![];
+        for index_info in indexes.iter() {
+            let mut key_columns = key_columns(index_info);
+            let mut renamed_key = false;
+            if let Some((column, new_name)) = &renamed {
+                for (name, _) in key_columns.iter_mut() {
+                    if name.eq_ignore_ascii_case(column) {
+                        *name = new_name.clone();
+                        renamed_key = true;
+                    }
+                }
+            }
+            let key_schema = KeySchema::from_column_names(&new_table_info.schema, &key_columns)?;
+            new_indexes.push((index_info, key_schema, renamed_key.then_some(key_columns)));
+        }
+        if new_table_info.schema != *schema {
+            self.system.alter_table(&TableRow {
+                table_id: table_info.table_id,
+                schema_id: table_info.schema_id,
+                table_name: table_info.table_name.clone(),
+                first_page_id: table_heap.get_first_page_id(),
+                schema: new_table_info.schema.clone(),
+            })?;
+        }
+        self.write_constraints(table_info, &new_table_info.constraints)?;
+        for (index_info, _, key_columns) in new_indexes.iter() {
+            if let Some(key_columns) = key_columns {
+                self.system
+                    .set_key_columns(index_info.index_id, key_columns)?;
+            }
+        }
+        let mut entries = self.entries.write().unwrap();
+        for (index_info, key_schema, _) in new_indexes {
+            entries.insert_index(Arc::new(IndexInfo {
+                key_schema,
+                ..IndexInfo::clone(index_info)
+            }));
+        }
+        entries.insert_table(Arc::new(new_table_info));
+        Ok(())
+    }
+
+    fn rename_table(
+        &self,
+        table_info: &TableInfo,
+        indexes: &[Arc<IndexInfo>],
+        new_name: TableName,
+    ) -> Result<(), String> {
+        // a table stays in its schema
+        if new_name.is_empty() || new_name.contains('.') {
+            return Err(format!("invalid table name {}", new_name));
+        }
+        let qualified_name = format!("{}.{}", table_info.schema_name, new_name);
+        if self.get_table(&qualified_name).is_some() {
+            return Err(format!("table {} already exists", qualified_name));
+        }
+        self.system.rename_table(table_info.table_id, &new_name)?;
+        let mut entries = self.entries.write().unwrap();
+        entries
+            .table_names
+            .remove(&(table_info.schema_id, table_info.table_name.clone()));
+        entries.insert_table(Arc::new(TableInfo {
+            table_name: new_name.clone(),
+            ..table_info.clone()
+        }));
+        for index_info in indexes {
+            entries.insert_index(Arc::new(IndexInfo {
+                table_name: new_name.clone(),
+                ..IndexInfo::clone(index_info)
+            }));
+        }
+        Ok(())
+    }
+
+    pub fn get_table_heap(&self, table_name: &str) -> Option<Arc<Mutex<TableHeap>>> {
+        self.get_table(table_name).map(|t| t.get_table_heap())
+    }
+}
+
+// // Update the internal tracking mechanisms
+// tables_.emplace(table_oid, meta);
+// table_names_.emplace(table_name, table_oid);
+// index_names_.emplace(table_name, std::unordered_map<std::string, index_oid_t>{});
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::query_types::{get_demo_schema, get_demo_table_heap_with_n_tuples, Value};
+    use crate::session::Session;
+    use concurrency_manager::lock_manager::LockTarget;
+
+    // A transaction of its own for one statement, committed once the statement is done with it,
+    // as statements outside of BEGIN run. DDL holds its table locks until then.
+    pub(crate) struct AutoCommit<'a> {
+        catalog: &'a Catalog,
+        txn: Arc<Transaction>,
+    }
+
+    impl std::ops::Deref for AutoCommit<'_> {
+        type Target = Transaction;
+
+        fn deref(&self) -> &Transaction {
+            &self.txn
+        }
+    }
+
+    impl Drop for AutoCommit<'_> {
+        fn drop(&mut self) {
+            if self.catalog.commit(&self.txn).is_err() {
+                let _ = self.catalog.abort(&self.txn);
+            }
+        }
+    }
+
+    pub(crate) fn autocommit(catalog: &Catalog) -> AutoCommit<'_> {
+        AutoCommit {
+            catalog,
+            txn: catalog.begin(IsolationLevel::default()),
+        }
+    }
+
+    pub(crate) fn person(name: &str, lastname: &str) -> Vec<Value> {
+        vec![
+            Value::Varchar(name.to_string()),
+            Value::Varchar(lastname.to_string()),
+            Value::Varchar("1 main st".to_string()),
+            Value::Varchar("100".to_string()),
+            Value::Varchar("30".to_string()),
+        ]
+    }
+
+    pub(crate) fn catalog_with_people() -> Catalog {
+        let catalog = Catalog::new();
+        create_people(&catalog);
+        catalog
+    }
+
+    fn create_people(catalog: &Catalog) {
+        let txn = catalog.begin(IsolationLevel::default());
+        catalog
+            .create_table(&txn, "people".to_string(), get_demo_schema(), true)
+            .unwrap();
+        catalog
+            .create_index(
+                &txn,
+                "people_pkey".to_string(),
+                "people".to_string(),
+                vec![("name".to_string(), SortOrder::Asc)],
+                IndexType::BPlusTreeIndex,
+                false,
+                true,
+            )
+            .unwrap();
+        catalog
+            .create_index(
+                &txn,
+                "people_lastname_key".to_string(),
+                "people".to_string(),
+                vec![("lastname".to_string(), SortOrder::Asc)],
+                IndexType::SkipListIndex,
+                true,
+                false,
+            )
+            .unwrap();
+        catalog.commit(&txn).unwrap();
+    }
+
+    pub(crate) fn insert_people(catalog: &Catalog, n: usize) {
+        let txn = catalog.begin(IsolationLevel::default());
+        for i in 0..n {
+            let mut row = person(&format!("name{}", i), &format!("lastname{}", i));
+            row[2] = Value::Varchar(format!("{} main st", i));
+            catalog.insert_tuple(&txn, "people", row).unwrap();
+        }
+        catalog.commit(&txn).unwrap();
+    }
+
+    pub(crate) fn alter(
+        catalog: &Catalog,
+        table_name: &str,
+        action: AlterTable,
+    ) -> Result<(), String> {
+        catalog.alter_table(&autocommit(catalog), table_name, action)
+    }
+
+    pub(crate) fn check(name: &str, expr: &str) -> AlterTable {
+        AlterTable::AddConstraint(TableConstraint::Check {
+            name: name.to_string(),
+            expr: Expr::parse(expr).unwrap(),
+        })
+    }
+
+    pub(crate) fn create_child(catalog: &Catalog, table_name: &str, on_delete: ReferentialAction) {
+        let schema = Schema::new(vec![
+            Column::new("owner".to_string(), TypeId::VARCHAR, 20),
+            Column::new("label".to_string(), TypeId::VARCHAR, 20),
+        ]);
+        catalog
+            .create_table(&autocommit(catalog), table_name.to_string(), schema, true)
+            .unwrap();
+        alter(
+            catalog,
+            table_name,
+            AlterTable::AddConstraint(TableConstraint::ForeignKey {
+                name: format!("{}_owner", table_name),
+                columns: vec!["owner".to_string()],
+                ref_table: "people".to_string(),
+                ref_columns: vec!["name".to_string()],
+                on_delete,
+            }),
+        )
+        .unwrap();
+    }
+
+    pub(crate) fn owned(owner: &str, label: &str) -> Vec<Value> {
+        vec![
+            Value::Varchar(owner.to_string()),
+            Value::Varchar(label.to_string()),
+        ]
+    }
+
+    pub(crate) fn rows(catalog: &Catalog, txn: &Transaction, table_name: &str) -> Vec<Vec<Value>> {
+        let mut rows = catalog
+            .scan(txn, table_name)
+            .unwrap()
+            .map(Result::unwrap)
+            .map(|(_, tuple)| tuple.get_values().to_vec())
+            .collect::<Vec<Vec<Value>>>();
+        rows.sort_by_key(|values| format!("{:?}", values));
+        rows
+    }
+
+    // The demo columns after an identity column "id".
+    fn schema_with_identity(type_id: TypeId, identity: Identity) -> Schema {
+        let mut columns = get_demo_schema().get_columns().to_vec();
+        columns.insert(
+            0,
+            Column::new("id".to_string(), type_id, 8).with_identity(identity),
+        );
+        Schema::new(columns)
+    }
+
+    pub(crate) fn names(catalog: &Catalog, txn: &Transaction) -> Vec<String> {
+        let mut names = catalog
+            .scan(txn, "people")
+            .unwrap()
+            .map(Result::unwrap)
+            .map(|(_, tuple)| tuple.get_value(0).unwrap().to_string())
+            .collect::<Vec<String>>();
+        names.sort();
+        names
+    }
+
+    pub(crate) fn varchar(value: &str) -> Value {
+        Value::Varchar(value.to_string())
+    }
+
+    pub(crate) fn key(index_info: &IndexInfo, value: &str) -> Vec<u8> {
+        index_info
+            .get_key_schema()
+            .encode_values(&[varchar(value)])
+            .unwrap()
+    }
+
+    pub(crate) fn lastname(catalog: &Catalog, txn: &Transaction, rid: RecordId) -> String {
+        let tuple = catalog.get_tuple(txn, "people", rid).unwrap().unwrap();
+        tuple.get_value(1).unwrap().to_string()
+    }
+
+    // The values of one column of scanned rows, in the order they came.
+    pub(crate) fn column(rows: &[(RecordId, Tuple)], column_id: usize) -> Vec<String> {
+        rows.iter()
+            .map(|(_, tuple)| tuple.get_value(column_id).unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_create_composite_index() {
+        let catalog = Catalog::new();
+        catalog
+            .create_table(
+                &autocommit(&catalog),
+                "people".to_string(),
+                get_demo_schema(),
+                true,
+            )
+            .unwrap();
+        *catalog
+            .get_table("people")
+            .unwrap()
+            .table_heap
+            .lock()
+            .unwrap() = get_demo_table_heap_with_n_tuples(Arc::clone(&catalog.bpm), 20);
+
+        for index_type in [
+            IndexType::SkipListIndex,
+            IndexType::BPlusTreeIndex,
+            IndexType::ExtendibleHashIndex,
+        ] {
+            let index_info = catalog
+                .create_index(
+                    &autocommit(&catalog),
+                    format!("people_lastname_age_{:?}", index_type),
+                    "people".to_string(),
+                    vec![
+                        ("LASTNAME".to_string(), SortOrder::Asc),
+                        ("age".to_string(), SortOrder::Desc),
+                    ],
+                    index_type,
+                    false,
+                    false,
+                )
+                .unwrap();
+            let key_schema = index_info.get_key_schema();
+            let low = key_schema
+                .encode_values(&[Value::Varchar("a".to_string()), Value::SmallInt(i16::MAX)])
+                .unwrap();
+            let high = key_schema
+                .encode_values(&[Value::Varchar("z".to_string()), Value::SmallInt(i16::MIN)])
+                .unwrap();
+            assert!(!index_info.get_index().range_query(&low, &high).is_empty());
+        }
+
+        let err = catalog.create_index(
+            &autocommit(&catalog),
+            "bad".to_string(),
+            "people".to_string(),
+            vec![("missing".to_string(), SortOrder::Asc)],
+            IndexType::SkipListIndex,
+            false,
+            false,
+        );
+        assert!(err.is_err());
+    }
+
+    #[test]
+    fn test_unique_index_creation_fails_on_duplicates() {
+        let catalog = catalog_with_people();
+        let txn = catalog.begin(IsolationLevel::default());
+        catalog
+            .insert_tuple(&txn, "people", person("ada", "lovelace"))
+            .unwrap();
+        catalog
+            .insert_tuple(&txn, "people", person("grace", "hopper"))
+            .unwrap();
+        catalog.commit(&txn).unwrap();
+        let err = catalog.create_index(
+            &autocommit(&catalog),
+            "people_address_key".to_string(),
+            "people".to_string(),
+            vec![("address".to_string(), SortOrder::Asc)],
+            IndexType::SkipListIndex,
+            true,
+            false,
+        );
+        assert!(err.is_err());
+        assert!(catalog.get_table_indexes("people").len() == 2);
+    }
+
+    #[test]
+    fn test_catalog_survives_restart() {
+        let path = std::env::temp_dir().join(format!("catalog_reopen_{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        {
+            let catalog = Catalog::open(&path).unwrap();
+            let txn = catalog.begin(IsolationLevel::default());
+            for table_name in ["people", "pets"] {
+                catalog
+                    .create_table(
+                        &autocommit(&catalog),
+                        table_name.to_string(),
+                        get_demo_schema(),
+                        true,
+                    )
+                    .unwrap();
+            }
+            let duplicate = catalog.create_table(
+                &autocommit(&catalog),
+                "pets".to_string(),
+                get_demo_schema(),
+                true,
+            );
+            assert!(duplicate.is_err());
+            for (index_name, column, index_type, is_primary_key) in [
+                ("people_pkey", "name", IndexType::BPlusTreeIndex, true),
+                (
+                    "people_address",
+                    "address",
+                    IndexType::ExtendibleHashIndex,
+                    false,
+                ),
+            ] {
+                catalog
+                    .create_index(
+                        &autocommit(&catalog),
+                        index_name.to_string(),
+                        "people".to_string(),
+                        vec![(column.to_string(), SortOrder::Asc)],
+                        index_type,
+                        true,
+                        is_primary_key,
+                    )
+                    .unwrap();
+            }
+            for i in 0..40 {
+                let mut row = person(&format!("name{}", i), "lastname");
+                row[2] = Value::Varchar(format!("{} main st", i));
+                catalog.insert_tuple(&txn, "people", row).unwrap();
+            }
+            assert!(catalog
+                .insert_tuple(&txn, "people", person("name0", "other"))
+                .is_err());
+            catalog.commit(&txn).unwrap();
+
+            // still running when the database is closed, its pages are written all the same
+            let running = catalog.begin(IsolationLevel::default());
+            for i in 40..45 {
+                let mut row = person(&format!("name{}", i), "lastname");
+                row[2] = Value::Varchar(format!("{} main st", i));
+                catalog.insert_tuple(&running, "people", row).unwrap();
+            }
+            let rid = |name: &str| {
+                catalog
+                    .scan(&running, "people")
+                    .unwrap()
+                    .map(Result::unwrap)
+                    .find(|(_, tuple)| tuple.get_value(0) == Some(&varchar(name)))
+                    .unwrap()
+                    .0
+            };
+            let mut row = person("name7", "lastname");
+            row[2] = Value::Varchar("70 main st".to_string());
+            catalog
+                .update_tuple(&running, "people", rid("name7"), row)
+                .unwrap();
+            catalog
+                .delete_tuple(&running, "people", rid("name8"))
+                .unwrap();
+            assert_eq!(names(&catalog, &running).len(), 44);
+        }
+
+        let catalog = Catalog::open(&path).unwrap();
+        let txn = catalog.begin(IsolationLevel::default());
+        let mut tables = catalog.get_table_names();
+        tables.sort();
+        assert_eq!(
+            tables,
+            vec!["public.people".to_string(), "public.pets".to_string()]
+        );
+        let table_info = catalog.get_table("people").unwrap();
+        assert_eq!(table_info.schema, get_demo_schema());
+        // what the running transaction wrote is rolled back
+        assert_eq!(table_info.table_heap.lock().unwrap().iter().count(), 40);
+        let mut expected = (0..40)
+            .map(|i| format!("'name{}'", i))
+            .collect::<Vec<String>>();
+        expected.sort();
+        assert_eq!(names(&catalog, &txn), expected);
+        assert_eq!(catalog.get_table_indexes("people").len(), 2);
+        for index_info in catalog.get_table_indexes("people") {
+            for key in ["name40", "70 main st"] {
+                let key = index_info
+                    .get_key_schema()
+                    .encode_values(&[Value::Varchar(key.to_string())])
+                    .unwrap();
+                assert!(index_info.get_index().find(&key).is_none());
+            }
+            let key = if index_info.is_primary_key() {
+                "name7".to_string()
+            } else {
+                "7 main st".to_string()
+            };
+            let key = index_info
+                .get_key_schema()
+                .encode_values(&[Value::Varchar(key)])
+                .unwrap();
+            let rid = index_info.get_index().find(&key).unwrap().into();
+            let tuple = table_info
+                .table_heap
+                .lock()
+                .unwrap()
+                .get_tuple(rid)
+                .unwrap();
+            assert_eq!(
+                tuple.get_value(0),
+                Some(&Value::Varchar("name7".to_string()))
+            );
+        }
+        // the restored indexes still enforce their constraints, new ids do not collide
+        assert!(catalog
+            .insert_tuple(&txn, "people", person("name3", "lastname"))
+            .is_err());
+        let mut row = person("someone", "else");
+        row[2] = Value::Varchar("3 main st".to_string());
+        assert!(catalog.insert_tuple(&txn, "people", row).is_err());
+        assert_eq!(catalog.table_next_id.load(Ordering::SeqCst), 2);
+        assert_eq!(catalog.index_next_id.load(Ordering::SeqCst), 2);
+        drop(catalog);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_drop_table_refuses_dependents_and_frees_pages() {
+        let catalog = catalog_with_people();
+        insert_people(&catalog, 200);
+        let pages = catalog.bpm.page_count();
+        assert_eq!(
+            catalog.drop_table(&autocommit(&catalog), "people", false),
+            Err(
+                "cannot drop table people because indexes people_lastname_key, people_pkey \
+                 depend on it"
+                    .to_string()
+            )
+        );
+        assert!(catalog.get_table_heap("people").is_some());
+        catalog
+            .drop_table(&autocommit(&catalog), "people", true)
+            .unwrap();
+        assert!(catalog.get_table_heap("people").is_none());
+        assert!(catalog.get_table_indexes("people").is_empty());
+        assert!(catalog
+            .drop_index(&autocommit(&catalog), "people", "people_pkey")
+            .is_err());
+
+        // the same table again fits into the freed pages
+        catalog
+            .create_table(
+                &autocommit(&catalog),
+                "people".to_string(),
+                get_demo_schema(),
+                true,
+            )
+            .unwrap();
+        insert_people(&catalog, 200);
+        assert_eq!(catalog.bpm.page_count(), pages);
+        catalog
+            .drop_table(&autocommit(&catalog), "people", false)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_drop_index_and_truncate_table() {
+        let catalog = catalog_with_people();
+        catalog
+            .create_index(
+                &autocommit(&catalog),
+                "people_address_hash".to_string(),
+                "people".to_string(),
+                vec![("address".to_string(), SortOrder::Asc)],
+                IndexType::ExtendibleHashIndex,
+                true,
+                false,
+            )
+            .unwrap();
+        insert_people(&catalog, 200);
+
+        catalog
+            .drop_index(&autocommit(&catalog), "people", "people_lastname_key")
+            .unwrap();
+        assert_eq!(catalog.get_table_indexes("people").len(), 2);
+        let mut row = person("someone", "lastname3");
+        row[2] = Value::Varchar("nowhere".to_string());
+        catalog
+            .insert_tuple(&autocommit(&catalog), "people", row)
+            .unwrap();
+
+        catalog
+            .truncate_table(&autocommit(&catalog), "people")
+            .unwrap();
+        let pages = catalog.bpm.page_count();
+        let table_heap = catalog.get_table_heap("people").unwrap();
+        assert_eq!(table_heap.lock().unwrap().iter().count(), 0);
+        for index_info in catalog.get_table_indexes("people") {
+            let low = vec![0x00];
+            let high = vec![0xFF; 64];
+            assert!(index_info.get_index().range_query(&low, &high).is_empty());
+        }
+        // the old keys are free again and the rows reuse the old pages
+        insert_people(&catalog, 200);
+        assert_eq!(table_heap.lock().unwrap().iter().count(), 200);
+        assert_eq!(catalog.bpm.page_count(), pages);
+    }
+
+    #[test]
+    fn test_truncate_waits_for_the_writers_of_the_table() {
+        let catalog = catalog_with_people();
+        insert_people(&catalog, 2);
+        let writer = catalog.begin(IsolationLevel::default());
+        catalog
+            .insert_tuple(&writer, "people", person("ada", "lovelace"))
+            .unwrap();
+
+        let truncater = catalog.begin(IsolationLevel::default());
+        truncater.set_lock_timeout(Some(Duration::from_millis(20)));
+        for truncated in [
+            catalog.truncate_table(&truncater, "people"),
+            catalog.drop_index(&truncater, "people", "people_lastname_key"),
+            catalog.drop_table(&truncater, "people", true),
+        ] {
+            assert!(truncated.is_err_and(|e| e.starts_with("could not lock Table")));
+        }
+        catalog.commit(&truncater).unwrap();
+        assert_eq!(catalog.get_table_indexes("people").len(), 2);
+        assert_eq!(
+            names(&catalog, &writer),
+            vec!["'ada'", "'name0'", "'name1'"]
+        );
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                sender
+                    .send(catalog.truncate_table(&autocommit(&catalog), "people"))
+                    .unwrap();
+            });
+            assert!(receiver.recv_timeout(Duration::from_millis(100)).is_err());
+            catalog.commit(&writer).unwrap();
+            assert_eq!(receiver.recv_timeout(Duration::from_secs(5)), Ok(Ok(())));
+        });
+        assert!(names(&catalog, &autocommit(&catalog)).is_empty());
+        catalog
+            .insert_tuple(&autocommit(&catalog), "people", person("ada", "lovelace"))
+            .unwrap();
+        assert_eq!(names(&catalog, &autocommit(&catalog)), vec!["'ada'"]);
+    }
+
+    #[test]
+    fn test_ddl_locks_the_table_until_the_transaction_ends() {
+        let catalog = catalog_with_people();
+        insert_people(&catalog, 2);
+        let table_id = catalog.get_table("people").unwrap().table_id;
+        let index = |txn: &Transaction| {
+            catalog.create_index(
+                txn,
+                "people_address_idx".to_string(),
+                "people".to_string(),
+                vec![("address".to_string(), SortOrder::Asc)],
+                IndexType::BPlusTreeIndex,
+                false,
+                false,
+            )
+        };
+        let bonus = || {
+            AlterTable::AddColumn(
+                Column::new("bonus".to_string(), TypeId::INTEGER, 4)
+                    .with_default(Value::Integer(7)),
+            )
+        };
+
+        let writer = catalog.begin(IsolationLevel::default());
+        catalog
+            .insert_tuple(&writer, "people", person("ada", "lovelace"))
+            .unwrap();
+        let ddl = catalog.begin(IsolationLevel::default());
+        ddl.set_lock_timeout(Some(Duration::from_millis(20)));
+        assert!(index(&ddl).is_err_and(|e| e.starts_with("could not lock Table")));
+        assert!(catalog.analyze(&ddl, Some("people")).is_err());
+        assert!(catalog.analyze(&ddl, None).is_err());
+        assert!(catalog.alter_table(&ddl, "people", bonus()).is_err());
+        catalog.commit(&ddl).unwrap();
+        assert!(catalog.get_statistics("people").is_none());
+        catalog.commit(&writer).unwrap();
+
+        // S keeps writers out until the end, readers go on
+        let ddl = catalog.begin(IsolationLevel::default());
+        index(&ddl).unwrap();
+        catalog.analyze(&ddl, None).unwrap();
+        assert_eq!(
+            catalog.lockm.held_mode(&ddl, LockTarget::Table(table_id)),
+            Some(LockMode::Shared)
+        );
+        let writer = catalog.begin(IsolationLevel::default());
+        writer.set_lock_timeout(Some(Duration::from_millis(20)));
+        assert!(matches!(
+            catalog.insert_tuple(&writer, "people", person("grace", "hopper")),
+            Err(DmlError::LockNotAvailable(_))
+        ));
+        assert_eq!(names(&catalog, &writer).len(), 3);
+        catalog.commit(&ddl).unwrap();
+        catalog
+            .insert_tuple(&writer, "people", person("grace", "hopper"))
+            .unwrap();
+        catalog.commit(&writer).unwrap();
+
+        let ddl = catalog.begin(IsolationLevel::default());
+        catalog.alter_table(&ddl, "people", bonus()).unwrap();
+        assert_eq!(
+            catalog.lockm.held_mode(&ddl, LockTarget::Table(table_id)),
+            Some(LockMode::Exclusive)
+        );
+        catalog.commit(&ddl).unwrap();
+        assert!(catalog
+            .lockm
+            .held_mode(&ddl, LockTarget::Table(table_id))
+            .is_none());
+    }
+
+    // Every kind of DDL on people, with rows written in between, left running.
+    fn run_ddl_on_people(catalog: &Catalog, txn: &Transaction) {
+        catalog.create_schema(txn, "staging", "etl").unwrap();
+        catalog
+            .create_table(txn, "staging.pets".to_string(), get_demo_schema(), true)
+            .unwrap();
+        catalog
+            .create_sequence(txn, "ids", SequenceOptions::new(1))
+            .unwrap();
+        catalog
+            .insert_tuple(txn, "people", person("ada", "lovelace"))
+            .unwrap();
+        catalog
+            .drop_index(txn, "people", "people_lastname_key")
+            .unwrap();
+        catalog
+            .alter_table(
+                txn,
+                "people",
+                AlterTable::AddColumn(Column::new("bonus".to_string(), TypeId::INTEGER, 4)),
+            )
+            .unwrap();
+        catalog.truncate_table(txn, "people").unwrap();
+        let mut row = person("grace", "hopper");
+        row.push(Value::Integer(1));
+        catalog.insert_tuple(txn, "people", row).unwrap();
+        catalog.drop_table(txn, "people", true).unwrap();
+    }
+
+    // The catalog of `catalog_with_people` with name0 and name1 in it.
+    fn assert_people_untouched(catalog: &Catalog) {
+        assert_eq!(catalog.get_table_names(), vec!["public.people".to_string()]);
+        assert!(catalog.get_schema("staging").is_none());
+        assert!(catalog.get_sequence("ids").is_none());
+        let table_info = catalog.get_table("people").unwrap();
+        assert_eq!(table_info.get_schema().get_columns().len(), 5);
+        assert_eq!(catalog.get_table_indexes("people").len(), 2);
+        let txn = autocommit(catalog);
+        assert_eq!(names(catalog, &txn), vec!["'name0'", "'name1'"]);
+        let index_info = catalog.get_index("people", "people_lastname_key").unwrap();
+        let rid = index_info.get_index().find(&key(&index_info, "lastname1"));
+        assert_eq!(lastname(catalog, &txn, rid.unwrap().into()), "'lastname1'");
+        assert!(catalog
+            .insert_tuple(&txn, "people", person("ada", "lastname0"))
+            .is_err());
+    }
+
+    #[test]
+    fn test_ddl_is_undone_when_its_transaction_aborts() {
+        let path = std::env::temp_dir().join(format!("catalog_abort_{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        {
+            let catalog = Catalog::open(&path).unwrap();
+            create_people(&catalog);
+            insert_people(&catalog, 2);
+
+            let txn = catalog.begin(IsolationLevel::default());
+            run_ddl_on_people(&catalog, &txn);
+            catalog.abort(&txn).unwrap();
+            assert_people_untouched(&catalog);
+            // what the aborted DDL allocated is free again
+            let txn = catalog.begin(IsolationLevel::default());
+            run_ddl_on_people(&catalog, &txn);
+            let created = catalog.pending_ddl.lock().unwrap()[&txn.get_id()]
+                .created
+                .iter()
+                .flat_map(Storage::page_ids)
+                .collect::<HashSet<PageId>>();
+            catalog.abort(&txn).unwrap();
+            assert!(created.contains(&catalog.bpm.new_page().unwrap()));
+
+            // left running when the database goes away
+            let txn = catalog.begin(IsolationLevel::default());
+            run_ddl_on_people(&catalog, &txn);
+        }
+        let catalog = Catalog::open(&path).unwrap();
+        assert_people_untouched(&catalog);
+        drop(catalog);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_uncommitted_ddl_is_not_seen_by_others() {
+        let catalog = catalog_with_people();
+        insert_people(&catalog, 2);
+        let ddl = catalog.begin(IsolationLevel::default());
+        catalog.truncate_table(&ddl, "people").unwrap();
+        catalog
+            .create_table(&ddl, "pets".to_string(), get_demo_schema(), true)
+            .unwrap();
+        assert!(names(&catalog, &ddl).is_empty());
+
+        let other = catalog.begin(IsolationLevel::default());
+        other.set_lock_timeout(Some(Duration::from_millis(20)));
+        assert_eq!(names(&catalog, &other), vec!["'name0'", "'name1'"]);
+        assert!(catalog.scan(&other, "pets").is_err());
+        assert!(matches!(
+            catalog.insert_tuple(&other, "people", person("ada", "lovelace")),
+            Err(DmlError::LockNotAvailable(_))
+        ));
+        assert!(catalog
+            .create_sequence(&other, "ids", SequenceOptions::new(1))
+            .is_err_and(|e| e.starts_with("could not lock Catalog")));
+        catalog.abort(&other).unwrap();
+
+        catalog.commit(&ddl).unwrap();
+        let other = autocommit(&catalog);
+        assert!(names(&catalog, &other).is_empty());
+        assert!(rows(&catalog, &other, "pets").is_empty());
+    }
+
+    #[test]
+    fn test_drops_survive_restart_and_free_pages_are_reused() {
+        let path = std::env::temp_dir().join(format!("catalog_drop_{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        {
+            let catalog = Catalog::open(&path).unwrap();
+            for table_name in ["people", "pets"] {
+                catalog
+                    .create_table(
+                        &autocommit(&catalog),
+                        table_name.to_string(),
+                        get_demo_schema(),
+                        true,
+                    )
+                    .unwrap();
+            }
+            catalog
+                .create_index(
+                    &autocommit(&catalog),
+                    "people_address_hash".to_string(),
+                    "people".to_string(),
+                    vec![("address".to_string(), SortOrder::Asc)],
+                    IndexType::ExtendibleHashIndex,
+                    true,
+                    false,
+                )
+                .unwrap();
+            insert_people(&catalog, 100);
+            catalog
+                .drop_table(&autocommit(&catalog), "pets", false)
+                .unwrap();
+            catalog
+                .truncate_table(&autocommit(&catalog), "people")
+                .unwrap();
+        }
+        let catalog = Catalog::open(&path).unwrap();
+        assert_eq!(catalog.get_table_names(), vec!["public.people".to_string()]);
+        let table_heap = catalog.get_table_heap("people").unwrap();
+        assert_eq!(table_heap.lock().unwrap().iter().count(), 0);
+        let pages = catalog.bpm.page_count();
+        insert_people(&catalog, 100);
+        assert_eq!(catalog.bpm.page_count(), pages);
+        assert!(catalog
+            .insert_tuple(&autocommit(&catalog), "people", {
+                let mut row = person("someone", "else");
+                row[2] = Value::Varchar("7 main st".to_string());
+                row
+            })
+            .is_err());
+        drop(catalog);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_alter_table_upgrades_rows_lazily() {
+        let catalog = catalog_with_people();
+        insert_people(&catalog, 3);
+        // the ALTERs run in the transaction writing the rows, another one would wait for it
+        let txn = catalog.begin(IsolationLevel::default());
+        let alter = |catalog: &Catalog, action| catalog.alter_table(&txn, "people", action);
+        alter(
+            &catalog,
+            AlterTable::AddColumn(
+                Column::new("bonus".to_string(), TypeId::INTEGER, 4)
+                    .with_default(Value::Integer(7)),
+            ),
+        )
+        .unwrap();
+        // the stored tuples are untouched, they are upgraded when read
+        let table_heap = catalog.get_table_heap("people").unwrap();
+        let (rid, stored) = table_heap.lock().unwrap().iter().next().unwrap();
+        assert_eq!(stored.get_values().len(), 5);
+        let tuple = catalog.get_tuple(&txn, "people", rid).unwrap().unwrap();
+        assert_eq!(tuple.get_value(5), Some(&Value::Integer(7)));
+        let mut row = person("ada", "lovelace");
+        row.push(Value::Integer(1));
+        catalog.insert_tuple(&txn, "people", row).unwrap();
+
+        alter(
+            &catalog,
+            AlterTable::RenameColumn {
+                column: "lastname".to_string(),
+                new_name: "surname".to_string(),
+            },
+        )
+        .unwrap();
+        let mut row = person("grace", "lovelace");
+        row.push(Value::Null);
+        assert!(matches!(
+            catalog.insert_tuple(&txn, "people", row).unwrap_err(),
+            DmlError::Constraint(ConstraintViolation::Unique { ref index_name, .. }) if index_name == "people_lastname_key"
+        ));
+
+        assert_eq!(
+            alter(&catalog, AlterTable::DropColumn("name".to_string())),
+            Err("column name is used by index people_pkey".to_string())
+        );
+        alter(&catalog, AlterTable::DropColumn("address".to_string())).unwrap();
+        assert_eq!(
+            alter(
+                &catalog,
+                AlterTable::AlterColumnType {
+                    column: "surname".to_string(),
+                    type_id: TypeId::INTEGER,
+                    length: 4,
+                },
+            ),
+            Err("column surname is used by index people_lastname_key".to_string())
+        );
+        alter(
+            &catalog,
+            AlterTable::AlterColumnType {
+                column: "salary".to_string(),
+                type_id: TypeId::VARCHAR,
+                length: 20,
+            },
+        )
+        .unwrap();
+        // "ada" cannot become a number
+        catalog
+            .update_tuple(
+                &txn,
+                "people",
+                rid,
+                vec![
+                    Value::Varchar("name0".to_string()),
+                    Value::Varchar("lastname0".to_string()),
+                    Value::Varchar("ada".to_string()),
+                    Value::Varchar("30".to_string()),
+                    Value::Null,
+                ],
+            )
+            .unwrap();
+        assert!(alter(
+            &catalog,
+            AlterTable::AlterColumnType {
+                column: "salary".to_string(),
+                type_id: TypeId::BIGINT,
+                length: 8,
+            },
+        )
+        .is_err());
+
+        alter(&catalog, AlterTable::RenameTo("staff".to_string())).unwrap();
+        assert!(catalog.get_table_heap("people").is_none());
+        let rows = catalog
+            .scan(&txn, "staff")
+            .unwrap()
+            .map(Result::unwrap)
+            .collect::<Vec<_>>();
+        assert_eq!(rows.len(), 4);
+        assert_eq!(
+            rows.iter().find(|(r, _)| *r == rid).unwrap().1.get_values(),
+            &[
+                Value::Varchar("name0".to_string()),
+                Value::Varchar("lastname0".to_string()),
+                Value::Varchar("ada".to_string()),
+                Value::SmallInt(30),
+                Value::Null,
+            ]
+        );
+        assert!(rows.iter().all(|(_, t)| t.get_values().len() == 5));
+        let index_info = catalog
+            .get_table_indexes("staff")
+            .into_iter()
+            .find(|i| i.is_primary_key())
+            .unwrap();
+        let key = index_info
+            .get_key_schema()
+            .encode_values(&[Value::Varchar("name1".to_string())])
+            .unwrap();
+        assert!(index_info.get_index().find(&key).is_some());
+    }
+
+    #[test]
+    fn test_alter_table_survives_restart() {
+        let path = std::env::temp_dir().join(format!("catalog_alter_{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let schema = {
+            let catalog = Catalog::open(&path).unwrap();
+            catalog
+                .create_table(
+                    &autocommit(&catalog),
+                    "people".to_string(),
+                    get_demo_schema(),
+                    true,
+                )
+                .unwrap();
+            catalog
+                .create_index(
+                    &autocommit(&catalog),
+                    "people_lastname".to_string(),
+                    "people".to_string(),
+                    vec![("lastname".to_string(), SortOrder::Asc)],
+                    IndexType::SkipListIndex,
+                    false,
+                    false,
+                )
+                .unwrap();
+            insert_people(&catalog, 10);
+            for action in [
+                AlterTable::AddColumn(
+                    Column::new("bonus".to_string(), TypeId::BIGINT, 8)
+                        .with_default(Value::BigInt(5)),
+                ),
+                AlterTable::DropColumn("address".to_string()),
+                AlterTable::RenameColumn {
+                    column: "lastname".to_string(),
+                    new_name: "surname".to_string(),
+                },
+            ] {
+                catalog
+                    .alter_table(&autocommit(&catalog), "people", action)
+                    .unwrap();
+            }
+            catalog
+                .alter_table(
+                    &autocommit(&catalog),
+                    "people",
+                    AlterTable::RenameTo("staff".to_string()),
+                )
+                .unwrap();
+            catalog.get_table("staff").unwrap().schema.clone()
+        };
+        let catalog = Catalog::open(&path).unwrap();
+        assert_eq!(catalog.get_table_names(), vec!["public.staff".to_string()]);
+        assert_eq!(catalog.get_table("staff").unwrap().schema, schema);
+        assert_eq!(schema.get_version(), 3);
+        let txn = catalog.begin(IsolationLevel::default());
+        let rows = catalog
+            .scan(&txn, "staff")
+            .unwrap()
+            .map(Result::unwrap)
+            .collect::<Vec<_>>();
+        assert_eq!(rows.len(), 10);
+        assert!(rows
+            .iter()
+            .all(|(_, t)| t.get_value(4) == Some(&Value::BigInt(5))));
+        let index_info = &catalog.get_table_indexes("staff")[0];
+        let key = index_info
+            .get_key_schema()
+            .encode_values(&[Value::Varchar("lastname3".to_string())])
+            .unwrap();
+        assert_eq!(index_info.get_index().range_query(&key, &key).len(), 1);
+        drop(catalog);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_schemas_separate_tables_and_survive_restart() {
+        let path = std::env::temp_dir().join(format!("catalog_schemas_{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        {
+            let catalog = Catalog::open(&path).unwrap();
+            catalog
+                .create_schema(&autocommit(&catalog), "staging", "etl")
+                .unwrap();
+            assert!(catalog
+                .create_schema(&autocommit(&catalog), "STAGING", "etl")
+                .is_err());
+            for table_name in ["people", "staging.people"] {
+                catalog
+                    .create_table(
+                        &autocommit(&catalog),
+                        table_name.to_string(),
+                        get_demo_schema(),
+                        true,
+                    )
+                    .unwrap();
+            }
+            assert!(catalog
+                .create_table(
+                    &autocommit(&catalog),
+                    "archive.people".to_string(),
+                    get_demo_schema(),
+                    true,
+                )
+                .is_err());
+            catalog
+                .create_index(
+                    &autocommit(&catalog),
+                    "people_name".to_string(),
+                    "staging.people".to_string(),
+                    vec![("name".to_string(), SortOrder::Asc)],
+                    IndexType::ExtendibleHashIndex,
+                    false,
+                    false,
+                )
+                .unwrap();
+            for i in 0..10 {
+                let name = format!("name{}", i);
+                catalog
+                    .insert_tuple(
+                        &autocommit(&catalog),
+                        "staging.people",
+                        person(&name, &name),
+                    )
+                    .unwrap();
+            }
+        }
+        let catalog = Catalog::open(&path).unwrap();
+        assert_eq!(catalog.get_schema_names(), vec!["public", "staging"]);
+        assert_eq!(catalog.get_schema("Staging").unwrap().get_owner(), "etl");
+        assert_eq!(
+            catalog.get_table_names(),
+            vec!["public.people", "staging.people"]
+        );
+        let txn = catalog.begin(IsolationLevel::default());
+        assert_eq!(
+            catalog
+                .scan(&txn, "people")
+                .unwrap()
+                .map(Result::unwrap)
+                .count(),
+            0
+        );
+        assert_eq!(
+            catalog
+                .scan(&txn, "public.people")
+                .unwrap()
+                .map(Result::unwrap)
+                .count(),
+            0
+        );
+        assert_eq!(
+            catalog
+                .scan(&txn, "STAGING.people")
+                .unwrap()
+                .map(Result::unwrap)
+                .count(),
+            10
+        );
+        assert!(catalog.get_index("people", "people_name").is_none());
+        assert!(catalog.get_index("staging.people", "people_name").is_some());
+        assert_eq!(
+            catalog.drop_schema(&autocommit(&catalog), "staging", false),
+            Err("cannot drop schema staging because tables people depend on it".to_string())
+        );
+
+        let pages = catalog.bpm.page_count();
+        catalog
+            .drop_schema(&autocommit(&catalog), "staging", true)
+            .unwrap();
+        assert!(catalog.get_table("staging.people").is_none());
+        assert!(catalog.get_schema("staging").is_none());
+        for i in 0..10 {
+            let name = format!("name{}", i);
+            catalog
+                .insert_tuple(&autocommit(&catalog), "people", person(&name, &name))
+                .unwrap();
+        }
+        assert_eq!(catalog.bpm.page_count(), pages);
+        drop(catalog);
+
+        let catalog = Catalog::open(&path).unwrap();
+        assert_eq!(catalog.get_schema_names(), vec!["public"]);
+        assert_eq!(catalog.get_table_names(), vec!["public.people"]);
+        drop(catalog);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_lookups_by_name_and_id() {
+        let catalog = catalog_with_people();
+        let table_info = catalog.get_table("people").unwrap();
+        assert_eq!(table_info.get_table_name(), "people");
+        assert!(Arc::ptr_eq(
+            &catalog.get_table_by_id(table_info.get_table_id()).unwrap(),
+            &table_info
+        ));
+        assert!(catalog.get_table("pets").is_none());
+        let index_info = catalog.get_index("people", "people_pkey").unwrap();
+        assert_eq!(index_info.get_table_name(), "people");
+        assert!(catalog.get_index("people", "people_name_key").is_none());
+        let mut index_names = catalog
+            .get_table_indexes("people")
+            .iter()
+            .map(|i| i.get_index_name().to_string())
+            .collect::<Vec<_>>();
+        index_names.sort();
+        assert_eq!(index_names, vec!["people_lastname_key", "people_pkey"]);
+
+        // a snapshot taken before a change keeps describing the table as it was
+        catalog
+            .alter_table(
+                &autocommit(&catalog),
+                "people",
+                AlterTable::RenameTo("staff".to_string()),
+            )
+            .unwrap();
+        assert_eq!(table_info.get_table_name(), "people");
+        assert!(catalog.get_table("people").is_none());
+        let renamed = catalog.get_table_by_id(table_info.get_table_id()).unwrap();
+        assert_eq!(renamed.get_table_name(), "staff");
+        assert_eq!(catalog.get_table_indexes("staff").len(), 2);
+        assert!(catalog.get_index("staff", "people_pkey").is_some());
+    }
+
+    #[test]
+    fn test_catalog_is_shared_between_threads() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Catalog>();
+
+        let catalog = Arc::new(catalog_with_people());
+        let writers = (0..4)
+            .map(|t| {
+                let catalog = Arc::clone(&catalog);
+                std::thread::spawn(move || {
+                    let txn = catalog.begin(IsolationLevel::default());
+                    for i in 0..100 {
+                        let name = format!("name{}-{}", t, i);
+                        let mut row = person(&name, &name);
+                        row[2] = Value::Varchar(format!("{} {} main st", t, i));
+                        catalog.insert_tuple(&txn, "people", row).unwrap();
+                    }
+                    catalog.commit(&txn).unwrap();
+                })
+            })
+            .collect::<Vec<_>>();
+        catalog
+            .create_index(
+                &autocommit(&catalog),
+                "people_address_key".to_string(),
+                "people".to_string(),
+                vec![("address".to_string(), SortOrder::Asc)],
+                IndexType::ExtendibleHashIndex,
+                true,
+                false,
+            )
+            .unwrap();
+        catalog
+            .alter_table(
+                &autocommit(&catalog),
+                "people",
+                AlterTable::RenameColumn {
+                    column: "age".to_string(),
+                    new_name: "years".to_string(),
+                },
+            )
+            .unwrap();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        // rows inserted before, during and after the index build are all in it
+        let index_info = catalog.get_index("people", "people_address_key").unwrap();
+        let txn = catalog.begin(IsolationLevel::default());
+        let rows = catalog
+            .scan(&txn, "people")
+            .unwrap()
+            .map(Result::unwrap)
+            .collect::<Vec<_>>();
+        assert_eq!(rows.len(), 400);
+        for (rid, tuple) in rows {
+            let key = index_info.entry_key(&tuple).unwrap().unwrap();
+            assert_eq!(index_info.get_index().find(&key), Some(rid.into()));
+        }
+    }
+
+    #[test]
+    fn test_sequences_and_identity_columns() {
+        let catalog = Catalog::new();
+        let session = Session::default();
+        let txn = catalog.begin(IsolationLevel::default());
+        catalog
+            .create_sequence(
+                &autocommit(&catalog),
+                "Ticket",
+                SequenceOptions::new(10).with_start(100),
+            )
+            .unwrap();
+        assert!(catalog
+            .create_sequence(
+                &autocommit(&catalog),
+                "public.ticket",
+                SequenceOptions::default()
+            )
+            .is_err());
+        assert!(catalog
+            .create_sequence(
+                &autocommit(&catalog),
+                "invalid",
+                SequenceOptions::new(1).with_start(0)
+            )
+            .is_err());
+        assert_eq!(
+            session.currval(&catalog, "ticket"),
+            Err("currval of sequence ticket is not yet defined in this session".to_string())
+        );
+        assert_eq!(session.nextval(&catalog, "ticket"), Ok(100));
+        assert_eq!(session.nextval(&catalog, "ticket"), Ok(110));
+        assert_eq!(session.currval(&catalog, "ticket"), Ok(110));
+        assert!(Session::default().currval(&catalog, "ticket").is_err());
+        session.setval(&catalog, "ticket", 500, false).unwrap();
+        assert_eq!(session.currval(&catalog, "ticket"), Ok(110));
+        let select = |expr: &str| {
+            Expr::parse(expr)
+                .unwrap()
+                .eval_constant_with(&session.functions(&catalog))
+        };
+        assert_eq!(select("nextval('ticket') + 1"), Ok(Value::BigInt(501)));
+        assert_eq!(select("currval('public.ticket')"), Ok(Value::BigInt(500)));
+        assert!(select("nextval('missing')").is_err());
+        assert!(Expr::parse("nextval('ticket')")
+            .unwrap()
+            .eval_constant()
+            .is_err());
+
+        // an identity column declared with the table and a SERIAL one added later
+        catalog
+            .create_table(
+                &autocommit(&catalog),
+                "people".to_string(),
+                schema_with_identity(TypeId::INTEGER, Identity::Always),
+                true,
+            )
+            .unwrap();
+        alter(
+            &catalog,
+            "people",
+            AlterTable::AddColumn(
+                Column::new("badge".to_string(), TypeId::SMALLINT, 2)
+                    .with_identity(Identity::ByDefault),
+            ),
+        )
+        .unwrap();
+        assert_eq!(
+            catalog.get_sequence_names(),
+            vec![
+                "public.people_badge_seq",
+                "public.people_id_seq",
+                "public.ticket"
+            ]
+        );
+        let functions = session.functions(&catalog);
+        let insert = |columns: &[&str], values: Vec<Value>| {
+            let columns = columns.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+            catalog.insert_values(&txn, "people", &columns, values, &functions)
+        };
+        insert(&["name"], vec![varchar("ada")]).unwrap();
+        insert(&["name"], vec![varchar("grace")]).unwrap();
+        insert(
+            &["name", "badge"],
+            vec![varchar("alan"), Value::SmallInt(7)],
+        )
+        .unwrap();
+        let ids = rows(&catalog, &txn, "people")
+            .into_iter()
+            .map(|row| (row[1].clone(), row[0].clone(), row[6].clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ids,
+            vec![
+                (varchar("ada"), Value::Integer(1), Value::SmallInt(1)),
+                (varchar("grace"), Value::Integer(2), Value::SmallInt(2)),
+                (varchar("alan"), Value::Integer(3), Value::SmallInt(7)),
+            ]
+        );
+        assert_eq!(session.currval(&catalog, "people_id_seq"), Ok(3));
+
+        // GENERATED ALWAYS values only come from the sequence
+        assert!(
+            insert(&["name", "id"], vec![varchar("edgar"), Value::Integer(9)])
+                .is_err_and(|e| e.to_string().contains("GENERATED ALWAYS"))
+        );
+        let mut values = person("edgar", "codd");
+        values.insert(0, Value::Integer(9));
+        values.push(Value::SmallInt(9));
+        assert!(catalog.insert_tuple(&txn, "people", values).is_err());
+        let (rid, tuple) = catalog
+            .scan(&txn, "people")
+            .unwrap()
+            .map(Result::unwrap)
+            .next()
+            .unwrap();
+        let mut values = tuple.get_values().to_vec();
+        values[6] = Value::SmallInt(42);
+        catalog
+            .update_tuple(&txn, "people", rid, values.clone())
+            .unwrap();
+        values[0] = Value::Integer(42);
+        assert!(catalog.update_tuple(&txn, "people", rid, values).is_err());
+        catalog.commit(&txn).unwrap();
+
+        assert!(alter(
+            &catalog,
+            "people",
+            AlterTable::SetDefault {
+                column: "id".to_string(),
+                default: None,
+            },
+        )
+        .is_err());
+        assert!(alter(
+            &catalog,
+            "people",
+            AlterTable::DropNotNull("id".to_string())
+        )
+        .is_err());
+        assert!(alter(
+            &catalog,
+            "people",
+            AlterTable::AddColumn(
+                Column::new("code".to_string(), TypeId::VARCHAR, 8).with_identity(Identity::Always)
+            ),
+        )
+        .is_err());
+        // the rows there are have no value for it, and the sequence goes with the failed ALTER
+        assert_eq!(
+            alter(
+                &catalog,
+                "people",
+                AlterTable::AddColumn(
+                    Column::new("serial".to_string(), TypeId::BIGINT, 8)
+                        .with_identity(Identity::Always)
+                ),
+            ),
+            Err("column serial of table public.people contains null values".to_string())
+        );
+        assert!(catalog.get_sequence("people_serial_seq").is_none());
+
+        // the sequence of an identity column is dropped with it
+        assert_eq!(
+            catalog.drop_sequence(&autocommit(&catalog), "people_id_seq"),
+            Err(
+                "cannot drop sequence people_id_seq because column id of table public.people uses it"
+                    .to_string()
+            )
+        );
+        alter(
+            &catalog,
+            "people",
+            AlterTable::DropColumn("badge".to_string()),
+        )
+        .unwrap();
+        assert!(catalog.get_sequence("people_badge_seq").is_none());
+        catalog
+            .drop_table(&autocommit(&catalog), "people", false)
+            .unwrap();
+        assert_eq!(catalog.get_sequence_names(), vec!["public.ticket"]);
+
+        catalog
+            .create_schema(&autocommit(&catalog), "billing", DEFAULT_USER)
+            .unwrap();
+        catalog
+            .create_sequence(
+                &autocommit(&catalog),
+                "billing.invoice",
+                SequenceOptions::default(),
+            )
+            .unwrap();
+        assert_eq!(
+            catalog.drop_schema(&autocommit(&catalog), "billing", false),
+            Err("cannot drop schema billing because sequences invoice depend on it".to_string())
+        );
+        catalog
+            .drop_schema(&autocommit(&catalog), "billing", true)
+            .unwrap();
+        catalog
+            .drop_sequence(&autocommit(&catalog), "ticket")
+            .unwrap();
+        assert!(catalog.get_sequence_names().is_empty());
+    }
+
+    #[test]
+    fn test_sequences_survive_restart_without_repeating_values() {
+        let path =
+            std::env::temp_dir().join(format!("catalog_sequences_{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let session = Session::default();
+        let names = vec!["name".to_string()];
+        let first_id = {
+            let catalog = Catalog::open(&path).unwrap();
+            let txn = catalog.begin(IsolationLevel::default());
+            catalog
+                .create_sequence(
+                    &autocommit(&catalog),
+                    "ticket",
+                    SequenceOptions::default().with_cache(5),
+                )
+                .unwrap();
+            catalog
+                .create_table(
+                    &autocommit(&catalog),
+                    "people".to_string(),
+                    schema_with_identity(TypeId::BIGINT, Identity::ByDefault),
+                    true,
+                )
+                .unwrap();
+            for value in 1..=3 {
+                assert_eq!(session.nextval(&catalog, "ticket"), Ok(value));
+            }
+            let rid = catalog
+                .insert_values(
+                    &txn,
+                    "people",
+                    &names,
+                    vec![Value::Varchar("ada".to_string())],
+                    &session.functions(&catalog),
+                )
+                .unwrap();
+            let tuple = catalog.get_tuple(&txn, "people", rid).unwrap().unwrap();
+            catalog.commit(&txn).unwrap();
+            tuple.get_id()
+        };
+        // nothing is written when the catalog is dropped, it is reopened as after a crash
+        let catalog = Catalog::open(&path).unwrap();
+        let txn = catalog.begin(IsolationLevel::default());
+        let sequence_info = catalog.get_sequence("ticket").unwrap();
+        assert_eq!(sequence_info.get_options().get_cache(), 5);
+        // the values of the reserved block not handed out are skipped
+        assert_eq!(session.nextval(&catalog, "ticket"), Ok(6));
+        assert!(catalog.get_sequence("people_id_seq").unwrap().is_identity());
+        let rid = catalog
+            .insert_values(
+                &txn,
+                "people",
+                &names,
+                vec![Value::Varchar("grace".to_string())],
+                &session.functions(&catalog),
+            )
+            .unwrap();
+        let tuple = catalog.get_tuple(&txn, "people", rid).unwrap().unwrap();
+        assert!(tuple.get_id() > first_id);
+        let ids = rows(&catalog, &txn, "people")
+            .into_iter()
+            .map(|row| row[0].clone())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec![Value::BigInt(1), Value::BigInt(33)]);
+
+        drop(catalog);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_analyze_collects_statistics_that_survive_restart() {
+        let path =
+            std::env::temp_dir().join(format!("catalog_statistics_{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let statistics = {
+            let catalog = Catalog::open(&path).unwrap();
+            for table_name in ["people", "others"] {
+                catalog
+                    .create_table(
+                        &autocommit(&catalog),
+                        table_name.to_string(),
+                        get_demo_schema(),
+                        true,
+                    )
+                    .unwrap();
+            }
+            insert_people(&catalog, 100);
+            assert!(catalog.get_statistics("people").is_none());
+            assert!(catalog
+                .analyze(&autocommit(&catalog), Some("nobody"))
+                .is_err());
+            catalog.analyze(&autocommit(&catalog), None).unwrap();
+
+            let statistics = catalog.get_statistics("people").unwrap();
+            assert_eq!(statistics.row_count, 100);
+            assert!(statistics.page_count >= 1);
+            let name = &statistics.columns[&0];
+            assert_eq!(name.null_fraction, 0.0);
+            assert!((name.n_distinct - 100.0).abs() < 10.0);
+            assert!(name.most_common.is_empty());
+            assert_eq!(
+                name.histogram.first(),
+                Some(&Value::Varchar("name0".to_string()))
+            );
+            let age = &statistics.columns[&4];
+            assert_eq!(age.n_distinct, 1.0);
+            assert_eq!(age.most_common, vec![(Value::SmallInt(30), 1.0)]);
+            assert!(age.histogram.is_empty());
+            assert_eq!(catalog.get_statistics("others").unwrap().row_count, 0);
+            statistics
+        };
+        let catalog = Catalog::open(&path).unwrap();
+        assert_eq!(catalog.get_statistics("people"), Some(statistics));
+
+        // analyzing again replaces the statistics, dropping the table takes them along
+        insert_people(&catalog, 10);
+        catalog
+            .analyze(&autocommit(&catalog), Some("people"))
+            .unwrap();
+        assert_eq!(catalog.get_statistics("people").unwrap().row_count, 110);
+        catalog
+            .drop_table(&autocommit(&catalog), "others", false)
+            .unwrap();
+        drop(catalog);
+        let catalog = Catalog::open(&path).unwrap();
+        assert_eq!(catalog.get_statistics("people").unwrap().row_count, 110);
+        assert!(catalog.get_statistics("others").is_none());
+
+        drop(catalog);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_auto_analyze_after_enough_changes() {
+        let catalog = catalog_with_people();
+        insert_people(&catalog, 60);
+        assert!(catalog.get_statistics("people").is_none());
+
+        catalog.set_auto_analyze(Some(AutoAnalyze::default()));
+        let new = |i| person(&format!("new{}", i), &format!("new{}", i));
+        let txn = catalog.begin(IsolationLevel::default());
+        let rids = (0..50)
+            .map(|i| catalog.insert_tuple(&txn, "people", new(i)).unwrap())
+            .collect::<Vec<RecordId>>();
+        // it runs once a transaction commits, and only sees what committed
+        assert!(catalog.get_statistics("people").is_none());
+        let other = catalog.begin(IsolationLevel::default());
+        catalog.insert_tuple(&other, "people", new(50)).unwrap();
+        catalog.commit(&other).unwrap();
+        assert_eq!(catalog.get_statistics("people").unwrap().row_count, 61);
+
+        // the next one is due after more than 50 + 6.1 committed changes
+        catalog.commit(&txn).unwrap();
+        let aborted = catalog.begin(IsolationLevel::default());
+        for i in 51..61 {
+            catalog.insert_tuple(&aborted, "people", new(i)).unwrap();
+        }
+        catalog.abort(&aborted).unwrap();
+        assert_eq!(catalog.get_statistics("people").unwrap().row_count, 61);
+        let txn = catalog.begin(IsolationLevel::default());
+        for rid in rids[..7].iter() {
+            catalog.delete_tuple(&txn, "people", *rid).unwrap();
+        }
+        catalog.commit(&txn).unwrap();
+        assert_eq!(catalog.get_statistics("people").unwrap().row_count, 104);
+
+        catalog.set_auto_analyze(None);
+        let txn = catalog.begin(IsolationLevel::default());
+        for i in 100..200 {
+            catalog.insert_tuple(&txn, "people", new(i)).unwrap();
+        }
+        catalog.commit(&txn).unwrap();
+        assert_eq!(catalog.get_statistics("people").unwrap().row_count, 104);
+    }
+}
@@ -0,0 +1,832 @@
+use super::dml::{LockedTable, LockedTables};
+use super::*;
+
+// Checking the rows a statement writes against the constraints of their table, following foreign
+// keys to the rows they reference or that reference them, and keeping constraints in the catalog.
+
+impl TableInfo {
+    pub(crate) fn constraint(&self, constraint_name: &str) -> Option<&ConstraintInfo> {
+        self.constraints
+            .iter()
+            .find(|c| c.constraint_name == constraint_name)
+    }
+
+    pub(crate) fn foreign_keys(&self) -> impl Iterator<Item = (&ConstraintInfo, &ForeignKey)> {
+        self.constraints.iter().filter_map(|c| match &c.kind {
+            ConstraintKind::ForeignKey(fk) => Some((c, fk)),
+            ConstraintKind::Check(_) => None,
+        })
+    }
+
+    // Values of the columns with the given ids, NULL for a column that is gone.
+    fn column_values(&self, tuple: &Tuple, column_ids: &[u32]) -> Vec<Value> {
+        column_ids
+            .iter()
+            .map(|id| {
+                self.schema
+                    .get_col_idx_by_id(*id)
+                    .and_then(|idx| tuple.get_value(idx))
+                    .cloned()
+                    .unwrap_or(Value::Null)
+            })
+            .collect()
+    }
+
+    // The NOT NULL and CHECK constraints a row has to satisfy, foreign keys need other tables.
+    pub(crate) fn check_row(&self, tuple: &Tuple) -> Result<(), DmlError> {
+        for (column, value) in self.schema.get_columns().iter().zip(tuple.get_values()) {
+            if column.is_not_null() && value.is_null() {
+                return Err(ConstraintViolation::NotNull {
+                    column: column.get_name().to_string(),
+                }
+                .into());
+            }
+        }
+        for constraint in self.constraints.iter() {
+            let ConstraintKind::Check(expr) = &constraint.kind else {
+                continue;
+            };
+            // like a WHERE clause in reverse, NULL lets the row through
+            match expr.eval(&self.schema, tuple.get_values())? {
+                Value::Boolean(true) | Value::Null => {}
+                Value::Boolean(false) => {
+                    return Err(ConstraintViolation::Check {
+                        constraint_name: constraint.constraint_name.clone(),
+                    }
+                    .into())
+                }
+                other => {
+                    return Err(format!(
+                        "check constraint {} evaluated to {}, not a boolean",
+                        constraint.constraint_name, other
+                    )
+                    .into())
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // The error for an existing row that does not satisfy a constraint being added.
+    pub(crate) fn existing_row_error(&self, error: DmlError) -> String {
+        match error {
+            DmlError::Constraint(ConstraintViolation::NotNull { column }) => format!(
+                "column {} of table {} contains null values",
+                column,
+                self.get_qualified_name()
+            ),
+            DmlError::Constraint(ConstraintViolation::Check { constraint_name }) => format!(
+                "check constraint {} of table {} is violated by some row",
+                constraint_name,
+                self.get_qualified_name()
+            ),
+            error => error.to_string(),
+        }
+    }
+}
+
+impl LockedTable<'_> {
+    // Whether a row with the key exists, found through a unique index on the referenced columns.
+    fn contains_key(&self, fk: &ForeignKey, key: &[Value]) -> Result<bool, DmlError> {
+        let schema = &self.info.schema;
+        let index_info = self
+            .indexes
+            .iter()
+            .find(|i| i.covers(schema, &fk.ref_columns))
+            .ok_or_else(|| {
+                format!(
+                    "no unique index on {} covers the referenced columns",
+                    self.info.get_qualified_name()
+                )
+            })?;
+        // the referencing columns may have another type
+        let key = key
+            .iter()
+            .zip(fk.ref_columns.iter())
+            .map(|(value, id)| {
+                let idx = schema.get_col_idx_by_id(*id).unwrap();
+                value.cast_to(schema.get_column(idx).unwrap().get_type())
+            })
+            .collect::<Result<Vec<Value>, String>>()?;
+        let key = index_info.key_schema.encode_values(&key)?;
+        // the entry may be kept only for a version written over
+        let Some(rid) = index_info.index.find(&key).map(RecordId::from) else {
+            return Ok(false);
+        };
+        let Some(tuple) = self.heap.get_tuple(rid) else {
+            return Ok(false);
+        };
+        Ok(index_info.entry_key(&schema.upgrade(tuple)?)?.as_ref() == Some(&key))
+    }
+
+    // Rows whose `fk` columns hold the key. Referencing columns need no index, the table is
+    // scanned.
+    fn referencing_rows(&self, fk: &ForeignKey, key: &[Value]) -> Vec<(RecordId, Tuple)> {
+        self.heap
+            .iter()
+            .filter_map(|(rid, tuple)| self.info.schema.upgrade(tuple).ok().map(|t| (rid, t)))
+            .filter(|(_, tuple)| {
+                self.info
+                    .column_values(tuple, &fk.columns)
+                    .iter()
+                    .zip(key)
+                    .all(|(a, b)| a.compare(b) == Some(std::cmp::Ordering::Equal))
+            })
+            .collect()
+    }
+}
+
+impl LockedTables<'_> {
+    // Every foreign key of the row has to find its key in the referenced table, unless part of
+    // the key is NULL.
+    pub(crate) fn check_references(
+        &self,
+        table_info: &TableInfo,
+        tuple: &Tuple,
+    ) -> Result<(), DmlError> {
+        for (constraint, fk) in table_info.foreign_keys() {
+            let key = table_info.column_values(tuple, &fk.columns);
+            if key.iter().any(|v| v.is_null()) {
+                continue;
+            }
+            let parent = &self.tables[&fk.ref_table_id];
+            if !parent.contains_key(fk, &key)? {
+                return Err(ConstraintViolation::ForeignKey {
+                    constraint_name: constraint.constraint_name.clone(),
+                    table: parent.info.get_qualified_name(),
+                    key,
+                }
+                .into());
+            }
+        }
+        Ok(())
+    }
+
+    // A key that rows of other tables reference cannot change.
+    pub(crate) fn check_unreferenced(
+        &self,
+        table_info: &TableInfo,
+        old_tuple: &Tuple,
+        new_tuple: &Tuple,
+    ) -> Result<(), DmlError> {
+        for child in self.tables.values() {
+            for (constraint, fk) in child.info.foreign_keys() {
+                if fk.ref_table_id != table_info.table_id {
+                    continue;
+                }
+                let key = table_info.column_values(old_tuple, &fk.ref_columns);
+                let new_key = table_info.column_values(new_tuple, &fk.ref_columns);
+                if key.iter().any(|v| v.is_null()) || key == new_key {
+                    continue;
+                }
+                if !child.referencing_rows(fk, &key).is_empty() {
+                    return Err(ConstraintViolation::Referenced {
+                        constraint_name: constraint.constraint_name.clone(),
+                        table: child.info.get_qualified_name(),
+                        key,
+                    }
+                    .into());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Collects the row and, following ON DELETE, the rows referencing it.
+    pub(crate) fn plan_delete(
+        &self,
+        table_id: TableId,
+        rid: RecordId,
+        tuple: Tuple,
+        plan: &mut DeletePlan,
+    ) -> Result<(), DmlError> {
+        if plan.deletes.contains_key(&(table_id, rid)) {
+            return Ok(());
+        }
+        let table_info = Arc::clone(&self.tables[&table_id].info);
+        plan.deletes.insert((table_id, rid), tuple.clone());
+        for child in self.tables.values() {
+            for (constraint, fk) in child.info.foreign_keys() {
+                if fk.ref_table_id != table_id {
+                    continue;
+                }
+                let key = table_info.column_values(&tuple, &fk.ref_columns);
+                if key.iter().any(|v| v.is_null()) {
+                    continue;
+                }
+                for (child_rid, child_tuple) in child.referencing_rows(fk, &key) {
+                    let row = (child.info.table_id, child_rid);
+                    match fk.on_delete {
+                        ReferentialAction::Cascade => {
+                            self.plan_delete(row.0, row.1, child_tuple, plan)?
+                        }
+                        ReferentialAction::SetNull => {
+                            let (_, columns) = plan
+                                .set_null
+                                .entry(row)
+                                .or_insert_with(|| (child_tuple, vec![]));
+                            columns.extend(fk.columns.iter().copied());
+                        }
+                        // only fine if the referencing row goes as well
+                        ReferentialAction::Restrict => plan.restricted.push((
+                            row,
+                            ConstraintViolation::Referenced {
+                                constraint_name: constraint.constraint_name.clone(),
+                                table: child.info.get_qualified_name(),
+                                key: key.clone(),
+                            },
+                        )),
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Checks the whole plan before changing anything, then sets the referencing columns to NULL
+    // and deletes the rows.
+    pub(crate) fn apply(&self, txn: &Transaction, plan: DeletePlan) -> Result<(), DmlError> {
+        if let Some((_, violation)) = plan
+            .restricted
+            .into_iter()
+            .find(|(row, _)| !plan.deletes.contains_key(row))
+        {
+            return Err(violation.into());
+        }
+        let mut updates = vec![];
+        for ((table_id, rid), (old_tuple, columns)) in plan.set_null {
+            if plan.deletes.contains_key(&(table_id, rid)) {
+                continue;
+            }
+            let table = &self.tables[&table_id];
+            let mut values = old_tuple.get_values().to_vec();
+            for id in columns {
+                if let Some(idx) = table.info.schema.get_col_idx_by_id(id) {
+                    values[idx] = Value::Null;
+                }
+            }
+            let new_tuple = table.info.schema.new_tuple(old_tuple.get_id(), values);
+            table.info.check_row(&new_tuple)?;
+            self.check_unreferenced(&table.info, &old_tuple, &new_tuple)?;
+            // as for `lock_row`, the statement cannot wait with the heaps latched
+            if table
+                .enter_gaps(txn, &new_tuple, Some(&old_tuple))?
+                .is_some()
+            {
+                txn.set_tainted();
+                return Err(DmlError::Conflict(format!(
+                    "a row of {} cannot be set to NULL, another transaction read the range",
+                    table.info.get_qualified_name()
+                )));
+            }
+            updates.push((table, rid, old_tuple, new_tuple));
+        }
+        for (table, rid, old_tuple, new_tuple) in updates {
+            table.update(txn, rid, &old_tuple, &new_tuple)?;
+        }
+        for ((table_id, rid), tuple) in plan.deletes {
+            self.tables[&table_id].delete(txn, rid, &tuple)?;
+        }
+        Ok(())
+    }
+}
+
+// Rows a DELETE removes or changes, by (table id, record id).
+#[derive(Default)]
+pub(crate) struct DeletePlan {
+    pub(crate) deletes: HashMap<(TableId, RecordId), Tuple>,
+    // the row as it is and the ids of the columns to set to NULL
+    pub(crate) set_null: HashMap<(TableId, RecordId), (Tuple, Vec<u32>)>,
+    pub(crate) restricted: Vec<((TableId, RecordId), ConstraintViolation)>,
+}
+
+impl Catalog {
+    // Foreign keys of other tables referencing the table, as "name on schema.table".
+    pub(crate) fn referencing_constraints(&self, table_info: &TableInfo) -> Vec<String> {
+        self.entries
+            .read()
+            .unwrap()
+            .referencing(table_info.table_id)
+            .iter()
+            .filter(|child| child.table_id != table_info.table_id)
+            .flat_map(|child| {
+                child
+                    .foreign_keys()
+                    .filter(|(_, fk)| fk.ref_table_id == table_info.table_id)
+                    .map(|(c, _)| {
+                        format!("{} on {}", c.constraint_name, child.get_qualified_name())
+                    })
+                    .collect::<Vec<String>>()
+            })
+            .collect()
+    }
+
+    // Drops the foreign keys of other tables that reference the table.
+    pub(crate) fn drop_referencing_constraints(
+        &self,
+        table_info: &TableInfo,
+    ) -> Result<(), String> {
+        let referencing = self
+            .entries
+            .read()
+            .unwrap()
+            .referencing(table_info.table_id);
+        for child in referencing {
+            if child.table_id == table_info.table_id {
+                continue;
+            }
+            let _table_heap = child.table_heap.lock().unwrap();
+            let constraints = child
+                .constraints
+                .iter()
+                .filter(|c| {
+                    !matches!(&c.kind, ConstraintKind::ForeignKey(fk)
+                        if fk.ref_table_id == table_info.table_id)
+                })
+                .cloned()
+                .collect::<Vec<ConstraintInfo>>();
+            self.write_constraints(&child, &constraints)?;
+            self.entries
+                .write()
+                .unwrap()
+                .insert_table(Arc::new(TableInfo {
+                    constraints,
+                    ..TableInfo::clone(&child)
+                }));
+        }
+        Ok(())
+    }
+
+    // Records the table's new set of constraints in sys_constraints.
+    pub(crate) fn write_constraints(
+        &self,
+        table_info: &TableInfo,
+        constraints: &[ConstraintInfo],
+    ) -> Result<(), String> {
+        for old in table_info.constraints.iter() {
+            if !constraints
+                .iter()
+                .any(|c| c.constraint_id == old.constraint_id)
+            {
+                self.system.delete_constraint(old.constraint_id)?;
+            }
+        }
+        for constraint in constraints {
+            match table_info
+                .constraints
+                .iter()
+                .find(|c| c.constraint_id == constraint.constraint_id)
+            {
+                None => self.system.insert_constraint(&ConstraintRow {
+                    table_id: table_info.table_id,
+                    constraint: constraint.clone(),
+                })?,
+                // a CHECK whose columns were renamed, foreign keys do not change
+                Some(old) if old != constraint => {
+                    if let ConstraintKind::Check(expr) = &constraint.kind {
+                        self.system.set_check(constraint.constraint_id, expr)?;
+                    }
+                }
+                Some(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    // ADD CONSTRAINT ... FOREIGN KEY, which latches the referenced table as well to check the
+    // stored rows against it.
+    pub(crate) fn add_foreign_key(
+        &self,
+        table_info: &Arc<TableInfo>,
+        name: String,
+        columns: &[String],
+        ref_table: &str,
+        ref_columns: &[String],
+        on_delete: ReferentialAction,
+    ) -> Result<(), String> {
+        if table_info.constraint(&name).is_some() {
+            return Err(format!(
+                "constraint {} already exists on {}",
+                name,
+                table_info.get_qualified_name()
+            ));
+        }
+        let ref_info = self
+            .get_table(ref_table)
+            .ok_or_else(|| format!("table {} does not exist", ref_table))?;
+        if columns.is_empty() || columns.len() != ref_columns.len() {
+            return Err(format!(
+                "foreign key {} has {} columns but references {}",
+                name,
+                columns.len(),
+                ref_columns.len()
+            ));
+        }
+        let fk = ForeignKey {
+            columns: columns
+                .iter()
+                .map(|c| table_info.column_id(c))
+                .collect::<Result<Vec<u32>, String>>()?,
+            ref_table_id: ref_info.table_id,
+            ref_columns: ref_columns
+                .iter()
+                .map(|c| ref_info.column_id(c))
+                .collect::<Result<Vec<u32>, String>>()?,
+            on_delete,
+        };
+        let tables = vec![Arc::clone(table_info), Arc::clone(&ref_info)];
+        self.with_locked(tables, |tables| {
+            let parent = &tables.tables[&ref_info.table_id];
+            if !parent
+                .indexes
+                .iter()
+                .any(|i| i.covers(&ref_info.schema, &fk.ref_columns))
+            {
+                return Err(format!(
+                    "there is no unique index on {} ({}) for foreign key {} to reference",
+                    ref_info.get_qualified_name(),
+                    ref_columns.join(", "),
+                    name
+                ));
+            }
+            let constraint_id = self.constraint_next_id.fetch_add(1, Ordering::SeqCst);
+            let mut constraints = table_info.constraints.clone();
+            constraints.push(ConstraintInfo::new(
+                constraint_id,
+                name,
+                ConstraintKind::ForeignKey(fk),
+            ));
+            let new_table_info = TableInfo {
+                constraints,
+                ..TableInfo::clone(table_info)
+            };
+            for (_, tuple) in tables.tables[&table_info.table_id].heap.iter() {
+                let tuple = new_table_info.schema.upgrade(tuple)?;
+                tables
+                    .check_references(&new_table_info, &tuple)
+                    .map_err(|e| e.to_string())?;
+            }
+            self.write_constraints(table_info, &new_table_info.constraints)?;
+            self.entries
+                .write()
+                .unwrap()
+                .insert_table(Arc::new(new_table_info));
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::catalog::test::{
+        alter, autocommit, catalog_with_people, check, create_child, owned, person, rows,
+    };
+    use crate::expression::NoFunctions;
+    use crate::query_types::get_demo_schema;
+
+    #[test]
+    fn test_not_null_check_and_default_constraints() {
+        let catalog = catalog_with_people();
+        let txn = catalog.begin(IsolationLevel::default());
+        // the ALTERs run in the transaction writing the rows, another one would wait for it
+        let alter = |catalog: &Catalog, table_name: &str, action| {
+            catalog.alter_table(&txn, table_name, action)
+        };
+        catalog
+            .insert_tuple(&txn, "people", person("ada", "lovelace"))
+            .unwrap();
+
+        alter(
+            &catalog,
+            "people",
+            AlterTable::SetNotNull("address".to_string()),
+        )
+        .unwrap();
+        let mut values = person("alan", "turing");
+        values[2] = Value::Null;
+        assert_eq!(
+            catalog.insert_tuple(&txn, "people", values),
+            Err(DmlError::Constraint(ConstraintViolation::NotNull {
+                column: "address".to_string()
+            }))
+        );
+
+        alter(&catalog, "people", check("salary_positive", "salary > 0")).unwrap();
+        let mut values = person("alan", "turing");
+        values[3] = Value::BigInt(-5);
+        assert_eq!(
+            catalog.insert_tuple(&txn, "people", values),
+            Err(DmlError::Constraint(ConstraintViolation::Check {
+                constraint_name: "salary_positive".to_string()
+            }))
+        );
+        // NULL does not violate a check
+        let mut values = person("alan", "turing");
+        values[3] = Value::Null;
+        let rid = catalog.insert_tuple(&txn, "people", values).unwrap();
+        let mut values = person("alan", "turing");
+        values[3] = Value::BigInt(0);
+        assert!(catalog.update_tuple(&txn, "people", rid, values).is_err());
+        assert_eq!(
+            alter(&catalog, "people", check("rich", "salary > 500")),
+            Err("check constraint rich of table public.people is violated by some row".to_string())
+        );
+        assert_eq!(
+            alter(&catalog, "people", check("salary_positive", "age > 0")),
+            Err("constraint salary_positive already exists on people".to_string())
+        );
+
+        alter(
+            &catalog,
+            "people",
+            AlterTable::SetDefault {
+                column: "age".to_string(),
+                default: Some(Expr::parse("6 * 3").unwrap()),
+            },
+        )
+        .unwrap();
+        let columns = ["name", "lastname", "address", "salary"].map(|c| c.to_string());
+        let rid = catalog
+            .insert_values(
+                &txn,
+                "people",
+                &columns,
+                person("grace", "hopper")[..4].to_vec(),
+                &NoFunctions,
+            )
+            .unwrap();
+        let tuple = catalog.get_tuple(&txn, "people", rid).unwrap().unwrap();
+        assert_eq!(tuple.get_value(4), Some(&Value::SmallInt(18)));
+        assert!(catalog
+            .insert_values(
+                &txn,
+                "people",
+                &columns[..1],
+                vec![Value::Varchar("x".to_string())],
+                &NoFunctions,
+            )
+            .is_err_and(|e| e.to_string().contains("not-null")));
+
+        assert_eq!(
+            alter(
+                &catalog,
+                "people",
+                AlterTable::DropColumn("salary".to_string())
+            ),
+            Err("column salary is used by constraint salary_positive".to_string())
+        );
+        alter(
+            &catalog,
+            "people",
+            AlterTable::RenameColumn {
+                column: "salary".to_string(),
+                new_name: "pay".to_string(),
+            },
+        )
+        .unwrap();
+        let mut values = person("edgar", "codd");
+        values[3] = Value::BigInt(-1);
+        assert!(catalog
+            .insert_tuple(&txn, "people", values.clone())
+            .is_err());
+        alter(
+            &catalog,
+            "people",
+            AlterTable::DropConstraint("salary_positive".to_string()),
+        )
+        .unwrap();
+        catalog.insert_tuple(&txn, "people", values).unwrap();
+
+        assert_eq!(
+            alter(
+                &catalog,
+                "people",
+                AlterTable::AddColumn(
+                    Column::new("bonus".to_string(), TypeId::BIGINT, 8).with_not_null(true)
+                ),
+            ),
+            Err("column bonus of table public.people contains null values".to_string())
+        );
+        alter(
+            &catalog,
+            "people",
+            AlterTable::AddColumn(
+                Column::new("bonus".to_string(), TypeId::BIGINT, 8)
+                    .with_not_null(true)
+                    .with_default(Value::BigInt(0)),
+            ),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_foreign_keys_cascade_set_null_and_restrict() {
+        let catalog = catalog_with_people();
+        let txn = catalog.begin(IsolationLevel::default());
+        create_child(&catalog, "pets", ReferentialAction::Cascade);
+        create_child(&catalog, "cars", ReferentialAction::SetNull);
+        create_child(&catalog, "houses", ReferentialAction::Restrict);
+        assert!(alter(
+            &catalog,
+            "pets",
+            AlterTable::AddConstraint(TableConstraint::ForeignKey {
+                name: "pets_address".to_string(),
+                columns: vec!["label".to_string()],
+                ref_table: "people".to_string(),
+                ref_columns: vec!["address".to_string()],
+                on_delete: ReferentialAction::Restrict,
+            }),
+        )
+        .is_err_and(|e| e.starts_with("there is no unique index on public.people (address)")));
+
+        let ada = catalog
+            .insert_tuple(&txn, "people", person("ada", "lovelace"))
+            .unwrap();
+        let alan = catalog
+            .insert_tuple(&txn, "people", person("alan", "turing"))
+            .unwrap();
+        assert_eq!(
+            catalog.insert_tuple(&txn, "pets", owned("nobody", "cat")),
+            Err(DmlError::Constraint(ConstraintViolation::ForeignKey {
+                constraint_name: "pets_owner".to_string(),
+                table: "public.people".to_string(),
+                key: vec![Value::Varchar("nobody".to_string())],
+            }))
+        );
+        catalog
+            .insert_tuple(
+                &txn,
+                "pets",
+                vec![Value::Null, Value::Varchar("stray".to_string())],
+            )
+            .unwrap();
+        catalog
+            .insert_tuple(&txn, "pets", owned("ada", "cat"))
+            .unwrap();
+        catalog
+            .insert_tuple(&txn, "pets", owned("ada", "dog"))
+            .unwrap();
+        catalog
+            .insert_tuple(&txn, "cars", owned("ada", "mini"))
+            .unwrap();
+        catalog
+            .insert_tuple(&txn, "houses", owned("alan", "cottage"))
+            .unwrap();
+
+        let referenced = Err(DmlError::Constraint(ConstraintViolation::Referenced {
+            constraint_name: "houses_owner".to_string(),
+            table: "public.houses".to_string(),
+            key: vec![Value::Varchar("alan".to_string())],
+        }));
+        assert_eq!(catalog.delete_tuple(&txn, "people", alan), referenced);
+        assert_eq!(
+            catalog.update_tuple(&txn, "people", alan, person("alan", "kay")),
+            Ok(())
+        );
+        assert_eq!(
+            catalog.update_tuple(&txn, "people", alan, person("alonzo", "kay")),
+            referenced
+        );
+
+        catalog.delete_tuple(&txn, "people", ada).unwrap();
+        assert_eq!(
+            rows(&catalog, &txn, "pets"),
+            vec![vec![Value::Null, Value::Varchar("stray".to_string())]]
+        );
+        assert_eq!(
+            rows(&catalog, &txn, "cars"),
+            vec![vec![Value::Null, Value::Varchar("mini".to_string())]]
+        );
+        assert_eq!(rows(&catalog, &txn, "people").len(), 1);
+        // DDL waits for the writers of the table to end
+        catalog.commit(&txn).unwrap();
+
+        assert_eq!(
+            catalog.drop_table(&autocommit(&catalog), "people", false),
+            Err(
+                "cannot drop table people because indexes people_lastname_key, people_pkey and \
+                 constraints pets_owner on public.pets, cars_owner on public.cars, houses_owner \
+                 on public.houses depend on it"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            catalog.drop_index(&autocommit(&catalog), "people", "people_pkey"),
+            Err(
+                "cannot drop index people_pkey because constraint pets_owner on public.pets \
+                 depends on it"
+                    .to_string()
+            )
+        );
+        assert!(catalog
+            .truncate_table(&autocommit(&catalog), "people")
+            .is_err_and(|e| e.starts_with("cannot truncate table people")));
+        assert_eq!(
+            alter(
+                &catalog,
+                "houses",
+                AlterTable::DropColumn("owner".to_string())
+            ),
+            Err("column owner is used by constraint houses_owner".to_string())
+        );
+
+        catalog
+            .drop_table(&autocommit(&catalog), "people", true)
+            .unwrap();
+        assert!(catalog
+            .get_table("houses")
+            .unwrap()
+            .get_constraints()
+            .is_empty());
+        catalog
+            .insert_tuple(&autocommit(&catalog), "houses", owned("nobody", "shed"))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_constraints_survive_restart() {
+        let path =
+            std::env::temp_dir().join(format!("catalog_constraints_{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        {
+            let catalog = Catalog::open(&path).unwrap();
+            catalog
+                .create_table(
+                    &autocommit(&catalog),
+                    "people".to_string(),
+                    get_demo_schema(),
+                    true,
+                )
+                .unwrap();
+            catalog
+                .create_index(
+                    &autocommit(&catalog),
+                    "people_pkey".to_string(),
+                    "people".to_string(),
+                    vec![("name".to_string(), SortOrder::Asc)],
+                    IndexType::ExtendibleHashIndex,
+                    false,
+                    true,
+                )
+                .unwrap();
+            create_child(&catalog, "pets", ReferentialAction::Cascade);
+            alter(&catalog, "people", check("adult", "age >= 18")).unwrap();
+            alter(
+                &catalog,
+                "people",
+                AlterTable::SetNotNull("lastname".to_string()),
+            )
+            .unwrap();
+            alter(
+                &catalog,
+                "people",
+                AlterTable::SetDefault {
+                    column: "address".to_string(),
+                    default: Some(Expr::parse("'nowhere'").unwrap()),
+                },
+            )
+            .unwrap();
+            alter(
+                &catalog,
+                "people",
+                AlterTable::RenameColumn {
+                    column: "age".to_string(),
+                    new_name: "years".to_string(),
+                },
+            )
+            .unwrap();
+        }
+        let catalog = Catalog::open(&path).unwrap();
+        let txn = catalog.begin(IsolationLevel::default());
+        let table_info = catalog.get_table("people").unwrap();
+        assert_eq!(
+            table_info.get_constraints()[0].get_kind(),
+            &ConstraintKind::Check(Expr::parse("years >= 18").unwrap())
+        );
+        let schema = table_info.get_schema();
+        assert!(schema.get_column(1).unwrap().is_not_null());
+        assert_eq!(
+            schema.get_column(2).unwrap().default_value(&NoFunctions),
+            Ok(Value::Varchar("nowhere".to_string()))
+        );
+
+        let mut values = person("ada", "lovelace");
+        values[4] = Value::SmallInt(12);
+        assert!(catalog.insert_tuple(&txn, "people", values).is_err());
+        let ada = catalog
+            .insert_tuple(&txn, "people", person("ada", "lovelace"))
+            .unwrap();
+        assert!(catalog
+            .insert_tuple(&txn, "pets", owned("alan", "cat"))
+            .is_err());
+        catalog
+            .insert_tuple(&txn, "pets", owned("ada", "cat"))
+            .unwrap();
+        catalog.delete_tuple(&txn, "people", ada).unwrap();
+        assert!(rows(&catalog, &txn, "pets").is_empty());
+
+        drop(catalog);
+        let _ = std::fs::remove_file(&path);
+    }
+}
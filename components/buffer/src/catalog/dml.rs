@@ -0,0 +1,2283 @@
+use super::constraints::DeletePlan;
+use super::*;
+
+// Reading and writing rows: latching the heaps a statement touches, keeping row versions for the
+// transaction manager, and locking rows and key ranges. The constraints writes are checked against
+// are in constraints.rs.
+
+// A table whose heap is latched by the running statement, with its indexes as they are while
+// the latch is held.
+//
+// Rows are versioned by the transaction manager. The heap holds the newest version of each row,
+// a row a transaction deletes stays marked deleted until the delete is old enough for no
+// transaction to see the row any more. Index entries are added for every version as it is
+// written. The entries of the version a transaction writes over stay until it commits, so
+// aborting can bring the row back; entries of versions it wrote over itself are removed right
+// away.
+pub(crate) struct LockedTable<'a> {
+    pub(crate) info: Arc<TableInfo>,
+    pub(crate) indexes: Vec<Arc<IndexInfo>>,
+    pub(crate) heap: &'a TableHeap,
+    pub(crate) txn_manager: &'a TransactionManager<Tuple>,
+    pub(crate) lock_manager: &'a LockManager,
+}
+
+impl LockedTable<'_> {
+    fn get_tuple(&self, rid: RecordId) -> Result<Tuple, DmlError> {
+        let tuple = self.heap.get_tuple(rid).ok_or_else(|| {
+            format!(
+                "no tuple at {:?} in {}",
+                rid,
+                self.info.get_qualified_name()
+            )
+        })?;
+        Ok(self.info.schema.upgrade(tuple)?)
+    }
+
+    // Whether the row's newest version is a delete.
+    fn is_deleted(&self, rid: RecordId) -> bool {
+        self.heap
+            .get_tuple_marked(rid)
+            .is_some_and(|(_, deleted)| deleted)
+    }
+
+    // Fails with a write-write conflict if the row's newest version is not visible to `txn`,
+    // or under READ COMMITTED, if it is not committed.
+    fn check_write(&self, txn: &Transaction, rid: RecordId) -> Result<(), DmlError> {
+        self.txn_manager
+            .check_write(txn, rid)
+            .map_err(DmlError::Conflict)
+    }
+
+    // Locks the row for writing. The statement locked the rows it writes before latching the
+    // heaps, this is for the rows it changes through foreign keys, which it cannot wait for
+    // with the heaps latched.
+    fn lock_row(&self, txn: &Transaction, rid: RecordId) -> Result<(), DmlError> {
+        let table_id = self.info.table_id;
+        self.lock_manager
+            .try_lock_table(txn, LockMode::IntentionExclusive, table_id)
+            .and_then(|_| {
+                self.lock_manager
+                    .try_lock_row(txn, LockMode::Exclusive, table_id, rid)
+            })
+            .map_err(|e| {
+                txn.set_tainted();
+                match e {
+                    // waiting for it would have been a write-write conflict
+                    LockError::NotAvailable(msg) => DmlError::Conflict(msg),
+                    e => DmlError::from(e),
+                }
+            })
+    }
+
+    // Enters the gaps the row's new entries go into in the ordered indexes, see `LockManager`.
+    // Returns the first gap held by another transaction, which has to be waited for without
+    // the heaps latched.
+    pub(crate) fn enter_gaps(
+        &self,
+        txn: &Transaction,
+        tuple: &Tuple,
+        old_tuple: Option<&Tuple>,
+    ) -> Result<Option<Held>, DmlError> {
+        for index_info in self.indexes.iter().filter(|i| i.index.is_ordered()) {
+            let Some(key) = index_info.entry_key(tuple)? else {
+                continue;
+            };
+            if let Some(old_tuple) = old_tuple {
+                if index_info.entry_key(old_tuple)?.as_ref() == Some(&key) {
+                    continue;
+                }
+            }
+            let next_key = index_info.index.next_entry(&key).map(|(key, _)| key);
+            match self.lock_manager.try_enter_gap(
+                txn,
+                self.info.table_id,
+                index_info.index_id,
+                next_key.clone(),
+            ) {
+                Ok(()) => {}
+                Err(LockError::NotAvailable(_)) => {
+                    return Ok(Some(Held::Gap {
+                        table_id: self.info.table_id,
+                        index_id: index_info.index_id,
+                        next_key,
+                    }))
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(None)
+    }
+
+    // Finds a row of another transaction that did not end yet holding one of the row's unique
+    // keys, when whether the key stays taken depends on how that transaction ends: it inserted
+    // the row, deleted it or changed the key. The write has to wait for it without the heaps
+    // latched, then check again. `rid` is the row itself when it is updated.
+    pub(crate) fn find_pending_key(
+        &self,
+        txn: &Transaction,
+        tuple: &Tuple,
+        rid: Option<RecordId>,
+    ) -> Result<Option<Held>, DmlError> {
+        let schema = &self.info.schema;
+        for index_info in self.indexes.iter().filter(|i| i.is_unique) {
+            let Some(key) = index_info.entry_key(tuple)? else {
+                continue;
+            };
+            let Some(holder) = index_info.index.find(&key).map(RecordId::from) else {
+                continue;
+            };
+            if Some(holder) == rid {
+                continue;
+            }
+            let Some(link) = self.txn_manager.get_version_link(holder) else {
+                continue;
+            };
+            if link.ts < TXN_START_ID
+                || link.ts == txn.get_id()
+                || self.txn_manager.get_transaction(link.ts).is_none()
+            {
+                continue;
+            }
+            let before = self
+                .txn_manager
+                .get_undo_log(link.prev)
+                .map(|log| log.tuple);
+            let now = self
+                .heap
+                .get_tuple_marked(holder)
+                .and_then(|(tuple, deleted)| (!deleted).then_some(tuple));
+            let has_key = |version: Option<Tuple>| -> Result<bool, DmlError> {
+                let version = version.map(|t| schema.upgrade(t)).transpose()?;
+                Ok(index_info.version_key(&version)?.as_ref() == Some(&key))
+            };
+            // taken either way
+            if has_key(before)? && has_key(now)? {
+                continue;
+            }
+            return Ok(Some(Held::Row {
+                table_id: self.info.table_id,
+                rid: holder,
+            }));
+        }
+        Ok(None)
+    }
+
+    // Registers the write of the row, whose newest version is `current`, with the transaction
+    // manager, and keeps the row as it was before in the heap, so a restart rolls it back
+    // unless the transaction ends. Returns the row as it was before the transaction wrote it,
+    // None if the transaction inserted it.
+    fn begin_write(
+        &self,
+        txn: &Transaction,
+        rid: RecordId,
+        current: Option<&Tuple>,
+    ) -> Result<Option<Tuple>, DmlError> {
+        self.lock_row(txn, rid)?;
+        let before = self
+            .txn_manager
+            .begin_write(txn, rid, current)
+            .map_err(DmlError::Conflict)?;
+        txn.append_write_set(self.info.table_id, rid);
+        self.heap.begin_write(rid, before.as_ref())?;
+        Ok(before.map(|t| self.info.schema.upgrade(t)).transpose()?)
+    }
+
+    // The row as it was before `txn` wrote it, None if it inserted it, and the row as it is
+    // now, None if it deleted it. None if `txn` did not write the row.
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn written_versions(
+        &self,
+        txn: &Transaction,
+        rid: RecordId,
+    ) -> Result<Option<(Option<Tuple>, Option<Tuple>)>, DmlError> {
+        let schema = &self.info.schema;
+        match self.txn_manager.get_version_link(rid) {
+            Some(link) if link.ts == txn.get_id() => {
+                let before = self
+                    .txn_manager
+                    .get_undo_log(link.prev)
+                    .map(|log| log.tuple);
+                let now = self
+                    .heap
+                    .get_tuple_marked(rid)
+                    .and_then(|(tuple, deleted)| (!deleted).then_some(tuple));
+                Ok(Some((
+                    before.map(|t| schema.upgrade(t)).transpose()?,
+                    now.map(|t| schema.upgrade(t)).transpose()?,
+                )))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    // Adds the row's entry to a unique index. An entry of another row `txn` wrote, kept only
+    // for the version it wrote over, gives way.
+    fn claim_entry(
+        &self,
+        txn: &Transaction,
+        index_info: &IndexInfo,
+        tuple: &Tuple,
+        rid: RecordId,
+    ) -> Result<Option<Vec<u8>>, DmlError> {
+        let schema = &self.info.schema;
+        let claimed = index_info.insert_entry(tuple, rid, schema);
+        let Err(DmlError::Constraint(ConstraintViolation::Unique { .. })) = claimed else {
+            return claimed;
+        };
+        let key = index_info.entry_key(tuple)?.unwrap();
+        let Some(holder) = index_info.index.find(&key).map(RecordId::from) else {
+            return claimed;
+        };
+        let stale = match self.written_versions(txn, holder)? {
+            Some((_, Some(now))) => index_info.entry_key(&now)?.as_ref() != Some(&key),
+            Some((_, None)) => true,
+            None => false,
+        };
+        if !stale {
+            return claimed;
+        }
+        index_info.delete_entry(&key, holder);
+        index_info.insert_entry(tuple, rid, schema)
+    }
+
+    // Adds the row's entries to `indexes`, claiming the unique keys first. If an entry cannot be
+    // added, those added before it are removed again. Returns the entries added.
+    fn insert_entries<'i>(
+        &self,
+        txn: &Transaction,
+        indexes: impl Iterator<Item = &'i IndexInfo> + Clone,
+        tuple: &Tuple,
+        rid: RecordId,
+    ) -> Result<Vec<(&'i IndexInfo, Vec<u8>)>, DmlError> {
+        let unique = indexes.clone().filter(|i| i.is_unique);
+        let mut inserted = vec![];
+        for index_info in unique.chain(indexes.filter(|i| !i.is_unique)) {
+            let entry = match index_info.is_unique {
+                true => self.claim_entry(txn, index_info, tuple, rid),
+                false => index_info.insert_entry(tuple, rid, &self.info.schema),
+            };
+            match entry {
+                Ok(Some(key)) => inserted.push((index_info, key)),
+                Ok(None) => {}
+                Err(e) => {
+                    delete_entries(inserted, rid);
+                    return Err(e);
+                }
+            }
+        }
+        Ok(inserted)
+    }
+
+    // Adds a row to the heap and all of the indexes. If the row cannot be added to an index, a
+    // unique violation or an index out of room, the tuple is taken out of the heap again, so the
+    // table is left unchanged. The row is inserted hidden and shown once the transaction manager
+    // knows who wrote it.
+    fn insert(&self, txn: &Transaction, tuple: &Tuple) -> Result<RecordId, DmlError> {
+        let rid = self.heap.insert_deleted_tuple(tuple)?;
+        let inserted = self
+            .lock_row(txn, rid)
+            .and_then(|_| self.insert_entries(txn, self.indexes.iter().map(|i| &**i), tuple, rid));
+        let written = inserted.and_then(|inserted| match self.begin_write(txn, rid, None) {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                delete_entries(inserted, rid);
+                Err(e)
+            }
+        });
+        if let Err(e) = written {
+            self.heap.apply_delete(rid);
+            return Err(e);
+        }
+        self.heap.rollback_delete(rid);
+        Ok(rid)
+    }
+
+    // Replaces the row at `rid`, moving its index entries to the new key values. The record id
+    // stays the same even if the tuple has to move to another page.
+    pub(crate) fn update(
+        &self,
+        txn: &Transaction,
+        rid: RecordId,
+        old_tuple: &Tuple,
+        new_tuple: &Tuple,
+    ) -> Result<(), DmlError> {
+        let before = self.begin_write(txn, rid, Some(old_tuple))?;
+        let mut changed = vec![];
+        // the old keys to drop, unless the version written over still needs them
+        let mut stale = vec![];
+        for index_info in self.indexes.iter() {
+            let old_key = index_info.entry_key(old_tuple)?;
+            if old_key.is_some() && old_key == index_info.entry_key(new_tuple)? {
+                continue;
+            }
+            changed.push(&**index_info);
+            if let Some(old_key) = old_key {
+                if index_info.version_key(&before)? != Some(old_key.clone()) {
+                    stale.push((index_info, old_key));
+                }
+            }
+        }
+        // add the new entries first, so a failure can be undone without side effects
+        let inserted = self.insert_entries(txn, changed.into_iter(), new_tuple, rid)?;
+        if let Err(e) = self.heap.update_tuple(rid, new_tuple) {
+            delete_entries(inserted, rid);
+            return Err(e.into());
+        }
+        for (index_info, old_key) in stale {
+            index_info.delete_entry(&old_key, rid);
+        }
+        Ok(())
+    }
+
+    // Marks the row deleted, it stays in the heap for the transactions that still see it.
+    pub(crate) fn delete(
+        &self,
+        txn: &Transaction,
+        rid: RecordId,
+        tuple: &Tuple,
+    ) -> Result<(), DmlError> {
+        let before = self.begin_write(txn, rid, Some(tuple))?;
+        self.heap.mark_delete(rid);
+        for index_info in self.indexes.iter() {
+            if let Some(key) = index_info.entry_key(tuple)? {
+                if index_info.version_key(&before)? != Some(key.clone()) {
+                    index_info.delete_entry(&key, rid);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Drops the index entries of the version a transaction wrote over, `before`, and the row's
+    // undo slot once it committed `now`.
+    pub(crate) fn commit_row(
+        &self,
+        rid: RecordId,
+        before: &Option<Tuple>,
+        now: &Option<Tuple>,
+    ) -> Result<(), DmlError> {
+        for index_info in self.indexes.iter() {
+            let now_key = index_info.version_key(now)?;
+            let before_key = index_info.version_key(before)?;
+            if let Some(key) = before_key.filter(|key| now_key.as_ref() != Some(key)) {
+                index_info.delete_entry(&key, rid);
+            }
+        }
+        self.heap.end_write(rid);
+        Ok(())
+    }
+
+    // Drops the index entries the aborting `txn` added for the row. Entries of other rows may
+    // have given way to them, so the row is restored in a second step, `restore_row`.
+    pub(crate) fn abort_row(&self, txn: &Transaction, rid: RecordId) -> Result<(), DmlError> {
+        let Some((before, now)) = self.written_versions(txn, rid)? else {
+            return Ok(());
+        };
+        for index_info in self.indexes.iter() {
+            let now_key = index_info.version_key(&now)?;
+            let before_key = index_info.version_key(&before)?;
+            if let Some(key) = now_key.filter(|key| before_key.as_ref() != Some(key)) {
+                index_info.delete_entry(&key, rid);
+            }
+        }
+        Ok(())
+    }
+
+    // Puts the row back as it was before the aborting `txn` wrote it, along with its index
+    // entries.
+    pub(crate) fn restore_row(&self, txn: &Transaction, rid: RecordId) -> Result<(), DmlError> {
+        let Some((before, _)) = self.written_versions(txn, rid)? else {
+            return Ok(());
+        };
+        match before {
+            Some(before) => {
+                self.heap.rollback_delete(rid);
+                self.heap.update_tuple(rid, &before)?;
+                for index_info in self.indexes.iter() {
+                    index_info.insert_entry(&before, rid, &self.info.schema)?;
+                }
+            }
+            None => {
+                self.heap.apply_delete(rid);
+            }
+        }
+        self.heap.end_write(rid);
+        Ok(())
+    }
+}
+
+// Takes the entries added for the row at `rid` out of their indexes again.
+fn delete_entries(entries: Vec<(&IndexInfo, Vec<u8>)>, rid: RecordId) {
+    for (index_info, key) in entries {
+        index_info.delete_entry(&key, rid);
+    }
+}
+
+// The heaps latched by a statement, keyed by table id.
+pub(crate) struct LockedTables<'a> {
+    pub(crate) tables: HashMap<TableId, LockedTable<'a>>,
+}
+
+// What a write found held by another transaction, it is waited for without the heaps latched.
+pub(crate) enum Held {
+    // the gap of an index below `next_key`
+    Gap {
+        table_id: TableId,
+        index_id: IndexId,
+        next_key: Option<Vec<u8>>,
+    },
+    // a row holding a unique key the write needs, written by a transaction that did not end yet
+    Row {
+        table_id: TableId,
+        rid: RecordId,
+    },
+}
+
+// How far locking a key range got with the heap latched, see `Catalog::lock_key_range`.
+enum KeyRangeLocks {
+    Locked,
+    // another transaction holds a lock on the row, or on the gap below `key`
+    Blocked {
+        table_id: TableId,
+        index_id: IndexId,
+        rid: Option<RecordId>,
+        key: Option<Vec<u8>>,
+    },
+    NoIndex,
+}
+
+impl Catalog {
+    // Runs `f` with the table's heap latched, handing it the table and its indexes as they are
+    // while the latch is held.
+    fn with_table<R>(
+        &self,
+        table_name: &str,
+        f: impl FnOnce(&TableInfo, &[Arc<IndexInfo>], &TableHeap) -> Result<R, DmlError>,
+    ) -> Result<R, DmlError> {
+        let does_not_exist = || DmlError::from(format!("table {} does not exist", table_name));
+        let table_heap = self
+            .get_table(table_name)
+            .ok_or_else(does_not_exist)?
+            .get_table_heap();
+        let table_heap_guard = table_heap.lock().unwrap();
+        let (table_info, indexes) = {
+            let entries = self.entries.read().unwrap();
+            // dropped, or dropped and created again, while waiting for the latch
+            let table_info = entries
+                .table(table_name)
+                .filter(|t| Arc::ptr_eq(&t.table_heap, &table_heap))
+                .ok_or_else(does_not_exist)?;
+            let indexes = entries.table_indexes(table_info.table_id);
+            (table_info, indexes)
+        };
+        f(&table_info, &indexes, &table_heap_guard)
+    }
+
+    // Runs `f` with the heaps of `tables` latched in table id order, the order in which every
+    // statement latching more than one heap takes them.
+    pub(crate) fn with_locked<R>(
+        &self,
+        mut tables: Vec<Arc<TableInfo>>,
+        f: impl FnOnce(&LockedTables) -> R,
+    ) -> R {
+        tables.sort_by_key(|t| t.table_id);
+        tables.dedup_by_key(|t| t.table_id);
+        let heaps = tables
+            .iter()
+            .map(|t| t.table_heap.lock().unwrap())
+            .collect::<Vec<MutexGuard<TableHeap>>>();
+        let entries = self.entries.read().unwrap();
+        let locked = LockedTables {
+            tables: tables
+                .iter()
+                .zip(heaps.iter())
+                .map(|(table_info, heap)| {
+                    let locked = LockedTable {
+                        info: Arc::clone(table_info),
+                        indexes: entries.table_indexes(table_info.table_id),
+                        heap,
+                        txn_manager: &self.txn_manager,
+                        lock_manager: &self.lockm,
+                    };
+                    (table_info.table_id, locked)
+                })
+                .collect(),
+        };
+        drop(entries);
+        f(&locked)
+    }
+
+    // Like `with_table`, but latches every table the table is connected to through foreign keys
+    // as well, which is what changing its rows may read or change.
+    fn with_tables<R>(
+        &self,
+        table_name: &str,
+        f: impl FnOnce(TableId, &LockedTables) -> Result<R, DmlError>,
+    ) -> Result<R, DmlError> {
+        let does_not_exist = || DmlError::from(format!("table {} does not exist", table_name));
+        let mut f = Some(f);
+        loop {
+            let group = {
+                let entries = self.entries.read().unwrap();
+                let table_info = entries.table(table_name).ok_or_else(does_not_exist)?;
+                entries.foreign_key_group(table_info.table_id)
+            };
+            let result = self.with_locked(group.clone(), |locked| {
+                let entries = self.entries.read().unwrap();
+                let Some(table_info) = entries.table(table_name) else {
+                    return Some(Err(does_not_exist()));
+                };
+                // changed by DDL while waiting for the latches, latch the tables as they are now
+                let current = entries.foreign_key_group(table_info.table_id);
+                if current.len() != group.len()
+                    || current
+                        .iter()
+                        .zip(group.iter())
+                        .any(|(a, b)| !Arc::ptr_eq(a, b))
+                {
+                    return None;
+                }
+                drop(entries);
+                Some(f.take().unwrap()(table_info.table_id, locked))
+            });
+            if let Some(result) = result {
+                return result;
+            }
+        }
+    }
+
+    fn coerce_values(schema: &Schema, values: Vec<Value>) -> Result<Vec<Value>, DmlError> {
+        if values.len() != schema.get_columns().len() {
+            return Err(DmlError::Invalid(format!(
+                "expected {} values, got {}",
+                schema.get_columns().len(),
+                values.len()
+            )));
+        }
+        values
+            .iter()
+            .zip(schema.get_columns())
+            .map(|(value, column)| value.cast_to(column.get_type()).map_err(DmlError::from))
+            .collect()
+    }
+
+    // Values for every column of the schema, the default for those not in `columns`.
+    fn fill_defaults(
+        schema: &Schema,
+        columns: &[String],
+        values: Vec<Value>,
+        functions: &dyn Functions,
+    ) -> Result<Vec<Value>, DmlError> {
+        if values.len() != columns.len() {
+            return Err(DmlError::Invalid(format!(
+                "expected {} values, got {}",
+                columns.len(),
+                values.len()
+            )));
+        }
+        let mut given: Vec<Option<Value>> = vec![None; schema.get_columns().len()];
+        for (column, value) in columns.iter().zip(values) {
+            let idx = schema
+                .get_col_idx(column)
+                .ok_or_else(|| format!("column {} does not exist", column))?;
+            if given[idx].replace(value).is_some() {
+                return Err(format!("column {} is specified more than once", column).into());
+            }
+        }
+        Self::refuse_generated(schema, &given)?;
+        let values = given
+            .into_iter()
+            .zip(schema.get_columns())
+            .map(|(value, column)| value.map_or_else(|| column.default_value(functions), Ok))
+            .collect::<Result<Vec<Value>, String>>()?;
+        Self::coerce_values(schema, values)
+    }
+
+    // Values of GENERATED ALWAYS identity columns only come from their sequence.
+    fn refuse_generated(schema: &Schema, given: &[Option<Value>]) -> Result<(), DmlError> {
+        match schema
+            .get_columns()
+            .iter()
+            .zip(given)
+            .find(|(column, value)| {
+                column.get_identity() == Some(Identity::Always) && value.is_some()
+            }) {
+            Some((column, _)) => Err(DmlError::Invalid(format!(
+                "cannot insert a value into column {}, it is GENERATED ALWAYS AS IDENTITY",
+                column.get_name()
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    // Takes the IX lock on the table a statement writes rows of, waiting for it if needed.
+    fn lock_table_for_write(
+        &self,
+        txn: &Transaction,
+        table_name: &str,
+    ) -> Result<TableId, DmlError> {
+        let table_info = self
+            .get_table(table_name)
+            .ok_or_else(|| format!("table {} does not exist", table_name))?;
+        self.lockm
+            .lock_table(txn, LockMode::IntentionExclusive, table_info.table_id)?;
+        Ok(table_info.table_id)
+    }
+
+    // Locks the row at `rid` for writing, waiting for the transaction writing it to finish. A
+    // snapshot of the row's newest version cannot be written over anyway, that fails right away
+    // instead. READ COMMITTED writes over the version the other transaction commits.
+    fn lock_row_for_write(
+        &self,
+        txn: &Transaction,
+        table_name: &str,
+        rid: RecordId,
+    ) -> Result<(), DmlError> {
+        let table_id = self.lock_table_for_write(txn, table_name)?;
+        if txn.get_isolation_level() != IsolationLevel::READCOMMITTED {
+            self.txn_manager
+                .check_write(txn, rid)
+                .map_err(DmlError::Conflict)?;
+        }
+        self.lockm
+            .lock_row(txn, LockMode::Exclusive, table_id, rid)?;
+        Ok(())
+    }
+
+    // Waits for the transaction holding the gap or the row to end. The write runs again, the
+    // gap its entry goes into or the key it needs may have changed meanwhile. A row is waited
+    // for with an S lock, which keeps it as it is until the transaction ends.
+    fn wait_for(&self, txn: &Transaction, held: Held) -> Result<(), DmlError> {
+        match held {
+            Held::Gap {
+                table_id,
+                index_id,
+                next_key,
+            } => self.lockm.enter_gap(txn, table_id, index_id, next_key)?,
+            Held::Row { table_id, rid } => {
+                self.lockm.lock_row(txn, LockMode::Shared, table_id, rid)?
+            }
+        }
+        Ok(())
+    }
+
+    fn insert(
+        &self,
+        txn: &Transaction,
+        table_name: &str,
+        mut values: impl FnMut(&Schema) -> Result<Vec<Value>, DmlError>,
+    ) -> Result<RecordId, DmlError> {
+        // holding the heap latch serializes the unique checks of concurrent inserts
+        txn.check_writable()?;
+        self.lock_table_for_write(txn, table_name)?;
+        let rid = loop {
+            let inserted = self.with_tables(table_name, |table_id, tables| {
+                let table = &tables.tables[&table_id];
+                let tuple = table
+                    .info
+                    .schema
+                    .new_tuple(self.system.next_tuple_id()?, values(&table.info.schema)?);
+                table.info.check_row(&tuple)?;
+                tables.check_references(&table.info, &tuple)?;
+                if let Some(held) = table.find_pending_key(txn, &tuple, None)? {
+                    return Ok(Err(held));
+                }
+                if let Some(gap) = table.enter_gaps(txn, &tuple, None)? {
+                    return Ok(Err(gap));
+                }
+                table.insert(txn, &tuple).map(Ok)
+            })?;
+            match inserted {
+                Ok(rid) => break rid,
+                Err(held) => self.wait_for(txn, held)?,
+            }
+        };
+        Ok(rid)
+    }
+
+    /// Adds a row to the table and all of its indexes. A constraint violation leaves the table
+    /// unchanged. A unique key held by a row another transaction is writing is waited for, it is
+    /// taken only if that transaction commits the row with it.
+    pub fn insert_tuple(
+        &self,
+        txn: &Transaction,
+        table_name: &str,
+        values: Vec<Value>,
+    ) -> Result<RecordId, DmlError> {
+        self.insert(txn, table_name, |schema| {
+            let given = values.iter().cloned().map(Some).collect::<Vec<_>>();
+            Self::refuse_generated(schema, &given)?;
+            Self::coerce_values(schema, values.clone())
+        })
+    }
+
+    /// Adds a row with values for `columns`, the other columns get their default, as in
+    /// `INSERT INTO t (a, b) VALUES (1, 2)`. Defaults such as `nextval('seq')` are evaluated
+    /// with `functions`.
+    pub fn insert_values(
+        &self,
+        txn: &Transaction,
+        table_name: &str,
+        columns: &[String],
+        values: Vec<Value>,
+        functions: &dyn Functions,
+    ) -> Result<RecordId, DmlError> {
+        self.insert(txn, table_name, |schema| {
+            Self::fill_defaults(schema, columns, values.clone(), functions)
+        })
+    }
+
+    /// Replaces the row at `rid`, moving its index entries to the new key values. The record id
+    /// stays the same even if the tuple has to move to another page. A key still referenced by
+    /// a foreign key cannot change.
+    pub fn update_tuple(
+        &self,
+        txn: &Transaction,
+        table_name: &str,
+        rid: RecordId,
+        values: Vec<Value>,
+    ) -> Result<(), DmlError> {
+        match self.update_tuple_with(txn, table_name, rid, |_| Some(values.clone()))? {
+            true => Ok(()),
+            false => Err(DmlError::Invalid(format!(
+                "no tuple at {:?} in {}",
+                rid, table_name
+            ))),
+        }
+    }
+
+    /// Like `update_tuple`, with the new values computed by `f` from the newest version of the
+    /// row. A READ COMMITTED transaction may not have seen that version when it picked the row,
+    /// `f` re-checks it and returns None to leave the row as it is. A row deleted since is left
+    /// as well. `f` runs again if the update had to wait for a key range another transaction
+    /// read, or for one writing a row that holds a unique key the update needs. Returns whether
+    /// the row was updated.
+    pub fn update_tuple_with(
+        &self,
+        txn: &Transaction,
+        table_name: &str,
+        rid: RecordId,
+        mut f: impl FnMut(&Tuple) -> Option<Vec<Value>>,
+    ) -> Result<bool, DmlError> {
+        txn.check_writable()?;
+        self.lock_row_for_write(txn, table_name, rid)?;
+        loop {
+            match self.try_update_tuple(txn, table_name, rid, &mut f)? {
+                Ok(updated) => return Ok(updated),
+                Err(held) => self.wait_for(txn, held)?,
+            }
+        }
+    }
+
+    // `update_tuple_with` with the row locked, failing with the gap or row it has to wait for.
+    fn try_update_tuple(
+        &self,
+        txn: &Transaction,
+        table_name: &str,
+        rid: RecordId,
+        f: &mut impl FnMut(&Tuple) -> Option<Vec<Value>>,
+    ) -> Result<Result<bool, Held>, DmlError> {
+        self.with_tables(table_name, |table_id, tables| {
+            let table = &tables.tables[&table_id];
+            table.check_write(txn, rid)?;
+            if table.is_deleted(rid) {
+                return Ok(Ok(false));
+            }
+            let old_tuple = table.get_tuple(rid)?;
+            let Some(values) = f(&old_tuple) else {
+                return Ok(Ok(false));
+            };
+            let new_tuple = table.info.schema.new_tuple(
+                old_tuple.get_id(),
+                Self::coerce_values(&table.info.schema, values)?,
+            );
+            if let Some(column) =
+                table
+                    .info
+                    .schema
+                    .get_columns()
+                    .iter()
+                    .enumerate()
+                    .find_map(|(idx, column)| {
+                        (column.get_identity() == Some(Identity::Always)
+                            && old_tuple.get_value(idx) != new_tuple.get_value(idx))
+                        .then_some(column)
+                    })
+            {
+                return Err(DmlError::Invalid(format!(
+                    "cannot change the value of column {}, it is GENERATED ALWAYS AS IDENTITY",
+                    column.get_name()
+                )));
+            }
+            table.info.check_row(&new_tuple)?;
+            tables.check_references(&table.info, &new_tuple)?;
+            tables.check_unreferenced(&table.info, &old_tuple, &new_tuple)?;
+            if let Some(held) = table.find_pending_key(txn, &new_tuple, Some(rid))? {
+                return Ok(Err(held));
+            }
+            if let Some(gap) = table.enter_gaps(txn, &new_tuple, Some(&old_tuple))? {
+                return Ok(Err(gap));
+            }
+            table.update(txn, rid, &old_tuple, &new_tuple)?;
+            Ok(Ok(true))
+        })
+    }
+
+    /// Removes the row at `rid` from the table and all of its indexes. Rows referencing it are
+    /// deleted, set to NULL or keep the row from being deleted, as their foreign key says.
+    pub fn delete_tuple(
+        &self,
+        txn: &Transaction,
+        table_name: &str,
+        rid: RecordId,
+    ) -> Result<(), DmlError> {
+        match self.delete_tuple_if(txn, table_name, rid, |_| true)? {
+            true => Ok(()),
+            false => Err(DmlError::Invalid(format!(
+                "no tuple at {:?} in {}",
+                rid, table_name
+            ))),
+        }
+    }
+
+    /// Like `delete_tuple`, if `recheck` holds for the newest version of the row, see
+    /// `update_tuple_with`. Returns whether the row was deleted.
+    pub fn delete_tuple_if(
+        &self,
+        txn: &Transaction,
+        table_name: &str,
+        rid: RecordId,
+        recheck: impl FnOnce(&Tuple) -> bool,
+    ) -> Result<bool, DmlError> {
+        txn.check_writable()?;
+        self.lock_row_for_write(txn, table_name, rid)?;
+        self.with_tables(table_name, |table_id, tables| {
+            let table = &tables.tables[&table_id];
+            table.check_write(txn, rid)?;
+            if table.is_deleted(rid) {
+                return Ok(false);
+            }
+            let tuple = table.get_tuple(rid)?;
+            if !recheck(&tuple) {
+                return Ok(false);
+            }
+            let mut plan = DeletePlan::default();
+            tables.plan_delete(table_id, rid, tuple, &mut plan)?;
+            tables.apply(txn, plan)?;
+            Ok(true)
+        })
+    }
+
+    /// The rows of the table as `scan` returns them, up to `limit` of them, each locked for
+    /// update until the transaction ends, as in `SELECT ... FOR UPDATE`. Rows locked by another
+    /// transaction are waited for, fail the statement or are skipped, as `wait` says. A snapshot
+    /// cannot lock a row changed since it was taken; a READ COMMITTED transaction gets the newest
+    /// version of the row instead, and leaves it out if it was deleted.
+    pub fn select_for_update(
+        &self,
+        txn: &Transaction,
+        table_name: &str,
+        limit: Option<usize>,
+        wait: RowLockWait,
+    ) -> Result<Vec<(RecordId, Tuple)>, DmlError> {
+        txn.check_writable()?;
+        let table_id = self.lock_table_for_write(txn, table_name)?;
+        let rows = self
+            .scan(txn, table_name)?
+            .collect::<Result<Vec<(RecordId, Tuple)>, DmlError>>()?;
+        let read_committed = txn.get_isolation_level() == IsolationLevel::READCOMMITTED;
+        let mut locked = vec![];
+        for (rid, tuple) in rows {
+            if limit.is_some_and(|limit| locked.len() >= limit) {
+                break;
+            }
+            let result = match wait {
+                RowLockWait::Wait => self.lock_row_for_write(txn, table_name, rid),
+                RowLockWait::NoWait | RowLockWait::SkipLocked => self
+                    .lockm
+                    .try_lock_row(txn, LockMode::Exclusive, table_id, rid)
+                    .map_err(DmlError::from),
+            };
+            match result {
+                Err(DmlError::LockNotAvailable(_)) if wait == RowLockWait::SkipLocked => continue,
+                result => result?,
+            }
+            if !read_committed {
+                self.txn_manager
+                    .check_write(txn, rid)
+                    .map_err(DmlError::Conflict)?;
+                locked.push((rid, tuple));
+                continue;
+            }
+            let newest = self.with_table(table_name, |table_info, _, table_heap| {
+                let Some((tuple, false)) = table_heap.get_tuple_marked(rid) else {
+                    return Ok(None);
+                };
+                Ok::<_, DmlError>(Some(table_info.schema.upgrade(tuple)?))
+            })?;
+            if let Some(tuple) = newest {
+                locked.push((rid, tuple));
+            }
+        }
+        Ok(locked)
+    }
+
+    /// The rows of the table whose `column` lies within the bounds, as `scan` returns them.
+    /// NULLs lie within no bounds. A SERIALIZABLE transaction locks the range against phantoms
+    /// until it ends: with next-key locks on an ordered index over the column, so rows cannot
+    /// be inserted into the range or moved in or out of it while rows elsewhere can, or with an
+    /// S lock on the table if the column has no such index.
+    pub fn range_scan(
+        &self,
+        txn: &Transaction,
+        table_name: &str,
+        column: &str,
+        low: Bound<Value>,
+        high: Bound<Value>,
+    ) -> Result<Vec<(RecordId, Tuple)>, DmlError> {
+        let table_info = self
+            .get_table(table_name)
+            .ok_or_else(|| format!("table {} does not exist", table_name))?;
+        let column_idx = table_info
+            .schema
+            .get_col_idx(column)
+            .ok_or_else(|| format!("column {} does not exist", column))?;
+        let type_id = table_info.schema.get_column(column_idx).unwrap().get_type();
+        let cast = |bound: Bound<Value>| -> Result<Bound<Value>, String> {
+            Ok(match bound {
+                Included(value) => Included(value.cast_to(type_id)?),
+                Excluded(value) => Excluded(value.cast_to(type_id)?),
+                Unbounded => Unbounded,
+            })
+        };
+        let (low, high) = (cast(low)?, cast(high)?);
+        if txn.get_isolation_level() == IsolationLevel::SERIALIZABLE {
+            let table_id = table_info.table_id;
+            self.lockm
+                .lock_table(txn, LockMode::IntentionShared, table_id)?;
+            if !self.lock_key_range(txn, table_name, column_idx, &low, &high)? {
+                self.lockm.lock_table(txn, LockMode::Shared, table_id)?;
+            }
+        }
+        // entries of the versions written over are gone once their writer commits, the rows
+        // are read as the transaction sees them instead
+        let within = |value: &Value| {
+            let above = match &low {
+                Included(low) => value.compare(low).is_some_and(|o| o.is_ge()),
+                Excluded(low) => value.compare(low).is_some_and(|o| o.is_gt()),
+                Unbounded => !value.is_null(),
+            };
+            let below = match &high {
+                Included(high) => value.compare(high).is_some_and(|o| o.is_le()),
+                Excluded(high) => value.compare(high).is_some_and(|o| o.is_lt()),
+                Unbounded => !value.is_null(),
+            };
+            above && below
+        };
+        self.scan(txn, table_name)?
+            .filter(|row| {
+                row.as_ref().map_or(true, |(_, tuple)| {
+                    tuple.get_value(column_idx).is_some_and(within)
+                })
+            })
+            .collect()
+    }
+
+    // Locks the entries of an ordered index over the column whose keys lie within the bounds,
+    // and the entry after them, each with the gap below it, in S mode. Locks another
+    // transaction holds are waited for without the heap latched, then the entries are read
+    // again. Returns false if the column has no such index.
+    fn lock_key_range(
+        &self,
+        txn: &Transaction,
+        table_name: &str,
+        column_idx: usize,
+        low: &Bound<Value>,
+        high: &Bound<Value>,
+    ) -> Result<bool, DmlError> {
+        loop {
+            let locks = self.with_table(table_name, |table_info, indexes, _| {
+                let Some(index_info) = indexes.iter().find(|i| {
+                    let columns = i.key_schema.get_columns();
+                    i.index.is_ordered()
+                        && columns.len() == 1
+                        && columns[0].column_idx == column_idx
+                }) else {
+                    return Ok(KeyRangeLocks::NoIndex);
+                };
+                let encode = |bound: &Bound<Value>| -> Result<Bound<Vec<u8>>, String> {
+                    let encode = |value: &Value| {
+                        index_info
+                            .key_schema
+                            .encode_values(std::slice::from_ref(value))
+                    };
+                    Ok(match bound {
+                        Included(value) => Included(encode(value)?),
+                        Excluded(value) => Excluded(encode(value)?),
+                        Unbounded => Unbounded,
+                    })
+                };
+                let (start, end) = match index_info.key_schema.get_columns()[0].order {
+                    SortOrder::Asc => (encode(low)?, encode(high)?),
+                    SortOrder::Desc => (encode(high)?, encode(low)?),
+                };
+                // the entries of an excluded end are locked too, the keys before them go into
+                // the gap below them
+                let end = match end {
+                    Excluded(key) => Included(key),
+                    end => end,
+                };
+                let index = index_info.get_index();
+                let entries = index.range_entries(
+                    start.as_ref().map(Vec::as_slice),
+                    end.as_ref().map(Vec::as_slice),
+                );
+                // past the last entry, the gap above it
+                let next = match &end {
+                    Included(key) => index.next_entry(key),
+                    _ => None,
+                };
+                let next = match next {
+                    Some((key, loc)) => (Some(key), Some(loc)),
+                    None => (None, None),
+                };
+                let table_id = table_info.table_id;
+                let entries = entries
+                    .into_iter()
+                    .map(|(key, loc)| (Some(key), Some(loc)))
+                    .chain(std::iter::once(next));
+                for (key, loc) in entries {
+                    let rid = loc.map(RecordId::from);
+                    let locked = rid
+                        .map_or(Ok(()), |rid| {
+                            self.lockm
+                                .try_lock_row(txn, LockMode::Shared, table_id, rid)
+                        })
+                        .and_then(|_| {
+                            self.lockm
+                                .try_lock_gap(txn, table_id, index_info.index_id, key.clone())
+                        });
+                    match locked {
+                        Ok(()) => {}
+                        Err(LockError::NotAvailable(_)) => {
+                            return Ok(KeyRangeLocks::Blocked {
+                                table_id,
+                                index_id: index_info.index_id,
+                                rid,
+                                key,
+                            })
+                        }
+                        Err(e) => return Err(e.into()),
+                    }
+                }
+                Ok(KeyRangeLocks::Locked)
+            })?;
+            match locks {
+                KeyRangeLocks::Locked => return Ok(true),
+                KeyRangeLocks::NoIndex => return Ok(false),
+                KeyRangeLocks::Blocked {
+                    table_id,
+                    index_id,
+                    rid,
+                    key,
+                } => {
+                    if let Some(rid) = rid {
+                        self.lockm.lock_row(txn, LockMode::Shared, table_id, rid)?;
+                    }
+                    self.lockm.lock_gap(txn, table_id, index_id, key)?;
+                }
+            }
+        }
+    }
+
+    /// The rows of the table in storage order as `txn` sees them, brought up to the current
+    /// schema as they are read. A row that cannot be, such as an older version holding a value
+    /// the column's new type cannot take, fails the scan there. DDL of another transaction that
+    /// has not ended is not seen.
+    pub fn scan<'a>(
+        &'a self,
+        txn: &'a Transaction,
+        table_name: &str,
+    ) -> Result<impl Iterator<Item = Result<(RecordId, Tuple), DmlError>> + 'a, DmlError> {
+        let table_info = self
+            .get_table_for(txn, table_name)
+            .ok_or_else(|| format!("table {} does not exist", table_name))?;
+        Ok(self.scan_table(txn, table_info))
+    }
+
+    // Scans the rows of the table `txn` sees, latching one page at a time.
+    pub(crate) fn scan_table<'a>(
+        &'a self,
+        txn: &'a Transaction,
+        table_info: Arc<TableInfo>,
+    ) -> impl Iterator<Item = Result<(RecordId, Tuple), DmlError>> + 'a {
+        txn.append_scan(table_info.table_id);
+        let rows = table_info.table_heap.lock().unwrap().iter_marked();
+        rows.filter_map(move |(rid, tuple, deleted)| {
+            let tuple = self.txn_manager.visible_version(txn, rid, tuple, deleted)?;
+            Some(
+                table_info
+                    .schema
+                    .upgrade(tuple)
+                    .map(|t| (rid, t))
+                    .map_err(DmlError::from),
+            )
+        })
+    }
+
+    pub fn get_tuple(
+        &self,
+        txn: &Transaction,
+        table_name: &str,
+        rid: RecordId,
+    ) -> Result<Option<Tuple>, DmlError> {
+        let table_info = self
+            .get_table_for(txn, table_name)
+            .ok_or_else(|| format!("table {} does not exist", table_name))?;
+        txn.append_read_set(table_info.table_id, rid);
+        let tuple = table_info
+            .table_heap
+            .lock()
+            .unwrap()
+            .get_tuple_marked(rid)
+            .and_then(|(tuple, deleted)| {
+                self.txn_manager.visible_version(txn, rid, tuple, deleted)
+            });
+        Ok(tuple.map(|t| table_info.schema.upgrade(t)).transpose()?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::catalog::test::{
+        alter, autocommit, catalog_with_people, column, insert_people, key, lastname, names,
+        person, rows, varchar,
+    };
+
+    #[test]
+    fn test_unique_violation_on_insert() {
+        let catalog = catalog_with_people();
+        let txn = catalog.begin(IsolationLevel::default());
+        catalog
+            .insert_tuple(&txn, "people", person("ada", "lovelace"))
+            .unwrap();
+        let err = catalog
+            .insert_tuple(&txn, "people", person("grace", "lovelace"))
+            .unwrap_err();
+        assert_eq!(
+            err,
+            DmlError::Constraint(ConstraintViolation::Unique {
+                index_name: "people_lastname_key".to_string(),
+                key: vec![Value::Varchar("lovelace".to_string())],
+            })
+        );
+        let err = catalog
+            .insert_tuple(&txn, "people", person("ada", "hopper"))
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            DmlError::Constraint(ConstraintViolation::Unique { ref index_name, .. }) if index_name == "people_pkey"
+        ));
+        // the rejected rows left neither heap tuples nor index entries behind
+        let table_info = catalog.get_table("people").unwrap();
+        assert_eq!(table_info.table_heap.lock().unwrap().iter().count(), 1);
+        catalog
+            .insert_tuple(&txn, "people", person("grace", "hopper"))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_primary_key_rejects_null_and_unique_allows_many_nulls() {
+        let catalog = catalog_with_people();
+        let txn = catalog.begin(IsolationLevel::default());
+        let mut no_name = person("", "codd");
+        no_name[0] = Value::Null;
+        assert_eq!(
+            catalog.insert_tuple(&txn, "people", no_name).unwrap_err(),
+            DmlError::Constraint(ConstraintViolation::NotNull {
+                column: "name".to_string()
+            })
+        );
+        for name in ["ada", "alan"] {
+            let mut no_lastname = person(name, "");
+            no_lastname[1] = Value::Null;
+            catalog.insert_tuple(&txn, "people", no_lastname).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_unique_violation_on_update() {
+        let catalog = catalog_with_people();
+        let txn = catalog.begin(IsolationLevel::default());
+        catalog
+            .insert_tuple(&txn, "people", person("ada", "lovelace"))
+            .unwrap();
+        let grace = catalog
+            .insert_tuple(&txn, "people", person("grace", "hopper"))
+            .unwrap();
+        let err = catalog
+            .update_tuple(&txn, "people", grace, person("grace", "lovelace"))
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            DmlError::Constraint(ConstraintViolation::Unique { .. })
+        ));
+        // renaming frees the old key and takes the new one
+        catalog
+            .update_tuple(&txn, "people", grace, person("grace", "brewster"))
+            .unwrap();
+        catalog
+            .insert_tuple(&txn, "people", person("edgar", "hopper"))
+            .unwrap();
+        assert!(catalog
+            .insert_tuple(&txn, "people", person("jim", "brewster"))
+            .is_err());
+    }
+
+    #[test]
+    fn test_concurrent_inserts_of_same_key() {
+        let catalog = Arc::new(catalog_with_people());
+        let handles = (0..8)
+            .map(|i| {
+                let catalog = Arc::clone(&catalog);
+                std::thread::spawn(move || {
+                    let txn = catalog.begin(IsolationLevel::default());
+                    match catalog.insert_tuple(&txn, "people", person("ada", &format!("{}", i))) {
+                        Ok(_) => catalog.commit(&txn).is_ok(),
+                        Err(_) => {
+                            catalog.abort(&txn).unwrap();
+                            false
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        let inserted = handles
+            .into_iter()
+            .map(|h| h.join().unwrap())
+            .filter(|ok| *ok)
+            .count();
+        assert_eq!(inserted, 1);
+        assert_eq!(names(&catalog, &autocommit(&catalog)), vec!["'ada'"]);
+    }
+
+    #[test]
+    fn test_unique_keys_of_running_writers_are_waited_for() {
+        let catalog = catalog_with_people();
+        insert_people(&catalog, 1);
+        let rid = catalog
+            .scan(&autocommit(&catalog), "people")
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap()
+            .0;
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let insert = |name: &str| {
+            let txn = catalog.begin(IsolationLevel::default());
+            let inserted = catalog
+                .insert_tuple(&txn, "people", person(name, name))
+                .map(|_| ());
+            if inserted.is_ok() {
+                catalog.commit(&txn).unwrap();
+            } else {
+                catalog.abort(&txn).unwrap();
+            }
+            sender.send(inserted).unwrap();
+        };
+        std::thread::scope(|s| {
+            // the key is free again once the inserting transaction aborts
+            let holder = catalog.begin(IsolationLevel::default());
+            catalog
+                .insert_tuple(&holder, "people", person("ada", "lovelace"))
+                .unwrap();
+            s.spawn(|| insert("ada"));
+            assert!(receiver.recv_timeout(Duration::from_millis(100)).is_err());
+            catalog.abort(&holder).unwrap();
+            assert_eq!(receiver.recv_timeout(Duration::from_secs(5)), Ok(Ok(())));
+
+            // and taken once it commits
+            let holder = catalog.begin(IsolationLevel::default());
+            catalog
+                .insert_tuple(&holder, "people", person("grace", "hopper"))
+                .unwrap();
+            s.spawn(|| insert("grace"));
+            assert!(receiver.recv_timeout(Duration::from_millis(100)).is_err());
+            catalog.commit(&holder).unwrap();
+            assert!(matches!(
+                receiver.recv_timeout(Duration::from_secs(5)),
+                Ok(Err(DmlError::Constraint(
+                    ConstraintViolation::Unique { .. }
+                )))
+            ));
+
+            // a key being deleted is free once the delete commits
+            let holder = catalog.begin(IsolationLevel::default());
+            catalog.delete_tuple(&holder, "people", rid).unwrap();
+            s.spawn(|| insert("name0"));
+            assert!(receiver.recv_timeout(Duration::from_millis(100)).is_err());
+            catalog.commit(&holder).unwrap();
+            assert_eq!(receiver.recv_timeout(Duration::from_secs(5)), Ok(Ok(())));
+        });
+        assert_eq!(
+            names(&catalog, &autocommit(&catalog)),
+            vec!["'ada'", "'grace'", "'name0'"]
+        );
+    }
+
+    #[test]
+    fn test_non_unique_secondary_index() {
+        let catalog = catalog_with_people();
+        catalog
+            .create_index(
+                &autocommit(&catalog),
+                "people_address_idx".to_string(),
+                "people".to_string(),
+                vec![("address".to_string(), SortOrder::Asc)],
+                IndexType::SkipListIndex,
+                false,
+                false,
+            )
+            .unwrap();
+        let txn = catalog.begin(IsolationLevel::default());
+        let rids = ["ada", "grace", "alan"]
+            .iter()
+            .map(|name| {
+                catalog
+                    .insert_tuple(&txn, "people", person(name, name))
+                    .unwrap()
+            })
+            .collect::<Vec<_>>();
+        let locations = rids.iter().map(|rid| (*rid).into()).collect::<Vec<_>>();
+        let index_info = catalog
+            .get_table_indexes("people")
+            .into_iter()
+            .find(|i| !i.is_unique())
+            .unwrap();
+        let key = index_info
+            .get_key_schema()
+            .encode_values(&[Value::Varchar("1 main st".to_string())])
+            .unwrap();
+        assert_eq!(index_info.get_index().range_query(&key, &key), locations);
+
+        let mut moved = person("grace", "grace");
+        moved[2] = Value::Varchar("2 main st".to_string());
+        catalog
+            .update_tuple(&txn, "people", rids[1], moved)
+            .unwrap();
+        assert_eq!(
+            index_info.get_index().range_query(&key, &key),
+            vec![locations[0], locations[2]]
+        );
+    }
+
+    #[test]
+    fn test_hash_index_point_lookups() {
+        let catalog = catalog_with_people();
+        catalog
+            .create_index(
+                &autocommit(&catalog),
+                "people_address_hash".to_string(),
+                "people".to_string(),
+                vec![("address".to_string(), SortOrder::Asc)],
+                IndexType::ExtendibleHashIndex,
+                true,
+                false,
+            )
+            .unwrap();
+        let txn = catalog.begin(IsolationLevel::default());
+        let mut locations = vec![];
+        for i in 0..50 {
+            let mut row = person(&format!("name{}", i), &format!("lastname{}", i));
+            row[2] = Value::Varchar(format!("{} main st", i));
+            locations.push(catalog.insert_tuple(&txn, "people", row).unwrap());
+        }
+        let mut taken = person("someone", "else");
+        taken[2] = Value::Varchar("7 main st".to_string());
+        assert!(matches!(
+            catalog.insert_tuple(&txn, "people", taken).unwrap_err(),
+            DmlError::Constraint(ConstraintViolation::Unique { ref index_name, .. }) if index_name == "people_address_hash"
+        ));
+        let index_info = catalog
+            .get_table_indexes("people")
+            .into_iter()
+            .find(|i| i.get_index_type() == IndexType::ExtendibleHashIndex)
+            .unwrap();
+        assert_eq!(
+            index_info.get_index().find(&key(&index_info, "7 main st")),
+            Some(locations[7].into())
+        );
+        assert_eq!(
+            index_info.get_index().find(&key(&index_info, "70 main st")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_index_entries_follow_moved_and_deleted_rows() {
+        let catalog = catalog_with_people();
+        let txn = catalog.begin(IsolationLevel::default());
+        let rids = (0..60)
+            .map(|i| {
+                let name = format!("name{}", i);
+                catalog
+                    .insert_tuple(&txn, "people", person(&name, &name))
+                    .unwrap()
+            })
+            .collect::<Vec<_>>();
+        let index_info = catalog
+            .get_table_indexes("people")
+            .into_iter()
+            .find(|i| i.is_primary_key())
+            .unwrap();
+        // a much longer address no longer fits the full first page, the row moves
+        let mut row = person("name0", "name0");
+        row[2] = Value::Varchar("x".repeat(2000));
+        catalog.update_tuple(&txn, "people", rids[0], row).unwrap();
+        let rid = index_info
+            .get_index()
+            .find(&key(&index_info, "name0"))
+            .unwrap()
+            .into();
+        assert_eq!(rids[0], rid);
+        let table_info = catalog.get_table("people").unwrap();
+        let tuple = table_info
+            .table_heap
+            .lock()
+            .unwrap()
+            .get_tuple(rid)
+            .unwrap();
+        assert_eq!(tuple.get_value(2), Some(&Value::Varchar("x".repeat(2000))));
+
+        catalog.delete_tuple(&txn, "people", rids[0]).unwrap();
+        assert_eq!(
+            index_info.get_index().find(&key(&index_info, "name0")),
+            None
+        );
+        assert!(catalog.delete_tuple(&txn, "people", rids[0]).is_err());
+        assert_eq!(table_info.table_heap.lock().unwrap().iter().count(), 59);
+        catalog
+            .insert_tuple(&txn, "people", person("name0", "name0"))
+            .unwrap();
+    }
+
+    // An index out of room, no entry can be added to it.
+    #[derive(Debug)]
+    struct FullIndex;
+
+    impl Index for FullIndex {
+        fn insert(&self, _key: Vec<u8>, _page_id: u64, _offset: usize) -> bool {
+            false
+        }
+
+        fn delete(&self, _key: &[u8], _page_id: u64, _offset: usize) -> bool {
+            false
+        }
+
+        fn find(&self, _key: &[u8]) -> Option<(u64, usize)> {
+            None
+        }
+
+        fn range_query(&self, _start: &[u8], _end: &[u8]) -> Vec<(u64, usize)> {
+            vec![]
+        }
+    }
+
+    #[test]
+    fn test_failed_index_writes_leave_the_table_unchanged() {
+        let catalog = catalog_with_people();
+        insert_people(&catalog, 1);
+        let index_info = catalog
+            .get_table_indexes("people")
+            .into_iter()
+            .find(|i| i.is_primary_key())
+            .unwrap();
+        catalog.register_index(IndexInfo {
+            index_name: "people_full".to_string(),
+            index: Arc::new(FullIndex),
+            index_id: 99,
+            is_unique: false,
+            is_primary_key: false,
+            ..IndexInfo::clone(&index_info)
+        });
+        let txn = catalog.begin(IsolationLevel::default());
+        let rid = catalog
+            .scan(&txn, "people")
+            .unwrap()
+            .map(Result::unwrap)
+            .next()
+            .unwrap()
+            .0;
+
+        // the unique entries claimed before are taken out again, and the row
+        assert!(catalog
+            .insert_tuple(&txn, "people", person("ada", "lovelace"))
+            .is_err());
+        assert_eq!(index_info.get_index().find(&key(&index_info, "ada")), None);
+        let lastname_key = catalog.get_index("people", "people_lastname_key").unwrap();
+        assert!(lastname_key
+            .get_index()
+            .range_query(&[], &[0xFF; 32])
+            .iter()
+            .all(|loc| RecordId::from(*loc) == rid));
+        let table_info = catalog.get_table("people").unwrap();
+        assert_eq!(table_info.table_heap.lock().unwrap().iter().count(), 1);
+
+        assert!(catalog
+            .update_tuple(&txn, "people", rid, person("bob", "lastname0"))
+            .is_err());
+        assert_eq!(index_info.get_index().find(&key(&index_info, "bob")), None);
+        assert_eq!(
+            index_info.get_index().find(&key(&index_info, "name0")),
+            Some(rid.into())
+        );
+        let tuple = catalog.get_tuple(&txn, "people", rid).unwrap().unwrap();
+        assert_eq!(tuple.get_value(0).unwrap().to_string(), "'name0'");
+    }
+
+    #[test]
+    fn test_statements_run_in_transactions() {
+        let catalog = catalog_with_people();
+        let txn = catalog.begin(IsolationLevel::default());
+        let ada = catalog
+            .insert_tuple(&txn, "people", person("ada", "lovelace"))
+            .unwrap();
+        let alan = catalog
+            .insert_tuple(&txn, "people", person("alan", "turing"))
+            .unwrap();
+        catalog.delete_tuple(&txn, "people", ada).unwrap();
+        let table_id = catalog.get_table("people").unwrap().get_table_id();
+        let mut written = txn.get_write_set()[&table_id]
+            .iter()
+            .copied()
+            .collect::<Vec<RecordId>>();
+        written.sort();
+        assert_eq!(written, vec![ada, alan]);
+
+        let commit_ts = catalog.commit(&txn).unwrap();
+        assert_eq!(txn.get_state(), TransactionState::COMMITTED);
+        assert!(catalog
+            .insert_tuple(&txn, "people", person("grace", "hopper"))
+            .is_err());
+        let next = catalog.begin(IsolationLevel::default());
+        assert_eq!(next.get_read_ts(), commit_ts);
+        assert!(next.get_id() > txn.get_id());
+
+        // a transaction that hit a conflict can only be aborted
+        next.set_tainted();
+        assert!(catalog.delete_tuple(&next, "people", alan).is_err());
+        assert!(catalog.commit(&next).is_err());
+        catalog.abort(&next).unwrap();
+        assert_eq!(next.get_state(), TransactionState::ABORTED);
+        let txn = catalog.begin(IsolationLevel::default());
+        assert_eq!(rows(&catalog, &txn, "people").len(), 1);
+    }
+
+    #[test]
+    fn test_snapshot_reads_and_write_conflicts() {
+        let catalog = catalog_with_people();
+        insert_people(&catalog, 2);
+        let rid = |name: &str| {
+            let txn = catalog.begin(IsolationLevel::default());
+            let rid = catalog
+                .scan(&txn, "people")
+                .unwrap()
+                .map(Result::unwrap)
+                .find(|(_, t)| t.get_value(0) == Some(&Value::Varchar(name.to_string())))
+                .unwrap()
+                .0;
+            rid
+        };
+        let (name0, name1) = (rid("name0"), rid("name1"));
+        let reader = catalog.begin(IsolationLevel::default());
+        let writer = catalog.begin(IsolationLevel::default());
+        let grace = catalog
+            .insert_tuple(&writer, "people", person("grace", "hopper"))
+            .unwrap();
+        catalog
+            .update_tuple(&writer, "people", name0, person("name0", "changed"))
+            .unwrap();
+        catalog.delete_tuple(&writer, "people", name1).unwrap();
+
+        // uncommitted changes are seen only by the writer
+        assert_eq!(names(&catalog, &writer), vec!["'grace'", "'name0'"]);
+        assert_eq!(names(&catalog, &reader), vec!["'name0'", "'name1'"]);
+        assert!(catalog
+            .get_tuple(&reader, "people", grace)
+            .unwrap()
+            .is_none());
+        assert_eq!(lastname(&catalog, &writer, name0), "'changed'");
+        assert_eq!(lastname(&catalog, &reader, name0), "'lastname0'");
+
+        // writing a row another transaction is writing is a conflict
+        assert!(matches!(
+            catalog.update_tuple(&reader, "people", name0, person("name0", "other")),
+            Err(DmlError::Conflict(_))
+        ));
+        assert_eq!(reader.get_state(), TransactionState::TAINTED);
+        assert!(catalog.scan(&reader, "people").is_ok());
+        assert!(catalog.commit(&reader).is_err());
+        catalog.abort(&reader).unwrap();
+
+        // a commit is seen by transactions beginning after it, not by those running already
+        let before = catalog.begin(IsolationLevel::default());
+        let also_before = catalog.begin(IsolationLevel::default());
+        catalog.commit(&writer).unwrap();
+        assert_eq!(names(&catalog, &before), vec!["'name0'", "'name1'"]);
+        assert_eq!(lastname(&catalog, &before, name0), "'lastname0'");
+        let after = catalog.begin(IsolationLevel::default());
+        assert_eq!(names(&catalog, &after), vec!["'grace'", "'name0'"]);
+        assert_eq!(lastname(&catalog, &after, name0), "'changed'");
+        // and those running already cannot write over it
+        assert!(matches!(
+            catalog.delete_tuple(&before, "people", name0),
+            Err(DmlError::Conflict(_))
+        ));
+        assert!(matches!(
+            catalog.delete_tuple(&also_before, "people", name1),
+            Err(DmlError::Conflict(_))
+        ));
+        catalog.abort(&before).unwrap();
+        catalog.abort(&also_before).unwrap();
+        catalog.delete_tuple(&after, "people", grace).unwrap();
+        catalog.commit(&after).unwrap();
+    }
+
+    #[test]
+    fn test_scans_fail_on_versions_the_schema_cannot_take() {
+        let catalog = catalog_with_people();
+        let mut row = person("ada", "lovelace");
+        row[2] = varchar("ten");
+        let rid = catalog
+            .insert_tuple(&autocommit(&catalog), "people", row)
+            .unwrap();
+        let reader = catalog.begin(IsolationLevel::default());
+        let mut row = person("ada", "lovelace");
+        row[2] = varchar("10");
+        catalog
+            .update_tuple(&autocommit(&catalog), "people", rid, row)
+            .unwrap();
+        // the newest version takes the new type, the one the reader sees does not
+        alter(
+            &catalog,
+            "people",
+            AlterTable::AlterColumnType {
+                column: "address".to_string(),
+                type_id: TypeId::BIGINT,
+                length: 8,
+            },
+        )
+        .unwrap();
+        let rows = catalog.scan(&reader, "people").unwrap().collect::<Vec<_>>();
+        assert_eq!(rows.len(), 1);
+        assert!(rows[0].is_err());
+        assert!(catalog.get_tuple(&reader, "people", rid).is_err());
+        assert!(catalog
+            .range_scan(&reader, "people", "name", Unbounded, Unbounded)
+            .is_err());
+        catalog.commit(&reader).unwrap();
+        let txn = autocommit(&catalog);
+        let rows = catalog
+            .scan(&txn, "people")
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(rows[0].1.get_value(2), Some(&Value::BigInt(10)));
+    }
+
+    #[test]
+    fn test_abort_restores_rows_and_index_entries() {
+        let catalog = catalog_with_people();
+        insert_people(&catalog, 3);
+        let index_info = catalog
+            .get_table_indexes("people")
+            .into_iter()
+            .find(|i| i.is_primary_key())
+            .unwrap();
+        let find = |name: &str| {
+            index_info
+                .get_index()
+                .find(&key(&index_info, name))
+                .map(RecordId::from)
+        };
+        let (name0, name1) = (find("name0").unwrap(), find("name1").unwrap());
+
+        let txn = catalog.begin(IsolationLevel::default());
+        let mut row = person("zed", "lastname0");
+        row[2] = Value::Varchar("x".repeat(2000));
+        catalog.update_tuple(&txn, "people", name0, row).unwrap();
+        catalog.delete_tuple(&txn, "people", name1).unwrap();
+        // the key of a row the transaction deleted can be taken again
+        let again = catalog
+            .insert_tuple(&txn, "people", person("name1", "lastname1"))
+            .unwrap();
+        assert_eq!(find("name1"), Some(again));
+        // keys of the rows as they were are kept until the transaction finishes
+        assert_eq!(find("name0"), Some(name0));
+        assert_eq!(find("zed"), Some(name0));
+        // another transaction needing one of them waits to see how the transaction ends
+        std::thread::scope(|s| {
+            let other = s.spawn(|| {
+                let other = catalog.begin(IsolationLevel::default());
+                let inserted = catalog.insert_tuple(&other, "people", person("name0", "other"));
+                catalog.abort(&other).unwrap();
+                inserted
+            });
+            std::thread::sleep(Duration::from_millis(100));
+            assert!(!other.is_finished());
+            catalog.abort(&txn).unwrap();
+            assert!(other.join().unwrap().is_err());
+        });
+
+        let txn = catalog.begin(IsolationLevel::default());
+        assert_eq!(names(&catalog, &txn), vec!["'name0'", "'name1'", "'name2'"]);
+        assert_eq!((find("name0"), find("name1")), (Some(name0), Some(name1)));
+        assert_eq!(find("zed"), None);
+        let tuple = catalog.get_tuple(&txn, "people", name0).unwrap().unwrap();
+        assert_eq!(
+            tuple.get_value(2),
+            Some(&Value::Varchar("0 main st".to_string()))
+        );
+        assert!(catalog
+            .insert_tuple(&txn, "people", person("name0", "other"))
+            .is_err());
+
+        // once the delete commits the key is free
+        catalog.delete_tuple(&txn, "people", name0).unwrap();
+        catalog.commit(&txn).unwrap();
+        assert_eq!(find("name0"), None);
+        let txn = catalog.begin(IsolationLevel::default());
+        catalog
+            .insert_tuple(&txn, "people", person("name0", "other"))
+            .unwrap();
+        catalog.commit(&txn).unwrap();
+    }
+
+    #[test]
+    fn test_garbage_collection_frees_deleted_rows_and_old_versions() {
+        let catalog = catalog_with_people();
+        insert_people(&catalog, 2);
+        let table_info = catalog.get_table("people").unwrap();
+        let txn = catalog.begin(IsolationLevel::default());
+        let rids = catalog
+            .scan(&txn, "people")
+            .unwrap()
+            .map(Result::unwrap)
+            .map(|(rid, _)| rid)
+            .collect::<Vec<RecordId>>();
+        catalog.commit(&txn).unwrap();
+
+        let reader = catalog.begin(IsolationLevel::default());
+        let txn = catalog.begin(IsolationLevel::default());
+        catalog.delete_tuple(&txn, "people", rids[0]).unwrap();
+        catalog
+            .update_tuple(&txn, "people", rids[1], person("name1", "changed"))
+            .unwrap();
+        catalog.commit(&txn).unwrap();
+        // the reader began before the delete and still sees the rows as they were
+        catalog.garbage_collect();
+        assert_eq!(names(&catalog, &reader), vec!["'name0'", "'name1'"]);
+        let lastname = catalog
+            .get_tuple(&reader, "people", rids[1])
+            .unwrap()
+            .unwrap();
+        assert_eq!(lastname.get_value(1).unwrap().to_string(), "'lastname1'");
+        let marked = table_info
+            .table_heap
+            .lock()
+            .unwrap()
+            .get_tuple_marked(rids[0]);
+        assert!(marked.is_some_and(|(_, deleted)| deleted));
+
+        catalog.commit(&reader).unwrap();
+        catalog.garbage_collect();
+        assert!(table_info
+            .table_heap
+            .lock()
+            .unwrap()
+            .get_tuple_marked(rids[0])
+            .is_none());
+        assert_eq!(catalog.txn_manager.get_version_link(rids[1]), None);
+        let txn = catalog.begin(IsolationLevel::default());
+        assert_eq!(names(&catalog, &txn), vec!["'name1'"]);
+
+        // and it runs on its own every GC_INTERVAL transactions
+        catalog
+            .update_tuple(&txn, "people", rids[1], person("name1", "again"))
+            .unwrap();
+        catalog.commit(&txn).unwrap();
+        assert!(catalog.txn_manager.get_version_link(rids[1]).is_some());
+        for _ in 0..GC_INTERVAL {
+            catalog.commit(&autocommit(&catalog)).unwrap();
+        }
+        assert_eq!(catalog.txn_manager.get_version_link(rids[1]), None);
+    }
+
+    #[test]
+    fn test_serializable_transactions_fail_on_write_skew() {
+        let catalog = catalog_with_people();
+        insert_people(&catalog, 2);
+        // both read every row, each changes one the other read
+        let write_skew = |level| {
+            let first = catalog.begin(level);
+            let second = catalog.begin(level);
+            let rids = catalog
+                .scan(&first, "people")
+                .unwrap()
+                .map(Result::unwrap)
+                .map(|(rid, _)| rid)
+                .collect::<Vec<RecordId>>();
+            assert_eq!(
+                catalog
+                    .scan(&second, "people")
+                    .unwrap()
+                    .map(Result::unwrap)
+                    .count(),
+                2
+            );
+            let lastname = |i| format!("{:?} {}", level, i);
+            catalog
+                .update_tuple(&first, "people", rids[0], person("name0", &lastname(0)))
+                .unwrap();
+            catalog
+                .update_tuple(&second, "people", rids[1], person("name1", &lastname(1)))
+                .unwrap();
+            catalog.commit(&first).unwrap();
+            (second, rids)
+        };
+        let (second, _) = write_skew(IsolationLevel::SNAPSHOTISOLATION);
+        catalog.commit(&second).unwrap();
+
+        let (second, rids) = write_skew(IsolationLevel::SERIALIZABLE);
+        assert!(matches!(
+            catalog.commit(&second),
+            Err(DmlError::SerializationFailure(_))
+        ));
+        catalog.abort(&second).unwrap();
+        let retried = catalog.begin(IsolationLevel::SERIALIZABLE);
+        assert_eq!(
+            lastname(&catalog, &retried, rids[1]),
+            "'SNAPSHOTISOLATION 1'"
+        );
+        catalog
+            .update_tuple(&retried, "people", rids[1], person("name1", "retried"))
+            .unwrap();
+        catalog.commit(&retried).unwrap();
+        let txn = catalog.begin(IsolationLevel::default());
+        assert_eq!(lastname(&catalog, &txn, rids[1]), "'retried'");
+    }
+
+    #[test]
+    fn test_read_committed_rechecks_the_newest_version() {
+        let catalog = catalog_with_people();
+        insert_people(&catalog, 3);
+        let txn = catalog.begin(IsolationLevel::READCOMMITTED);
+        let rows = catalog
+            .scan(&txn, "people")
+            .unwrap()
+            .map(Result::unwrap)
+            .collect::<Vec<(RecordId, Tuple)>>();
+        let writer = catalog.begin(IsolationLevel::default());
+        catalog
+            .update_tuple(&writer, "people", rows[0].0, person("name0", "moved"))
+            .unwrap();
+        catalog.delete_tuple(&writer, "people", rows[1].0).unwrap();
+        catalog
+            .update_tuple(&writer, "people", rows[2].0, person("name2", "moved2"))
+            .unwrap();
+        catalog.commit(&writer).unwrap();
+
+        // the rows picked as lastname0 and lastname1 are not any more
+        let recheck = |tuple: &Tuple, i: usize| tuple.get_value(1) == rows[i].1.get_value(1);
+        let updated = catalog
+            .update_tuple_with(&txn, "people", rows[0].0, |tuple| {
+                recheck(tuple, 0).then(|| person("name0", "mine"))
+            })
+            .unwrap();
+        assert!(!updated);
+        assert!(!catalog
+            .delete_tuple_if(&txn, "people", rows[1].0, |tuple| recheck(tuple, 1))
+            .unwrap());
+        // a snapshot transaction would conflict, this one updates the newest version
+        let updated = catalog
+            .update_tuple_with(&txn, "people", rows[2].0, |tuple| {
+                Some(person(
+                    "name2",
+                    &format!(
+                        "{} mine",
+                        tuple.get_value(1).unwrap().to_string().trim_matches('\'')
+                    ),
+                ))
+            })
+            .unwrap();
+        assert!(updated);
+        assert_eq!(names(&catalog, &txn), vec!["'name0'", "'name1'", "'name2'"]);
+        catalog.begin_statement(&txn);
+        assert_eq!(names(&catalog, &txn), vec!["'name0'", "'name2'"]);
+        let rows = catalog
+            .scan(&txn, "people")
+            .unwrap()
+            .map(Result::unwrap)
+            .collect::<Vec<(RecordId, Tuple)>>();
+        assert_eq!(column(&rows, 1), vec!["'moved'", "'moved2 mine'"]);
+        catalog.commit(&txn).unwrap();
+    }
+
+    #[test]
+    fn test_reading_deleted_rows_back_as_of_an_earlier_time() {
+        let catalog = catalog_with_people();
+        catalog.set_version_retention(Duration::from_secs(3600));
+        insert_people(&catalog, 2);
+        let before_delete = SystemTime::now();
+        let txn = catalog.begin(IsolationLevel::default());
+        let rids = catalog
+            .scan(&txn, "people")
+            .unwrap()
+            .map(Result::unwrap)
+            .map(|(rid, _)| rid)
+            .collect::<Vec<RecordId>>();
+        for rid in rids {
+            catalog.delete_tuple(&txn, "people", rid).unwrap();
+        }
+        let deleted_at = catalog.commit(&txn).unwrap();
+        for _ in 0..GC_INTERVAL {
+            catalog.commit(&autocommit(&catalog)).unwrap();
+        }
+        catalog.garbage_collect();
+
+        let as_of = catalog.begin_as_of(AsOf::Time(before_delete)).unwrap();
+        assert_eq!(names(&catalog, &as_of), vec!["'name0'", "'name1'"]);
+        assert!(catalog
+            .insert_tuple(&as_of, "people", person("name2", "lastname2"))
+            .is_err());
+        catalog.commit(&as_of).unwrap();
+        let as_of = catalog.begin_as_of(AsOf::CommitTs(deleted_at)).unwrap();
+        assert!(names(&catalog, &as_of).is_empty());
+        catalog.commit(&as_of).unwrap();
+
+        // once the window is closed the deleted rows leave the heap
+        catalog.set_version_retention(Duration::ZERO);
+        catalog.garbage_collect();
+        assert!(catalog.begin_as_of(AsOf::CommitTs(deleted_at - 1)).is_err());
+        let table_info = catalog.get_table("people").unwrap();
+        assert_eq!(
+            table_info.table_heap.lock().unwrap().iter_marked().count(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_writers_wait_for_the_row_lock() {
+        let catalog = catalog_with_people();
+        insert_people(&catalog, 2);
+        let txn = catalog.begin(IsolationLevel::default());
+        let rids = catalog
+            .scan(&txn, "people")
+            .unwrap()
+            .map(Result::unwrap)
+            .map(|(rid, _)| rid)
+            .collect::<Vec<RecordId>>();
+        catalog.commit(&txn).unwrap();
+        let holder = catalog.begin(IsolationLevel::default());
+        catalog
+            .update_tuple(&holder, "people", rids[0], person("name0", "held"))
+            .unwrap();
+        let table_id = catalog.get_table("people").unwrap().table_id;
+        assert_eq!(
+            catalog
+                .lockm
+                .held_mode(&holder, LockTarget::Row(table_id, rids[0])),
+            Some(LockMode::Exclusive)
+        );
+
+        // a snapshot cannot write over the uncommitted version, it fails without waiting
+        let snapshot = catalog.begin(IsolationLevel::default());
+        assert!(matches!(
+            catalog.update_tuple(&snapshot, "people", rids[0], person("name0", "mine")),
+            Err(DmlError::Conflict(_))
+        ));
+        catalog.abort(&snapshot).unwrap();
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                let txn = catalog.begin(IsolationLevel::READCOMMITTED);
+                let updated = catalog
+                    .update_tuple_with(&txn, "people", rids[0], |tuple| {
+                        let lastname = tuple.get_value(1).unwrap().to_string();
+                        Some(person(
+                            "name0",
+                            &format!("{} mine", lastname.trim_matches('\'')),
+                        ))
+                    })
+                    .unwrap();
+                sender.send(updated).unwrap();
+                catalog.commit(&txn).unwrap();
+            });
+            // other rows are not locked
+            let other = catalog.begin(IsolationLevel::default());
+            catalog
+                .update_tuple(&other, "people", rids[1], person("name1", "other"))
+                .unwrap();
+            catalog.commit(&other).unwrap();
+            assert!(receiver.recv_timeout(Duration::from_millis(100)).is_err());
+            catalog.commit(&holder).unwrap();
+            assert_eq!(receiver.recv_timeout(Duration::from_secs(5)), Ok(true));
+        });
+        let txn = catalog.begin(IsolationLevel::default());
+        let tuple = catalog.get_tuple(&txn, "people", rids[0]).unwrap().unwrap();
+        assert_eq!(tuple.get_value(1).unwrap().to_string(), "'held mine'");
+    }
+
+    #[test]
+    fn test_deadlocked_writers_are_broken_up() {
+        let catalog = catalog_with_people();
+        assert_eq!(
+            catalog.get_deadlock_interval(),
+            Some(DEFAULT_DEADLOCK_INTERVAL)
+        );
+        catalog.set_deadlock_interval(Some(Duration::from_millis(10)));
+        insert_people(&catalog, 2);
+        let txn = catalog.begin(IsolationLevel::default());
+        let rids = catalog
+            .scan(&txn, "people")
+            .unwrap()
+            .map(Result::unwrap)
+            .map(|(rid, _)| rid)
+            .collect::<Vec<RecordId>>();
+        catalog.commit(&txn).unwrap();
+
+        let older = catalog.begin(IsolationLevel::READCOMMITTED);
+        let younger = catalog.begin(IsolationLevel::READCOMMITTED);
+        catalog
+            .update_tuple(&older, "people", rids[0], person("name0", "older0"))
+            .unwrap();
+        catalog
+            .update_tuple(&younger, "people", rids[1], person("name1", "younger1"))
+            .unwrap();
+        std::thread::scope(|s| {
+            let waiter = s.spawn(|| {
+                catalog.update_tuple(&older, "people", rids[1], person("name1", "older1"))
+            });
+            assert!(matches!(
+                catalog.update_tuple(&younger, "people", rids[0], person("name0", "younger0")),
+                Err(DmlError::Deadlock(_))
+            ));
+            catalog.abort(&younger).unwrap();
+            waiter.join().unwrap().unwrap();
+        });
+        catalog.commit(&older).unwrap();
+        let txn = catalog.begin(IsolationLevel::default());
+        let lastnames = catalog
+            .scan(&txn, "people")
+            .unwrap()
+            .map(Result::unwrap)
+            .map(|(_, tuple)| tuple.get_value(1).unwrap().to_string())
+            .collect::<Vec<String>>();
+        assert_eq!(lastnames, vec!["'older0'", "'older1'"]);
+
+        catalog.set_deadlock_interval(None);
+        assert_eq!(catalog.get_deadlock_interval(), None);
+    }
+
+    #[test]
+    fn test_select_for_update_skips_or_fails_on_locked_rows() {
+        let catalog = catalog_with_people();
+        insert_people(&catalog, 3);
+        let first = catalog.begin(IsolationLevel::default());
+        let rows = catalog
+            .select_for_update(&first, "people", Some(1), RowLockWait::Wait)
+            .unwrap();
+        assert_eq!(column(&rows, 1), vec!["'lastname0'"]);
+        let second = catalog.begin(IsolationLevel::default());
+        let rows = catalog
+            .select_for_update(&second, "people", Some(1), RowLockWait::SkipLocked)
+            .unwrap();
+        assert_eq!(column(&rows, 1), vec!["'lastname1'"]);
+
+        let third = catalog.begin(IsolationLevel::default());
+        assert!(matches!(
+            catalog.select_for_update(&third, "people", None, RowLockWait::NoWait),
+            Err(DmlError::LockNotAvailable(_))
+        ));
+        // failing to lock leaves the transaction running
+        let rows = catalog
+            .select_for_update(&third, "people", None, RowLockWait::SkipLocked)
+            .unwrap();
+        assert_eq!(column(&rows, 1), vec!["'lastname2'"]);
+        catalog
+            .update_tuple(&third, "people", rows[0].0, person("name2", "mine2"))
+            .unwrap();
+        // writers wait for the rows locked for update, up to the lock timeout
+        third.set_lock_timeout(Some(Duration::from_millis(20)));
+        let locked = catalog
+            .select_for_update(&first, "people", None, RowLockWait::SkipLocked)
+            .unwrap();
+        assert_eq!(column(&locked, 1), vec!["'lastname0'"]);
+        let started = std::time::Instant::now();
+        assert!(matches!(
+            catalog.update_tuple(&third, "people", locked[0].0, person("name0", "mine")),
+            Err(DmlError::LockNotAvailable(_))
+        ));
+        assert!(started.elapsed() >= Duration::from_millis(20));
+        catalog.commit(&first).unwrap();
+        catalog.commit(&second).unwrap();
+
+        // the lock is free, and the row unchanged since the snapshot
+        catalog
+            .update_tuple(&third, "people", locked[0].0, person("name0", "mine"))
+            .unwrap();
+        catalog.commit(&third).unwrap();
+        let txn = catalog.begin(IsolationLevel::default());
+        let rows = catalog
+            .select_for_update(&txn, "people", None, RowLockWait::NoWait)
+            .unwrap();
+        assert_eq!(column(&rows, 1), vec!["'mine'", "'lastname1'", "'mine2'"]);
+        catalog.commit(&txn).unwrap();
+    }
+
+    #[test]
+    fn test_serializable_range_scans_keep_phantoms_out() {
+        let catalog = catalog_with_people();
+        insert_people(&catalog, 5);
+        let snapshot = catalog.begin(IsolationLevel::default());
+        let rows = catalog
+            .range_scan(
+                &snapshot,
+                "people",
+                "name",
+                Excluded(varchar("name1")),
+                Unbounded,
+            )
+            .unwrap();
+        assert_eq!(column(&rows, 0), vec!["'name2'", "'name3'", "'name4'"]);
+        let table_id = catalog.get_table("people").unwrap().table_id;
+        assert_eq!(
+            catalog
+                .lockm
+                .held_mode(&snapshot, LockTarget::Table(table_id)),
+            None
+        );
+        catalog.commit(&snapshot).unwrap();
+
+        let scanner = catalog.begin(IsolationLevel::SERIALIZABLE);
+        let rows = catalog
+            .range_scan(
+                &scanner,
+                "people",
+                "name",
+                Included(varchar("name1")),
+                Included(varchar("name2")),
+            )
+            .unwrap();
+        assert_eq!(column(&rows, 0), vec!["'name1'", "'name2'"]);
+
+        // rows outside the locked gaps go in right away
+        let writer = catalog.begin(IsolationLevel::default());
+        catalog
+            .insert_tuple(&writer, "people", person("a", "a"))
+            .unwrap();
+        catalog
+            .insert_tuple(&writer, "people", person("name35", "name35"))
+            .unwrap();
+        writer.set_lock_timeout(Some(Duration::from_millis(20)));
+        assert!(matches!(
+            catalog.insert_tuple(&writer, "people", person("name15", "name15")),
+            Err(DmlError::LockNotAvailable(_))
+        ));
+        // nor can a row move into the range
+        let rid = catalog
+            .range_scan(
+                &writer,
+                "people",
+                "name",
+                Included(varchar("name4")),
+                Unbounded,
+            )
+            .unwrap()[0]
+            .0;
+        assert!(matches!(
+            catalog.update_tuple(&writer, "people", rid, person("name12", "lastname4")),
+            Err(DmlError::LockNotAvailable(_))
+        ));
+        writer.set_lock_timeout(None);
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                let inserted = catalog.insert_tuple(&writer, "people", person("name15", "name15"));
+                sender.send(inserted.is_ok()).unwrap();
+            });
+            assert!(receiver.recv_timeout(Duration::from_millis(100)).is_err());
+            // the range reads the same until the scanner ends
+            let rows = catalog
+                .range_scan(
+                    &scanner,
+                    "people",
+                    "name",
+                    Included(varchar("name1")),
+                    Included(varchar("name2")),
+                )
+                .unwrap();
+            assert_eq!(column(&rows, 0), vec!["'name1'", "'name2'"]);
+            catalog.commit(&scanner).unwrap();
+            assert_eq!(receiver.recv_timeout(Duration::from_secs(5)), Ok(true));
+        });
+        catalog.commit(&writer).unwrap();
+        let txn = catalog.begin(IsolationLevel::default());
+        let rows = catalog
+            .range_scan(
+                &txn,
+                "people",
+                "name",
+                Unbounded,
+                Excluded(varchar("name2")),
+            )
+            .unwrap();
+        assert_eq!(
+            column(&rows, 0),
+            vec!["'name0'", "'name1'", "'a'", "'name15'"]
+        );
+    }
+
+    #[test]
+    fn test_range_scans_without_an_index_lock_the_table() {
+        let catalog = catalog_with_people();
+        insert_people(&catalog, 3);
+        let scanner = catalog.begin(IsolationLevel::SERIALIZABLE);
+        let rows = catalog
+            .range_scan(
+                &scanner,
+                "people",
+                "address",
+                Unbounded,
+                Included(Value::Varchar("1 main st".to_string())),
+            )
+            .unwrap();
+        assert_eq!(rows.len(), 2);
+        let table_id = catalog.get_table("people").unwrap().table_id;
+        assert_eq!(
+            catalog
+                .lockm
+                .held_mode(&scanner, LockTarget::Table(table_id)),
+            Some(LockMode::Shared)
+        );
+        assert!(catalog
+            .range_scan(&scanner, "people", "phone", Unbounded, Unbounded)
+            .is_err());
+
+        let writer = catalog.begin(IsolationLevel::default());
+        writer.set_lock_timeout(Some(Duration::from_millis(20)));
+        assert!(matches!(
+            catalog.insert_tuple(&writer, "people", person("other", "other")),
+            Err(DmlError::LockNotAvailable(_))
+        ));
+        catalog.commit(&scanner).unwrap();
+        catalog
+            .insert_tuple(&writer, "people", person("other", "other"))
+            .unwrap();
+        catalog.commit(&writer).unwrap();
+    }
+}
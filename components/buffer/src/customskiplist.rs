@@ -1,170 +1,457 @@
-use std::{cell::RefCell, rc::Rc, vec};
+use crossbeam_epoch::{self as epoch, Atomic, Guard, Owned, Shared};
+use parking_lot::{Mutex, MutexGuard};
+use std::fmt::{self, Debug, Formatter};
+use std::marker::PhantomData;
+use std::ops::Bound;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
-type Link = Option<Rc<RefCell<Node>>>;
+// Concurrent ordered map, a lazy skip list (Herlihy, Lev, Luchangco, Shavit):
+//
+// - readers never latch, they walk the towers and skip nodes that are marked or not fully linked
+// - writers latch only the predecessors they link into (and the victim for a delete), validate
+//   that nothing changed under them and retry otherwise
+// - a deleted node is first marked (logical delete) and then unlinked at every level while its
+//   predecessors are latched; after that no new link to it can appear, so it is handed to the
+//   epoch collector and freed once every thread that might still be looking at it has moved on
+//
+// Latches are always taken in descending key order, which keeps writers deadlock free.
 
-#[derive(Default)]
-#[allow(dead_code)]
-struct Node {
-    next: Vec<Link>,
-    pub offset: u64,
-    pub command: String,
+pub const DEFAULT_MAX_LEVEL: usize = 16;
+
+struct Node<K, V> {
+    // None only for the head sentinel, which sorts before every key
+    key: Option<K>,
+    value: Option<V>,
+    next: Box<[Atomic<Node<K, V>>]>,
+    lock: Mutex<()>,
+    marked: AtomicBool,
+    fully_linked: AtomicBool,
 }
 
-impl Node {
-    fn new(links: Vec<Link>, offset: u64, command: String) -> Rc<RefCell<Node>> {
-        Rc::new(RefCell::new(Node {
-            next: links,
-            offset,
-            command,
-        }))
+impl<K, V> Node<K, V> {
+    fn new(key: Option<K>, value: Option<V>, height: usize) -> Self {
+        Self {
+            key,
+            value,
+            next: (0..height).map(|_| Atomic::null()).collect(),
+            lock: Mutex::new(()),
+            marked: AtomicBool::new(false),
+            fully_linked: AtomicBool::new(false),
+        }
+    }
+
+    fn top_level(&self) -> usize {
+        self.next.len() - 1
+    }
+
+    fn is_live(&self) -> bool {
+        self.fully_linked.load(Ordering::Acquire) && !self.marked.load(Ordering::Acquire)
     }
 }
 
-#[allow(dead_code)]
-#[derive(Clone)]
-pub struct SkipList {
-    head: Link,
-    tails: Vec<Link>,
+pub struct SkipList<K, V> {
+    head: Atomic<Node<K, V>>,
     max_level: usize,
-    pub length: u64,
+    length: AtomicUsize,
 }
 
-impl SkipList {
-    #[allow(dead_code)]
+// Predecessors and successors of a key on every level.
+type Window<'g, K, V> = (Vec<Shared<'g, Node<K, V>>>, Vec<Shared<'g, Node<K, V>>>);
+// A node a delete has marked, together with its latch.
+type Victim<'g, K, V> = (Shared<'g, Node<K, V>>, MutexGuard<'g, ()>);
+
+impl<K: Ord, V: Clone> SkipList<K, V> {
     pub fn new(max_level: usize) -> Self {
+        let max_level = max_level.max(1);
         Self {
-            head: None,
-            tails: vec![None; max_level + 1],
+            head: Atomic::new(Node::new(None, None, max_level)),
             max_level,
-            length: 0,
+            length: AtomicUsize::new(0),
         }
     }
-    #[allow(dead_code)]
+
     fn get_level(&self) -> usize {
         let mut n = 0;
-        while rand::random::<bool>() && n < self.max_level {
+        while rand::random::<bool>() && n < self.max_level - 1 {
             n += 1;
         }
         n
     }
-    #[allow(dead_code)]
-    pub fn append(&mut self, offset: u64, value: String) {
-        let level = 1 + if self.head.is_none() {
-            self.max_level
-        } else {
-            self.get_level()
-        };
 
-        let new = Node::new(vec![None; level], offset, value);
+    pub fn len(&self) -> usize {
+        self.length.load(Ordering::SeqCst)
+    }
 
-        for i in 0..level {
-            if let Some(old) = self.tails[i].take() {
-                let next = &mut old.borrow_mut().next;
-                next[i] = Some(new.clone());
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Fills the window around `key` and returns the highest level it was found on.
+    fn find<'g>(&self, key: &K, guard: &'g Guard) -> (Option<usize>, Window<'g, K, V>) {
+        let mut preds = vec![Shared::null(); self.max_level];
+        let mut succs = vec![Shared::null(); self.max_level];
+        let mut found = None;
+        let mut pred = self.head.load(Ordering::Acquire, guard);
+        for level in (0..self.max_level).rev() {
+            let mut curr = unsafe { pred.deref() }.next[level].load(Ordering::Acquire, guard);
+            while let Some(node) = unsafe { curr.as_ref() } {
+                if node.key.as_ref().unwrap() >= key {
+                    break;
+                }
+                pred = curr;
+                curr = node.next[level].load(Ordering::Acquire, guard);
             }
+            if found.is_none()
+                && unsafe { curr.as_ref() }.is_some_and(|n| n.key.as_ref() == Some(key))
+            {
+                found = Some(level);
+            }
+            preds[level] = pred;
+            succs[level] = curr;
         }
+        (found, (preds, succs))
+    }
 
-        if self.head.is_none() {
-            self.head = Some(new.clone());
+    // Latches the distinct predecessors of levels 0..=top and checks that each still links to
+    // the expected successor and that neither side was deleted in the meantime. A delete has
+    // already marked its successor, the victim, so it skips that part of the check.
+    fn lock_window<'g>(
+        preds: &[Shared<'g, Node<K, V>>],
+        succs: &[Shared<'g, Node<K, V>>],
+        top: usize,
+        deleting: bool,
+        guard: &'g Guard,
+    ) -> Option<Vec<MutexGuard<'g, ()>>> {
+        let mut latches = vec![];
+        let mut prev = Shared::null();
+        for level in 0..=top {
+            let pred = unsafe { preds[level].deref() };
+            if preds[level] != prev {
+                latches.push(pred.lock.lock());
+                prev = preds[level];
+            }
+            let succ = unsafe { succs[level].as_ref() };
+            if pred.marked.load(Ordering::Acquire)
+                || (!deleting && succ.is_some_and(|s| s.marked.load(Ordering::Acquire)))
+                || pred.next[level].load(Ordering::Acquire, guard) != succs[level]
+            {
+                return None;
+            }
         }
-        self.length += 1;
-    }
-
-    #[allow(dead_code)]
-    pub fn find(&self, offset: u64) -> Option<String> {
-        match self.head {
-            Some(ref head) => {
-                let mut start_level = self.max_level;
-                let node = head.clone();
-                let mut result = None;
-                loop {
-                    if node.borrow().next[start_level].is_some() {
-                        break;
+        Some(latches)
+    }
+
+    // Inserts the entry unless the key is present, false in that case.
+    pub fn insert(&self, key: K, value: V) -> bool {
+        let top = self.get_level();
+        let guard = &epoch::pin();
+        let mut entry = Some((key, value));
+        loop {
+            let (found, (preds, succs)) = self.find(&entry.as_ref().unwrap().0, guard);
+            if let Some(level) = found {
+                let node = unsafe { succs[level].deref() };
+                if !node.marked.load(Ordering::Acquire) {
+                    // the key is being inserted by someone else, wait until it is visible
+                    while !node.fully_linked.load(Ordering::Acquire) {
+                        std::hint::spin_loop();
                     }
-                    start_level -= 1;
+                    return false;
                 }
-                let mut n = node;
-                for level in (0..=start_level).rev() {
-                    loop {
-                        let next = n.clone();
-                        match next.borrow().next[level] {
-                            Some(ref next) if next.borrow().offset <= offset => n = next.clone(),
-                            _ => break,
-                        };
-                    }
-                    if n.borrow().offset == offset {
-                        let tmp = n.borrow();
-                        result = Some(tmp.command.clone());
-                        break;
-                    }
+                // being deleted, retry once it is gone
+                continue;
+            }
+            let Some(_latches) = Self::lock_window(&preds, &succs, top, false, guard) else {
+                continue;
+            };
+            let (key, value) = entry.take().unwrap();
+            let node = Node::new(Some(key), Some(value), top + 1);
+            for (level, succ) in succs.iter().enumerate().take(top + 1) {
+                node.next[level].store(*succ, Ordering::Relaxed);
+            }
+            let node = Owned::new(node).into_shared(guard);
+            for (level, pred) in preds.iter().enumerate().take(top + 1) {
+                unsafe { pred.deref() }.next[level].store(node, Ordering::Release);
+            }
+            unsafe { node.deref() }
+                .fully_linked
+                .store(true, Ordering::Release);
+            self.length.fetch_add(1, Ordering::SeqCst);
+            return true;
+        }
+    }
+
+    pub fn remove(&self, key: &K) -> Option<V> {
+        self.remove_if(key, |_| true)
+    }
+
+    // Removes the entry if `predicate` holds for its value, e.g. to delete an exact
+    // (key, value) pair.
+    pub fn remove_if(&self, key: &K, predicate: impl Fn(&V) -> bool) -> Option<V> {
+        let guard = &epoch::pin();
+        let mut victim: Option<Victim<'_, K, V>> = None;
+        loop {
+            let (found, (preds, succs)) = self.find(key, guard);
+            if victim.is_none() {
+                let level = found?;
+                let node = unsafe { succs[level].deref() };
+                if !node.fully_linked.load(Ordering::Acquire)
+                    || node.top_level() != level
+                    || node.marked.load(Ordering::Acquire)
+                {
+                    return None;
                 }
-                result
+                let latch = node.lock.lock();
+                if node.marked.load(Ordering::Acquire) || !predicate(node.value.as_ref().unwrap()) {
+                    return None;
+                }
+                node.marked.store(true, Ordering::Release);
+                victim = Some((succs[level], latch));
+            }
+            let (node_ptr, _) = victim.as_ref().unwrap();
+            let node = unsafe { node_ptr.deref() };
+            let top = node.top_level();
+            // the victim must be every level's successor, otherwise the window is stale
+            let succs = vec![*node_ptr; top + 1];
+            let Some(_latches) = Self::lock_window(&preds, &succs, top, true, guard) else {
+                continue;
+            };
+            for level in (0..=top).rev() {
+                let next = node.next[level].load(Ordering::Acquire, guard);
+                unsafe { preds[level].deref() }.next[level].store(next, Ordering::Release);
             }
-            None => None,
+            let value = node.value.clone();
+            self.length.fetch_sub(1, Ordering::SeqCst);
+            let (node_ptr, latch) = victim.take().unwrap();
+            drop(latch);
+            drop(_latches);
+            unsafe { guard.defer_destroy(node_ptr) };
+            return value;
         }
     }
-    #[allow(dead_code)]
-    fn iter_level(&self, level: usize) -> ListIterator {
-        ListIterator::new(self.head.clone(), level)
+
+    pub fn get(&self, key: &K) -> Option<V> {
+        let guard = &epoch::pin();
+        let (found, (_, succs)) = self.find(key, guard);
+        let node = unsafe { succs[found?].deref() };
+        node.is_live().then(|| node.value.clone().unwrap())
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
     }
-}
 
-impl IntoIterator for SkipList {
-    type Item = (u64, String);
-    type IntoIter = ListIterator;
+    // First node at level 0 that lies after `start`.
+    fn seek<'g>(&self, start: Bound<&K>, guard: &'g Guard) -> Shared<'g, Node<K, V>> {
+        let before = |node: &Node<K, V>| match start {
+            Bound::Unbounded => false,
+            Bound::Included(key) => node.key.as_ref().unwrap() < key,
+            Bound::Excluded(key) => node.key.as_ref().unwrap() <= key,
+        };
+        let mut pred = self.head.load(Ordering::Acquire, guard);
+        let mut curr = Shared::null();
+        for level in (0..self.max_level).rev() {
+            curr = unsafe { pred.deref() }.next[level].load(Ordering::Acquire, guard);
+            while let Some(node) = unsafe { curr.as_ref() } {
+                if !before(node) {
+                    break;
+                }
+                pred = curr;
+                curr = node.next[level].load(Ordering::Acquire, guard);
+            }
+        }
+        curr
+    }
 
-    fn into_iter(self) -> Self::IntoIter {
-        ListIterator::new(self.head, 0)
+    // Iterates the live entries within the bounds in key order. The iterator keeps the epoch
+    // pinned, so memory of entries deleted meanwhile is only reclaimed once it is dropped.
+    pub fn range(&self, start: Bound<&K>, end: Bound<&K>) -> Range<'_, K, V>
+    where
+        K: Clone,
+    {
+        let guard = epoch::pin();
+        let next = self.seek(start, &guard).as_raw();
+        Range {
+            guard,
+            next,
+            end: end.cloned(),
+            _list: PhantomData,
+        }
+    }
+
+    // Iterates from the first key >= `key`.
+    pub fn seek_from(&self, key: &K) -> Range<'_, K, V>
+    where
+        K: Clone,
+    {
+        self.range(Bound::Included(key), Bound::Unbounded)
     }
-}
 
-#[allow(dead_code)]
-pub struct ListIterator {
-    current: Option<Rc<RefCell<Node>>>,
-    level: usize,
+    pub fn iter(&self) -> Range<'_, K, V>
+    where
+        K: Clone,
+    {
+        self.range(Bound::Unbounded, Bound::Unbounded)
+    }
 }
 
-impl ListIterator {
-    fn new(start_at: Option<Rc<RefCell<Node>>>, level: usize) -> Self {
-        Self {
-            current: start_at,
-            level,
+impl<K, V> Drop for SkipList<K, V> {
+    fn drop(&mut self) {
+        // nobody else can reach the list anymore, deleted nodes were already handed to the
+        // collector and are no longer linked at level 0
+        unsafe {
+            let guard = epoch::unprotected();
+            let mut node = self.head.load(Ordering::Relaxed, guard);
+            while !node.is_null() {
+                let next = node.deref().next[0].load(Ordering::Relaxed, guard);
+                drop(node.into_owned());
+                node = next;
+            }
         }
     }
 }
 
-impl Iterator for ListIterator {
-    type Item = (u64, String);
-
-    fn next(&mut self) -> Option<(u64, String)> {
-        let current = &self.current;
-        let mut result = None;
-        self.current = match current {
-            Some(ref current) => {
-                let current = current.borrow();
-                result = Some((current.offset, current.command.clone()));
-                current.next[self.level].clone()
-            }
-            _ => None,
-        };
-        result
+impl<K: Ord + Clone + Debug, V: Clone + Debug> Debug for SkipList<K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
     }
 }
 
-impl std::fmt::Debug for SkipList {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        match self.head {
-            Some(ref _head) => {
-                for level in (0..=self.max_level).rev() {
-                    let _ = write!(f, "{}: ", level);
-                    for n in self.iter_level(level) {
-                        let _ = write!(f, "[{}] ", n.0);
-                    }
-                    let _ = writeln!(f);
-                }
-                Ok(())
+pub struct Range<'a, K, V> {
+    guard: Guard,
+    next: *const Node<K, V>,
+    end: Bound<K>,
+    _list: PhantomData<&'a SkipList<K, V>>,
+}
+
+impl<K: Ord + Clone, V: Clone> Iterator for Range<'_, K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<(K, V)> {
+        loop {
+            // safe while the guard is pinned: the node was reachable after we pinned
+            let node = unsafe { self.next.as_ref() }?;
+            self.next = node.next[0].load(Ordering::Acquire, &self.guard).as_raw();
+            let key = node.key.as_ref().unwrap();
+            let in_range = match &self.end {
+                Bound::Unbounded => true,
+                Bound::Included(end) => key <= end,
+                Bound::Excluded(end) => key < end,
+            };
+            if !in_range {
+                self.next = std::ptr::null();
+                return None;
+            }
+            if node.is_live() {
+                return Some((key.clone(), node.value.clone().unwrap()));
             }
-            None => write!(f, "The list is empty: []"),
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_insert_get_remove() {
+        let list = SkipList::new(DEFAULT_MAX_LEVEL);
+        for i in [5u64, 1, 9, 3, 7] {
+            assert!(list.insert(i, i * 10));
+        }
+        assert!(!list.insert(3, 0));
+        assert_eq!(list.len(), 5);
+        assert_eq!(list.get(&3), Some(30));
+        assert_eq!(list.get(&4), None);
+        assert_eq!(list.remove_if(&3, |v| *v == 0), None);
+        assert_eq!(list.remove(&3), Some(30));
+        assert_eq!(list.remove(&3), None);
+        assert!(!list.contains_key(&3));
+        assert!(list.insert(3, 31));
+        assert_eq!(
+            list.iter().collect::<Vec<_>>(),
+            vec![(1, 10), (3, 31), (5, 50), (7, 70), (9, 90)]
+        );
+    }
+
+    #[test]
+    fn test_range_and_seek() {
+        let list = SkipList::new(4);
+        for i in 0..100u64 {
+            list.insert(i * 2, ());
+        }
+        let keys = |r: Range<'_, u64, ()>| r.map(|(k, _)| k).collect::<Vec<_>>();
+        assert_eq!(
+            keys(list.range(Bound::Included(&10), Bound::Excluded(&20))),
+            vec![10, 12, 14, 16, 18]
+        );
+        assert_eq!(
+            keys(list.range(Bound::Excluded(&193), Bound::Included(&198))),
+            vec![194, 196, 198]
+        );
+        assert_eq!(keys(list.seek_from(&195)), vec![196, 198]);
+        assert_eq!(list.seek_from(&500).count(), 0);
+        assert_eq!(list.iter().count(), 100);
+    }
+
+    #[test]
+    fn test_remove_while_iterating() {
+        let list = SkipList::new(DEFAULT_MAX_LEVEL);
+        for i in 0..10u64 {
+            list.insert(i, i);
+        }
+        let mut iter = list.iter();
+        assert_eq!(iter.next(), Some((0, 0)));
+        list.remove(&1);
+        list.remove(&2);
+        list.insert(11, 11);
+        assert_eq!(iter.next(), Some((3, 3)));
+        assert_eq!(iter.count(), 7);
+    }
+
+    #[test]
+    fn test_concurrent_inserts_and_removes() {
+        let list = Arc::new(SkipList::new(DEFAULT_MAX_LEVEL));
+        let handles = (0..8u64)
+            .map(|t| {
+                let list = Arc::clone(&list);
+                std::thread::spawn(move || {
+                    for i in 0..1000u64 {
+                        // every thread races for the same even keys, odd keys are its own
+                        let shared = i * 2;
+                        let own = (t * 1000 + i) * 2 + 1;
+                        list.insert(shared, t);
+                        assert!(list.insert(own, t));
+                        if i % 2 == 0 {
+                            assert_eq!(list.remove(&own), Some(t));
+                        }
+                        list.remove(&shared);
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        handles.into_iter().for_each(|h| h.join().unwrap());
+        let entries = list.iter().collect::<Vec<_>>();
+        assert!(entries.windows(2).all(|w| w[0].0 < w[1].0));
+        let odd = entries.iter().filter(|(k, _)| k % 2 == 1).count();
+        assert_eq!(odd, 8 * 500);
+        assert_eq!(list.len(), entries.len());
+    }
+
+    #[test]
+    fn test_exactly_one_insert_wins() {
+        let list = Arc::new(SkipList::new(DEFAULT_MAX_LEVEL));
+        let handles = (0..8u64)
+            .map(|t| {
+                let list = Arc::clone(&list);
+                std::thread::spawn(move || list.insert(42u64, t))
+            })
+            .collect::<Vec<_>>();
+        let wins = handles
+            .into_iter()
+            .map(|h| h.join().unwrap())
+            .filter(|won| *won)
+            .count();
+        assert_eq!(wins, 1);
+    }
+}
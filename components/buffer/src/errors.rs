@@ -0,0 +1,135 @@
+use crate::query_types::Value;
+use concurrency_manager::lock_manager::LockError;
+use std::fmt::{Display, Formatter, Result};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConstraintViolation {
+    // A UNIQUE or PRIMARY KEY index already holds an entry with the same key.
+    Unique {
+        index_name: String,
+        key: Vec<Value>,
+    },
+    NotNull {
+        column: String,
+    },
+    // A CHECK constraint evaluated to false for the row.
+    Check {
+        constraint_name: String,
+    },
+    // The row references a key that is not present in the referenced table.
+    ForeignKey {
+        constraint_name: String,
+        table: String,
+        key: Vec<Value>,
+    },
+    // The key is still referenced from `table` and cannot be changed or deleted.
+    Referenced {
+        constraint_name: String,
+        table: String,
+        key: Vec<Value>,
+    },
+}
+
+fn format_key(key: &[Value]) -> String {
+    key.iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+impl Display for ConstraintViolation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            ConstraintViolation::Unique { index_name, key } => {
+                write!(
+                    f,
+                    "duplicate key ({}) violates unique constraint {}",
+                    format_key(key),
+                    index_name
+                )
+            }
+            ConstraintViolation::NotNull { column } => {
+                write!(
+                    f,
+                    "null value in column {} violates not-null constraint",
+                    column
+                )
+            }
+            ConstraintViolation::Check { constraint_name } => {
+                write!(f, "new row violates check constraint {}", constraint_name)
+            }
+            ConstraintViolation::ForeignKey {
+                constraint_name,
+                table,
+                key,
+            } => write!(
+                f,
+                "key ({}) is not present in table {}, violates foreign key constraint {}",
+                format_key(key),
+                table,
+                constraint_name
+            ),
+            ConstraintViolation::Referenced {
+                constraint_name,
+                table,
+                key,
+            } => write!(
+                f,
+                "key ({}) is still referenced from table {} by foreign key constraint {}",
+                format_key(key),
+                table,
+                constraint_name
+            ),
+        }
+    }
+}
+
+// Errors returned by the catalog's insert/update paths.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DmlError {
+    Constraint(ConstraintViolation),
+    Invalid(String),
+    // A write-write conflict with another transaction, the transaction can only be aborted.
+    Conflict(String),
+    // A serializable transaction could not commit, it can only be aborted but can be retried.
+    SerializationFailure(String),
+    // The transaction was picked to break a deadlock, it can only be aborted but can be retried.
+    Deadlock(String),
+    // A row or table lock could not be taken without waiting, or within the lock timeout.
+    LockNotAvailable(String),
+}
+
+impl Display for DmlError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            DmlError::Constraint(violation) => write!(f, "{}", violation),
+            DmlError::Invalid(msg)
+            | DmlError::Conflict(msg)
+            | DmlError::SerializationFailure(msg)
+            | DmlError::Deadlock(msg)
+            | DmlError::LockNotAvailable(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl From<ConstraintViolation> for DmlError {
+    fn from(violation: ConstraintViolation) -> Self {
+        DmlError::Constraint(violation)
+    }
+}
+
+impl From<String> for DmlError {
+    fn from(msg: String) -> Self {
+        DmlError::Invalid(msg)
+    }
+}
+
+impl From<LockError> for DmlError {
+    fn from(e: LockError) -> Self {
+        match e {
+            LockError::Invalid(msg) => DmlError::Invalid(msg),
+            LockError::NotAvailable(msg) => DmlError::LockNotAvailable(msg),
+            LockError::Deadlock(msg) => DmlError::Deadlock(msg),
+        }
+    }
+}
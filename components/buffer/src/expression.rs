@@ -0,0 +1,895 @@
+use crate::query_types::{Schema, Value};
+use std::cmp::Ordering;
+use std::fmt::{Display, Formatter, Result};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Expressions of DEFAULT and CHECK constraints, e.g. `salary * 2 > 1000 AND age IS NOT NULL`.
+// They are kept in the catalog as text, so `Display` writes an expression in a form `parse`
+// reads back: binary operations are fully parenthesized and strings quoted.
+//
+// Evaluation follows SQL: an operation on NULL is NULL, AND/OR use three-valued logic, and a CHECK
+// passes unless its expression is false.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BinaryOp {
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+    And,
+    Or,
+    Plus,
+    Minus,
+    Multiply,
+    Divide,
+    Modulo,
+    Concat,
+}
+
+impl BinaryOp {
+    fn symbol(&self) -> &'static str {
+        match self {
+            BinaryOp::Eq => "=",
+            BinaryOp::NotEq => "<>",
+            BinaryOp::Lt => "<",
+            BinaryOp::LtEq => "<=",
+            BinaryOp::Gt => ">",
+            BinaryOp::GtEq => ">=",
+            BinaryOp::And => "AND",
+            BinaryOp::Or => "OR",
+            BinaryOp::Plus => "+",
+            BinaryOp::Minus => "-",
+            BinaryOp::Multiply => "*",
+            BinaryOp::Divide => "/",
+            BinaryOp::Modulo => "%",
+            BinaryOp::Concat => "||",
+        }
+    }
+
+    // Binding power, higher binds tighter.
+    fn precedence(&self) -> u8 {
+        match self {
+            BinaryOp::Or => 1,
+            BinaryOp::And => 2,
+            BinaryOp::Eq
+            | BinaryOp::NotEq
+            | BinaryOp::Lt
+            | BinaryOp::LtEq
+            | BinaryOp::Gt
+            | BinaryOp::GtEq => 4,
+            BinaryOp::Plus | BinaryOp::Minus | BinaryOp::Concat => 5,
+            BinaryOp::Multiply | BinaryOp::Divide | BinaryOp::Modulo => 6,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    Literal(Value),
+    Column(String),
+    Not(Box<Expr>),
+    Negate(Box<Expr>),
+    Binary {
+        left: Box<Expr>,
+        op: BinaryOp,
+        right: Box<Expr>,
+    },
+    IsNull {
+        expr: Box<Expr>,
+        negated: bool,
+    },
+    InList {
+        expr: Box<Expr>,
+        list: Vec<Expr>,
+        negated: bool,
+    },
+    Function {
+        name: String,
+        args: Vec<Expr>,
+    },
+}
+
+impl From<Value> for Expr {
+    fn from(value: Value) -> Self {
+        Expr::Literal(value)
+    }
+}
+
+const PREFIX_NOT_PRECEDENCE: u8 = 3;
+const IS_IN_PRECEDENCE: u8 = 4;
+const NEGATE_PRECEDENCE: u8 = 6;
+
+impl Expr {
+    /// Parses an expression, e.g. the text between the parentheses of `CHECK (...)`.
+    pub fn parse(text: &str) -> std::result::Result<Expr, String> {
+        let mut parser = Parser {
+            tokens: tokenize(text)?,
+            pos: 0,
+        };
+        let expr = parser.expr(0)?;
+        match parser.peek() {
+            None => Ok(expr),
+            Some(token) => Err(format!("unexpected {} in expression", token)),
+        }
+    }
+
+    /// Names of the columns the expression reads.
+    pub fn columns(&self) -> Vec<&str> {
+        let mut columns = vec![];
+        self.visit(&mut |expr| {
+            if let Expr::Column(name) = expr {
+                columns.push(name.as_str());
+            }
+        });
+        columns
+    }
+
+    pub fn references(&self, column: &str) -> bool {
+        self.columns()
+            .iter()
+            .any(|c| c.eq_ignore_ascii_case(column))
+    }
+
+    fn visit<'a>(&'a self, f: &mut impl FnMut(&'a Expr)) {
+        f(self);
+        match self {
+            Expr::Literal(_) | Expr::Column(_) => {}
+            Expr::Not(expr) | Expr::Negate(expr) | Expr::IsNull { expr, .. } => expr.visit(f),
+            Expr::Binary { left, right, .. } => {
+                left.visit(f);
+                right.visit(f);
+            }
+            Expr::InList { expr, list, .. } => {
+                expr.visit(f);
+                list.iter().for_each(|e| e.visit(f));
+            }
+            Expr::Function { args, .. } => args.iter().for_each(|e| e.visit(f)),
+        }
+    }
+
+    /// The same expression with every reference to `column` renamed.
+    pub fn rename_column(&self, column: &str, new_name: &str) -> Expr {
+        let rename = |e: &Expr| Box::new(e.rename_column(column, new_name));
+        match self {
+            Expr::Column(name) if name.eq_ignore_ascii_case(column) => {
+                Expr::Column(new_name.to_string())
+            }
+            Expr::Literal(_) | Expr::Column(_) => self.clone(),
+            Expr::Not(expr) => Expr::Not(rename(expr)),
+            Expr::Negate(expr) => Expr::Negate(rename(expr)),
+            Expr::IsNull { expr, negated } => Expr::IsNull {
+                expr: rename(expr),
+                negated: *negated,
+            },
+            Expr::Binary { left, op, right } => Expr::Binary {
+                left: rename(left),
+                op: *op,
+                right: rename(right),
+            },
+            Expr::InList {
+                expr,
+                list,
+                negated,
+            } => Expr::InList {
+                expr: rename(expr),
+                list: list
+                    .iter()
+                    .map(|e| e.rename_column(column, new_name))
+                    .collect(),
+                negated: *negated,
+            },
+            Expr::Function { name, args } => Expr::Function {
+                name: name.clone(),
+                args: args
+                    .iter()
+                    .map(|e| e.rename_column(column, new_name))
+                    .collect(),
+            },
+        }
+    }
+
+    /// Evaluates the expression over a row of `schema`.
+    pub fn eval(&self, schema: &Schema, values: &[Value]) -> std::result::Result<Value, String> {
+        self.eval_with(schema, values, &NoFunctions)
+    }
+
+    /// Evaluates the expression, calling database functions such as `nextval` through
+    /// `functions`.
+    pub fn eval_with(
+        &self,
+        schema: &Schema,
+        values: &[Value],
+        functions: &dyn Functions,
+    ) -> std::result::Result<Value, String> {
+        let value = match self {
+            Expr::Literal(value) => value.clone(),
+            Expr::Column(name) => {
+                let idx = schema
+                    .get_col_idx(name)
+                    .ok_or_else(|| format!("column {} does not exist", name))?;
+                values.get(idx).cloned().unwrap_or(Value::Null)
+            }
+            Expr::Not(expr) => match expr.eval_with(schema, values, functions)? {
+                Value::Null => Value::Null,
+                Value::Boolean(b) => Value::Boolean(!b),
+                other => return Err(format!("argument of NOT must be boolean, not {}", other)),
+            },
+            Expr::Negate(expr) => match expr.eval_with(schema, values, functions)? {
+                Value::Null => Value::Null,
+                Value::Decimal(v) => Value::Decimal(-v),
+                other => Value::BigInt(
+                    other
+                        .as_i64()
+                        .and_then(i64::checked_neg)
+                        .ok_or_else(|| format!("cannot negate {}", other))?,
+                ),
+            },
+            Expr::IsNull { expr, negated } => {
+                Value::Boolean(expr.eval_with(schema, values, functions)?.is_null() != *negated)
+            }
+            Expr::Binary { left, op, right } => {
+                let left = left.eval_with(schema, values, functions)?;
+                // short-circuit as far as three-valued logic allows
+                match (op, &left) {
+                    (BinaryOp::And, Value::Boolean(false)) => return Ok(left),
+                    (BinaryOp::Or, Value::Boolean(true)) => return Ok(left),
+                    _ => {}
+                }
+                binary(*op, left, right.eval_with(schema, values, functions)?)?
+            }
+            Expr::InList {
+                expr,
+                list,
+                negated,
+            } => {
+                let value = expr.eval_with(schema, values, functions)?;
+                let mut result = Value::Boolean(false);
+                for item in list {
+                    match binary(
+                        BinaryOp::Eq,
+                        value.clone(),
+                        item.eval_with(schema, values, functions)?,
+                    )? {
+                        Value::Boolean(true) => {
+                            result = Value::Boolean(true);
+                            break;
+                        }
+                        Value::Null => result = Value::Null,
+                        _ => {}
+                    }
+                }
+                match (result, negated) {
+                    (Value::Boolean(b), true) => Value::Boolean(!b),
+                    (result, _) => result,
+                }
+            }
+            Expr::Function { name, args } => {
+                let args = args
+                    .iter()
+                    .map(|arg| arg.eval_with(schema, values, functions))
+                    .collect::<std::result::Result<Vec<Value>, String>>()?;
+                match functions.call(name, &args) {
+                    Some(value) => value?,
+                    None => call(name, args)?,
+                }
+            }
+        };
+        Ok(value)
+    }
+
+    /// Evaluates an expression that reads no columns, such as a DEFAULT.
+    pub fn eval_constant(&self) -> std::result::Result<Value, String> {
+        self.eval_constant_with(&NoFunctions)
+    }
+
+    pub fn eval_constant_with(
+        &self,
+        functions: &dyn Functions,
+    ) -> std::result::Result<Value, String> {
+        if let Some(column) = self.columns().first() {
+            return Err(format!(
+                "cannot use column {} in a constant expression",
+                column
+            ));
+        }
+        self.eval_with(&Schema::default(), &[], functions)
+    }
+
+    /// Whether the expression calls a database function, whose value is only known when the
+    /// statement runs.
+    pub fn calls_database(&self) -> bool {
+        let mut calls = false;
+        self.visit(&mut |expr| {
+            if let Expr::Function { name, .. } = expr {
+                calls |= DATABASE_FUNCTIONS.contains(&name.as_str());
+            }
+        });
+        calls
+    }
+}
+
+fn binary(op: BinaryOp, left: Value, right: Value) -> std::result::Result<Value, String> {
+    let as_bool = |value: &Value| match value {
+        Value::Null => Ok(None),
+        Value::Boolean(b) => Ok(Some(*b)),
+        other => Err(format!(
+            "argument of {} must be boolean, not {}",
+            op.symbol(),
+            other
+        )),
+    };
+    if let BinaryOp::And | BinaryOp::Or = op {
+        let value = match (op, as_bool(&left)?, as_bool(&right)?) {
+            (BinaryOp::And, Some(false), _) | (BinaryOp::And, _, Some(false)) => Some(false),
+            (BinaryOp::And, Some(true), Some(true)) => Some(true),
+            (BinaryOp::Or, Some(true), _) | (BinaryOp::Or, _, Some(true)) => Some(true),
+            (BinaryOp::Or, Some(false), Some(false)) => Some(false),
+            _ => None,
+        };
+        return Ok(value.map_or(Value::Null, Value::Boolean));
+    }
+    if left.is_null() || right.is_null() {
+        return Ok(Value::Null);
+    }
+    let mismatch = || {
+        format!(
+            "operator {} cannot be applied to {} and {}",
+            op.symbol(),
+            left,
+            right
+        )
+    };
+    let value = match op {
+        BinaryOp::Eq
+        | BinaryOp::NotEq
+        | BinaryOp::Lt
+        | BinaryOp::LtEq
+        | BinaryOp::Gt
+        | BinaryOp::GtEq => {
+            let ordering = left.compare(&right).ok_or_else(mismatch)?;
+            Value::Boolean(match op {
+                BinaryOp::Eq => ordering == Ordering::Equal,
+                BinaryOp::NotEq => ordering != Ordering::Equal,
+                BinaryOp::Lt => ordering == Ordering::Less,
+                BinaryOp::LtEq => ordering != Ordering::Greater,
+                BinaryOp::Gt => ordering == Ordering::Greater,
+                _ => ordering != Ordering::Less,
+            })
+        }
+        BinaryOp::Concat => {
+            let text = |value: &Value| match value {
+                Value::Varchar(s) => s.clone(),
+                other => other.to_string(),
+            };
+            Value::Varchar(text(&left) + &text(&right))
+        }
+        _ => arithmetic(op, &left, &right).ok_or_else(mismatch)??,
+    };
+    Ok(value)
+}
+
+// Integral operands stay integral, anything else numeric is computed as a decimal.
+fn arithmetic(
+    op: BinaryOp,
+    left: &Value,
+    right: &Value,
+) -> Option<std::result::Result<Value, String>> {
+    if let (Value::Boolean(_), _) | (_, Value::Boolean(_)) = (left, right) {
+        return None;
+    }
+    let integral = |v: &Value| !matches!(v, Value::Decimal(_));
+    if integral(left) && integral(right) {
+        let (l, r) = (left.as_i64()?, right.as_i64()?);
+        if matches!(op, BinaryOp::Divide | BinaryOp::Modulo) && r == 0 {
+            return Some(Err("division by zero".to_string()));
+        }
+        let value = match op {
+            BinaryOp::Plus => l.checked_add(r),
+            BinaryOp::Minus => l.checked_sub(r),
+            BinaryOp::Multiply => l.checked_mul(r),
+            BinaryOp::Divide => l.checked_div(r),
+            _ => l.checked_rem(r),
+        };
+        return Some(
+            value
+                .map(Value::BigInt)
+                .ok_or("integer out of range".to_string()),
+        );
+    }
+    let (l, r) = (left.as_f64()?, right.as_f64()?);
+    if matches!(op, BinaryOp::Divide | BinaryOp::Modulo) && r == 0.0 {
+        return Some(Err("division by zero".to_string()));
+    }
+    let value = match op {
+        BinaryOp::Plus => l + r,
+        BinaryOp::Minus => l - r,
+        BinaryOp::Multiply => l * r,
+        BinaryOp::Divide => l / r,
+        _ => l % r,
+    };
+    Some(Ok(Value::Decimal(value)))
+}
+
+// Functions `call` knows and those the database provides, an expression using any other does
+// not parse.
+const FUNCTIONS: [&str; 7] = [
+    "now",
+    "current_timestamp",
+    "coalesce",
+    "lower",
+    "upper",
+    "length",
+    "abs",
+];
+const DATABASE_FUNCTIONS: [&str; 3] = ["nextval", "currval", "setval"];
+
+/// Functions that need the database, such as `nextval('seq')`. Returning `None` leaves the
+/// call to the built-in functions.
+pub trait Functions {
+    fn call(&self, name: &str, args: &[Value]) -> Option<std::result::Result<Value, String>>;
+}
+
+/// For expressions evaluated outside of a statement, e.g. CHECK constraints.
+pub struct NoFunctions;
+
+impl Functions for NoFunctions {
+    fn call(&self, _name: &str, _args: &[Value]) -> Option<std::result::Result<Value, String>> {
+        None
+    }
+}
+
+fn call(name: &str, args: Vec<Value>) -> std::result::Result<Value, String> {
+    let arity = |n: usize| {
+        if args.len() == n {
+            Ok(())
+        } else {
+            Err(format!("{} takes {} arguments", name, n))
+        }
+    };
+    let value = match name.to_lowercase().as_str() {
+        "now" | "current_timestamp" => {
+            arity(0)?;
+            let micros = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_err(|e| e.to_string())?
+                .as_micros();
+            Value::Timestamp(micros as i64)
+        }
+        "coalesce" => args
+            .into_iter()
+            .find(|v| !v.is_null())
+            .unwrap_or(Value::Null),
+        function @ ("lower" | "upper" | "length") => {
+            arity(1)?;
+            match &args[0] {
+                Value::Null => Value::Null,
+                Value::Varchar(s) if function == "lower" => Value::Varchar(s.to_lowercase()),
+                Value::Varchar(s) if function == "upper" => Value::Varchar(s.to_uppercase()),
+                Value::Varchar(s) => Value::BigInt(s.chars().count() as i64),
+                other => return Err(format!("{} expects text, not {}", name, other)),
+            }
+        }
+        "abs" => {
+            arity(1)?;
+            match &args[0] {
+                Value::Null => Value::Null,
+                Value::Decimal(v) => Value::Decimal(v.abs()),
+                other => Value::BigInt(
+                    other
+                        .as_i64()
+                        .and_then(i64::checked_abs)
+                        .ok_or_else(|| format!("abs expects a number, not {}", other))?,
+                ),
+            }
+        }
+        name if DATABASE_FUNCTIONS.contains(&name) => {
+            return Err(format!("function {} cannot be used here", name))
+        }
+        _ => return Err(format!("function {} does not exist", name)),
+    };
+    Ok(value)
+}
+
+fn is_plain_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !KEYWORDS.iter().any(|k| k.eq_ignore_ascii_case(name))
+}
+
+impl Display for Expr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        let list = |exprs: &[Expr]| {
+            exprs
+                .iter()
+                .map(|e| e.to_string())
+                .collect::<Vec<String>>()
+                .join(", ")
+        };
+        match self {
+            Expr::Literal(Value::Null) => write!(f, "NULL"),
+            Expr::Literal(Value::Boolean(b)) => write!(f, "{}", if *b { "TRUE" } else { "FALSE" }),
+            Expr::Literal(Value::Varchar(s)) => write!(f, "'{}'", s.replace('\'', "''")),
+            Expr::Literal(Value::Decimal(v)) => write!(f, "{:?}", v),
+            Expr::Literal(value) => write!(f, "{}", value),
+            Expr::Column(name) if is_plain_identifier(name) => write!(f, "{}", name),
+            Expr::Column(name) => write!(f, "\"{}\"", name.replace('"', "\"\"")),
+            Expr::Not(expr) => write!(f, "(NOT {})", expr),
+            Expr::Negate(expr) => write!(f, "(- {})", expr),
+            Expr::Binary { left, op, right } => write!(f, "({} {} {})", left, op.symbol(), right),
+            Expr::IsNull { expr, negated } => {
+                write!(
+                    f,
+                    "({} IS {}NULL)",
+                    expr,
+                    if *negated { "NOT " } else { "" }
+                )
+            }
+            Expr::InList {
+                expr,
+                list: items,
+                negated,
+            } => write!(
+                f,
+                "({} {}IN ({}))",
+                expr,
+                if *negated { "NOT " } else { "" },
+                list(items)
+            ),
+            Expr::Function { name, args } => write!(f, "{}({})", name, list(args)),
+        }
+    }
+}
+
+const KEYWORDS: [&str; 9] = [
+    "AND",
+    "OR",
+    "NOT",
+    "IS",
+    "IN",
+    "NULL",
+    "TRUE",
+    "FALSE",
+    "CURRENT_TIMESTAMP",
+];
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Identifier(String),
+    // a name in double quotes, never a keyword
+    QuotedIdentifier(String),
+    Number(String),
+    String(String),
+    Symbol(&'static str),
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Token::Identifier(s) | Token::Number(s) => write!(f, "{}", s),
+            Token::QuotedIdentifier(s) => write!(f, "\"{}\"", s),
+            Token::String(s) => write!(f, "'{}'", s),
+            Token::Symbol(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+const SYMBOLS: [&str; 16] = [
+    "<>", "!=", "<=", ">=", "||", "=", "<", ">", "+", "-", "*", "/", "%", "(", ")", ",",
+];
+
+fn tokenize(text: &str) -> std::result::Result<Vec<Token>, String> {
+    let chars = text.chars().collect::<Vec<char>>();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit()
+            || (c == '.' && chars.get(i + 1).is_some_and(|c| c.is_ascii_digit()))
+        {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            tokens.push(Token::Number(chars[start..i].iter().collect()));
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Identifier(chars[start..i].iter().collect()));
+        } else if c == '\'' || c == '"' {
+            // quotes are escaped by doubling them
+            let mut s = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => return Err(format!("unterminated {} in expression", c)),
+                    Some(&q) if q == c && chars.get(i + 1) == Some(&c) => {
+                        s.push(c);
+                        i += 2;
+                    }
+                    Some(&q) if q == c => {
+                        i += 1;
+                        break;
+                    }
+                    Some(&other) => {
+                        s.push(other);
+                        i += 1;
+                    }
+                }
+            }
+            tokens.push(match c {
+                '\'' => Token::String(s),
+                _ => Token::QuotedIdentifier(s),
+            });
+        } else {
+            let rest = chars[i..].iter().take(2).collect::<String>();
+            let symbol = SYMBOLS
+                .iter()
+                .find(|s| rest.starts_with(**s))
+                .ok_or_else(|| format!("unexpected {} in expression", c))?;
+            tokens.push(Token::Symbol(symbol));
+            i += symbol.len();
+        }
+    }
+    Ok(tokens)
+}
+
+// A precedence climbing parser over the tokens of one expression.
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> std::result::Result<Token, String> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or("unexpected end of expression")?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Identifier(s)) if s.eq_ignore_ascii_case(keyword))
+    }
+
+    fn accept_keyword(&mut self, keyword: &str) -> bool {
+        let accepted = self.peek_keyword(keyword);
+        if accepted {
+            self.pos += 1;
+        }
+        accepted
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> std::result::Result<(), String> {
+        match self.accept_keyword(keyword) {
+            true => Ok(()),
+            false => Err(format!("expected {} in expression", keyword)),
+        }
+    }
+
+    fn accept(&mut self, symbol: &str) -> bool {
+        let accepted = matches!(self.peek(), Some(Token::Symbol(s)) if *s == symbol);
+        if accepted {
+            self.pos += 1;
+        }
+        accepted
+    }
+
+    fn expect(&mut self, symbol: &str) -> std::result::Result<(), String> {
+        match self.accept(symbol) {
+            true => Ok(()),
+            false => Err(format!("expected {} in expression", symbol)),
+        }
+    }
+
+    fn binary_op(&self) -> Option<BinaryOp> {
+        let op = match self.peek()? {
+            Token::Symbol("=") => BinaryOp::Eq,
+            Token::Symbol("<>" | "!=") => BinaryOp::NotEq,
+            Token::Symbol("<") => BinaryOp::Lt,
+            Token::Symbol("<=") => BinaryOp::LtEq,
+            Token::Symbol(">") => BinaryOp::Gt,
+            Token::Symbol(">=") => BinaryOp::GtEq,
+            Token::Symbol("+") => BinaryOp::Plus,
+            Token::Symbol("-") => BinaryOp::Minus,
+            Token::Symbol("*") => BinaryOp::Multiply,
+            Token::Symbol("/") => BinaryOp::Divide,
+            Token::Symbol("%") => BinaryOp::Modulo,
+            Token::Symbol("||") => BinaryOp::Concat,
+            Token::Identifier(s) if s.eq_ignore_ascii_case("AND") => BinaryOp::And,
+            Token::Identifier(s) if s.eq_ignore_ascii_case("OR") => BinaryOp::Or,
+            _ => return None,
+        };
+        Some(op)
+    }
+
+    // An expression of operators binding tighter than `min_precedence`.
+    fn expr(&mut self, min_precedence: u8) -> std::result::Result<Expr, String> {
+        let mut left = self.prefix()?;
+        loop {
+            if IS_IN_PRECEDENCE > min_precedence && self.accept_keyword("IS") {
+                let negated = self.accept_keyword("NOT");
+                self.expect_keyword("NULL")?;
+                left = Expr::IsNull {
+                    expr: Box::new(left),
+                    negated,
+                };
+                continue;
+            }
+            let negated_in = self.peek_keyword("NOT")
+                && matches!(self.tokens.get(self.pos + 1), Some(Token::Identifier(s)) if s.eq_ignore_ascii_case("IN"));
+            if IS_IN_PRECEDENCE > min_precedence && (negated_in || self.peek_keyword("IN")) {
+                self.pos += if negated_in { 2 } else { 1 };
+                self.expect("(")?;
+                let list = self.list()?;
+                left = Expr::InList {
+                    expr: Box::new(left),
+                    list,
+                    negated: negated_in,
+                };
+                continue;
+            }
+            let Some(op) = self
+                .binary_op()
+                .filter(|op| op.precedence() > min_precedence)
+            else {
+                return Ok(left);
+            };
+            self.pos += 1;
+            let right = self.expr(op.precedence())?;
+            left = Expr::Binary {
+                left: Box::new(left),
+                op,
+                right: Box::new(right),
+            };
+        }
+    }
+
+    // Comma separated expressions up to the closing parenthesis.
+    fn list(&mut self) -> std::result::Result<Vec<Expr>, String> {
+        let mut list = vec![];
+        if self.accept(")") {
+            return Ok(list);
+        }
+        loop {
+            list.push(self.expr(0)?);
+            if self.accept(")") {
+                return Ok(list);
+            }
+            self.expect(",")?;
+        }
+    }
+
+    fn prefix(&mut self) -> std::result::Result<Expr, String> {
+        let expr = match self.next()? {
+            Token::Symbol("(") => {
+                let expr = self.expr(0)?;
+                self.expect(")")?;
+                expr
+            }
+            Token::Symbol("-") => Expr::Negate(Box::new(self.expr(NEGATE_PRECEDENCE)?)),
+            Token::Number(n) if n.contains('.') => Expr::Literal(Value::Decimal(
+                n.parse().map_err(|_| format!("invalid number {}", n))?,
+            )),
+            Token::Number(n) => Expr::Literal(Value::BigInt(
+                n.parse().map_err(|_| format!("invalid number {}", n))?,
+            )),
+            Token::String(s) => Expr::Literal(Value::Varchar(s)),
+            Token::QuotedIdentifier(name) => Expr::Column(name),
+            Token::Identifier(word) => match word.to_uppercase().as_str() {
+                "NULL" => Expr::Literal(Value::Null),
+                "TRUE" => Expr::Literal(Value::Boolean(true)),
+                "FALSE" => Expr::Literal(Value::Boolean(false)),
+                "NOT" => Expr::Not(Box::new(self.expr(PREFIX_NOT_PRECEDENCE)?)),
+                "CURRENT_TIMESTAMP" => Expr::Function {
+                    name: "now".to_string(),
+                    args: vec![],
+                },
+                _ if self.accept("(") => {
+                    let name = word.to_lowercase();
+                    if !FUNCTIONS.contains(&name.as_str())
+                        && !DATABASE_FUNCTIONS.contains(&name.as_str())
+                    {
+                        return Err(format!("function {} does not exist", name));
+                    }
+                    Expr::Function {
+                        name,
+                        args: self.list()?,
+                    }
+                }
+                _ => Expr::Column(word),
+            },
+            token => return Err(format!("unexpected {} in expression", token)),
+        };
+        Ok(expr)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::query_types::get_demo_schema;
+
+    #[test]
+    fn test_parse_and_display_round_trip() {
+        for text in [
+            "salary * 2 + 1 > 1000 AND age IS NOT NULL",
+            "NOT name = 'o''brien' OR lastname NOT IN ('a', 'b')",
+            "-age % 7 <> 0.5 || 'x'",
+            "lower(\"name\") IN ('ada') AND now() > 0",
+        ] {
+            let expr = Expr::parse(text).unwrap();
+            assert_eq!(Expr::parse(&expr.to_string()).unwrap(), expr);
+        }
+        assert_eq!(
+            Expr::parse("a + b * c").unwrap().to_string(),
+            "(a + (b * c))"
+        );
+        assert_eq!(
+            Expr::parse("NOT a = 1 AND b = 2").unwrap().to_string(),
+            "((NOT (a = 1)) AND (b = 2))"
+        );
+        assert!(Expr::parse("a +").is_err());
+        assert!(Expr::parse("(a").is_err());
+        assert!(Expr::parse("a b").is_err());
+        assert_eq!(
+            Expr::parse("check (a > 1)"),
+            Err("function check does not exist".to_string())
+        );
+    }
+
+    #[test]
+    fn test_eval_uses_three_valued_logic() {
+        let schema = get_demo_schema();
+        let row = vec![
+            Value::Varchar("ada".to_string()),
+            Value::Null,
+            Value::Varchar("1 main st".to_string()),
+            Value::BigInt(600),
+            Value::SmallInt(36),
+        ];
+        let eval = |text: &str| Expr::parse(text).unwrap().eval(&schema, &row);
+        assert_eq!(eval("salary * 2 > 1000"), Ok(Value::Boolean(true)));
+        assert_eq!(eval("age / 5"), Ok(Value::BigInt(7)));
+        assert_eq!(eval("age / 8.0"), Ok(Value::Decimal(4.5)));
+        assert_eq!(eval("lastname = 'x'"), Ok(Value::Null));
+        assert_eq!(eval("lastname = 'x' OR age > 18"), Ok(Value::Boolean(true)));
+        assert_eq!(eval("lastname = 'x' AND age > 18"), Ok(Value::Null));
+        assert_eq!(
+            eval("lastname = 'x' AND age < 18"),
+            Ok(Value::Boolean(false))
+        );
+        assert_eq!(eval("lastname IS NULL"), Ok(Value::Boolean(true)));
+        assert_eq!(
+            eval("upper(name) IN ('ALAN', 'ADA')"),
+            Ok(Value::Boolean(true))
+        );
+        assert_eq!(
+            eval("coalesce(lastname, name)"),
+            Ok(Value::Varchar("ada".to_string()))
+        );
+        assert!(eval("age / 0").is_err());
+        assert!(eval("name > 1").is_err());
+        assert!(eval("missing = 1").is_err());
+        assert!(Expr::parse("age + 1").unwrap().eval_constant().is_err());
+        assert_eq!(
+            Expr::parse("40 + 2").unwrap().eval_constant(),
+            Ok(Value::BigInt(42))
+        );
+    }
+}
@@ -0,0 +1,758 @@
+use crate::bufferpoolmanager::BufferPoolManager;
+use crate::frameheader::PageData;
+use crate::index::Index;
+use common::types::PageId;
+use std::fmt::{self, Debug, Formatter};
+use std::sync::Arc;
+use storage_engine::disk_manager::PAGE_SIZE;
+
+// Disk-based extendible hashing, every page goes through the buffer pool:
+//
+//   header page      [max_depth u32][bucket_max_size u32][directory page id u32; 1 << max_depth]
+//   directory page   [max_depth u32][global_depth u32][local depth u8; 1 << max_depth]
+//                    [bucket page id u32; 1 << max_depth]
+//   bucket page      [count u16][overflow page id u32]([key_len u16][key][page_id u64][offset u64])*
+//
+// The header picks a directory with the top bits of the 32 bit hash, a directory picks a bucket
+// with the low `global_depth` bits. A full bucket is split on the next bit of its local depth,
+// doubling the directory when the local depth catches up with the global depth. An empty bucket
+// is merged into its split image and the directory shrinks again once no bucket needs its top
+// bit. Non-unique indexes keep one entry per (key, location), all of them in the key's bucket.
+// Entries that cannot be told apart by splitting, e.g. many rows with the same key, go to a chain
+// of overflow pages behind the bucket page; the chain is only reached through the bucket page.
+//
+// Writers hold the header latch for the whole operation, readers crab down from the header.
+
+const HEADER_MAX_DEPTH: u32 = 9;
+const DIRECTORY_MAX_DEPTH: u32 = 9;
+const INVALID_PAGE_ID: u32 = u32::MAX;
+pub const DEFAULT_BUCKET_MAX_SIZE: usize = 256;
+
+const DIRECTORY_LOCAL_DEPTHS: usize = 8;
+const DIRECTORY_BUCKET_IDS: usize = DIRECTORY_LOCAL_DEPTHS + (1 << DIRECTORY_MAX_DEPTH);
+const BUCKET_HEADER: usize = 2 + 4;
+const ENTRY_OVERHEAD: usize = 2 + 8 + 8;
+
+type Location = (u64, usize);
+type Entry = (Vec<u8>, Location);
+
+fn read_u32(data: &PageData, at: usize) -> u32 {
+    u32::from_be_bytes(data[at..at + 4].try_into().unwrap())
+}
+
+fn write_u32(data: &mut PageData, at: usize, v: u32) {
+    data[at..at + 4].copy_from_slice(&v.to_be_bytes());
+}
+
+// FNV-1a, stable across runs since the hashes end up on disk.
+fn hash_key(key: &[u8]) -> u32 {
+    key.iter().fold(0x811c9dc5u32, |h, b| {
+        (h ^ *b as u32).wrapping_mul(0x01000193)
+    })
+}
+
+struct HashHeader {
+    max_depth: u32,
+    bucket_max_size: u32,
+    directory_page_ids: Vec<u32>,
+}
+
+impl HashHeader {
+    fn from_page(data: &PageData) -> Self {
+        let max_depth = read_u32(data, 0);
+        Self {
+            max_depth,
+            bucket_max_size: read_u32(data, 4),
+            directory_page_ids: (0..1usize << max_depth)
+                .map(|i| read_u32(data, 8 + 4 * i))
+                .collect(),
+        }
+    }
+
+    fn write_to(&self, data: &mut PageData) {
+        write_u32(data, 0, self.max_depth);
+        write_u32(data, 4, self.bucket_max_size);
+        for (i, page_id) in self.directory_page_ids.iter().enumerate() {
+            write_u32(data, 8 + 4 * i, *page_id);
+        }
+    }
+
+    fn directory_index(&self, hash: u32) -> usize {
+        if self.max_depth == 0 {
+            return 0;
+        }
+        (hash >> (32 - self.max_depth)) as usize
+    }
+}
+
+struct HashDirectory {
+    max_depth: u32,
+    global_depth: u32,
+    local_depths: Vec<u8>,
+    bucket_page_ids: Vec<u32>,
+}
+
+impl HashDirectory {
+    fn new(bucket_page_id: PageId) -> Self {
+        let slots = 1usize << DIRECTORY_MAX_DEPTH;
+        let mut bucket_page_ids = vec![INVALID_PAGE_ID; slots];
+        bucket_page_ids[0] = bucket_page_id as u32;
+        Self {
+            max_depth: DIRECTORY_MAX_DEPTH,
+            global_depth: 0,
+            local_depths: vec![0; slots],
+            bucket_page_ids,
+        }
+    }
+
+    fn from_page(data: &PageData) -> Self {
+        let max_depth = read_u32(data, 0);
+        let slots = 1usize << max_depth;
+        Self {
+            max_depth,
+            global_depth: read_u32(data, 4),
+            local_depths: data[DIRECTORY_LOCAL_DEPTHS..DIRECTORY_LOCAL_DEPTHS + slots].to_vec(),
+            bucket_page_ids: (0..slots)
+                .map(|i| read_u32(data, DIRECTORY_BUCKET_IDS + 4 * i))
+                .collect(),
+        }
+    }
+
+    fn write_to(&self, data: &mut PageData) {
+        write_u32(data, 0, self.max_depth);
+        write_u32(data, 4, self.global_depth);
+        data[DIRECTORY_LOCAL_DEPTHS..DIRECTORY_LOCAL_DEPTHS + self.local_depths.len()]
+            .copy_from_slice(&self.local_depths);
+        for (i, page_id) in self.bucket_page_ids.iter().enumerate() {
+            write_u32(data, DIRECTORY_BUCKET_IDS + 4 * i, *page_id);
+        }
+    }
+
+    fn size(&self) -> usize {
+        1 << self.global_depth
+    }
+
+    fn bucket_index(&self, hash: u32) -> usize {
+        (hash as usize) & (self.size() - 1)
+    }
+
+    // Doubles the directory, the new upper half points at the same buckets as the lower half.
+    fn grow(&mut self) {
+        let size = self.size();
+        for i in 0..size {
+            self.local_depths[size + i] = self.local_depths[i];
+            self.bucket_page_ids[size + i] = self.bucket_page_ids[i];
+        }
+        self.global_depth += 1;
+    }
+
+    fn shrink(&mut self) {
+        while self.global_depth > 0
+            && self.local_depths[..self.size()]
+                .iter()
+                .all(|d| (*d as u32) < self.global_depth)
+        {
+            self.global_depth -= 1;
+        }
+    }
+
+    // Points every slot of `from` and `to` at `to` with the given local depth.
+    fn repoint(&mut self, from: u32, to: u32, local_depth: u8) {
+        for i in 0..self.size() {
+            if self.bucket_page_ids[i] == from || self.bucket_page_ids[i] == to {
+                self.bucket_page_ids[i] = to;
+                self.local_depths[i] = local_depth;
+            }
+        }
+    }
+}
+
+struct HashBucket {
+    overflow_page_id: u32,
+    entries: Vec<Entry>,
+}
+
+impl HashBucket {
+    fn from_page(data: &PageData) -> Self {
+        let count = u16::from_be_bytes([data[0], data[1]]) as usize;
+        let mut entries = Vec::with_capacity(count);
+        let mut at = BUCKET_HEADER;
+        for _ in 0..count {
+            let key_len = u16::from_be_bytes([data[at], data[at + 1]]) as usize;
+            let key = data[at + 2..at + 2 + key_len].to_vec();
+            at += 2 + key_len;
+            let page_id = u64::from_be_bytes(data[at..at + 8].try_into().unwrap());
+            let offset = u64::from_be_bytes(data[at + 8..at + 16].try_into().unwrap());
+            at += 16;
+            entries.push((key, (page_id, offset as usize)));
+        }
+        // stored off by one, so a zeroed page is an empty bucket without overflow
+        let overflow_page_id = match read_u32(data, 2) {
+            0 => INVALID_PAGE_ID,
+            page_id => page_id - 1,
+        };
+        Self {
+            overflow_page_id,
+            entries,
+        }
+    }
+
+    fn write_to(&self, data: &mut PageData) {
+        data[0..2].copy_from_slice(&(self.entries.len() as u16).to_be_bytes());
+        write_u32(data, 2, self.overflow_page_id.wrapping_add(1));
+        let mut at = BUCKET_HEADER;
+        for (key, (page_id, offset)) in self.entries.iter() {
+            data[at..at + 2].copy_from_slice(&(key.len() as u16).to_be_bytes());
+            data[at + 2..at + 2 + key.len()].copy_from_slice(key);
+            at += 2 + key.len();
+            data[at..at + 8].copy_from_slice(&page_id.to_be_bytes());
+            data[at + 8..at + 16].copy_from_slice(&(*offset as u64).to_be_bytes());
+            at += 16;
+        }
+    }
+}
+
+// Splits the entries into page sized chunks of at most `max_size` entries, at least one chunk.
+fn pack(entries: Vec<Entry>, max_size: usize) -> Vec<Vec<Entry>> {
+    let mut chunks = vec![vec![]];
+    let mut bytes = BUCKET_HEADER;
+    for entry in entries {
+        let size = entry.0.len() + ENTRY_OVERHEAD;
+        let chunk = chunks.last().unwrap();
+        if chunk.len() == max_size || bytes + size > PAGE_SIZE {
+            chunks.push(vec![]);
+            bytes = BUCKET_HEADER;
+        }
+        bytes += size;
+        chunks.last_mut().unwrap().push(entry);
+    }
+    chunks
+}
+
+fn locations(entries: &[Entry], key: &[u8]) -> Vec<Location> {
+    let mut locations = entries
+        .iter()
+        .filter(|(k, _)| k == key)
+        .map(|(_, loc)| *loc)
+        .collect::<Vec<Location>>();
+    locations.sort();
+    locations
+}
+
+pub struct ExtendibleHashIndex {
+    bpm: Arc<BufferPoolManager>,
+    header_page_id: PageId,
+    unique: bool,
+}
+
+impl Debug for ExtendibleHashIndex {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExtendibleHashIndex")
+            .field("header_page_id", &self.header_page_id)
+            .field("unique", &self.unique)
+            .finish()
+    }
+}
+
+impl ExtendibleHashIndex {
+    pub fn new(bpm: Arc<BufferPoolManager>, bucket_max_size: usize) -> Result<Self, String> {
+        Self::create(bpm, bucket_max_size, true)
+    }
+
+    // An index that keeps one entry per (key, location).
+    pub fn new_non_unique(
+        bpm: Arc<BufferPoolManager>,
+        bucket_max_size: usize,
+    ) -> Result<Self, String> {
+        Self::create(bpm, bucket_max_size, false)
+    }
+
+    fn create(
+        bpm: Arc<BufferPoolManager>,
+        bucket_max_size: usize,
+        unique: bool,
+    ) -> Result<Self, String> {
+        let header_page_id = bpm
+            .new_page()
+            .ok_or("no free frame for the hash index header")?;
+        HashHeader {
+            max_depth: HEADER_MAX_DEPTH,
+            bucket_max_size: bucket_max_size.clamp(1, u32::MAX as usize) as u32,
+            directory_page_ids: vec![INVALID_PAGE_ID; 1 << HEADER_MAX_DEPTH],
+        }
+        .write_to(&mut bpm.write_page(header_page_id));
+        Ok(Self {
+            bpm,
+            header_page_id,
+            unique,
+        })
+    }
+
+    // Reattaches to an index whose pages are already in the database file.
+    pub fn open(bpm: Arc<BufferPoolManager>, header_page_id: PageId, unique: bool) -> Self {
+        Self {
+            bpm,
+            header_page_id,
+            unique,
+        }
+    }
+
+    pub fn get_header_page_id(&self) -> PageId {
+        self.header_page_id
+    }
+
+    // Global depth of the directory the key hashes to, 0 if it does not exist yet.
+    pub fn global_depth(&self, key: &[u8]) -> u32 {
+        let header = HashHeader::from_page(&self.bpm.read_page(self.header_page_id));
+        match header.directory_page_ids[header.directory_index(hash_key(key))] {
+            INVALID_PAGE_ID => 0,
+            page_id => {
+                HashDirectory::from_page(&self.bpm.read_page(page_id as PageId)).global_depth
+            }
+        }
+    }
+
+    // Entries of the bucket whose page is latched by `head` and the overflow pages behind it.
+    fn read_chain(&self, head: &PageData) -> (Vec<u32>, Vec<Entry>) {
+        let bucket = HashBucket::from_page(head);
+        let mut overflow_page_ids = vec![];
+        let mut entries = bucket.entries;
+        let mut next = bucket.overflow_page_id;
+        while next != INVALID_PAGE_ID {
+            let overflow = HashBucket::from_page(&self.bpm.read_page(next as PageId));
+            overflow_page_ids.push(next);
+            entries.extend(overflow.entries);
+            next = overflow.overflow_page_id;
+        }
+        (overflow_page_ids, entries)
+    }
+
+    // Allocates `n` pages, or none at all if the pool runs out of frames.
+    fn new_pages(&self, n: usize) -> Option<Vec<u32>> {
+        let mut page_ids = vec![];
+        while page_ids.len() < n {
+            match self.bpm.new_page() {
+                Some(page_id) => page_ids.push(page_id as u32),
+                None => {
+                    for page_id in page_ids {
+                        self.bpm.delete_page(page_id as PageId);
+                    }
+                    return None;
+                }
+            }
+        }
+        Some(page_ids)
+    }
+
+    // Rewrites the whole bucket, growing or trimming its overflow chain as needed. The bucket
+    // is left as it was if the pages it needs cannot be allocated.
+    fn write_chain(
+        &self,
+        head: &mut PageData,
+        mut overflow_page_ids: Vec<u32>,
+        entries: Vec<Entry>,
+        max_size: usize,
+    ) -> bool {
+        let chunks = pack(entries, max_size);
+        let missing = (chunks.len() - 1).saturating_sub(overflow_page_ids.len());
+        let Some(page_ids) = self.new_pages(missing) else {
+            return false;
+        };
+        overflow_page_ids.extend(page_ids);
+        self.write_overflow(overflow_page_ids, chunks)
+            .write_to(head);
+        true
+    }
+
+    // Writes all but the first chunk of a bucket to the overflow pages behind its head, which
+    // are at least as many as it needs, and frees the pages left over. Returns the head for the
+    // caller to write, it need not have the head page pinned meanwhile.
+    fn write_overflow(
+        &self,
+        mut overflow_page_ids: Vec<u32>,
+        mut chunks: Vec<Vec<Entry>>,
+    ) -> HashBucket {
+        for page_id in overflow_page_ids.drain(chunks.len() - 1..) {
+            self.bpm.delete_page(page_id as PageId);
+        }
+        let head = chunks.remove(0);
+        for (i, entries) in chunks.into_iter().enumerate() {
+            HashBucket {
+                overflow_page_id: overflow_page_ids
+                    .get(i + 1)
+                    .copied()
+                    .unwrap_or(INVALID_PAGE_ID),
+                entries,
+            }
+            .write_to(&mut self.bpm.write_page(overflow_page_ids[i] as PageId));
+        }
+        HashBucket {
+            overflow_page_id: overflow_page_ids
+                .first()
+                .copied()
+                .unwrap_or(INVALID_PAGE_ID),
+            entries: head,
+        }
+    }
+
+    fn lookup(&self, key: &[u8]) -> Vec<Location> {
+        let hash = hash_key(key);
+        let header_guard = self.bpm.read_page(self.header_page_id);
+        let header = HashHeader::from_page(&header_guard);
+        let directory_page_id = header.directory_page_ids[header.directory_index(hash)];
+        if directory_page_id == INVALID_PAGE_ID {
+            return vec![];
+        }
+        let directory_guard = self.bpm.read_page(directory_page_id as PageId);
+        drop(header_guard);
+        let directory = HashDirectory::from_page(&directory_guard);
+        let bucket_page_id = directory.bucket_page_ids[directory.bucket_index(hash)];
+        let bucket_guard = self.bpm.read_page(bucket_page_id as PageId);
+        drop(directory_guard);
+        locations(&self.read_chain(&bucket_guard).1, key)
+    }
+
+    fn insert_entry(&self, key: Vec<u8>, location: Location) -> bool {
+        if BUCKET_HEADER + key.len() + ENTRY_OVERHEAD > PAGE_SIZE {
+            return false;
+        }
+        let hash = hash_key(&key);
+        let mut header_guard = self.bpm.write_page(self.header_page_id);
+        let mut header = HashHeader::from_page(&header_guard);
+        let max_size = header.bucket_max_size as usize;
+        let directory_idx = header.directory_index(hash);
+        if header.directory_page_ids[directory_idx] == INVALID_PAGE_ID {
+            let (Some(directory_page_id), Some(bucket_page_id)) =
+                (self.bpm.new_page(), self.bpm.new_page())
+            else {
+                return false;
+            };
+            HashDirectory::new(bucket_page_id)
+                .write_to(&mut self.bpm.write_page(directory_page_id));
+            header.directory_page_ids[directory_idx] = directory_page_id as u32;
+            header.write_to(&mut header_guard);
+        }
+        let mut directory_guard = self
+            .bpm
+            .write_page(header.directory_page_ids[directory_idx] as PageId);
+        let mut directory = HashDirectory::from_page(&directory_guard);
+        loop {
+            let bucket_idx = directory.bucket_index(hash);
+            let bucket_page_id = directory.bucket_page_ids[bucket_idx];
+            let mut bucket_guard = self.bpm.write_page(bucket_page_id as PageId);
+            let (overflow_page_ids, mut entries) = self.read_chain(&bucket_guard);
+            let existing = locations(&entries, &key);
+            if (self.unique && !existing.is_empty()) || existing.contains(&location) {
+                return false;
+            }
+            entries.push((key.clone(), location));
+            // splitting only helps if the entries differ in the bits a directory can look at
+            let local_depth = directory.local_depths[bucket_idx];
+            let mask = (1u32 << directory.max_depth) - 1;
+            let splittable = local_depth as u32 != directory.max_depth
+                && entries
+                    .iter()
+                    .any(|(k, _)| hash_key(k) & mask != hash & mask);
+            if pack(entries.clone(), max_size).len() == 1 || !splittable {
+                return self.write_chain(&mut bucket_guard, overflow_page_ids, entries, max_size);
+            }
+            entries.pop();
+
+            // split the bucket on the next bit of its local depth. Every page both halves need
+            // is allocated before either is rewritten, so running out of frames loses nothing.
+            let split_bit = 1u32 << local_depth;
+            let (moved, kept): (Vec<Entry>, Vec<Entry>) = entries
+                .into_iter()
+                .partition(|(k, _)| hash_key(k) & split_bit != 0);
+            let (moved, kept) = (pack(moved, max_size), pack(kept, max_size));
+            let missing = (kept.len() - 1).saturating_sub(overflow_page_ids.len());
+            // the image's head page and its overflow pages
+            let Some(mut page_ids) = self.new_pages(missing + moved.len()) else {
+                return false;
+            };
+            let image_page_ids = page_ids.split_off(missing);
+            let mut overflow_page_ids = overflow_page_ids;
+            overflow_page_ids.extend(page_ids);
+            let image_page_id = image_page_ids[0] as PageId;
+            if local_depth as u32 == directory.global_depth {
+                directory.grow();
+            }
+            for i in 0..directory.size() {
+                if directory.bucket_page_ids[i] == bucket_page_id {
+                    directory.local_depths[i] = local_depth + 1;
+                    if i as u32 & split_bit != 0 {
+                        directory.bucket_page_ids[i] = image_page_id as u32;
+                    }
+                }
+            }
+            self.write_overflow(overflow_page_ids, kept)
+                .write_to(&mut bucket_guard);
+            self.write_overflow(image_page_ids[1..].to_vec(), moved)
+                .write_to(&mut self.bpm.write_page(image_page_id));
+            directory.write_to(&mut directory_guard);
+        }
+    }
+
+    fn delete_entry(&self, key: &[u8], location: Location) -> bool {
+        let hash = hash_key(key);
+        let header_guard = self.bpm.write_page(self.header_page_id);
+        let header = HashHeader::from_page(&header_guard);
+        let directory_page_id = header.directory_page_ids[header.directory_index(hash)];
+        if directory_page_id == INVALID_PAGE_ID {
+            return false;
+        }
+        let mut directory_guard = self.bpm.write_page(directory_page_id as PageId);
+        let mut directory = HashDirectory::from_page(&directory_guard);
+        {
+            let mut bucket_guard = self
+                .bpm
+                .write_page(directory.bucket_page_ids[directory.bucket_index(hash)] as PageId);
+            let (overflow_page_ids, mut entries) = self.read_chain(&bucket_guard);
+            let Some(pos) = entries
+                .iter()
+                .position(|(k, loc)| k == key && *loc == location)
+            else {
+                return false;
+            };
+            entries.remove(pos);
+            self.write_chain(
+                &mut bucket_guard,
+                overflow_page_ids,
+                entries,
+                header.bucket_max_size as usize,
+            );
+        }
+
+        // merge the bucket with its split image while one of the two is empty
+        loop {
+            let bucket_idx = directory.bucket_index(hash);
+            let local_depth = directory.local_depths[bucket_idx];
+            if local_depth == 0 {
+                break;
+            }
+            let image_idx = bucket_idx ^ (1 << (local_depth - 1));
+            if directory.local_depths[image_idx] != local_depth {
+                break;
+            }
+            let bucket_page_id = directory.bucket_page_ids[bucket_idx];
+            let image_page_id = directory.bucket_page_ids[image_idx];
+            let (keep, free) = if self.bucket_is_empty(bucket_page_id) {
+                (image_page_id, bucket_page_id)
+            } else if self.bucket_is_empty(image_page_id) {
+                (bucket_page_id, image_page_id)
+            } else {
+                break;
+            };
+            directory.repoint(free, keep, local_depth - 1);
+            self.bpm.delete_page(free as PageId);
+        }
+        directory.shrink();
+        directory.write_to(&mut directory_guard);
+        true
+    }
+
+    // An empty bucket never keeps overflow pages, see `write_chain`.
+    fn bucket_is_empty(&self, page_id: u32) -> bool {
+        HashBucket::from_page(&self.bpm.read_page(page_id as PageId))
+            .entries
+            .is_empty()
+    }
+
+    // Hash order says nothing about key order, so a range is a scan over every bucket.
+    fn scan(&self, start: &[u8], end: &[u8]) -> Vec<Location> {
+        let header_guard = self.bpm.read_page(self.header_page_id);
+        let header = HashHeader::from_page(&header_guard);
+        let mut entries = vec![];
+        for directory_page_id in header.directory_page_ids.iter() {
+            if *directory_page_id == INVALID_PAGE_ID {
+                continue;
+            }
+            let directory =
+                HashDirectory::from_page(&self.bpm.read_page(*directory_page_id as PageId));
+            let mut bucket_page_ids = directory.bucket_page_ids[..directory.size()].to_vec();
+            bucket_page_ids.sort();
+            bucket_page_ids.dedup();
+            for bucket_page_id in bucket_page_ids {
+                let bucket_guard = self.bpm.read_page(bucket_page_id as PageId);
+                entries.extend(
+                    self.read_chain(&bucket_guard)
+                        .1
+                        .into_iter()
+                        .filter(|(k, _)| start <= k.as_slice() && k.as_slice() <= end),
+                );
+            }
+        }
+        entries.sort();
+        entries.into_iter().map(|(_, loc)| loc).collect()
+    }
+}
+
+impl Index for ExtendibleHashIndex {
+    fn insert(&self, key: Vec<u8>, page_id: u64, offset: usize) -> bool {
+        self.insert_entry(key, (page_id, offset))
+    }
+
+    fn delete(&self, key: &[u8], page_id: u64, offset: usize) -> bool {
+        self.delete_entry(key, (page_id, offset))
+    }
+
+    fn find(&self, key: &[u8]) -> Option<(u64, usize)> {
+        self.lookup(key).first().copied()
+    }
+
+    fn range_query(&self, start: &[u8], end: &[u8]) -> Vec<(u64, usize)> {
+        if start == end {
+            return self.lookup(start);
+        }
+        self.scan(start, end)
+    }
+
+    // The header, its directories, their buckets and overflow pages.
+    fn page_ids(&self) -> Vec<PageId> {
+        // readers latch the header first, holding it keeps writers out for the whole walk
+        let header_guard = self.bpm.read_page(self.header_page_id);
+        let header = HashHeader::from_page(&header_guard);
+        let mut page_ids = vec![self.header_page_id];
+        for &directory_page_id in header.directory_page_ids.iter() {
+            if directory_page_id == INVALID_PAGE_ID {
+                continue;
+            }
+            page_ids.push(directory_page_id as PageId);
+            let directory =
+                HashDirectory::from_page(&self.bpm.read_page(directory_page_id as PageId));
+            let mut buckets = directory.bucket_page_ids[..directory.size()].to_vec();
+            buckets.sort_unstable();
+            buckets.dedup();
+            for bucket_page_id in buckets {
+                let (overflow_page_ids, _) =
+                    self.read_chain(&self.bpm.read_page(bucket_page_id as PageId));
+                page_ids.push(bucket_page_id as PageId);
+                page_ids.extend(overflow_page_ids.into_iter().map(|p| p as PageId));
+            }
+        }
+        page_ids
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::index_key::encode_u64;
+
+    #[test]
+    fn test_insert_find_and_directory_growth() {
+        let bpm = Arc::new(BufferPoolManager::new(8, 2));
+        let index = ExtendibleHashIndex::new(Arc::clone(&bpm), 4).unwrap();
+        for i in 0..2000u64 {
+            assert!(index.insert(encode_u64(i), i, i as usize % 7));
+        }
+        assert!(!index.insert(encode_u64(42), 1, 1));
+        for i in 0..2000u64 {
+            assert_eq!(index.find(&encode_u64(i)), Some((i, i as usize % 7)));
+        }
+        assert_eq!(index.find(&encode_u64(5000)), None);
+        assert!((0..2000u64).any(|i| index.global_depth(&encode_u64(i)) > 0));
+        assert_eq!(
+            index.range_query(&encode_u64(10), &encode_u64(14)),
+            (10..15u64).map(|i| (i, i as usize % 7)).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_delete_merges_buckets_and_shrinks_directory() {
+        let bpm = Arc::new(BufferPoolManager::new(8, 2));
+        let index = ExtendibleHashIndex::new(Arc::clone(&bpm), 2).unwrap();
+        for i in 0..300u64 {
+            assert!(index.insert(encode_u64(i), i, 0));
+        }
+        assert!(!index.delete(&encode_u64(7), 8, 0));
+        for i in 0..300u64 {
+            assert!(index.delete(&encode_u64(i), i, 0), "{}", i);
+            assert_eq!(index.find(&encode_u64(i)), None);
+            if i < 299 {
+                assert_eq!(index.find(&encode_u64(299)), Some((299, 0)));
+            }
+        }
+        assert!((0..300u64).all(|i| index.global_depth(&encode_u64(i)) == 0));
+    }
+
+    #[test]
+    fn test_non_unique_entries() {
+        let bpm = Arc::new(BufferPoolManager::new(8, 2));
+        let index = ExtendibleHashIndex::new_non_unique(Arc::clone(&bpm), 3).unwrap();
+        // more rows per key than a bucket holds, they end up in overflow pages
+        for page_id in 0..10u64 {
+            assert!(index.insert(b"codd".to_vec(), page_id, 0));
+            assert!(index.insert(b"gray".to_vec(), page_id, 1));
+        }
+        assert!(!index.insert(b"codd".to_vec(), 3, 0));
+        assert_eq!(index.range_query(b"codd", b"codd").len(), 10);
+        assert!(index.delete(b"codd", 3, 0));
+        assert!(!index.delete(b"codd", 3, 1));
+        assert_eq!(
+            index.range_query(b"codd", b"codd"),
+            (0..10u64)
+                .filter(|p| *p != 3)
+                .map(|p| (p, 0))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(index.find(b"gray"), Some((0, 1)));
+        for page_id in 0..10u64 {
+            index.delete(b"codd", page_id, 0);
+            assert!(index.delete(b"gray", page_id, 1));
+        }
+        assert!(index.range_query(b"a", b"z").is_empty());
+        assert!(index.insert(b"codd".to_vec(), 3, 0));
+    }
+
+    #[test]
+    fn test_a_split_short_of_frames_loses_no_entries() {
+        // a key whose entries all move to the split image, and one in the same directory
+        // staying behind
+        let moving = (0..u64::MAX)
+            .map(encode_u64)
+            .find(|key| hash_key(key) & 1 == 1)
+            .unwrap();
+        let directory = hash_key(&moving) >> (32 - HEADER_MAX_DEPTH);
+        let staying = (0..u64::MAX)
+            .map(encode_u64)
+            .find(|key| {
+                hash_key(key) & 1 == 0 && hash_key(key) >> (32 - HEADER_MAX_DEPTH) == directory
+            })
+            .unwrap();
+        let bpm = Arc::new(BufferPoolManager::new(4, 2));
+        let index = ExtendibleHashIndex::new_non_unique(Arc::clone(&bpm), 1).unwrap();
+        // the same key cannot be split apart, its entries fill an overflow chain
+        for page_id in 0..3u64 {
+            assert!(index.insert(moving.clone(), page_id, 0));
+        }
+        // the header, directory and bucket being split stay pinned, which leaves one frame for
+        // allocating the image and its overflow pages one after the other
+        assert!(index.insert(staying.clone(), 9, 0));
+        assert_eq!(
+            index.range_query(&moving, &moving),
+            vec![(0, 0), (1, 0), (2, 0)]
+        );
+        assert_eq!(index.find(&staying), Some((9, 0)));
+        assert!(index.global_depth(&staying) > 0);
+    }
+
+    #[test]
+    fn test_concurrent_inserts_and_lookups() {
+        let bpm = Arc::new(BufferPoolManager::new(16, 2));
+        let index = Arc::new(ExtendibleHashIndex::new(Arc::clone(&bpm), 8).unwrap());
+        let handles = (0..4u64)
+            .map(|t| {
+                let index = Arc::clone(&index);
+                std::thread::spawn(move || {
+                    for i in (t * 500)..(t + 1) * 500 {
+                        assert!(index.insert(encode_u64(i), i, 0));
+                        assert_eq!(index.find(&encode_u64(i)), Some((i, 0)));
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        handles.into_iter().for_each(|h| h.join().unwrap());
+        assert!((0..2000u64).all(|i| index.find(&encode_u64(i)) == Some((i, 0))));
+    }
+}
@@ -1,108 +1,64 @@
-#[allow(unused)]
-use crate::query_types::TablePage;
-use std::sync::atomic::AtomicU64;
+use parking_lot::RwLock;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use storage_engine::disk_manager::PAGE_SIZE;
 
+pub type PageData = [u8; PAGE_SIZE];
+
+// One in-memory frame of the buffer pool. The pin count and dirty flag live outside the page
+// latch so the pool can inspect them while a guard holds the data.
 #[derive(Debug)]
-#[allow(dead_code)]
 pub struct FrameHeader {
-    is_dirty: bool,
-    pub data: [u8; 4096],
+    is_dirty: AtomicBool,
+    pub data: Arc<RwLock<PageData>>,
     pub frame_id: usize,
+    page_id: AtomicUsize,
     pin_count: AtomicU64,
 }
 
-impl PartialEq for FrameHeader {
-    fn eq(&self, other: &Self) -> bool {
-        self.data == other.data && self.frame_id == other.frame_id
+impl Default for FrameHeader {
+    fn default() -> Self {
+        Self::new(0)
     }
 }
 
-impl Eq for FrameHeader {}
-
-impl Default for FrameHeader {
-    fn default() -> Self {
+impl FrameHeader {
+    pub fn new(frame_id: usize) -> Self {
         Self {
-            is_dirty: false,
-            data: [0u8; 4096],
-            frame_id: 0,
+            is_dirty: AtomicBool::new(false),
+            data: Arc::new(RwLock::new([0u8; PAGE_SIZE])),
+            frame_id,
+            page_id: AtomicUsize::new(0),
             pin_count: AtomicU64::new(0),
         }
     }
-}
 
-// enum CliError {
-//     IoError(io::Error),
-//     ParseError(num::ParseIntError),
-// }
-
-// impl From<TablePage> for FrameHeader {
-//     fn from(table_page: TablePage) -> Self {
-//         Self {
-//             is_dirty: false,
-//             data: table_page.data,
-//             frame_id: (),
-//             pin_count: (),
-//         }
-//     }
-// }
+    // Page currently held by the frame, only meaningful while the frame is in the page table.
+    pub fn get_page_id(&self) -> usize {
+        self.page_id.load(Ordering::SeqCst)
+    }
 
-// impl FromIterator<u8> for FrameHeader {
-//     fn from_iter<T: IntoIterator<Item = u8>>(iter: T) -> Self {
-//         let data: Vec<u8> = iter.into_iter().collect();
-//         FrameHeader {
-//             is_dirty: false,
-//             data,
-//             frame_id: 0,
-//             pin_count: AtomicU64::new(0),
-//         }
-//     }
-// }
+    pub fn set_page_id(&self, page_id: usize) {
+        self.page_id.store(page_id, Ordering::SeqCst);
+    }
 
-// impl<'a> IntoIterator for &'a mut FrameHeader {
-//     type Item = &'a mut u8;
-//     type IntoIter = std::slice::IterMut<'a, u8>;
+    pub fn pin(&self) -> u64 {
+        self.pin_count.fetch_add(1, Ordering::SeqCst) + 1
+    }
 
-//     fn into_iter(self) -> Self::IntoIter {
-//         self.data.iter_mut()
-//     }
-// }
+    pub fn unpin(&self) -> u64 {
+        self.pin_count.fetch_sub(1, Ordering::SeqCst) - 1
+    }
 
-#[allow(dead_code)]
-impl FrameHeader {
-    fn new(frame_id: usize) -> Self {
-        Self {
-            is_dirty: false,
-            data: [0; 4096],
-            frame_id,
-            pin_count: AtomicU64::new(0),
-        }
+    pub fn get_pin_count(&self) -> u64 {
+        self.pin_count.load(Ordering::SeqCst)
     }
-    // TODO: handle the case of update and insert of the tuple record
-    pub fn write_data(&mut self, _frame_id: usize, data: Vec<u8>) -> Option<usize> {
-        let frame_id = _frame_id;
-        if frame_id + data.len() < self.data.len() {
-            for i in 0..data.len() {
-                self.data[frame_id + i] = data[i];
-            }
-            self.is_dirty = true;
-            self.pin_count
-                .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
-            Some(frame_id)
-        } else {
-            None
-        }
+
+    pub fn is_dirty(&self) -> bool {
+        self.is_dirty.load(Ordering::SeqCst)
     }
 
-    pub fn read_data(&mut self, frame_id: usize) -> Option<u8> {
-        let id = frame_id as u8;
-        let result = if self.data.contains(&id) {
-            self.is_dirty = true;
-            self.pin_count
-                .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
-            Some(self.data[frame_id])
-        } else {
-            None
-        };
-        result
+    pub fn set_dirty(&self, is_dirty: bool) {
+        self.is_dirty.store(is_dirty, Ordering::SeqCst);
     }
 }
@@ -0,0 +1,97 @@
+use common::types::PageId;
+use std::fmt::Debug;
+use std::ops::Bound;
+
+// Common interface of the index structures the catalog can build over a table. Keys are the
+// memcomparable encodings produced by `index_key::KeySchema`, values are (page_id, offset).
+//
+// A unique index maps each key to a single location. A non-unique index stores every entry
+// under its key followed by the location, so rows sharing a key become distinct entries ordered
+// by location. Encoded keys are prefix-free, which keeps the entries in key order.
+pub trait Index: Debug + Send + Sync {
+    // Inserts the entry unless it is already present, in which case the existing entry is kept
+    // and false is returned. For a unique index any entry with the same key counts as present.
+    // The check and the insert are atomic, unique constraints rely on it.
+    fn insert(&self, key: Vec<u8>, page_id: u64, offset: usize) -> bool;
+
+    // Removes the exact (key, location) entry, other rows with the same key are untouched.
+    fn delete(&self, key: &[u8], page_id: u64, offset: usize) -> bool;
+
+    // First location stored under the key.
+    fn find(&self, key: &[u8]) -> Option<(u64, usize)>;
+
+    // Every location whose key lies within the bounds, both bounds are inclusive.
+    fn range_query(&self, start: &[u8], end: &[u8]) -> Vec<(u64, usize)>;
+
+    // Whether the entries are kept in key order. Only ordered indexes have `range_entries` and
+    // `next_entry`, which key-range locks are taken on.
+    fn is_ordered(&self) -> bool {
+        false
+    }
+
+    // Every entry whose key lies within the bounds in key order, with the key it is stored under.
+    fn range_entries(
+        &self,
+        _start: Bound<&[u8]>,
+        _end: Bound<&[u8]>,
+    ) -> Vec<(Vec<u8>, (u64, usize))> {
+        vec![]
+    }
+
+    // The first entry stored after every entry under `key`, with the key it is stored under.
+    fn next_entry(&self, _key: &[u8]) -> Option<(Vec<u8>, (u64, usize))> {
+        None
+    }
+
+    // Pages the index keeps in the buffer pool, freed when the index is dropped. In-memory
+    // indexes have none.
+    fn page_ids(&self) -> Vec<PageId> {
+        vec![]
+    }
+}
+
+const LOCATION_SUFFIX_LEN: usize = 16;
+
+// Key under which a non-unique index stores the entry.
+pub(crate) fn entry_key(mut key: Vec<u8>, page_id: u64, offset: usize) -> Vec<u8> {
+    key.extend_from_slice(&page_id.to_be_bytes());
+    key.extend_from_slice(&(offset as u64).to_be_bytes());
+    key
+}
+
+// Inclusive bounds covering every location stored under the keys in [start, end] of a
+// non-unique index.
+pub(crate) fn entry_range(start: &[u8], end: &[u8]) -> (Vec<u8>, Vec<u8>) {
+    let mut start = start.to_vec();
+    start.extend_from_slice(&[0x00; LOCATION_SUFFIX_LEN]);
+    let mut end = end.to_vec();
+    end.extend_from_slice(&[0xFF; LOCATION_SUFFIX_LEN]);
+    (start, end)
+}
+
+// Bounds on the keys entries are stored under, for bounds on their keys. A non-unique index
+// stores the location after the key, see `entry_range`.
+pub(crate) fn stored_bounds(
+    unique: bool,
+    start: Bound<&[u8]>,
+    end: Bound<&[u8]>,
+) -> (Bound<Vec<u8>>, Bound<Vec<u8>>) {
+    let with_suffix = |key: &[u8], byte: u8| {
+        let mut key = key.to_vec();
+        if !unique {
+            key.extend_from_slice(&[byte; LOCATION_SUFFIX_LEN]);
+        }
+        key
+    };
+    let start = match start {
+        Bound::Included(key) => Bound::Included(with_suffix(key, 0x00)),
+        Bound::Excluded(key) => Bound::Excluded(with_suffix(key, 0xFF)),
+        Bound::Unbounded => Bound::Unbounded,
+    };
+    let end = match end {
+        Bound::Included(key) => Bound::Included(with_suffix(key, 0xFF)),
+        Bound::Excluded(key) => Bound::Excluded(with_suffix(key, 0x00)),
+        Bound::Unbounded => Bound::Unbounded,
+    };
+    (start, end)
+}
@@ -0,0 +1,339 @@
+use crate::query_types::{Schema, Tuple, TypeId, Value};
+
+// Index keys are encoded into a memcomparable form: comparing two encoded keys byte by byte
+// gives the same order as comparing the original values column by column. Each column is
+// encoded as:
+//
+//   [null flag: 0x00 = NULL, 0x01 = present][payload]
+//
+//   integers   big-endian with the sign bit flipped, at the width of the column type
+//   decimals   IEEE-754 bits, sign bit flipped for positives and all bits flipped for negatives;
+//              -0.0 is encoded as 0.0, NaN has no key
+//   varchar    bytes with 0x00 escaped as 0x00 0xFF, terminated by 0x00 0x00
+//
+// A DESC column is encoded as usual and then every byte of its segment is inverted. Since each
+// segment is prefix-free the inversion reverses the order of that column only.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyColumn {
+    pub column_idx: usize,
+    pub type_id: TypeId,
+    pub order: SortOrder,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct KeySchema {
+    columns: Vec<KeyColumn>,
+}
+
+impl KeySchema {
+    pub fn new(columns: Vec<KeyColumn>) -> Self {
+        Self { columns }
+    }
+
+    /// Resolves `(column name, order)` pairs against the table schema.
+    pub fn from_column_names(
+        schema: &Schema,
+        names: &[(String, SortOrder)],
+    ) -> Result<Self, String> {
+        if names.is_empty() {
+            return Err("an index needs at least one key column".to_string());
+        }
+        let mut columns = Vec::with_capacity(names.len());
+        for (name, order) in names {
+            let column_idx = schema
+                .get_col_idx(name)
+                .ok_or_else(|| format!("column {} does not exist", name))?;
+            let type_id = schema.get_column(column_idx).unwrap().get_type();
+            if !type_id.is_orderable() {
+                return Err(format!(
+                    "column {} of type {:?} cannot be indexed",
+                    name, type_id
+                ));
+            }
+            columns.push(KeyColumn {
+                column_idx,
+                type_id,
+                order: *order,
+            });
+        }
+        Ok(Self { columns })
+    }
+
+    pub fn get_columns(&self) -> &[KeyColumn] {
+        &self.columns
+    }
+
+    /// Upper bound of the encoded key length, given the declared column lengths.
+    pub fn key_size(&self, schema: &Schema) -> usize {
+        self.columns
+            .iter()
+            .map(|c| {
+                1 + match c.type_id {
+                    // worst case every byte is escaped, plus the terminator
+                    TypeId::VARCHAR => {
+                        2 * schema.get_column(c.column_idx).unwrap().get_length() as usize + 2
+                    }
+                    TypeId::DECIMAL => 8,
+                    other => encoded_int_width(other),
+                }
+            })
+            .sum()
+    }
+
+    pub fn encode_tuple(&self, tuple: &Tuple) -> Result<Vec<u8>, String> {
+        self.encode_values(&self.key_values(tuple))
+    }
+
+    pub fn has_null(&self, tuple: &Tuple) -> bool {
+        self.columns
+            .iter()
+            .any(|c| tuple.get_value(c.column_idx).is_none_or(|v| v.is_null()))
+    }
+
+    pub fn key_values(&self, tuple: &Tuple) -> Vec<Value> {
+        self.columns
+            .iter()
+            .map(|c| {
+                tuple
+                    .get_value(c.column_idx)
+                    .cloned()
+                    .unwrap_or(Value::Null)
+            })
+            .collect()
+    }
+
+    /// Encodes one value per key column, e.g. the literals of a point lookup.
+    pub fn encode_values(&self, values: &[Value]) -> Result<Vec<u8>, String> {
+        if values.len() != self.columns.len() {
+            return Err(format!(
+                "expected {} key values, got {}",
+                self.columns.len(),
+                values.len()
+            ));
+        }
+        let mut key = Vec::new();
+        for (column, value) in self.columns.iter().zip(values) {
+            let start = key.len();
+            encode_value(&mut key, column.type_id, value)?;
+            if column.order == SortOrder::Desc {
+                key[start..].iter_mut().for_each(|b| *b = !*b);
+            }
+        }
+        Ok(key)
+    }
+}
+
+fn encoded_int_width(type_id: TypeId) -> usize {
+    match type_id {
+        TypeId::BOOLEAN | TypeId::TINYINT => 1,
+        TypeId::SMALLINT => 2,
+        TypeId::INTEGER => 4,
+        _ => 8,
+    }
+}
+
+fn encode_value(key: &mut Vec<u8>, type_id: TypeId, value: &Value) -> Result<(), String> {
+    if value.is_null() {
+        key.push(0x00);
+        return Ok(());
+    }
+    key.push(0x01);
+    match type_id {
+        TypeId::BOOLEAN
+        | TypeId::TINYINT
+        | TypeId::SMALLINT
+        | TypeId::INTEGER
+        | TypeId::BIGINT
+        | TypeId::TIMESTAMP => {
+            let v = value
+                .as_i64()
+                .ok_or_else(|| format!("{} is not a valid {:?} key", value, type_id))?;
+            let width = encoded_int_width(type_id);
+            let bits = width * 8;
+            if bits < 64 && (v < -(1i64 << (bits - 1)) || v >= (1i64 << (bits - 1))) {
+                return Err(format!("{} is out of range for {:?}", v, type_id));
+            }
+            // flipping the sign bit makes two's complement sort as unsigned
+            let flipped = (v as u64) ^ (1u64 << (bits - 1));
+            key.extend_from_slice(&flipped.to_be_bytes()[8 - width..]);
+        }
+        TypeId::DECIMAL => {
+            let v = value
+                .as_f64()
+                .ok_or_else(|| format!("{} is not a valid DECIMAL key", value))?;
+            // NaN compares equal to nothing, not even itself, so it has no place in the order
+            if v.is_nan() {
+                return Err("NaN cannot be used in a key".to_string());
+            }
+            // -0.0 equals 0.0, it gets the same key
+            let bits = if v == 0.0 { 0.0f64 } else { v }.to_bits();
+            let bits = if bits >> 63 == 1 {
+                !bits
+            } else {
+                bits ^ (1 << 63)
+            };
+            key.extend_from_slice(&bits.to_be_bytes());
+        }
+        TypeId::VARCHAR => {
+            let Value::Varchar(s) = value else {
+                return Err(format!("{} is not a valid VARCHAR key", value));
+            };
+            for b in s.as_bytes() {
+                key.push(*b);
+                if *b == 0x00 {
+                    key.push(0xFF);
+                }
+            }
+            key.extend_from_slice(&[0x00, 0x00]);
+        }
+        other => {
+            return Err(format!(
+                "values of type {:?} cannot be used in a key",
+                other
+            ))
+        }
+    }
+    Ok(())
+}
+
+/// Encodes a bare u64 (e.g. a tuple id) so it sorts numerically.
+pub fn encode_u64(v: u64) -> Vec<u8> {
+    v.to_be_bytes().to_vec()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn key(type_id: TypeId, order: SortOrder, value: Value) -> Vec<u8> {
+        KeySchema::new(vec![KeyColumn {
+            column_idx: 0,
+            type_id,
+            order,
+        }])
+        .encode_values(&[value])
+        .unwrap()
+    }
+
+    #[test]
+    fn test_integer_keys_sort_like_values() {
+        let values = [i64::MIN, -300, -1, 0, 1, 2, 300, i64::MAX];
+        for w in values.windows(2) {
+            let a = key(TypeId::BIGINT, SortOrder::Asc, Value::BigInt(w[0]));
+            let b = key(TypeId::BIGINT, SortOrder::Asc, Value::BigInt(w[1]));
+            assert!(a < b, "{} < {}", w[0], w[1]);
+        }
+        let a = key(TypeId::SMALLINT, SortOrder::Asc, Value::SmallInt(-2));
+        let b = key(TypeId::SMALLINT, SortOrder::Asc, Value::SmallInt(7));
+        assert!(a < b);
+        assert_eq!(a.len(), 3);
+        assert!(KeySchema::new(vec![KeyColumn {
+            column_idx: 0,
+            type_id: TypeId::TINYINT,
+            order: SortOrder::Asc,
+        }])
+        .encode_values(&[Value::Integer(1000)])
+        .is_err());
+    }
+
+    #[test]
+    fn test_decimal_and_varchar_keys() {
+        let decimals = [
+            f64::NEG_INFINITY,
+            -1e9,
+            -2.5,
+            0.0,
+            0.5,
+            3.25,
+            1e12,
+            f64::INFINITY,
+        ];
+        for w in decimals.windows(2) {
+            let a = key(TypeId::DECIMAL, SortOrder::Asc, Value::Decimal(w[0]));
+            let b = key(TypeId::DECIMAL, SortOrder::Asc, Value::Decimal(w[1]));
+            assert!(a < b, "{} < {}", w[0], w[1]);
+        }
+        assert_eq!(
+            key(TypeId::DECIMAL, SortOrder::Asc, Value::Decimal(-0.0)),
+            key(TypeId::DECIMAL, SortOrder::Asc, Value::Decimal(0.0))
+        );
+        assert!(KeySchema::new(vec![KeyColumn {
+            column_idx: 0,
+            type_id: TypeId::DECIMAL,
+            order: SortOrder::Asc,
+        }])
+        .encode_values(&[Value::Decimal(f64::NAN)])
+        .is_err());
+        let strings = ["", "a", "a\0", "a\0b", "ab", "b"];
+        for w in strings.windows(2) {
+            let a = key(
+                TypeId::VARCHAR,
+                SortOrder::Asc,
+                Value::Varchar(w[0].to_string()),
+            );
+            let b = key(
+                TypeId::VARCHAR,
+                SortOrder::Asc,
+                Value::Varchar(w[1].to_string()),
+            );
+            assert!(a < b, "{:?} < {:?}", w[0], w[1]);
+        }
+    }
+
+    #[test]
+    fn test_composite_keys_with_desc_column() {
+        // (lastname ASC, salary DESC)
+        let schema = KeySchema::new(vec![
+            KeyColumn {
+                column_idx: 0,
+                type_id: TypeId::VARCHAR,
+                order: SortOrder::Asc,
+            },
+            KeyColumn {
+                column_idx: 1,
+                type_id: TypeId::BIGINT,
+                order: SortOrder::Desc,
+            },
+        ]);
+        let k = |name: &str, salary: i64| {
+            schema
+                .encode_values(&[Value::Varchar(name.to_string()), Value::BigInt(salary)])
+                .unwrap()
+        };
+        assert!(k("codd", 100) < k("codd", 50));
+        assert!(k("codd", 50) < k("gray", 900));
+        assert!(k("co", 1) < k("codd", 900));
+        // NULLs sort first ascending and last descending
+        assert!(
+            key(TypeId::INTEGER, SortOrder::Asc, Value::Null)
+                < key(TypeId::INTEGER, SortOrder::Asc, Value::Integer(i32::MIN))
+        );
+        assert!(
+            key(TypeId::INTEGER, SortOrder::Desc, Value::Null)
+                > key(TypeId::INTEGER, SortOrder::Desc, Value::Integer(i32::MIN))
+        );
+    }
+
+    #[test]
+    fn test_vector_columns_are_not_orderable() {
+        assert!(key_err(TypeId::VECTOR));
+        assert!(!TypeId::VECTOR.is_orderable());
+    }
+
+    fn key_err(type_id: TypeId) -> bool {
+        KeySchema::new(vec![KeyColumn {
+            column_idx: 0,
+            type_id,
+            order: SortOrder::Asc,
+        }])
+        .encode_values(&[Value::Vector(vec![1.0])])
+        .is_err()
+    }
+}
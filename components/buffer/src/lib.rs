@@ -1,9 +1,20 @@
+pub mod bplustreeindex;
 pub mod bufferpoolmanager;
 pub mod catalog;
 mod create_handler;
-mod customskiplist;
-mod frameheader;
-mod page_guard;
+pub mod customskiplist;
+pub mod errors;
+pub mod expression;
+pub mod extendiblehashindex;
+pub mod frameheader;
+pub mod index;
+pub mod index_key;
+pub mod page_guard;
 pub mod query_types;
+pub mod sequence;
+pub mod session;
 pub mod skiplistindex;
+pub mod statistics;
+mod system_catalog;
+pub mod table_heap;
 pub mod test;
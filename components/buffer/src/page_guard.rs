@@ -1,46 +1,88 @@
-use crate::frameheader::FrameHeader;
+use crate::bufferpoolmanager::LRUKReplacer;
+use crate::frameheader::{FrameHeader, PageData};
+use common::types::PageId;
+use parking_lot::{ArcRwLockReadGuard, ArcRwLockWriteGuard, RawRwLock};
+use std::collections::HashMap;
+use std::ops::{Deref, DerefMut};
 use std::sync::{Arc, Mutex};
 
-#[allow(dead_code)]
-pub(crate) struct ReadPageGuard {
-    is_valid: bool,
-    operation: Arc<Mutex<FrameHeader>>,
+// Keeps a frame pinned in the buffer pool. Dropping it unpins the frame under the pool latch and
+// hands it back to the replacer once nobody uses it anymore.
+pub(crate) struct PinnedFrame {
+    pub(crate) page_id: PageId,
+    pub(crate) frame: Arc<FrameHeader>,
+    pub(crate) page_table: Arc<Mutex<HashMap<PageId, usize>>>,
+    pub(crate) replacer: Arc<Mutex<LRUKReplacer>>,
 }
-#[allow(dead_code)]
+
+impl Drop for PinnedFrame {
+    fn drop(&mut self) {
+        let _latch = self.page_table.lock().unwrap();
+        if self.frame.unpin() == 0 {
+            self.replacer
+                .lock()
+                .unwrap()
+                .SetEvictable(self.frame.frame_id, true);
+        }
+    }
+}
+
+// Shared access to a pinned page. The data latch is released before the pin (fields drop in
+// declaration order), so the pool never waits on a latch of an evictable frame.
+pub struct ReadPageGuard {
+    data: ArcRwLockReadGuard<RawRwLock, PageData>,
+    pin: PinnedFrame,
+}
+
 impl ReadPageGuard {
-    pub(crate) fn new() -> Self {
+    pub(crate) fn new(pin: PinnedFrame) -> Self {
         Self {
-            is_valid: true,
-            operation: Arc::new(Mutex::new(FrameHeader::default())),
+            data: pin.frame.data.read_arc(),
+            pin,
         }
     }
 
-    pub(crate) fn read_page_data(&self, frame_id: usize) {
-        let mut guard = self.operation.lock().unwrap();
-        guard.read_data(frame_id);
+    pub fn page_id(&self) -> PageId {
+        self.pin.page_id
+    }
+}
+
+impl Deref for ReadPageGuard {
+    type Target = PageData;
+
+    fn deref(&self) -> &PageData {
+        &self.data
     }
 }
 
-#[allow(dead_code)]
-pub(crate) struct WritePageGuard {
-    is_valid: bool,
-    operation: Arc<Mutex<FrameHeader>>,
+// Exclusive access to a pinned page. The page is marked dirty as soon as the guard is taken.
+pub struct WritePageGuard {
+    data: ArcRwLockWriteGuard<RawRwLock, PageData>,
+    pin: PinnedFrame,
 }
 
 impl WritePageGuard {
-    pub(crate) fn new() -> Self {
-        Self {
-            is_valid: true,
-            operation: Arc::new(Mutex::new(FrameHeader::default())),
-        }
+    pub(crate) fn new(pin: PinnedFrame) -> Self {
+        let data = pin.frame.data.write_arc();
+        pin.frame.set_dirty(true);
+        Self { data, pin }
     }
 
-    pub(crate) fn write_page_data(&mut self, frame_id: usize, data: Vec<u8>) -> Option<usize> {
-        let mut guard = self.operation.lock().unwrap();
-        if let Some(val) = guard.write_data(frame_id, data) {
-            Some(val)
-        } else {
-            None
-        }
+    pub fn page_id(&self) -> PageId {
+        self.pin.page_id
+    }
+}
+
+impl Deref for WritePageGuard {
+    type Target = PageData;
+
+    fn deref(&self) -> &PageData {
+        &self.data
+    }
+}
+
+impl DerefMut for WritePageGuard {
+    fn deref_mut(&mut self) -> &mut PageData {
+        &mut self.data
     }
 }
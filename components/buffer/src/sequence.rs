@@ -0,0 +1,337 @@
+use std::sync::Mutex;
+
+// A sequence hands out values in blocks of `cache`. Before the first value of a block is handed
+// out, the last value of the block is made durable as the sequence's state, so after a crash the
+// sequence carries on past every value it may have handed out. Values of a block still unused
+// at the crash are skipped, a sequence has gaps but never repeats a value unless it cycles.
+// Only reserving a block writes to disk; the values of a block are handed out under the
+// sequence's own latch.
+
+const DEFAULT_CACHE: i64 = 32;
+
+/// The options of `CREATE SEQUENCE`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SequenceOptions {
+    increment: i64,
+    min_value: i64,
+    max_value: i64,
+    start: Option<i64>,
+    cache: i64,
+    cycle: bool,
+}
+
+impl Default for SequenceOptions {
+    fn default() -> Self {
+        Self::new(1)
+    }
+}
+
+impl SequenceOptions {
+    /// An ascending sequence counts from 1 up, a descending one from -1 down.
+    pub fn new(increment: i64) -> Self {
+        let (min_value, max_value) = if increment < 0 {
+            (i64::MIN, -1)
+        } else {
+            (1, i64::MAX)
+        };
+        Self {
+            increment,
+            min_value,
+            max_value,
+            start: None,
+            cache: DEFAULT_CACHE,
+            cycle: false,
+        }
+    }
+
+    pub fn with_min_value(mut self, min_value: i64) -> Self {
+        self.min_value = min_value;
+        self
+    }
+
+    pub fn with_max_value(mut self, max_value: i64) -> Self {
+        self.max_value = max_value;
+        self
+    }
+
+    pub fn with_start(mut self, start: i64) -> Self {
+        self.start = Some(start);
+        self
+    }
+
+    pub fn with_cache(mut self, cache: i64) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    pub fn with_cycle(mut self, cycle: bool) -> Self {
+        self.cycle = cycle;
+        self
+    }
+
+    pub fn get_increment(&self) -> i64 {
+        self.increment
+    }
+
+    pub fn get_min_value(&self) -> i64 {
+        self.min_value
+    }
+
+    pub fn get_max_value(&self) -> i64 {
+        self.max_value
+    }
+
+    // Without START, an ascending sequence starts at its minimum and a descending one at its
+    // maximum.
+    pub fn get_start(&self) -> i64 {
+        self.start.unwrap_or(if self.increment > 0 {
+            self.min_value
+        } else {
+            self.max_value
+        })
+    }
+
+    pub fn get_cache(&self) -> i64 {
+        self.cache
+    }
+
+    pub fn is_cycle(&self) -> bool {
+        self.cycle
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.increment == 0 {
+            return Err("INCREMENT must not be zero".to_string());
+        }
+        if self.min_value >= self.max_value {
+            return Err(format!(
+                "MINVALUE ({}) must be less than MAXVALUE ({})",
+                self.min_value, self.max_value
+            ));
+        }
+        let start = self.get_start();
+        if start < self.min_value || start > self.max_value {
+            return Err(format!(
+                "START value ({}) must be between MINVALUE ({}) and MAXVALUE ({})",
+                start, self.min_value, self.max_value
+            ));
+        }
+        if self.cache < 1 {
+            return Err(format!("CACHE ({}) must be greater than zero", self.cache));
+        }
+        Ok(())
+    }
+}
+
+/// The durable state of a sequence. Unless `is_called`, the next value is `last_value` itself.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SequenceState {
+    pub last_value: i64,
+    pub is_called: bool,
+}
+
+#[derive(Debug)]
+struct Allocation {
+    // the value handed out last, or the one to hand out next if nothing was yet
+    last_value: i64,
+    is_called: bool,
+    // values left of the reserved block
+    cached: i64,
+}
+
+#[derive(Debug)]
+pub struct Sequence {
+    options: SequenceOptions,
+    allocation: Mutex<Allocation>,
+}
+
+impl Sequence {
+    /// A sequence carrying on from `state` as it was made durable.
+    pub fn new(options: SequenceOptions, state: SequenceState) -> Self {
+        Self {
+            options,
+            allocation: Mutex::new(Allocation {
+                last_value: state.last_value,
+                is_called: state.is_called,
+                cached: 0,
+            }),
+        }
+    }
+
+    pub fn get_options(&self) -> &SequenceOptions {
+        &self.options
+    }
+
+    /// The state to make durable for a new sequence.
+    pub fn initial_state(options: &SequenceOptions) -> SequenceState {
+        SequenceState {
+            last_value: options.get_start(),
+            is_called: false,
+        }
+    }
+
+    /// The next value of the sequence. `persist` makes the state the sequence carries on from
+    /// after a restart durable, it is called whenever a new block of values is reserved.
+    pub fn nextval(
+        &self,
+        sequence_name: &str,
+        persist: impl FnOnce(SequenceState) -> Result<(), String>,
+    ) -> Result<i64, String> {
+        let options = &self.options;
+        let mut allocation = self.allocation.lock().unwrap();
+        let next = if !allocation.is_called {
+            allocation.last_value
+        } else {
+            match allocation.last_value.checked_add(options.increment) {
+                Some(next) if next >= options.min_value && next <= options.max_value => next,
+                _ if options.cycle && options.increment > 0 => options.min_value,
+                _ if options.cycle => options.max_value,
+                _ => {
+                    let (bound, limit) = if options.increment > 0 {
+                        ("maximum", options.max_value)
+                    } else {
+                        ("minimum", options.min_value)
+                    };
+                    return Err(format!(
+                        "nextval: reached {} value of sequence {} ({})",
+                        bound, sequence_name, limit
+                    ));
+                }
+            }
+        };
+        if allocation.cached == 0 {
+            // the last value of a block of `cache` values starting at `next`, cut short by the
+            // sequence's bounds
+            let end = (next as i128 + options.increment as i128 * (options.cache as i128 - 1))
+                .clamp(options.min_value as i128, options.max_value as i128);
+            let end = end - (end - next as i128) % options.increment as i128;
+            persist(SequenceState {
+                last_value: end as i64,
+                is_called: true,
+            })?;
+            allocation.cached = ((end - next as i128) / options.increment as i128) as i64 + 1;
+        }
+        allocation.last_value = next;
+        allocation.is_called = true;
+        allocation.cached -= 1;
+        Ok(next)
+    }
+
+    /// Sets the sequence's current value. Unless `is_called`, the next `nextval` returns `value`
+    /// itself. The values still cached are dropped.
+    pub fn setval(
+        &self,
+        sequence_name: &str,
+        value: i64,
+        is_called: bool,
+        persist: impl FnOnce(SequenceState) -> Result<(), String>,
+    ) -> Result<(), String> {
+        let options = &self.options;
+        if value < options.min_value || value > options.max_value {
+            return Err(format!(
+                "setval: value {} is out of bounds for sequence {} ({}..{})",
+                value, sequence_name, options.min_value, options.max_value
+            ));
+        }
+        let mut allocation = self.allocation.lock().unwrap();
+        persist(SequenceState {
+            last_value: value,
+            is_called,
+        })?;
+        allocation.last_value = value;
+        allocation.is_called = is_called;
+        allocation.cached = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::cell::RefCell;
+
+    // A sequence whose durable state is kept in `durable`.
+    fn take(
+        sequence: &Sequence,
+        durable: &RefCell<SequenceState>,
+        n: usize,
+    ) -> Result<Vec<i64>, String> {
+        (0..n)
+            .map(|_| {
+                sequence.nextval("s", |state| {
+                    *durable.borrow_mut() = state;
+                    Ok(())
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_values_are_handed_out_in_reserved_blocks() {
+        let options = SequenceOptions::new(2).with_start(10).with_cache(3);
+        options.validate().unwrap();
+        let durable = RefCell::new(Sequence::initial_state(&options));
+        let sequence = Sequence::new(options.clone(), *durable.borrow());
+        assert_eq!(take(&sequence, &durable, 2).unwrap(), vec![10, 12]);
+        assert_eq!(
+            *durable.borrow(),
+            SequenceState {
+                last_value: 14,
+                is_called: true
+            }
+        );
+        assert_eq!(take(&sequence, &durable, 2).unwrap(), vec![14, 16]);
+        assert_eq!(durable.borrow().last_value, 20);
+
+        // after a crash the rest of the block is skipped, no value is handed out twice
+        let restarted = Sequence::new(options, *durable.borrow());
+        assert_eq!(take(&restarted, &durable, 1).unwrap(), vec![22]);
+
+        // nothing is handed out unless the block is durable
+        let failing = Sequence::new(SequenceOptions::default(), *durable.borrow());
+        assert!(failing
+            .nextval("s", |_| Err("disk full".to_string()))
+            .is_err());
+        assert_eq!(take(&failing, &durable, 1).unwrap(), vec![27]);
+    }
+
+    #[test]
+    fn test_bounds_cycle_and_setval() {
+        let options = SequenceOptions::new(-1)
+            .with_min_value(-3)
+            .with_max_value(-1)
+            .with_cache(10);
+        let durable = RefCell::new(Sequence::initial_state(&options));
+        let sequence = Sequence::new(options.clone(), *durable.borrow());
+        assert_eq!(take(&sequence, &durable, 3).unwrap(), vec![-1, -2, -3]);
+        assert_eq!(durable.borrow().last_value, -3);
+        assert_eq!(
+            take(&sequence, &durable, 1).unwrap_err(),
+            "nextval: reached minimum value of sequence s (-3)"
+        );
+
+        let cycling = Sequence::new(options.with_cycle(true), *durable.borrow());
+        assert_eq!(take(&cycling, &durable, 2).unwrap(), vec![-1, -2]);
+
+        let persist = |state| {
+            *durable.borrow_mut() = state;
+            Ok(())
+        };
+        assert!(cycling.setval("s", 0, true, persist).is_err());
+        cycling.setval("s", -2, false, persist).unwrap();
+        assert_eq!(take(&cycling, &durable, 1).unwrap(), vec![-2]);
+
+        let near_max = SequenceOptions::new(i64::MAX / 2 + 1).with_cache(5);
+        let durable = RefCell::new(Sequence::initial_state(&near_max));
+        let sequence = Sequence::new(near_max, *durable.borrow());
+        assert_eq!(
+            take(&sequence, &durable, 2).unwrap(),
+            vec![1, i64::MAX / 2 + 2]
+        );
+        assert!(take(&sequence, &durable, 1).is_err());
+
+        assert!(SequenceOptions::new(0).validate().is_err());
+        assert!(SequenceOptions::new(1).with_start(0).validate().is_err());
+        assert!(SequenceOptions::new(1).with_cache(0).validate().is_err());
+    }
+}
@@ -0,0 +1,256 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::catalog::{Catalog, SequenceInfo, PUBLIC_SCHEMA};
+use crate::expression::Functions;
+use crate::query_types::Value;
+use common::transaction::{IsolationLevel, Transaction};
+
+/// Owner of the objects created without naming one.
+pub const DEFAULT_USER: &str = "sparrow";
+
+// State of one client connection. The catalog is shared by all sessions, the search path is not:
+// a table name without a schema is looked up in the schemas of the search path in order, and a
+// table created without one goes to the first schema of the path that exists. `currval` is per
+// session as well, it is the value `nextval` last returned to this session. Statements run in
+// the transaction the session began with BEGIN, or in one of their own if there is none, at the
+// session's isolation level unless BEGIN named another, and wait for locks up to the session's
+// lock timeout.
+#[derive(Clone, Debug)]
+pub struct Session {
+    user: String,
+    search_path: Vec<String>,
+    sequence_values: RefCell<HashMap<u32, i64>>,
+    transaction: Option<Arc<Transaction>>,
+    isolation_level: IsolationLevel,
+    lock_timeout: Option<Duration>,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self::new(DEFAULT_USER)
+    }
+}
+
+impl Session {
+    pub fn new(user: &str) -> Self {
+        Self {
+            user: user.to_string(),
+            search_path: vec![PUBLIC_SCHEMA.to_string()],
+            sequence_values: RefCell::new(HashMap::new()),
+            transaction: None,
+            isolation_level: IsolationLevel::default(),
+            lock_timeout: None,
+        }
+    }
+
+    pub fn get_transaction(&self) -> Option<Arc<Transaction>> {
+        self.transaction.clone()
+    }
+
+    pub fn set_transaction(&mut self, transaction: Option<Arc<Transaction>>) {
+        self.transaction = transaction;
+    }
+
+    pub fn get_isolation_level(&self) -> IsolationLevel {
+        self.isolation_level
+    }
+
+    pub fn set_isolation_level(&mut self, isolation_level: IsolationLevel) {
+        self.isolation_level = isolation_level;
+    }
+
+    pub fn get_lock_timeout(&self) -> Option<Duration> {
+        self.lock_timeout
+    }
+
+    // None waits for locks as long as it takes.
+    pub fn set_lock_timeout(&mut self, lock_timeout: Option<Duration>) {
+        self.lock_timeout = lock_timeout;
+    }
+
+    pub fn get_user(&self) -> &str {
+        &self.user
+    }
+
+    pub fn get_search_path(&self) -> &[String] {
+        &self.search_path
+    }
+
+    // The schemas do not have to exist, those that do not are skipped.
+    pub fn set_search_path(&mut self, schema_names: Vec<String>) {
+        self.search_path = schema_names
+            .into_iter()
+            .map(|schema_name| schema_name.to_lowercase())
+            .collect();
+    }
+
+    /// The qualified name of the existing table `table_name` refers to.
+    pub fn resolve_table(&self, catalog: &Catalog, table_name: &str) -> Result<String, String> {
+        if table_name.contains('.') {
+            return Ok(table_name.to_string());
+        }
+        self.search_path
+            .iter()
+            .map(|schema_name| format!("{}.{}", schema_name, table_name))
+            .find(|qualified_name| catalog.get_table(qualified_name).is_some())
+            .ok_or_else(|| format!("table {} does not exist", table_name))
+    }
+
+    /// The qualified name a table created as `table_name` gets.
+    pub fn qualify_new_table(&self, catalog: &Catalog, table_name: &str) -> Result<String, String> {
+        if table_name.contains('.') {
+            return Ok(table_name.to_string());
+        }
+        self.search_path
+            .iter()
+            .find(|schema_name| catalog.get_schema(schema_name).is_some())
+            .map(|schema_name| format!("{}.{}", schema_name, table_name))
+            .ok_or_else(|| "no schema in the search path to create the table in".to_string())
+    }
+
+    /// The existing sequence `sequence_name` refers to, looked up like a table.
+    pub fn resolve_sequence(
+        &self,
+        catalog: &Catalog,
+        sequence_name: &str,
+    ) -> Result<Arc<SequenceInfo>, String> {
+        let does_not_exist = || format!("sequence {} does not exist", sequence_name);
+        if sequence_name.contains('.') {
+            return catalog
+                .get_sequence(sequence_name)
+                .ok_or_else(does_not_exist);
+        }
+        self.search_path
+            .iter()
+            .find_map(|schema_name| {
+                catalog.get_sequence(&format!("{}.{}", schema_name, sequence_name))
+            })
+            .ok_or_else(does_not_exist)
+    }
+
+    pub fn nextval(&self, catalog: &Catalog, sequence_name: &str) -> Result<i64, String> {
+        let sequence_info = self.resolve_sequence(catalog, sequence_name)?;
+        let value = catalog.nextval(&sequence_info)?;
+        self.sequence_values
+            .borrow_mut()
+            .insert(sequence_info.get_sequence_id(), value);
+        Ok(value)
+    }
+
+    pub fn currval(&self, catalog: &Catalog, sequence_name: &str) -> Result<i64, String> {
+        let sequence_info = self.resolve_sequence(catalog, sequence_name)?;
+        self.sequence_values
+            .borrow()
+            .get(&sequence_info.get_sequence_id())
+            .copied()
+            .ok_or_else(|| {
+                format!(
+                    "currval of sequence {} is not yet defined in this session",
+                    sequence_name
+                )
+            })
+    }
+
+    pub fn setval(
+        &self,
+        catalog: &Catalog,
+        sequence_name: &str,
+        value: i64,
+        is_called: bool,
+    ) -> Result<i64, String> {
+        let sequence_info = self.resolve_sequence(catalog, sequence_name)?;
+        catalog.setval(&sequence_info, value, is_called)?;
+        if is_called {
+            self.sequence_values
+                .borrow_mut()
+                .insert(sequence_info.get_sequence_id(), value);
+        }
+        Ok(value)
+    }
+
+    /// `nextval`, `currval` and `setval` for the expressions of a statement run by the session.
+    pub fn functions<'a>(&'a self, catalog: &'a Catalog) -> SessionFunctions<'a> {
+        SessionFunctions {
+            session: self,
+            catalog,
+        }
+    }
+}
+
+pub struct SessionFunctions<'a> {
+    session: &'a Session,
+    catalog: &'a Catalog,
+}
+
+impl Functions for SessionFunctions<'_> {
+    fn call(&self, name: &str, args: &[Value]) -> Option<Result<Value, String>> {
+        let (session, catalog) = (self.session, self.catalog);
+        let value = match (name, args) {
+            ("nextval", [Value::Varchar(sequence_name)]) => session.nextval(catalog, sequence_name),
+            ("currval", [Value::Varchar(sequence_name)]) => session.currval(catalog, sequence_name),
+            ("setval", [Value::Varchar(sequence_name), value, rest @ ..])
+                if matches!(rest, [] | [Value::Boolean(_)]) =>
+            {
+                let is_called = rest != [Value::Boolean(false)];
+                match value.as_i64() {
+                    Some(value) => session.setval(catalog, sequence_name, value, is_called),
+                    None => Err(format!("setval expects a number, not {}", value)),
+                }
+            }
+            ("nextval" | "currval", _) => Err(format!("{} expects a sequence name", name)),
+            ("setval", _) => {
+                Err("setval expects a sequence name, a number and optionally is_called".to_string())
+            }
+            _ => return None,
+        };
+        Some(value.map(Value::BigInt))
+    }
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::query_types::get_demo_schema;
+    use common::transaction::IsolationLevel;
+
+    #[test]
+    fn test_search_path_resolves_unqualified_names() {
+        let catalog = Catalog::new();
+        let txn = catalog.begin(IsolationLevel::default());
+        catalog
+            .create_schema(&txn, "Staging", DEFAULT_USER)
+            .unwrap();
+        for table_name in ["public.people", "staging.people", "staging.pets"] {
+            catalog
+                .create_table(&txn, table_name.to_string(), get_demo_schema(), true)
+                .unwrap();
+        }
+        catalog.commit(&txn).unwrap();
+        let mut session = Session::default();
+        assert_eq!(
+            session.resolve_table(&catalog, "people"),
+            Ok("public.people".to_string())
+        );
+        assert!(session.resolve_table(&catalog, "pets").is_err());
+
+        session.set_search_path(vec!["missing".to_string(), "STAGING".to_string()]);
+        assert_eq!(
+            session.resolve_table(&catalog, "people"),
+            Ok("staging.people".to_string())
+        );
+        assert_eq!(
+            session.resolve_table(&catalog, "public.people"),
+            Ok("public.people".to_string())
+        );
+        assert_eq!(
+            session.qualify_new_table(&catalog, "orders"),
+            Ok("staging.orders".to_string())
+        );
+
+        session.set_search_path(vec![]);
+        assert!(session.resolve_table(&catalog, "people").is_err());
+        assert!(session.qualify_new_table(&catalog, "orders").is_err());
+    }
+}
@@ -1,47 +1,119 @@
-use skiplist::SkipMap;
-use std::ops::Bound::Included;
-use std::sync::{Arc, Mutex};
+use crate::customskiplist::{SkipList, DEFAULT_MAX_LEVEL};
+use crate::index::{entry_key, entry_range, stored_bounds, Index};
+use std::ops::Bound::{self, Included, Unbounded};
+use std::sync::Arc;
+
+type KeyMap = SkipList<Vec<u8>, (u64, usize)>;
 
 #[derive(Debug, Clone)]
 pub struct SkipListIndex {
-    index: Arc<Mutex<SkipMap<u64, (u64, usize)>>>, // id → (page_id, offset)
+    index: Arc<KeyMap>, // encoded key → (page_id, offset)
+    unique: bool,
+}
+
+impl Default for SkipListIndex {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl SkipListIndex {
     pub fn new() -> Self {
         Self {
-            index: Arc::new(Mutex::new(SkipMap::new())),
+            index: Arc::new(SkipList::new(DEFAULT_MAX_LEVEL)),
+            unique: true,
+        }
+    }
+
+    // An index that keeps one entry per (key, location), e.g. for a secondary index on lastname.
+    pub fn new_non_unique() -> Self {
+        Self {
+            unique: false,
+            ..Self::new()
         }
     }
 
-    pub fn insert(&self, tuple_id: u64, page_id: u64, offset: usize) {
+    pub fn insert(&self, key: Vec<u8>, page_id: u64, offset: usize) -> bool {
+        let key = if self.unique {
+            key
+        } else {
+            entry_key(key, page_id, offset)
+        };
+        self.index.insert(key, (page_id, offset))
+    }
+
+    pub fn delete(&self, key: &[u8], page_id: u64, offset: usize) -> bool {
+        if !self.unique {
+            return self
+                .index
+                .remove(&entry_key(key.to_vec(), page_id, offset))
+                .is_some();
+        }
         self.index
-            .lock()
-            .unwrap()
-            .insert(tuple_id, (page_id, offset));
+            .remove_if(&key.to_vec(), |loc| *loc == (page_id, offset))
+            .is_some()
     }
 
-    pub fn find(&self, tuple_id: u64) -> Option<(u64, usize)> {
-        let guard = self.index.lock().unwrap();
-        match guard.get(&tuple_id) {
-            Some(guard) => Some(*guard),
-            None => None,
+    pub fn find(&self, key: &[u8]) -> Option<(u64, usize)> {
+        if !self.unique {
+            return self.range_query(key, key).first().copied();
         }
+        self.index.get(&key.to_vec())
     }
 
-    pub fn range_query(&self, start: u64, end: u64) -> Vec<(u64, usize)> {
+    pub fn range_query(&self, start: &[u8], end: &[u8]) -> Vec<(u64, usize)> {
+        let (start, end) = if self.unique {
+            (start.to_vec(), end.to_vec())
+        } else {
+            entry_range(start, end)
+        };
         self.index
-            .lock()
-            .unwrap()
             .range(Included(&start), Included(&end))
-            .map(|(_, loc)| *loc)
+            .map(|(_, loc)| loc)
             .collect()
     }
 }
 
+impl Index for SkipListIndex {
+    fn insert(&self, key: Vec<u8>, page_id: u64, offset: usize) -> bool {
+        SkipListIndex::insert(self, key, page_id, offset)
+    }
+
+    fn delete(&self, key: &[u8], page_id: u64, offset: usize) -> bool {
+        SkipListIndex::delete(self, key, page_id, offset)
+    }
+
+    fn find(&self, key: &[u8]) -> Option<(u64, usize)> {
+        SkipListIndex::find(self, key)
+    }
+
+    fn range_query(&self, start: &[u8], end: &[u8]) -> Vec<(u64, usize)> {
+        SkipListIndex::range_query(self, start, end)
+    }
+
+    fn is_ordered(&self) -> bool {
+        true
+    }
+
+    fn range_entries(
+        &self,
+        start: Bound<&[u8]>,
+        end: Bound<&[u8]>,
+    ) -> Vec<(Vec<u8>, (u64, usize))> {
+        let (start, end) = stored_bounds(self.unique, start, end);
+        self.index.range(start.as_ref(), end.as_ref()).collect()
+    }
+
+    fn next_entry(&self, key: &[u8]) -> Option<(Vec<u8>, (u64, usize))> {
+        let (start, _) = stored_bounds(self.unique, Bound::Excluded(key), Unbounded);
+        self.index.range(start.as_ref(), Unbounded).next()
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::SkipListIndex;
+    use crate::index_key::encode_u64;
 
     #[test]
     fn test_insert() {
@@ -52,15 +124,134 @@ mod test {
         let tuple3 = 3u64;
         let page_3 = 10;
         let l = SkipListIndex::new();
-        l.insert(tuple1, page_1, 10);
-        l.insert(tuple2, page_2, 10);
-        l.insert(tuple3, page_3, 10);
-        let t1 = l.find(tuple1).unwrap();
-        let t2 = l.find(tuple2).unwrap();
-        let t3 = l.find(tuple3).unwrap();
+        l.insert(encode_u64(tuple1), page_1, 10);
+        l.insert(encode_u64(tuple2), page_2, 10);
+        l.insert(encode_u64(tuple3), page_3, 10);
+        let t1 = l.find(&encode_u64(tuple1)).unwrap();
+        let t2 = l.find(&encode_u64(tuple2)).unwrap();
+        let t3 = l.find(&encode_u64(tuple3)).unwrap();
         assert_eq!(page_1, t1.0);
         assert_eq!(page_2, t2.0);
         assert_eq!(page_3, t3.0);
-        assert_eq!(l.range_query(0, 2), vec![(t1), (t2)])
+        assert_eq!(
+            l.range_query(&encode_u64(0), &encode_u64(2)),
+            vec![(t1), (t2)]
+        )
+    }
+
+    #[test]
+    fn test_insert_keeps_existing_entry() {
+        let l = SkipListIndex::new();
+        assert!(l.insert(encode_u64(1), 5, 0));
+        assert!(!l.insert(encode_u64(1), 6, 0));
+        assert_eq!(l.find(&encode_u64(1)), Some((5, 0)));
+        assert!(!l.delete(&encode_u64(1), 6, 0));
+        assert!(l.delete(&encode_u64(1), 5, 0));
+        assert!(!l.delete(&encode_u64(1), 5, 0));
+        assert!(l.insert(encode_u64(1), 6, 0));
+    }
+
+    #[test]
+    fn test_concurrent_inserts_of_same_key() {
+        let l = SkipListIndex::new();
+        let handles = (0..8u64)
+            .map(|i| {
+                let l = l.clone();
+                std::thread::spawn(move || l.insert(encode_u64(42), i, 0))
+            })
+            .collect::<Vec<_>>();
+        let inserted = handles
+            .into_iter()
+            .map(|h| h.join().unwrap())
+            .filter(|inserted| *inserted)
+            .count();
+        assert_eq!(inserted, 1);
+    }
+
+    #[test]
+    fn test_non_unique_keys() {
+        let l = SkipListIndex::new_non_unique();
+        for (key, page) in [(2, 0), (1, 1), (2, 2), (2, 3), (3, 4)] {
+            assert!(l.insert(encode_u64(key), page, 0));
+        }
+        assert!(!l.insert(encode_u64(2), 2, 0));
+        assert!(l.insert(encode_u64(2), 2, 1));
+        assert_eq!(l.find(&encode_u64(2)), Some((0, 0)));
+        assert_eq!(
+            l.range_query(&encode_u64(2), &encode_u64(2)),
+            vec![(0, 0), (2, 0), (2, 1), (3, 0)]
+        );
+        assert_eq!(l.range_query(&encode_u64(1), &encode_u64(3)).len(), 6);
+        // only the exact (key, location) pair goes away
+        assert!(l.delete(&encode_u64(2), 2, 0));
+        assert!(!l.delete(&encode_u64(2), 2, 0));
+        assert!(!l.delete(&encode_u64(3), 3, 0));
+        assert_eq!(
+            l.range_query(&encode_u64(2), &encode_u64(2)),
+            vec![(0, 0), (2, 1), (3, 0)]
+        );
+    }
+
+    #[test]
+    fn test_concurrent_inserts_and_deletes() {
+        let l = SkipListIndex::new_non_unique();
+        let handles = (0..4u64)
+            .map(|t| {
+                let l = l.clone();
+                std::thread::spawn(move || {
+                    for i in 0..200 {
+                        assert!(l.insert(encode_u64(i % 10), t, i as usize));
+                        if i % 2 == 1 {
+                            assert!(l.delete(&encode_u64(i % 10), t, i as usize));
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        handles.into_iter().for_each(|h| h.join().unwrap());
+        assert_eq!(l.range_query(&encode_u64(0), &encode_u64(9)).len(), 400);
+        assert_eq!(l.range_query(&encode_u64(1), &encode_u64(1)).len(), 0);
+    }
+
+    #[test]
+    fn test_entries_are_read_with_their_keys_in_order() {
+        use crate::index::Index;
+        use std::ops::Bound::{Excluded, Included, Unbounded};
+
+        let unique = SkipListIndex::new();
+        let non_unique = SkipListIndex::new_non_unique();
+        for i in [3u64, 1, 7, 5] {
+            assert!(Index::insert(&unique, encode_u64(i), i, 0));
+            assert!(Index::insert(&non_unique, encode_u64(i), i, 0));
+            assert!(Index::insert(&non_unique, encode_u64(i), i, 1));
+        }
+        let locations = |entries: Vec<(Vec<u8>, (u64, usize))>| {
+            entries.into_iter().map(|(_, loc)| loc).collect::<Vec<_>>()
+        };
+        assert!(unique.is_ordered());
+        let (three, six) = (encode_u64(3), encode_u64(6));
+        assert_eq!(
+            locations(unique.range_entries(Included(&three[..]), Included(&six[..]))),
+            vec![(3, 0), (5, 0)]
+        );
+        assert_eq!(
+            locations(non_unique.range_entries(Excluded(&three[..]), Unbounded)),
+            vec![(5, 0), (5, 1), (7, 0), (7, 1)]
+        );
+        assert_eq!(
+            locations(non_unique.range_entries(Unbounded, Included(&three[..]))),
+            vec![(1, 0), (1, 1), (3, 0), (3, 1)]
+        );
+        // a unique index stores the key as it is, a non-unique one with the location after it
+        let (key, loc) = unique.next_entry(&three).unwrap();
+        assert_eq!((key, loc), (encode_u64(5), (5, 0)));
+        let (key, loc) = non_unique.next_entry(&three).unwrap();
+        assert_eq!(loc, (5, 0));
+        assert!(key.starts_with(&encode_u64(5)) && key.len() > encode_u64(5).len());
+        assert_eq!(
+            non_unique.next_entry(&six).map(|(_, loc)| loc),
+            Some((7, 0))
+        );
+        assert_eq!(unique.next_entry(&encode_u64(7)), None);
     }
 }
@@ -0,0 +1,308 @@
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use rand::random_range;
+
+use crate::query_types::{Schema, Tuple, Value};
+
+// What ANALYZE knows about a table. Row counts and null fractions are exact, every row is read;
+// distinct values are counted with a HyperLogLog sketch, and most common values and histograms
+// come from a uniform sample of at most SAMPLE_SIZE rows.
+
+/// Most common values and histogram buckets kept per column.
+pub const STATISTICS_TARGET: usize = 20;
+const SAMPLE_SIZE: usize = 300 * STATISTICS_TARGET;
+// wider values are left out of most common values and histograms, as they rarely repeat and
+// would not fit a catalog row
+const MAX_WIDTH: usize = 64;
+const HLL_PRECISION: u32 = 10;
+
+/// Estimates the number of distinct values it was given in a fixed 2^HLL_PRECISION bytes.
+#[derive(Clone, Debug)]
+pub struct HyperLogLog {
+    registers: Vec<u8>,
+}
+
+impl Default for HyperLogLog {
+    fn default() -> Self {
+        Self {
+            registers: vec![0; 1 << HLL_PRECISION],
+        }
+    }
+}
+
+impl HyperLogLog {
+    pub fn add(&mut self, value: &Value) {
+        let hash = hash_value(value);
+        let register = (hash >> (64 - HLL_PRECISION)) as usize;
+        // the position of the first set bit of the rest, counting from 1
+        let rank = ((hash << HLL_PRECISION) | (1 << (HLL_PRECISION - 1))).leading_zeros() + 1;
+        self.registers[register] = self.registers[register].max(rank as u8);
+    }
+
+    pub fn estimate(&self) -> f64 {
+        let m = self.registers.len() as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let sum = self
+            .registers
+            .iter()
+            .map(|r| 2f64.powi(-(*r as i32)))
+            .sum::<f64>();
+        let estimate = alpha * m * m / sum;
+        let zeros = self.registers.iter().filter(|r| **r == 0).count();
+        // few values leave registers empty, counting those is more accurate
+        if estimate <= 2.5 * m && zeros > 0 {
+            m * (m / zeros as f64).ln()
+        } else {
+            estimate
+        }
+    }
+}
+
+fn hash_value(value: &Value) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    match value {
+        Value::Decimal(v) => v.to_bits().hash(&mut hasher),
+        Value::Varchar(v) => v.hash(&mut hasher),
+        Value::Boolean(v) => v.hash(&mut hasher),
+        Value::Vector(v) => v.iter().for_each(|x| x.to_bits().hash(&mut hasher)),
+        other => other.as_i64().hash(&mut hasher),
+    }
+    hasher.finish()
+}
+
+fn is_wide(value: &Value) -> bool {
+    match value {
+        Value::Varchar(v) => v.len() > MAX_WIDTH,
+        Value::Vector(_) => true,
+        _ => false,
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ColumnStatistics {
+    /// Fraction of the rows that are NULL.
+    pub null_fraction: f64,
+    /// Estimated number of distinct non-NULL values.
+    pub n_distinct: f64,
+    /// The most common values with the fraction of the rows holding each, most common first.
+    pub most_common: Vec<(Value, f64)>,
+    /// Bounds of buckets holding about as many of the other values each, in ascending order.
+    pub histogram: Vec<Value>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TableStatistics {
+    pub row_count: u64,
+    pub page_count: u64,
+    /// By column id.
+    pub columns: HashMap<u32, ColumnStatistics>,
+}
+
+impl TableStatistics {
+    /// Statistics of the rows of a table of `schema`, brought up to the schema's version.
+    pub fn collect(
+        schema: &Schema,
+        rows: impl Iterator<Item = Tuple>,
+        page_count: u64,
+    ) -> Result<Self, String> {
+        let width = schema.get_columns().len();
+        let mut sketches = vec![HyperLogLog::default(); width];
+        let mut nulls = vec![0u64; width];
+        let mut sample: Vec<Vec<Value>> = vec![];
+        let mut row_count = 0u64;
+        for tuple in rows {
+            let values = schema.upgrade(tuple)?.get_values().to_vec();
+            for (idx, value) in values.iter().enumerate() {
+                if value.is_null() {
+                    nulls[idx] += 1;
+                } else {
+                    sketches[idx].add(value);
+                }
+            }
+            row_count += 1;
+            // reservoir sampling keeps every row read so far with the same chance
+            if sample.len() < SAMPLE_SIZE {
+                sample.push(values);
+            } else {
+                let slot = random_range(0..row_count) as usize;
+                if slot < SAMPLE_SIZE {
+                    sample[slot] = values;
+                }
+            }
+        }
+        let columns = schema
+            .get_columns()
+            .iter()
+            .enumerate()
+            .map(|(idx, column)| {
+                let values = sample.iter().map(|row| &row[idx]).collect::<Vec<&Value>>();
+                let (most_common, histogram) = distribution(&values);
+                let statistics = ColumnStatistics {
+                    null_fraction: match row_count {
+                        0 => 0.0,
+                        _ => nulls[idx] as f64 / row_count as f64,
+                    },
+                    n_distinct: match nulls[idx] == row_count {
+                        true => 0.0,
+                        false => sketches[idx].estimate().round().max(1.0),
+                    },
+                    most_common,
+                    histogram,
+                };
+                (column.get_column_id(), statistics)
+            })
+            .collect();
+        Ok(Self {
+            row_count,
+            page_count,
+            columns,
+        })
+    }
+}
+
+/// When a table is analyzed without ANALYZE: once more than `threshold` plus `scale_factor`
+/// times the rows it had when it was last analyzed were inserted, updated or deleted.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AutoAnalyze {
+    pub threshold: u64,
+    pub scale_factor: f64,
+}
+
+impl Default for AutoAnalyze {
+    fn default() -> Self {
+        Self {
+            threshold: 50,
+            scale_factor: 0.1,
+        }
+    }
+}
+
+impl AutoAnalyze {
+    pub fn is_due(&self, modifications: u64, row_count: u64) -> bool {
+        modifications as f64 > self.threshold as f64 + self.scale_factor * row_count as f64
+    }
+}
+
+// The most common values of a column's sample and the histogram of the rest.
+fn distribution(sample: &[&Value]) -> (Vec<(Value, f64)>, Vec<Value>) {
+    let mut counts: Vec<(&Value, usize)> = vec![];
+    let mut positions: HashMap<u64, Vec<usize>> = HashMap::new();
+    for value in sample.iter().filter(|v| !v.is_null() && !is_wide(v)) {
+        let candidates = positions.entry(hash_value(value)).or_default();
+        match candidates.iter().find(|at| counts[**at].0 == *value) {
+            Some(at) => counts[*at].1 += 1,
+            None => {
+                candidates.push(counts.len());
+                counts.push((value, 1));
+            }
+        }
+    }
+    counts.sort_by(|(a, a_count), (b, b_count)| {
+        b_count
+            .cmp(a_count)
+            .then(a.compare(b).unwrap_or(std::cmp::Ordering::Equal))
+    });
+    let total = counts.iter().map(|(_, count)| count).sum::<usize>();
+    // a value is common if it is well above the average frequency, or if the sample seems to
+    // hold every value of the column
+    let all_repeat = counts.iter().all(|(_, count)| *count > 1);
+    let common = |count: usize| {
+        count > 1
+            && ((all_repeat && counts.len() <= STATISTICS_TARGET)
+                || count as f64 > 1.25 * total as f64 / counts.len() as f64)
+    };
+    let most_common = counts
+        .iter()
+        .take(STATISTICS_TARGET)
+        .take_while(|(_, count)| common(*count))
+        .map(|(value, count)| ((*value).clone(), *count as f64 / sample.len() as f64))
+        .collect::<Vec<(Value, f64)>>();
+
+    let mut rest = counts[most_common.len()..]
+        .iter()
+        .flat_map(|(value, count)| std::iter::repeat_n(*value, *count))
+        .collect::<Vec<&Value>>();
+    if rest.len() < 2 || rest.iter().any(|v| v.compare(rest[0]).is_none()) {
+        return (most_common, vec![]);
+    }
+    rest.sort_by(|a, b| a.compare(b).unwrap());
+    let buckets = STATISTICS_TARGET.min(rest.len() - 1);
+    let histogram = (0..=buckets)
+        .map(|i| rest[i * (rest.len() - 1) / buckets].clone())
+        .collect();
+    (most_common, histogram)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::query_types::{Column, TypeId};
+
+    #[test]
+    fn test_hyperloglog_estimates_distinct_values() {
+        for n in [10, 1_000, 100_000] {
+            let mut sketch = HyperLogLog::default();
+            for i in 0..n {
+                sketch.add(&Value::BigInt(i));
+                sketch.add(&Value::BigInt(i));
+            }
+            let error = (sketch.estimate() - n as f64).abs() / n as f64;
+            assert!(
+                error < 0.1,
+                "{} distinct values estimated as {}",
+                n,
+                sketch.estimate()
+            );
+        }
+    }
+
+    #[test]
+    fn test_collect_statistics() {
+        let schema = Schema::new(vec![
+            Column::new("id".to_string(), TypeId::INTEGER, 4),
+            Column::new("city".to_string(), TypeId::VARCHAR, 20),
+        ]);
+        // ids 0..1000, a third of the cities NULL and the rest mostly "oslo"
+        let rows = (0..1000).map(|i| {
+            let city = match i % 3 {
+                0 => Value::Null,
+                1 => Value::Varchar("oslo".to_string()),
+                _ if i % 10 == 2 => Value::Varchar("bergen".to_string()),
+                _ => Value::Varchar(format!("town {}", i)),
+            };
+            schema.new_tuple(i as u64, vec![Value::Integer(i), city])
+        });
+        let statistics = TableStatistics::collect(&schema, rows, 7).unwrap();
+        assert_eq!(statistics.row_count, 1000);
+        assert_eq!(statistics.page_count, 7);
+
+        let id = &statistics.columns[&0];
+        assert_eq!(id.null_fraction, 0.0);
+        assert!((id.n_distinct - 1000.0).abs() < 100.0);
+        assert!(id.most_common.is_empty());
+        assert_eq!(id.histogram.len(), STATISTICS_TARGET + 1);
+        assert_eq!(id.histogram.first(), Some(&Value::Integer(0)));
+        assert_eq!(id.histogram[STATISTICS_TARGET / 2], Value::Integer(499));
+        assert_eq!(id.histogram.last(), Some(&Value::Integer(999)));
+
+        let city = &statistics.columns[&1];
+        assert!((city.null_fraction - 0.334).abs() < 0.001);
+        let most_common = city
+            .most_common
+            .iter()
+            .map(|(value, _)| value.to_string())
+            .collect::<Vec<String>>();
+        assert_eq!(most_common, vec!["'oslo'", "'bergen'"]);
+        assert!((city.most_common[0].1 - 0.333).abs() < 0.001);
+        // the towns are in the histogram, each of them is there once
+        assert!(city
+            .histogram
+            .iter()
+            .all(|v| v.to_string().starts_with("'town")));
+
+        let empty = TableStatistics::collect(&schema, std::iter::empty(), 0).unwrap();
+        assert_eq!(empty.columns[&1].n_distinct, 0.0);
+        assert!(empty.columns[&1].histogram.is_empty());
+    }
+}
@@ -0,0 +1,1110 @@
+use crate::bufferpoolmanager::BufferPoolManager;
+use crate::catalog::{ConstraintInfo, ConstraintKind, ForeignKey, IndexType, ReferentialAction};
+use crate::expression::Expr;
+use crate::index_key::SortOrder;
+use crate::query_types::{Column, Identity, Schema, Tuple, TypeId, Value};
+use crate::sequence::{Sequence, SequenceOptions, SequenceState};
+use crate::statistics::{ColumnStatistics, TableStatistics};
+use crate::table_heap::TableHeap;
+use common::types::{PageId, RecordId};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+// The catalog lives in seven system tables inside the database file, found through the
+// superblock on page 0:
+//
+//   superblock       [magic u32][sys_tables page id u32][sys_columns page id u32]
+//                    [sys_indexes page id u32][sys_schemas page id u32]
+//                    [sys_constraints page id u32][sys_sequences page id u32]
+//                    [tuple id high-water mark u64][sys_statistics page id u32]
+//
+//   sys_schemas      (schema_id, schema_name, owner)
+//   sys_tables       (table_id, table_name, first_page_id, schema_versions, schema_id)
+//   sys_columns      (table_id, position, column_name, type_id, length, schema_version,
+//                     column_id, missing_value, default, not_null, identity)
+//   sys_indexes      (index_id, table_id, index_name, key_columns, index_type, is_unique,
+//                     is_primary_key, header_page_id)
+//   sys_constraints  (constraint_id, table_id, constraint_name, check, columns, ref_table_id,
+//                     ref_columns, on_delete)
+//   sys_sequences    (sequence_id, schema_id, sequence_name, increment, min_value, max_value,
+//                     start, cache, cycle, last_value, is_called, owner_table_id,
+//                     owner_column_id)
+//   sys_statistics   (table_id, NULL, row_count, page_count) per analyzed table and
+//                    (table_id, column_id, null_fraction, n_distinct, n, n most common values,
+//                     their n frequencies, histogram bounds...) per column
+//
+// Expressions are stored as text. A CHECK constraint has only `check` set, a FOREIGN KEY the
+// column ids of both tables, as in "1,3", and the rest. The sequence of an identity column is
+// owned by the column and dropped with it. ANALYZE replaces the statistics rows of a table by
+// deleting the table's row first and writing it last, so column rows without one are leftovers
+// of a crash and ignored.
+//
+// Tuple ids of every table, system tables included, come from one sequence whose state is the
+// high-water mark in the superblock: ids up to it may have been handed out, ids past it have
+// not. A zero mark means no id was handed out yet.
+//
+// DDL is part of the transaction it runs in. The rows it writes get undo slots as the rows of
+// user tables do and its deletes are only marked, until the transaction commits and they are
+// made durable, or aborts and they are rolled back. Undo slots found on open belong to
+// transactions that never ended and are rolled back before anything is loaded. A table is
+// written column rows first, and those are on disk before the sys_tables row that makes the
+// table exist; an index has a single row. Dropping a table deletes its sys_tables row first. ALTER TABLE
+// writes the columns of the new schema version next to the old ones and switches over by
+// updating `schema_versions` in the table's row, the column ids of every version as in
+// "0,1,2;0,2,3". A DDL statement that fails takes its rows out again, and column and index rows
+// orphaned by a crash or left behind by an older schema version are dropped on the next startup.
+// So are the tables of a dropped schema. Only the superblock and the pages of the system tables
+// are written here, the pages of user tables reach the disk through the pool whenever it writes
+// them, and with them the undo slots of rows whose transactions have not ended.
+//
+// Files written before schemas, constraints, sequences and statistics existed have no
+// sys_schemas, sys_constraints, sys_sequences or sys_statistics page in the superblock, no schema_id in their sys_tables rows
+// and neither default, not_null nor identity in their sys_columns rows. The missing system
+// tables are created on open and those tables belong to the schema with id 0. Their tuple ids
+// were drawn at random, counting up from 1 next to them is as safe as drawing more.
+
+const SUPERBLOCK_PAGE_ID: PageId = 0;
+const MAGIC: u32 = 0x5350_5257; // "SPRW"
+const TUPLE_ID_AT: usize = 28;
+const SYS_STATISTICS_AT: usize = 36;
+const TUPLE_ID_CACHE: i64 = 1024;
+
+pub(crate) struct SchemaRow {
+    pub(crate) schema_id: u32,
+    pub(crate) schema_name: String,
+    pub(crate) owner: String,
+}
+
+pub(crate) struct TableRow {
+    pub(crate) table_id: u32,
+    pub(crate) schema_id: u32,
+    pub(crate) table_name: String,
+    pub(crate) first_page_id: PageId,
+    pub(crate) schema: Schema,
+}
+
+pub(crate) struct ConstraintRow {
+    pub(crate) table_id: u32,
+    pub(crate) constraint: ConstraintInfo,
+}
+
+pub(crate) struct SequenceRow {
+    pub(crate) sequence_id: u32,
+    pub(crate) schema_id: u32,
+    pub(crate) sequence_name: String,
+    pub(crate) options: SequenceOptions,
+    pub(crate) state: SequenceState,
+    // the table and column id of the identity column owning the sequence
+    pub(crate) owned_by: Option<(u32, u32)>,
+}
+
+pub(crate) struct IndexRow {
+    pub(crate) index_id: u32,
+    pub(crate) table_id: u32,
+    pub(crate) index_name: String,
+    pub(crate) key_columns: Vec<(String, SortOrder)>,
+    pub(crate) index_type: IndexType,
+    pub(crate) is_unique: bool,
+    pub(crate) is_primary_key: bool,
+    // only set for indexes that keep their pages in the pool
+    pub(crate) header_page_id: Option<PageId>,
+}
+
+fn read_u32(data: &[u8], at: usize) -> u32 {
+    u32::from_be_bytes(data[at..at + 4].try_into().unwrap())
+}
+
+fn write_u32(data: &mut [u8], at: usize, v: u32) {
+    data[at..at + 4].copy_from_slice(&v.to_be_bytes());
+}
+
+fn read_u64(data: &[u8], at: usize) -> u64 {
+    u64::from_be_bytes(data[at..at + 8].try_into().unwrap())
+}
+
+fn write_u64(data: &mut [u8], at: usize, v: u64) {
+    data[at..at + 8].copy_from_slice(&v.to_be_bytes());
+}
+
+fn corrupt(tuple: &Tuple) -> String {
+    format!("corrupt catalog row {}", tuple)
+}
+
+fn int(tuple: &Tuple, idx: usize) -> Result<i64, String> {
+    tuple
+        .get_value(idx)
+        .and_then(Value::as_i64)
+        .ok_or_else(|| corrupt(tuple))
+}
+
+fn text(tuple: &Tuple, idx: usize) -> Result<String, String> {
+    match tuple.get_value(idx) {
+        Some(Value::Varchar(s)) => Ok(s.clone()),
+        _ => Err(corrupt(tuple)),
+    }
+}
+
+fn type_id_from_code(code: i64) -> Option<TypeId> {
+    let type_id = match code {
+        0 => TypeId::INVALID,
+        1 => TypeId::BOOLEAN,
+        2 => TypeId::TINYINT,
+        3 => TypeId::SMALLINT,
+        4 => TypeId::INTEGER,
+        5 => TypeId::BIGINT,
+        6 => TypeId::DECIMAL,
+        7 => TypeId::VARCHAR,
+        8 => TypeId::TIMESTAMP,
+        9 => TypeId::VECTOR,
+        _ => return None,
+    };
+    Some(type_id)
+}
+
+fn index_type_code(index_type: IndexType) -> i32 {
+    match index_type {
+        IndexType::BPlusTreeIndex => 0,
+        IndexType::SkipListIndex => 1,
+        IndexType::ExtendibleHashIndex => 2,
+    }
+}
+
+fn index_type_from_code(code: i64) -> Option<IndexType> {
+    match code {
+        0 => Some(IndexType::BPlusTreeIndex),
+        1 => Some(IndexType::SkipListIndex),
+        2 => Some(IndexType::ExtendibleHashIndex),
+        _ => None,
+    }
+}
+
+fn identity_code(identity: Identity) -> i32 {
+    match identity {
+        Identity::Always => 0,
+        Identity::ByDefault => 1,
+    }
+}
+
+fn identity_from_code(code: i64) -> Option<Identity> {
+    match code {
+        0 => Some(Identity::Always),
+        1 => Some(Identity::ByDefault),
+        _ => None,
+    }
+}
+
+fn referential_action_code(action: ReferentialAction) -> i32 {
+    match action {
+        ReferentialAction::Restrict => 0,
+        ReferentialAction::Cascade => 1,
+        ReferentialAction::SetNull => 2,
+    }
+}
+
+fn referential_action_from_code(code: i64) -> Option<ReferentialAction> {
+    match code {
+        0 => Some(ReferentialAction::Restrict),
+        1 => Some(ReferentialAction::Cascade),
+        2 => Some(ReferentialAction::SetNull),
+        _ => None,
+    }
+}
+
+// "LASTNAME ASC, AGE DESC"
+fn format_key_columns(key_columns: &[(String, SortOrder)]) -> String {
+    key_columns
+        .iter()
+        .map(|(name, order)| match order {
+            SortOrder::Asc => format!("{} ASC", name),
+            SortOrder::Desc => format!("{} DESC", name),
+        })
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+fn format_versions(versions: &[Vec<u32>]) -> String {
+    versions
+        .iter()
+        .map(|ids| {
+            ids.iter()
+                .map(|id| id.to_string())
+                .collect::<Vec<String>>()
+                .join(",")
+        })
+        .collect::<Vec<String>>()
+        .join(";")
+}
+
+fn parse_versions(text: &str) -> Option<Vec<Vec<u32>>> {
+    let versions = text
+        .split(';')
+        .map(|ids| ids.split(',').map(|id| id.parse().ok()).collect())
+        .collect::<Option<Vec<Vec<u32>>>>()?;
+    Some(versions)
+}
+
+fn parse_key_columns(text: &str) -> Vec<(String, SortOrder)> {
+    text.split(", ")
+        .filter_map(|column| {
+            let (name, order) = column.rsplit_once(' ')?;
+            let order = match order {
+                "DESC" => SortOrder::Desc,
+                _ => SortOrder::Asc,
+            };
+            Some((name.to_string(), order))
+        })
+        .collect()
+}
+
+pub(crate) struct SystemCatalog {
+    bpm: Arc<BufferPoolManager>,
+    sys_tables: TableHeap,
+    sys_columns: TableHeap,
+    sys_indexes: TableHeap,
+    sys_schemas: TableHeap,
+    sys_constraints: TableHeap,
+    sys_sequences: TableHeap,
+    sys_statistics: TableHeap,
+    // sequences write their state outside of DDL, whenever they reserve a block of values
+    sequences_latch: Mutex<()>,
+    tuple_ids: Sequence,
+    // rows written by the running DDL statement, by system table, None outside of one
+    journal: Mutex<Option<Vec<(usize, RecordId)>>>,
+}
+
+impl SystemCatalog {
+    // Opens the system tables of the pool's database file, creating them if the file is empty.
+    pub(crate) fn open(bpm: Arc<BufferPoolManager>) -> Result<Self, String> {
+        if bpm.page_count() == 0 {
+            return Self::bootstrap(bpm);
+        }
+        let page = bpm.read_page(SUPERBLOCK_PAGE_ID);
+        if read_u32(&page[..], 0) != MAGIC {
+            return Err("the file is not a sparrowdb database".to_string());
+        }
+        let [sys_tables, sys_columns, sys_indexes] =
+            [4, 8, 12].map(|at| read_u32(&page[..], at) as PageId);
+        let high_water_mark = read_u64(&page[..], TUPLE_ID_AT);
+        drop(page);
+        let system = Self {
+            sys_tables: TableHeap::open(Arc::clone(&bpm), sys_tables),
+            sys_columns: TableHeap::open(Arc::clone(&bpm), sys_columns),
+            sys_indexes: TableHeap::open(Arc::clone(&bpm), sys_indexes),
+            sys_schemas: Self::open_or_create(&bpm, 16)?,
+            sys_constraints: Self::open_or_create(&bpm, 20)?,
+            sys_sequences: Self::open_or_create(&bpm, 24)?,
+            sys_statistics: Self::open_or_create(&bpm, SYS_STATISTICS_AT)?,
+            sequences_latch: Mutex::new(()),
+            tuple_ids: Self::tuple_id_sequence(high_water_mark),
+            journal: Mutex::new(None),
+            bpm,
+        };
+        // DDL of transactions that did not end before the database was closed
+        let mut rolled_back = 0;
+        for heap in system.heaps() {
+            rolled_back += heap.roll_back_unfinished()?;
+        }
+        if rolled_back > 0 {
+            system.flush();
+        }
+        Ok(system)
+    }
+
+    fn tuple_id_sequence(high_water_mark: u64) -> Sequence {
+        let options = SequenceOptions::new(1).with_cache(TUPLE_ID_CACHE);
+        let state = match high_water_mark {
+            0 => Sequence::initial_state(&options),
+            _ => SequenceState {
+                last_value: high_water_mark as i64,
+                is_called: true,
+            },
+        };
+        Sequence::new(options, state)
+    }
+
+    /// A tuple id no row of the database has had. Only handing out the first id of a block
+    /// writes the superblock.
+    pub(crate) fn next_tuple_id(&self) -> Result<u64, String> {
+        let id = self.tuple_ids.nextval("of tuple ids", |state| {
+            let mut page = self.bpm.write_page(SUPERBLOCK_PAGE_ID);
+            write_u64(&mut page[..], TUPLE_ID_AT, state.last_value as u64);
+            drop(page);
+            self.bpm.flush_page(SUPERBLOCK_PAGE_ID);
+            Ok(())
+        })?;
+        Ok(id as u64)
+    }
+
+    // The system table whose first page id is at `at` in the superblock. Page 0 is the
+    // superblock, a zero page id means the file predates the table.
+    fn open_or_create(bpm: &Arc<BufferPoolManager>, at: usize) -> Result<TableHeap, String> {
+        let page_id = read_u32(&bpm.read_page(SUPERBLOCK_PAGE_ID)[..], at) as PageId;
+        if page_id != SUPERBLOCK_PAGE_ID {
+            return Ok(TableHeap::open(Arc::clone(bpm), page_id));
+        }
+        let heap = TableHeap::new(Arc::clone(bpm))?;
+        let mut page = bpm.write_page(SUPERBLOCK_PAGE_ID);
+        write_u32(&mut page[..], at, heap.get_first_page_id() as u32);
+        drop(page);
+        bpm.flush_page(heap.get_first_page_id());
+        bpm.flush_page(SUPERBLOCK_PAGE_ID);
+        Ok(heap)
+    }
+
+    fn bootstrap(bpm: Arc<BufferPoolManager>) -> Result<Self, String> {
+        let superblock = bpm.new_page().ok_or("no free frame for the superblock")?;
+        debug_assert_eq!(superblock, SUPERBLOCK_PAGE_ID);
+        let system = Self {
+            sys_tables: TableHeap::new(Arc::clone(&bpm))?,
+            sys_columns: TableHeap::new(Arc::clone(&bpm))?,
+            sys_indexes: TableHeap::new(Arc::clone(&bpm))?,
+            sys_schemas: TableHeap::new(Arc::clone(&bpm))?,
+            sys_constraints: TableHeap::new(Arc::clone(&bpm))?,
+            sys_sequences: TableHeap::new(Arc::clone(&bpm))?,
+            sys_statistics: TableHeap::new(Arc::clone(&bpm))?,
+            sequences_latch: Mutex::new(()),
+            tuple_ids: Self::tuple_id_sequence(0),
+            journal: Mutex::new(None),
+            bpm,
+        };
+        let mut page = system.bpm.write_page(SUPERBLOCK_PAGE_ID);
+        write_u32(&mut page[..], 0, MAGIC);
+        for (at, heap) in [
+            (4, &system.sys_tables),
+            (8, &system.sys_columns),
+            (12, &system.sys_indexes),
+            (16, &system.sys_schemas),
+            (20, &system.sys_constraints),
+            (24, &system.sys_sequences),
+            (SYS_STATISTICS_AT, &system.sys_statistics),
+        ] {
+            write_u32(&mut page[..], at, heap.get_first_page_id() as u32);
+        }
+        drop(page);
+        system.flush();
+        Ok(system)
+    }
+
+    pub(crate) fn insert_table(&self, row: &TableRow) -> Result<(), String> {
+        let mut written = vec![];
+        let result = self.write_columns(row, &mut written).and_then(|_| {
+            self.insert_row(
+                &self.sys_tables,
+                vec![
+                    Value::BigInt(row.table_id as i64),
+                    Value::Varchar(row.table_name.clone()),
+                    Value::BigInt(row.first_page_id as i64),
+                    Value::Varchar(format_versions(row.schema.get_versions())),
+                    Value::BigInt(row.schema_id as i64),
+                ],
+            )
+        });
+        self.flush();
+        if result.is_err() {
+            self.delete_rows(&self.sys_columns, written);
+        }
+        result.map(|_| ())
+    }
+
+    // Column rows of the table's current schema version, on disk when this returns.
+    fn write_columns(&self, row: &TableRow, written: &mut Vec<RecordId>) -> Result<(), String> {
+        for (position, column) in row.schema.get_columns().iter().enumerate() {
+            written.push(self.insert_row(
+                &self.sys_columns,
+                vec![
+                    Value::BigInt(row.table_id as i64),
+                    Value::Integer(position as i32),
+                    Value::Varchar(column.get_name().to_string()),
+                    Value::Integer(column.get_type() as i32),
+                    Value::Integer(column.get_length() as i32),
+                    Value::BigInt(row.schema.get_version() as i64),
+                    Value::BigInt(column.get_column_id() as i64),
+                    column.get_missing_value().clone(),
+                    column
+                        .get_default()
+                        .map_or(Value::Null, |default| Value::Varchar(default.to_string())),
+                    Value::Boolean(column.is_not_null()),
+                    column
+                        .get_identity()
+                        .map_or(Value::Null, |identity| Value::Integer(identity_code(identity))),
+                ],
+            )?);
+        }
+        self.flush();
+        Ok(())
+    }
+
+    fn delete_rows(&self, heap: &TableHeap, rids: Vec<RecordId>) {
+        for rid in rids {
+            self.delete_row(heap, rid);
+        }
+        self.flush();
+    }
+
+    fn column_rows(&self, table_id: u32, keep_version: Option<u32>) -> Vec<RecordId> {
+        self.sys_columns
+            .iter()
+            .filter(|(_, tuple)| {
+                int(tuple, 0).is_ok_and(|id| id == table_id as i64)
+                    && keep_version.is_none_or(|v| int(tuple, 5).is_ok_and(|c| c != v as i64))
+            })
+            .map(|(rid, _)| rid)
+            .collect()
+    }
+
+    // Switches the table over to the current version of `row.schema`.
+    pub(crate) fn alter_table(&self, row: &TableRow) -> Result<(), String> {
+        let mut written = vec![];
+        let result = self.write_columns(row, &mut written).and_then(|_| {
+            self.set_value(
+                &self.sys_tables,
+                row.table_id,
+                3,
+                Value::Varchar(format_versions(row.schema.get_versions())),
+            )
+        });
+        if result.is_err() {
+            self.delete_rows(&self.sys_columns, written);
+            return result;
+        }
+        let outdated = self.column_rows(row.table_id, Some(row.schema.get_version()));
+        self.delete_rows(&self.sys_columns, outdated);
+        Ok(())
+    }
+
+    pub(crate) fn rename_table(&self, table_id: u32, table_name: &str) -> Result<(), String> {
+        self.set_value(
+            &self.sys_tables,
+            table_id,
+            1,
+            Value::Varchar(table_name.to_string()),
+        )
+    }
+
+    pub(crate) fn insert_schema(&self, row: &SchemaRow) -> Result<(), String> {
+        self.insert_row(
+            &self.sys_schemas,
+            vec![
+                Value::BigInt(row.schema_id as i64),
+                Value::Varchar(row.schema_name.clone()),
+                Value::Varchar(row.owner.clone()),
+            ],
+        )?;
+        self.flush();
+        Ok(())
+    }
+
+    // The schema's tables are gone with it, their rows are dropped on the next startup if
+    // deleting them is cut short.
+    pub(crate) fn delete_schema(&self, schema_id: u32) -> Result<(), String> {
+        let (rid, _) = Self::find_row(&self.sys_schemas, schema_id).ok_or("no such catalog row")?;
+        self.delete_row(&self.sys_schemas, rid);
+        self.flush();
+        Ok(())
+    }
+
+    pub(crate) fn insert_constraint(&self, row: &ConstraintRow) -> Result<(), String> {
+        let ids = |ids: &[u32]| {
+            Value::Varchar(
+                ids.iter()
+                    .map(|id| id.to_string())
+                    .collect::<Vec<String>>()
+                    .join(","),
+            )
+        };
+        let constraint = &row.constraint;
+        let (check, columns, ref_table_id, ref_columns, on_delete) = match constraint.get_kind() {
+            ConstraintKind::Check(expr) => (
+                Value::Varchar(expr.to_string()),
+                Value::Null,
+                Value::Null,
+                Value::Null,
+                Value::Null,
+            ),
+            ConstraintKind::ForeignKey(fk) => (
+                Value::Null,
+                ids(&fk.columns),
+                Value::BigInt(fk.ref_table_id as i64),
+                ids(&fk.ref_columns),
+                Value::Integer(referential_action_code(fk.on_delete)),
+            ),
+        };
+        self.insert_row(
+            &self.sys_constraints,
+            vec![
+                Value::BigInt(constraint.get_constraint_id() as i64),
+                Value::BigInt(row.table_id as i64),
+                Value::Varchar(constraint.get_constraint_name().to_string()),
+                check,
+                columns,
+                ref_table_id,
+                ref_columns,
+                on_delete,
+            ],
+        )?;
+        self.flush();
+        Ok(())
+    }
+
+    pub(crate) fn set_check(&self, constraint_id: u32, expr: &Expr) -> Result<(), String> {
+        self.set_value(
+            &self.sys_constraints,
+            constraint_id,
+            3,
+            Value::Varchar(expr.to_string()),
+        )
+    }
+
+    pub(crate) fn delete_constraint(&self, constraint_id: u32) -> Result<(), String> {
+        let (rid, _) =
+            Self::find_row(&self.sys_constraints, constraint_id).ok_or("no such catalog row")?;
+        self.delete_row(&self.sys_constraints, rid);
+        self.flush();
+        Ok(())
+    }
+
+    pub(crate) fn load_constraints(&self) -> Result<Vec<ConstraintRow>, String> {
+        self.sys_constraints
+            .iter()
+            .map(|(_, tuple)| {
+                let ids = |idx| -> Result<Vec<u32>, String> {
+                    text(&tuple, idx)?
+                        .split(',')
+                        .map(|id| id.parse().map_err(|_| corrupt(&tuple)))
+                        .collect()
+                };
+                let kind = match tuple.get_value(3) {
+                    Some(Value::Varchar(check)) => ConstraintKind::Check(Expr::parse(check)?),
+                    _ => ConstraintKind::ForeignKey(ForeignKey {
+                        columns: ids(4)?,
+                        ref_table_id: int(&tuple, 5)? as u32,
+                        ref_columns: ids(6)?,
+                        on_delete: referential_action_from_code(int(&tuple, 7)?)
+                            .ok_or_else(|| corrupt(&tuple))?,
+                    }),
+                };
+                Ok(ConstraintRow {
+                    table_id: int(&tuple, 1)? as u32,
+                    constraint: ConstraintInfo::new(int(&tuple, 0)? as u32, text(&tuple, 2)?, kind),
+                })
+            })
+            .collect()
+    }
+
+    pub(crate) fn insert_sequence(&self, row: &SequenceRow) -> Result<RecordId, String> {
+        let options = &row.options;
+        let (owner_table_id, owner_column_id) = match row.owned_by {
+            Some((table_id, column_id)) => (
+                Value::BigInt(table_id as i64),
+                Value::BigInt(column_id as i64),
+            ),
+            None => (Value::Null, Value::Null),
+        };
+        let _latch = self.sequences_latch.lock().unwrap();
+        let rid = self.insert_row(
+            &self.sys_sequences,
+            vec![
+                Value::BigInt(row.sequence_id as i64),
+                Value::BigInt(row.schema_id as i64),
+                Value::Varchar(row.sequence_name.clone()),
+                Value::BigInt(options.get_increment()),
+                Value::BigInt(options.get_min_value()),
+                Value::BigInt(options.get_max_value()),
+                Value::BigInt(options.get_start()),
+                Value::BigInt(options.get_cache()),
+                Value::Boolean(options.is_cycle()),
+                Value::BigInt(row.state.last_value),
+                Value::Boolean(row.state.is_called),
+                owner_table_id,
+                owner_column_id,
+            ],
+        )?;
+        self.flush();
+        Ok(rid)
+    }
+
+    // The state the sequence at `rid` carries on from after a restart. The state is as wide
+    // as before, the row is overwritten in its slot and only its page is written out.
+    pub(crate) fn set_sequence_state(
+        &self,
+        rid: RecordId,
+        state: SequenceState,
+    ) -> Result<(), String> {
+        let _latch = self.sequences_latch.lock().unwrap();
+        let tuple = self
+            .sys_sequences
+            .get_tuple(rid)
+            .ok_or("no such catalog row")?;
+        let mut values = tuple.get_values().to_vec();
+        values[9] = Value::BigInt(state.last_value);
+        values[10] = Value::Boolean(state.is_called);
+        self.sys_sequences
+            .update_tuple(rid, &Tuple::new(tuple.get_id(), values))?;
+        self.bpm.flush_page(rid.page_id);
+        Ok(())
+    }
+
+    pub(crate) fn delete_sequence(&self, rid: RecordId) -> Result<(), String> {
+        let _latch = self.sequences_latch.lock().unwrap();
+        if !self.delete_row(&self.sys_sequences, rid) {
+            return Err("no such catalog row".to_string());
+        }
+        self.flush();
+        Ok(())
+    }
+
+    pub(crate) fn load_sequences(&self) -> Result<Vec<(RecordId, SequenceRow)>, String> {
+        self.sys_sequences
+            .iter()
+            .map(|(rid, tuple)| {
+                let flag = |idx| match tuple.get_value(idx) {
+                    Some(Value::Boolean(b)) => Ok(*b),
+                    _ => Err(corrupt(&tuple)),
+                };
+                let owned_by = match (int(&tuple, 11), int(&tuple, 12)) {
+                    (Ok(table_id), Ok(column_id)) => Some((table_id as u32, column_id as u32)),
+                    _ => None,
+                };
+                let row = SequenceRow {
+                    sequence_id: int(&tuple, 0)? as u32,
+                    schema_id: int(&tuple, 1)? as u32,
+                    sequence_name: text(&tuple, 2)?,
+                    options: SequenceOptions::new(int(&tuple, 3)?)
+                        .with_min_value(int(&tuple, 4)?)
+                        .with_max_value(int(&tuple, 5)?)
+                        .with_start(int(&tuple, 6)?)
+                        .with_cache(int(&tuple, 7)?)
+                        .with_cycle(flag(8)?),
+                    state: SequenceState {
+                        last_value: int(&tuple, 9)?,
+                        is_called: flag(10)?,
+                    },
+                    owned_by,
+                };
+                Ok((rid, row))
+            })
+            .collect()
+    }
+
+    // Replaces the statistics of the table. Only called under the catalog's DDL latch.
+    pub(crate) fn set_statistics(
+        &self,
+        table_id: u32,
+        statistics: &TableStatistics,
+    ) -> Result<(), String> {
+        self.delete_statistics(table_id);
+        let mut written = vec![];
+        let mut write = |values: Vec<Value>| -> Result<(), String> {
+            written.push(self.insert_row(&self.sys_statistics, values)?);
+            Ok(())
+        };
+        let mut columns = statistics.columns.iter().collect::<Vec<_>>();
+        columns.sort_by_key(|(column_id, _)| **column_id);
+        let result = columns
+            .into_iter()
+            .try_for_each(|(column_id, column)| {
+                let mut values = vec![
+                    Value::BigInt(table_id as i64),
+                    Value::BigInt(*column_id as i64),
+                    Value::Decimal(column.null_fraction),
+                    Value::Decimal(column.n_distinct),
+                    Value::BigInt(column.most_common.len() as i64),
+                ];
+                values.extend(column.most_common.iter().map(|(value, _)| value.clone()));
+                values.extend(
+                    column
+                        .most_common
+                        .iter()
+                        .map(|(_, frequency)| Value::Decimal(*frequency)),
+                );
+                values.extend(column.histogram.iter().cloned());
+                write(values)
+            })
+            .and_then(|_| {
+                write(vec![
+                    Value::BigInt(table_id as i64),
+                    Value::Null,
+                    Value::BigInt(statistics.row_count as i64),
+                    Value::BigInt(statistics.page_count as i64),
+                ])
+            });
+        if result.is_err() {
+            self.delete_rows(&self.sys_statistics, written);
+        }
+        self.flush();
+        result
+    }
+
+    // The table's row goes first, the statistics are gone once it is.
+    pub(crate) fn delete_statistics(&self, table_id: u32) {
+        let rows = |table_row: bool| {
+            self.sys_statistics
+                .iter()
+                .filter(|(_, tuple)| {
+                    int(tuple, 0).is_ok_and(|id| id == table_id as i64)
+                        && matches!(tuple.get_value(1), Some(Value::Null)) == table_row
+                })
+                .map(|(rid, _)| rid)
+                .collect::<Vec<RecordId>>()
+        };
+        self.delete_rows(&self.sys_statistics, rows(true));
+        self.delete_rows(&self.sys_statistics, rows(false));
+    }
+
+    pub(crate) fn load_statistics(&self) -> Result<Vec<(u32, TableStatistics)>, String> {
+        let mut tables: HashMap<u32, TableStatistics> = HashMap::new();
+        let mut columns = vec![];
+        for (_, tuple) in self.sys_statistics.iter() {
+            let table_id = int(&tuple, 0)? as u32;
+            if matches!(tuple.get_value(1), Some(Value::Null)) {
+                let statistics = TableStatistics {
+                    row_count: int(&tuple, 2)? as u64,
+                    page_count: int(&tuple, 3)? as u64,
+                    columns: HashMap::new(),
+                };
+                tables.insert(table_id, statistics);
+                continue;
+            }
+            let fraction = |idx| match tuple.get_value(idx) {
+                Some(Value::Decimal(f)) => Ok(*f),
+                _ => Err(corrupt(&tuple)),
+            };
+            let values = tuple.get_values();
+            let n = int(&tuple, 4)? as usize;
+            if values.len() < 5 + 2 * n {
+                return Err(corrupt(&tuple));
+            }
+            let most_common = (0..n)
+                .map(|i| Ok((values[5 + i].clone(), fraction(5 + n + i)?)))
+                .collect::<Result<Vec<(Value, f64)>, String>>()?;
+            let column = ColumnStatistics {
+                null_fraction: fraction(2)?,
+                n_distinct: fraction(3)?,
+                most_common,
+                histogram: values[5 + 2 * n..].to_vec(),
+            };
+            columns.push((table_id, int(&tuple, 1)? as u32, column));
+        }
+        for (table_id, column_id, column) in columns {
+            if let Some(statistics) = tables.get_mut(&table_id) {
+                statistics.columns.insert(column_id, column);
+            }
+        }
+        let mut tables = tables.into_iter().collect::<Vec<_>>();
+        tables.sort_by_key(|(table_id, _)| *table_id);
+        Ok(tables)
+    }
+
+    pub(crate) fn insert_index(&self, row: &IndexRow) -> Result<(), String> {
+        self.insert_row(
+            &self.sys_indexes,
+            vec![
+                Value::BigInt(row.index_id as i64),
+                Value::BigInt(row.table_id as i64),
+                Value::Varchar(row.index_name.clone()),
+                Value::Varchar(format_key_columns(&row.key_columns)),
+                Value::Integer(index_type_code(row.index_type)),
+                Value::Boolean(row.is_unique),
+                Value::Boolean(row.is_primary_key),
+                row.header_page_id
+                    .map_or(Value::Null, |page_id| Value::BigInt(page_id as i64)),
+            ],
+        )?;
+        self.flush();
+        Ok(())
+    }
+
+    // Starts recording the rows the DDL statement about to run writes. They keep undo slots until
+    // its transaction commits or aborts, see `commit_writes` and `roll_back_writes`.
+    pub(crate) fn begin_journal(&self) {
+        *self.journal.lock().unwrap() = Some(vec![]);
+    }
+
+    // The rows written since `begin_journal`, by system table.
+    pub(crate) fn end_journal(&self) -> Vec<(usize, RecordId)> {
+        self.journal.lock().unwrap().take().unwrap_or_default()
+    }
+
+    fn insert_row(&self, heap: &TableHeap, values: Vec<Value>) -> Result<RecordId, String> {
+        let tuple = Tuple::new(self.next_tuple_id()?, values);
+        let mut journal = self.journal.lock().unwrap();
+        let Some(journal) = journal.as_mut() else {
+            return heap.insert_tuple(&tuple);
+        };
+        // hidden until its undo slot is in place
+        let rid = heap.insert_deleted_tuple(&tuple)?;
+        heap.begin_write(rid, None)?;
+        heap.rollback_delete(rid);
+        journal.push((self.heap_no(heap), rid));
+        Ok(rid)
+    }
+
+    fn update_row(&self, heap: &TableHeap, rid: RecordId, tuple: &Tuple) -> Result<(), String> {
+        if let Some(journal) = self.journal.lock().unwrap().as_mut() {
+            let before = heap.get_tuple(rid).ok_or("no such catalog row")?;
+            heap.begin_write(rid, Some(&before))?;
+            journal.push((self.heap_no(heap), rid));
+        }
+        heap.update_tuple(rid, tuple)
+    }
+
+    // Only marked deleted during DDL, the delete is applied once its transaction commits.
+    fn delete_row(&self, heap: &TableHeap, rid: RecordId) -> bool {
+        let mut journal = self.journal.lock().unwrap();
+        let Some(journal) = journal.as_mut() else {
+            return heap.apply_delete(rid);
+        };
+        let Some(before) = heap.get_tuple(rid) else {
+            return false;
+        };
+        if heap.begin_write(rid, Some(&before)).is_err() {
+            return false;
+        }
+        journal.push((self.heap_no(heap), rid));
+        heap.mark_delete(rid)
+    }
+
+    // Makes the rows written by the DDL of a committing transaction durable.
+    pub(crate) fn commit_writes(&self, writes: &[(usize, RecordId)]) {
+        let heaps = self.heaps();
+        for (heap_no, rid) in writes {
+            let heap = heaps[*heap_no];
+            if heap
+                .get_tuple_marked(*rid)
+                .is_some_and(|(_, deleted)| deleted)
+            {
+                heap.apply_delete(*rid);
+            }
+            heap.end_write(*rid);
+        }
+        self.flush();
+    }
+
+    // Puts the rows written by the DDL of an aborting transaction back as they were.
+    pub(crate) fn roll_back_writes(&self, writes: &[(usize, RecordId)]) -> Result<(), String> {
+        let heaps = self.heaps();
+        let _latch = self.sequences_latch.lock().unwrap();
+        let result = writes.iter().rev().try_for_each(|(heap_no, rid)| {
+            let heap = heaps[*heap_no];
+            if !std::ptr::eq(heap, &self.sys_sequences) {
+                return heap.roll_back(*rid);
+            }
+            // the values handed out meanwhile stay reserved
+            let current = heap.get_tuple(*rid);
+            heap.roll_back(*rid)?;
+            match (current, heap.get_tuple(*rid)) {
+                (Some(current), Some(restored)) => {
+                    let mut values = restored.get_values().to_vec();
+                    values[9..11].clone_from_slice(&current.get_values()[9..11]);
+                    heap.update_tuple(*rid, &Tuple::new(restored.get_id(), values))
+                }
+                _ => Ok(()),
+            }
+        });
+        self.flush();
+        result
+    }
+
+    fn heap_no(&self, heap: &TableHeap) -> usize {
+        self.heaps()
+            .iter()
+            .position(|h| std::ptr::eq(*h, heap))
+            .unwrap()
+    }
+
+    // The row of `heap` whose first column is `id`.
+    fn find_row(heap: &TableHeap, id: u32) -> Option<(RecordId, Tuple)> {
+        heap.iter()
+            .find(|(_, tuple)| int(tuple, 0).is_ok_and(|row_id| row_id == id as i64))
+    }
+
+    fn set_value(&self, heap: &TableHeap, id: u32, idx: usize, value: Value) -> Result<(), String> {
+        let (rid, tuple) = Self::find_row(heap, id).ok_or("no such catalog row")?;
+        let mut values = tuple.get_values().to_vec();
+        values[idx] = value;
+        self.update_row(heap, rid, &Tuple::new(tuple.get_id(), values))?;
+        self.flush();
+        Ok(())
+    }
+
+    pub(crate) fn set_first_page_id(&self, table_id: u32, page_id: PageId) -> Result<(), String> {
+        self.set_value(&self.sys_tables, table_id, 2, Value::BigInt(page_id as i64))
+    }
+
+    pub(crate) fn set_key_columns(
+        &self,
+        index_id: u32,
+        key_columns: &[(String, SortOrder)],
+    ) -> Result<(), String> {
+        self.set_value(
+            &self.sys_indexes,
+            index_id,
+            3,
+            Value::Varchar(format_key_columns(key_columns)),
+        )
+    }
+
+    pub(crate) fn set_header_page_id(&self, index_id: u32, page_id: PageId) -> Result<(), String> {
+        self.set_value(
+            &self.sys_indexes,
+            index_id,
+            7,
+            Value::BigInt(page_id as i64),
+        )
+    }
+
+    // The table is gone once its sys_tables row is, its columns are cleaned up on startup if
+    // deleting them is cut short. Rows of the table's indexes are deleted separately.
+    pub(crate) fn delete_table(&self, table_id: u32) -> Result<(), String> {
+        let (rid, _) = Self::find_row(&self.sys_tables, table_id).ok_or("no such catalog row")?;
+        self.delete_row(&self.sys_tables, rid);
+        self.flush();
+        self.delete_rows(&self.sys_columns, self.column_rows(table_id, None));
+        Ok(())
+    }
+
+    pub(crate) fn delete_index(&self, index_id: u32) -> Result<(), String> {
+        let (rid, _) = Self::find_row(&self.sys_indexes, index_id).ok_or("no such catalog row")?;
+        self.delete_row(&self.sys_indexes, rid);
+        self.flush();
+        Ok(())
+    }
+
+    fn heaps(&self) -> [&TableHeap; 7] {
+        [
+            &self.sys_tables,
+            &self.sys_columns,
+            &self.sys_indexes,
+            &self.sys_schemas,
+            &self.sys_constraints,
+            &self.sys_sequences,
+            &self.sys_statistics,
+        ]
+    }
+
+    // Pages of the superblock and the system tables.
+    pub(crate) fn page_ids(&self) -> Vec<PageId> {
+        let mut page_ids = vec![SUPERBLOCK_PAGE_ID];
+        for heap in self.heaps() {
+            page_ids.extend(heap.page_ids());
+        }
+        page_ids
+    }
+
+    // Writes the superblock and the pages of the system tables. The pages of user tables are
+    // left to the pool, they may hold rows of transactions that have not ended.
+    fn flush(&self) {
+        for page_id in self.page_ids() {
+            self.bpm.flush_page(page_id);
+        }
+    }
+
+    // Tables in the order they were created, with their columns in schema order.
+    pub(crate) fn load_tables(&self) -> Result<Vec<TableRow>, String> {
+        type ColumnRow = (i64, Column, RecordId);
+        let mut columns: HashMap<(u32, u32), Vec<ColumnRow>> = HashMap::new();
+        for (rid, tuple) in self.sys_columns.iter() {
+            let type_id = type_id_from_code(int(&tuple, 3)?).ok_or_else(|| corrupt(&tuple))?;
+            let column = Column::new(text(&tuple, 2)?, type_id, int(&tuple, 4)? as u32)
+                .with_column_id(int(&tuple, 6)? as u32)
+                .with_missing_value(tuple.get_value(7).ok_or_else(|| corrupt(&tuple))?.clone())
+                .with_not_null(matches!(tuple.get_value(9), Some(Value::Boolean(true))));
+            let column = match tuple.get_value(8) {
+                Some(Value::Varchar(default)) => column.with_default(Expr::parse(default)?),
+                _ => column,
+            };
+            let column = match int(&tuple, 10) {
+                Ok(code) => {
+                    column.with_identity(identity_from_code(code).ok_or_else(|| corrupt(&tuple))?)
+                }
+                Err(_) => column,
+            };
+            columns
+                .entry((int(&tuple, 0)? as u32, int(&tuple, 5)? as u32))
+                .or_default()
+                .push((int(&tuple, 1)?, column, rid));
+        }
+        let mut tables = vec![];
+        for (_, tuple) in self.sys_tables.iter() {
+            let table_id = int(&tuple, 0)? as u32;
+            let versions = parse_versions(&text(&tuple, 3)?).ok_or_else(|| corrupt(&tuple))?;
+            let version = versions.len() as u32 - 1;
+            let mut table_columns = columns.remove(&(table_id, version)).unwrap_or_default();
+            table_columns.sort_by_key(|(position, _, _)| *position);
+            tables.push(TableRow {
+                table_id,
+                schema_id: int(&tuple, 4).unwrap_or(0) as u32,
+                table_name: text(&tuple, 1)?,
+                first_page_id: int(&tuple, 2)? as PageId,
+                schema: Schema::with_versions(
+                    table_columns.into_iter().map(|(_, c, _)| c).collect(),
+                    versions,
+                ),
+            });
+        }
+        // columns of a table whose creation did not finish or of an outdated schema version
+        let orphaned = columns.into_values().flatten().map(|(_, _, rid)| rid);
+        self.delete_rows(&self.sys_columns, orphaned.collect());
+        Ok(tables)
+    }
+
+    pub(crate) fn load_schemas(&self) -> Result<Vec<SchemaRow>, String> {
+        self.sys_schemas
+            .iter()
+            .map(|(_, tuple)| {
+                Ok(SchemaRow {
+                    schema_id: int(&tuple, 0)? as u32,
+                    schema_name: text(&tuple, 1)?,
+                    owner: text(&tuple, 2)?,
+                })
+            })
+            .collect()
+    }
+
+    pub(crate) fn load_indexes(&self) -> Result<Vec<IndexRow>, String> {
+        self.sys_indexes
+            .iter()
+            .map(|(_, tuple)| {
+                let flag = |idx| match tuple.get_value(idx) {
+                    Some(Value::Boolean(b)) => Ok(*b),
+                    _ => Err(corrupt(&tuple)),
+                };
+                Ok(IndexRow {
+                    index_id: int(&tuple, 0)? as u32,
+                    table_id: int(&tuple, 1)? as u32,
+                    index_name: text(&tuple, 2)?,
+                    key_columns: parse_key_columns(&text(&tuple, 3)?),
+                    index_type: index_type_from_code(int(&tuple, 4)?)
+                        .ok_or_else(|| corrupt(&tuple))?,
+                    is_unique: flag(5)?,
+                    is_primary_key: flag(6)?,
+                    header_page_id: int(&tuple, 7).ok().map(|page_id| page_id as PageId),
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_key_columns_round_trip() {
+        let key_columns = vec![
+            ("LAST NAME".to_string(), SortOrder::Asc),
+            ("age".to_string(), SortOrder::Desc),
+        ];
+        assert_eq!(
+            parse_key_columns(&format_key_columns(&key_columns)),
+            key_columns
+        );
+    }
+
+    #[test]
+    fn test_rejects_files_without_superblock() {
+        let bpm = Arc::new(BufferPoolManager::new(4, 2));
+        bpm.new_page().unwrap();
+        assert!(SystemCatalog::open(bpm).is_err());
+    }
+}
@@ -0,0 +1,819 @@
+use crate::bufferpoolmanager::BufferPoolManager;
+use crate::frameheader::PageData;
+use crate::index::Index;
+use crate::index_key::{encode_u64, KeySchema};
+use crate::query_types::{Schema, Tuple};
+use crate::skiplistindex::SkipListIndex;
+use common::types::{PageId, RecordId};
+use std::collections::HashMap;
+use std::fmt::{self, Debug, Display, Formatter};
+use std::sync::{Arc, Mutex};
+use storage_engine::disk_manager::PAGE_SIZE;
+
+// Tuples live in a linked list of slotted pages, every page goes through the buffer pool:
+//
+//   table page   [next page id u32][slot count u16][free space end u16]
+//                ([tuple offset u16][tuple length u16][flags u8])* ... free ... tuple data
+//
+// Slots grow from the front of the page and tuple data from the back. A record id is the page and
+// the slot, so it never changes: an update that no longer fits the page moves the tuple to another
+// page and turns its home slot into a forwarding pointer [page id u32][slot u16]. Deleting takes
+// two steps, `mark_delete` hides the tuple but can still be rolled back, `apply_delete` frees the
+// slot for good. Space of freed slots and overwritten tuples is not reclaimed.
+//
+// A record written by a transaction that has not ended has an undo slot next to it, holding
+// [home page id u32][home slot u16] and the tuple as it was before, nothing if the transaction
+// inserted it. Pages may reach the disk at any time, an undo slot found on startup means the
+// transaction never ended and the record is rolled back to it.
+//
+// Inserts are serialized by the latch on the last page id, callers serialize writes to the same
+// record.
+
+const INVALID_PAGE_ID: u32 = u32::MAX;
+const PAGE_HEADER: usize = 4 + 2 + 2;
+const SLOT_SIZE: usize = 2 + 2 + 1;
+const FORWARD_SIZE: usize = 4 + 2;
+const MAX_TUPLE_SIZE: usize = PAGE_SIZE - PAGE_HEADER - SLOT_SIZE;
+
+// slot flags
+const DELETED: u8 = 1; // marked deleted, the tuple is still in place
+const FREE: u8 = 2; // delete applied, the slot is never handed out again
+const MOVED: u8 = 4; // holds a forwarding pointer instead of the tuple
+const MOVED_IN: u8 = 8; // tuple of a forwarded record, only reachable through its home slot
+const UNDO: u8 = 16; // image of a record from before a running transaction wrote it
+
+fn read_u16(data: &PageData, at: usize) -> u16 {
+    u16::from_be_bytes(data[at..at + 2].try_into().unwrap())
+}
+
+fn write_u16(data: &mut PageData, at: usize, v: u16) {
+    data[at..at + 2].copy_from_slice(&v.to_be_bytes());
+}
+
+fn read_u32(data: &PageData, at: usize) -> u32 {
+    u32::from_be_bytes(data[at..at + 4].try_into().unwrap())
+}
+
+fn write_u32(data: &mut PageData, at: usize, v: u32) {
+    data[at..at + 4].copy_from_slice(&v.to_be_bytes());
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Slot {
+    offset: usize,
+    len: usize,
+    flags: u8,
+}
+
+fn init_page(data: &mut PageData) {
+    write_u32(data, 0, INVALID_PAGE_ID);
+    write_u16(data, 4, 0);
+    write_u16(data, 6, PAGE_SIZE as u16);
+}
+
+fn next_page_id(data: &PageData) -> Option<PageId> {
+    match read_u32(data, 0) {
+        INVALID_PAGE_ID => None,
+        page_id => Some(page_id as PageId),
+    }
+}
+
+fn slot_count(data: &PageData) -> u16 {
+    read_u16(data, 4)
+}
+
+fn free_space_end(data: &PageData) -> usize {
+    read_u16(data, 6) as usize
+}
+
+fn free_space(data: &PageData) -> usize {
+    free_space_end(data) - (PAGE_HEADER + slot_count(data) as usize * SLOT_SIZE)
+}
+
+fn get_slot(data: &PageData, slot: u16) -> Option<Slot> {
+    if slot >= slot_count(data) {
+        return None;
+    }
+    let at = PAGE_HEADER + slot as usize * SLOT_SIZE;
+    Some(Slot {
+        offset: read_u16(data, at) as usize,
+        len: read_u16(data, at + 2) as usize,
+        flags: data[at + 4],
+    })
+}
+
+fn set_slot(data: &mut PageData, slot: u16, s: Slot) {
+    let at = PAGE_HEADER + slot as usize * SLOT_SIZE;
+    write_u16(data, at, s.offset as u16);
+    write_u16(data, at + 2, s.len as u16);
+    data[at + 4] = s.flags;
+}
+
+fn read_forward(data: &PageData, s: Slot) -> RecordId {
+    RecordId::new(
+        read_u32(data, s.offset) as PageId,
+        read_u16(data, s.offset + 4),
+    )
+}
+
+fn write_forward(data: &mut PageData, s: Slot, to: RecordId) {
+    write_u32(data, s.offset, to.page_id as u32);
+    write_u16(data, s.offset + 4, to.slot);
+}
+
+// Appends a slot holding `bytes`, None if the page has no room left.
+fn insert_into_page(data: &mut PageData, bytes: &[u8], flags: u8) -> Option<u16> {
+    let slot = slot_count(data);
+    if free_space(data) < bytes.len() + SLOT_SIZE || slot == u16::MAX {
+        return None;
+    }
+    let offset = free_space_end(data) - bytes.len();
+    data[offset..offset + bytes.len()].copy_from_slice(bytes);
+    write_u16(data, 6, offset as u16);
+    write_u16(data, 4, slot + 1);
+    set_slot(
+        data,
+        slot,
+        Slot {
+            offset,
+            len: bytes.len(),
+            flags,
+        },
+    );
+    Some(slot)
+}
+
+// Rewrites the tuple of a slot, in place if it is not longer than before and at the end of the
+// free space otherwise. False if the page has no room for it.
+fn overwrite_in_page(data: &mut PageData, slot: u16, bytes: &[u8]) -> bool {
+    let mut s = get_slot(data, slot).unwrap();
+    if bytes.len() > s.len {
+        if free_space(data) < bytes.len() {
+            return false;
+        }
+        s.offset = free_space_end(data) - bytes.len();
+        write_u16(data, 6, s.offset as u16);
+    }
+    s.len = bytes.len();
+    data[s.offset..s.offset + s.len].copy_from_slice(bytes);
+    set_slot(data, slot, s);
+    true
+}
+
+fn serialize(tuple: &Tuple) -> Result<Vec<u8>, String> {
+    bincode::serialize(tuple).map_err(|e| format!("could not serialize {}: {}", tuple, e))
+}
+
+fn deserialize(bytes: &[u8]) -> Tuple {
+    bincode::deserialize(bytes).expect("corrupt tuple in table page")
+}
+
+fn read_tuple(bpm: &BufferPoolManager, at: RecordId) -> Tuple {
+    let page = bpm.read_page(at.page_id);
+    let slot = get_slot(&page, at.slot).unwrap();
+    deserialize(&page[slot.offset..slot.offset + slot.len])
+}
+
+pub struct TableHeap {
+    bpm: Arc<BufferPoolManager>,
+    first_page_id: PageId,
+    last_page_id: Mutex<PageId>,
+    index: SkipListIndex,
+    // the undo slot of each record written by a running transaction
+    undo: Mutex<HashMap<RecordId, RecordId>>,
+}
+
+impl Debug for TableHeap {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TableHeap")
+            .field("first_page_id", &self.first_page_id)
+            .field("last_page_id", &*self.last_page_id.lock().unwrap())
+            .finish()
+    }
+}
+
+impl Display for TableHeap {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Table Heap:")?;
+        for (rid, tuple) in self.iter() {
+            writeln!(f, "  ({}, {}): {}", rid.page_id, rid.slot, tuple)?;
+        }
+        Ok(())
+    }
+}
+
+impl TableHeap {
+    // An empty heap with its first page allocated in the pool.
+    pub fn new(bpm: Arc<BufferPoolManager>) -> Result<Self, String> {
+        let first_page_id = Self::allocate_page(&bpm)?;
+        Ok(Self {
+            bpm,
+            first_page_id,
+            last_page_id: Mutex::new(first_page_id),
+            index: SkipListIndex::new(),
+            undo: Mutex::new(HashMap::new()),
+        })
+    }
+
+    // A heap whose pages are already in the database file, e.g. after a restart.
+    pub fn open(bpm: Arc<BufferPoolManager>, first_page_id: PageId) -> Self {
+        let mut last_page_id = first_page_id;
+        while let Some(page_id) = next_page_id(&bpm.read_page(last_page_id)) {
+            last_page_id = page_id;
+        }
+        Self {
+            bpm,
+            first_page_id,
+            last_page_id: Mutex::new(last_page_id),
+            index: SkipListIndex::new(),
+            undo: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn allocate_page(bpm: &BufferPoolManager) -> Result<PageId, String> {
+        let page_id = bpm
+            .new_page()
+            .ok_or_else(|| "no free frame for a new table page".to_string())?;
+        init_page(&mut bpm.write_page(page_id));
+        Ok(page_id)
+    }
+
+    pub fn get_first_page_id(&self) -> PageId {
+        self.first_page_id
+    }
+
+    // Every page of the heap, in list order.
+    pub fn page_ids(&self) -> Vec<PageId> {
+        let mut page_ids = vec![self.first_page_id];
+        while let Some(page_id) = next_page_id(&self.bpm.read_page(*page_ids.last().unwrap())) {
+            page_ids.push(page_id);
+        }
+        page_ids
+    }
+
+    pub fn insert_tuple(&self, tuple: &Tuple) -> Result<RecordId, String> {
+        self.insert_bytes(&serialize(tuple)?, 0)
+    }
+
+    // Inserts the tuple marked deleted, readers do not see it until `rollback_delete`.
+    pub fn insert_deleted_tuple(&self, tuple: &Tuple) -> Result<RecordId, String> {
+        self.insert_bytes(&serialize(tuple)?, DELETED)
+    }
+
+    // Appends to the last page, or to a new page linked behind it once the last page is full.
+    fn insert_bytes(&self, bytes: &[u8], flags: u8) -> Result<RecordId, String> {
+        if bytes.len() > MAX_TUPLE_SIZE {
+            return Err(format!(
+                "tuple of {} bytes does not fit a page",
+                bytes.len()
+            ));
+        }
+        let mut last_page_id = self.last_page_id.lock().unwrap();
+        let mut page = self.bpm.write_page(*last_page_id);
+        if let Some(slot) = insert_into_page(&mut page, bytes, flags) {
+            return Ok(RecordId::new(*last_page_id, slot));
+        }
+        let page_id = Self::allocate_page(&self.bpm)?;
+        let slot = insert_into_page(&mut self.bpm.write_page(page_id), bytes, flags).unwrap();
+        write_u32(&mut page, 0, page_id as u32);
+        *last_page_id = page_id;
+        Ok(RecordId::new(page_id, slot))
+    }
+
+    // Flags of the record's home slot and where its tuple is stored. None if the record does not
+    // exist or was deleted for good.
+    fn locate(&self, rid: RecordId) -> Option<(u8, RecordId)> {
+        let page = self.bpm.checked_read_page(rid.page_id)?;
+        let slot = get_slot(&page, rid.slot)?;
+        if slot.flags & (FREE | MOVED_IN | UNDO) != 0 {
+            return None;
+        }
+        if slot.flags & MOVED != 0 {
+            return Some((slot.flags, read_forward(&page, slot)));
+        }
+        Some((slot.flags, rid))
+    }
+
+    // The tuple of the record, None if there is none or it is marked deleted.
+    pub fn get_tuple(&self, rid: RecordId) -> Option<Tuple> {
+        let (flags, at) = self.locate(rid)?;
+        if flags & DELETED != 0 {
+            return None;
+        }
+        Some(read_tuple(&self.bpm, at))
+    }
+
+    // The tuple of the record even if it is marked deleted, and whether it is.
+    pub fn get_tuple_marked(&self, rid: RecordId) -> Option<(Tuple, bool)> {
+        let (flags, at) = self.locate(rid)?;
+        Some((read_tuple(&self.bpm, at), flags & DELETED != 0))
+    }
+
+    // Replaces the tuple of the record. It stays on its page if there is room, otherwise it moves
+    // and the record id keeps pointing at it.
+    pub fn update_tuple(&self, rid: RecordId, tuple: &Tuple) -> Result<(), String> {
+        let bytes = serialize(tuple)?;
+        let (flags, at) = self
+            .locate(rid)
+            .ok_or_else(|| format!("no tuple at {:?}", rid))?;
+        if flags & DELETED != 0 {
+            return Err(format!("tuple at {:?} is deleted", rid));
+        }
+        if overwrite_in_page(&mut self.bpm.write_page(at.page_id), at.slot, &bytes) {
+            return Ok(());
+        }
+        let moved = self.insert_bytes(&bytes, MOVED_IN)?;
+        if at != rid {
+            self.set_flags(at, FREE);
+        }
+        let mut page = self.bpm.write_page(rid.page_id);
+        let mut slot = get_slot(&page, rid.slot).unwrap();
+        // the home slot held a tuple or a forwarding pointer before, both are long enough
+        debug_assert!(slot.len >= FORWARD_SIZE);
+        slot.flags = MOVED;
+        write_forward(&mut page, slot, moved);
+        set_slot(&mut page, rid.slot, slot);
+        Ok(())
+    }
+
+    fn set_flags(&self, rid: RecordId, flags: u8) {
+        let mut page = self.bpm.write_page(rid.page_id);
+        let mut slot = get_slot(&page, rid.slot).unwrap();
+        slot.flags = flags;
+        set_slot(&mut page, rid.slot, slot);
+    }
+
+    // Hides the tuple from readers. False if it does not exist or is already marked.
+    pub fn mark_delete(&self, rid: RecordId) -> bool {
+        self.toggle_deleted(rid, true)
+    }
+
+    // Undoes `mark_delete`, e.g. when the deleting transaction aborts.
+    pub fn rollback_delete(&self, rid: RecordId) -> bool {
+        self.toggle_deleted(rid, false)
+    }
+
+    fn toggle_deleted(&self, rid: RecordId, deleted: bool) -> bool {
+        let Some(mut page) = self.bpm.checked_write_page(rid.page_id) else {
+            return false;
+        };
+        let Some(mut slot) = get_slot(&page, rid.slot) else {
+            return false;
+        };
+        if slot.flags & (FREE | MOVED_IN | UNDO) != 0 || (slot.flags & DELETED != 0) == deleted {
+            return false;
+        }
+        slot.flags ^= DELETED;
+        set_slot(&mut page, rid.slot, slot);
+        true
+    }
+
+    // Frees the record for good, along with the tuple it was moved to.
+    pub fn apply_delete(&self, rid: RecordId) -> bool {
+        let Some((flags, at)) = self.locate(rid) else {
+            return false;
+        };
+        if flags & MOVED != 0 {
+            self.set_flags(at, FREE);
+        }
+        self.set_flags(rid, FREE);
+        true
+    }
+
+    // Keeps the record as it is before a transaction writes it, None for one it inserts, in an
+    // undo slot until `end_write`. Later writes of the same transaction keep the first image.
+    pub fn begin_write(&self, rid: RecordId, before: Option<&Tuple>) -> Result<(), String> {
+        let mut undo = self.undo.lock().unwrap();
+        if undo.contains_key(&rid) {
+            return Ok(());
+        }
+        let mut bytes = (rid.page_id as u32).to_be_bytes().to_vec();
+        bytes.extend(rid.slot.to_be_bytes());
+        if let Some(before) = before {
+            bytes.extend(serialize(before)?);
+        }
+        undo.insert(rid, self.insert_bytes(&bytes, UNDO)?);
+        Ok(())
+    }
+
+    // Drops the undo slot of the record once the transaction writing it committed, or put the
+    // record back itself.
+    pub fn end_write(&self, rid: RecordId) {
+        if let Some(at) = self.undo.lock().unwrap().remove(&rid) {
+            self.free_undo(at);
+        }
+    }
+
+    // An undo slot that is the last of its page gives its space back, as it mostly is for the
+    // rows of short transactions.
+    fn free_undo(&self, at: RecordId) {
+        let mut page = self.bpm.write_page(at.page_id);
+        let mut slot = get_slot(&page, at.slot).unwrap();
+        if at.slot + 1 == slot_count(&page) && slot.offset == free_space_end(&page) {
+            write_u16(&mut page, 4, at.slot);
+            write_u16(&mut page, 6, (slot.offset + slot.len) as u16);
+            return;
+        }
+        slot.flags = FREE;
+        set_slot(&mut page, at.slot, slot);
+    }
+
+    // Puts the record back as it was before the transaction writing it began to, from its undo
+    // slot, when the transaction aborts. Nothing to do if it has none.
+    pub fn roll_back(&self, rid: RecordId) -> Result<(), String> {
+        let Some(at) = self.undo.lock().unwrap().remove(&rid) else {
+            return Ok(());
+        };
+        let (_, before) = self.read_undo(at);
+        self.restore(rid, before)?;
+        self.free_undo(at);
+        Ok(())
+    }
+
+    // The home record id of an undo slot and the image it holds.
+    fn read_undo(&self, at: RecordId) -> (RecordId, Option<Tuple>) {
+        let page = self.bpm.read_page(at.page_id);
+        let slot = get_slot(&page, at.slot).unwrap();
+        // the home record id is laid out like a forwarding pointer
+        let rid = read_forward(&page, slot);
+        let before = (slot.len > FORWARD_SIZE)
+            .then(|| deserialize(&page[slot.offset + FORWARD_SIZE..slot.offset + slot.len]));
+        (rid, before)
+    }
+
+    fn restore(&self, rid: RecordId, before: Option<Tuple>) -> Result<(), String> {
+        match before {
+            Some(before) => {
+                self.rollback_delete(rid);
+                self.update_tuple(rid, &before)
+            }
+            None => {
+                self.apply_delete(rid);
+                Ok(())
+            }
+        }
+    }
+
+    // Rolls back the records whose undo slots are in the heap, written by transactions that
+    // had not ended when the database was last closed. Returns how many there were.
+    pub fn roll_back_unfinished(&self) -> Result<usize, String> {
+        let mut undone = vec![];
+        for page_id in self.page_ids() {
+            let page = self.bpm.read_page(page_id);
+            for slot_id in 0..slot_count(&page) {
+                if get_slot(&page, slot_id).unwrap().flags == UNDO {
+                    undone.push(RecordId::new(page_id, slot_id));
+                }
+            }
+        }
+        for at in undone.iter().rev() {
+            let (rid, before) = self.read_undo(*at);
+            self.restore(rid, before)?;
+            self.free_undo(*at);
+        }
+        Ok(undone.len())
+    }
+
+    // Streams the live tuples in page and slot order.
+    pub fn iter(&self) -> TableIterator {
+        TableIterator {
+            bpm: Arc::clone(&self.bpm),
+            next_page_id: Some(self.first_page_id),
+            with_deleted: false,
+            records: vec![].into_iter(),
+        }
+    }
+
+    // Like `iter`, but the tuples marked deleted are handed out as well, with whether they are.
+    pub fn iter_marked(&self) -> impl Iterator<Item = (RecordId, Tuple, bool)> {
+        let mut iter = self.iter();
+        iter.with_deleted = true;
+        std::iter::from_fn(move || iter.next_record())
+    }
+
+    // Indexes the heap on the tuple id. Indexes over table columns are built with `build_index`.
+    pub fn create_index(&mut self) -> Box<SkipListIndex> {
+        for (rid, tuple) in self.iter() {
+            let (page_id, slot) = rid.into();
+            self.index.insert(encode_u64(tuple.get_id()), page_id, slot);
+        }
+        Box::new(self.index.clone())
+    }
+
+    // Adds every tuple of the heap to the index, `key_schema` refers to columns of `schema`. When
+    // `unique` is set a duplicate key fails the build; keys containing a NULL are never
+    // considered duplicates and are left out.
+    pub fn build_index(
+        &self,
+        schema: &Schema,
+        key_schema: &KeySchema,
+        index: &dyn Index,
+        unique: bool,
+    ) -> Result<(), String> {
+        for (rid, tuple) in self.iter() {
+            let tuple = schema.upgrade(tuple)?;
+            if unique && key_schema.has_null(&tuple) {
+                continue;
+            }
+            let (page_id, slot) = rid.into();
+            if !index.insert(key_schema.encode_tuple(&tuple)?, page_id, slot) && unique {
+                return Err(format!("duplicate key in tuple {}", tuple));
+            }
+        }
+        Ok(())
+    }
+}
+
+// Sequential scan over a table heap. Only the page being read is pinned: its live tuples are
+// decoded while it is latched and handed out after it is released again, so a scan can cover
+// tables far larger than the pool. Tuples moved by an update are read from their new page and
+// show up under their home record id. Tuples inserted into pages the scan has not reached yet
+// are seen, earlier pages are not revisited.
+pub struct TableIterator {
+    bpm: Arc<BufferPoolManager>,
+    next_page_id: Option<PageId>,
+    with_deleted: bool,
+    records: std::vec::IntoIter<(RecordId, Tuple, bool)>,
+}
+
+impl TableIterator {
+    fn load_page(&mut self, page_id: PageId) {
+        let page = self.bpm.read_page(page_id);
+        let mut records = vec![];
+        for slot_id in 0..slot_count(&page) {
+            let slot = get_slot(&page, slot_id).unwrap();
+            let rid = RecordId::new(page_id, slot_id);
+            let deleted = slot.flags & DELETED != 0;
+            if slot.flags & (FREE | MOVED_IN | UNDO) != 0 || (deleted && !self.with_deleted) {
+                continue;
+            }
+            if slot.flags & MOVED != 0 {
+                records.push((rid, Err(read_forward(&page, slot)), deleted));
+            } else {
+                let tuple = deserialize(&page[slot.offset..slot.offset + slot.len]);
+                records.push((rid, Ok(tuple), deleted));
+            }
+        }
+        self.next_page_id = next_page_id(&page);
+        // moved tuples are read once this page is unpinned
+        drop(page);
+        self.records = records
+            .into_iter()
+            .map(|(rid, tuple, deleted)| {
+                let tuple = tuple.unwrap_or_else(|at| read_tuple(&self.bpm, at));
+                (rid, tuple, deleted)
+            })
+            .collect::<Vec<_>>()
+            .into_iter();
+    }
+
+    fn next_record(&mut self) -> Option<(RecordId, Tuple, bool)> {
+        loop {
+            if let Some(record) = self.records.next() {
+                return Some(record);
+            }
+            let page_id = self.next_page_id?;
+            self.load_page(page_id);
+        }
+    }
+}
+
+impl Iterator for TableIterator {
+    type Item = (RecordId, Tuple);
+
+    fn next(&mut self) -> Option<(RecordId, Tuple)> {
+        self.next_record().map(|(rid, tuple, _)| (rid, tuple))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::query_types::Value;
+
+    fn tuple(id: u64, text: &str) -> Tuple {
+        Tuple::new(
+            id,
+            vec![Value::Varchar(text.to_string()), Value::BigInt(id as i64)],
+        )
+    }
+
+    #[test]
+    fn test_insert_and_get_across_pages() {
+        let bpm = Arc::new(BufferPoolManager::new(4, 2));
+        let heap = TableHeap::new(Arc::clone(&bpm)).unwrap();
+        let rids = (0..500u64)
+            .map(|i| heap.insert_tuple(&tuple(i, &format!("row {}", i))).unwrap())
+            .collect::<Vec<_>>();
+        assert!(rids.last().unwrap().page_id != heap.get_first_page_id());
+        for (i, rid) in rids.iter().enumerate() {
+            assert_eq!(heap.get_tuple(*rid).unwrap().get_id(), i as u64);
+        }
+        let scanned = heap.iter().collect::<Vec<_>>();
+        assert_eq!(
+            scanned.iter().map(|(rid, _)| *rid).collect::<Vec<_>>(),
+            rids
+        );
+        assert!(heap
+            .get_tuple(RecordId::new(rids[0].page_id, 999))
+            .is_none());
+        assert!(heap
+            .insert_tuple(&tuple(0, &"x".repeat(PAGE_SIZE)))
+            .is_err());
+    }
+
+    #[test]
+    fn test_update_in_place_and_by_moving() {
+        let bpm = Arc::new(BufferPoolManager::new(4, 2));
+        let heap = TableHeap::new(Arc::clone(&bpm)).unwrap();
+        let rids = (0..100u64)
+            .map(|i| heap.insert_tuple(&tuple(i, "short")).unwrap())
+            .collect::<Vec<_>>();
+        let rid = rids[0];
+
+        heap.update_tuple(rid, &tuple(0, "s")).unwrap();
+        assert_eq!(
+            heap.get_tuple(rid).unwrap().get_value(0),
+            Some(&Value::Varchar("s".to_string()))
+        );
+        // the first page is full, a longer tuple has to move
+        let long = "l".repeat(1000);
+        heap.update_tuple(rid, &tuple(0, &long)).unwrap();
+        assert_eq!(
+            heap.get_tuple(rid).unwrap().get_value(0),
+            Some(&Value::Varchar(long.clone()))
+        );
+        // and can move again, the home slot keeps pointing at it
+        let longer = "l".repeat(3000);
+        heap.update_tuple(rid, &tuple(0, &longer)).unwrap();
+        heap.update_tuple(rid, &tuple(0, "back")).unwrap();
+        assert_eq!(
+            heap.get_tuple(rid).unwrap().get_value(0),
+            Some(&Value::Varchar("back".to_string()))
+        );
+        let scanned = heap.iter().collect::<Vec<_>>();
+        assert_eq!(scanned.len(), 100);
+        assert_eq!(scanned[0].0, rid);
+        assert_eq!(
+            scanned[0].1.get_value(0),
+            Some(&Value::Varchar("back".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_mark_rollback_and_apply_delete() {
+        let bpm = Arc::new(BufferPoolManager::new(4, 2));
+        let heap = TableHeap::new(Arc::clone(&bpm)).unwrap();
+        let a = heap.insert_tuple(&tuple(1, "a")).unwrap();
+        let b = heap.insert_tuple(&tuple(2, "b")).unwrap();
+
+        assert!(heap.mark_delete(a));
+        assert!(!heap.mark_delete(a));
+        assert!(heap.get_tuple(a).is_none());
+        assert!(heap.update_tuple(a, &tuple(1, "a")).is_err());
+        assert_eq!(heap.iter().count(), 1);
+        let (marked, deleted) = heap.get_tuple_marked(a).unwrap();
+        assert_eq!((marked.get_id(), deleted), (1, true));
+        let marked = heap
+            .iter_marked()
+            .map(|(rid, _, deleted)| (rid, deleted))
+            .collect::<Vec<_>>();
+        assert_eq!(marked, vec![(a, true), (b, false)]);
+        assert!(heap.rollback_delete(a));
+        assert_eq!(heap.get_tuple(a).unwrap().get_id(), 1);
+
+        heap.update_tuple(b, &tuple(2, &"b".repeat(PAGE_SIZE / 2)))
+            .unwrap();
+        heap.update_tuple(b, &tuple(2, &"b".repeat(PAGE_SIZE - 100)))
+            .unwrap();
+        assert!(heap.mark_delete(b));
+        assert!(heap.apply_delete(b));
+        assert!(!heap.apply_delete(b));
+        assert!(!heap.rollback_delete(b));
+        assert!(heap.get_tuple(b).is_none());
+        assert_eq!(heap.iter().map(|(rid, _)| rid).collect::<Vec<_>>(), vec![a]);
+        assert!(heap.get_tuple_marked(b).is_none());
+
+        // inserted hidden, shown once the delete is rolled back
+        let c = heap.insert_deleted_tuple(&tuple(3, "c")).unwrap();
+        assert!(heap.get_tuple(c).is_none());
+        assert_eq!(heap.iter().count(), 1);
+        assert!(heap.rollback_delete(c));
+        assert_eq!(heap.get_tuple(c).unwrap().get_id(), 3);
+    }
+
+    #[test]
+    fn test_unfinished_writes_are_rolled_back_on_open() {
+        let bpm = Arc::new(BufferPoolManager::new(4, 2));
+        let heap = TableHeap::new(Arc::clone(&bpm)).unwrap();
+        let a = heap.insert_tuple(&tuple(1, "a")).unwrap();
+        let b = heap.insert_tuple(&tuple(2, "b")).unwrap();
+
+        // written and ended, the undo slot gives its space back
+        let free = free_space(&bpm.read_page(a.page_id));
+        heap.begin_write(a, Some(&tuple(1, "a"))).unwrap();
+        heap.update_tuple(a, &tuple(1, "x")).unwrap();
+        heap.end_write(a);
+        assert_eq!(free_space(&bpm.read_page(a.page_id)), free);
+
+        // written and not ended: updated twice, moved, deleted and inserted
+        heap.begin_write(a, Some(&tuple(1, "x"))).unwrap();
+        heap.update_tuple(a, &tuple(1, &"y".repeat(3000))).unwrap();
+        heap.begin_write(a, Some(&tuple(1, "y"))).unwrap();
+        heap.update_tuple(a, &tuple(1, "z")).unwrap();
+        heap.begin_write(b, Some(&tuple(2, "b"))).unwrap();
+        assert!(heap.mark_delete(b));
+        let c = heap.insert_deleted_tuple(&tuple(3, "c")).unwrap();
+        heap.begin_write(c, None).unwrap();
+        assert!(heap.rollback_delete(c));
+        assert_eq!(
+            heap.iter().map(|(rid, _)| rid).collect::<Vec<_>>(),
+            vec![a, c]
+        );
+
+        let heap = TableHeap::open(Arc::clone(&bpm), heap.get_first_page_id());
+        assert_eq!(heap.roll_back_unfinished(), Ok(3));
+        let values = heap
+            .iter()
+            .map(|(rid, tuple)| (rid, tuple.get_value(0).unwrap().clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            values,
+            vec![
+                (a, Value::Varchar("x".to_string())),
+                (b, Value::Varchar("b".to_string()))
+            ]
+        );
+        assert_eq!(heap.roll_back_unfinished(), Ok(0));
+
+        // rolled back one record at a time, as when the transaction aborts
+        heap.begin_write(a, Some(&tuple(1, "x"))).unwrap();
+        heap.update_tuple(a, &tuple(1, "w")).unwrap();
+        let d = heap.insert_tuple(&tuple(4, "d")).unwrap();
+        heap.begin_write(d, None).unwrap();
+        heap.roll_back(a).unwrap();
+        heap.roll_back(d).unwrap();
+        heap.roll_back(d).unwrap();
+        assert_eq!(
+            heap.get_tuple(a).unwrap().get_value(0),
+            Some(&Value::Varchar("x".to_string()))
+        );
+        assert!(heap.get_tuple(d).is_none());
+        assert_eq!(heap.roll_back_unfinished(), Ok(0));
+    }
+
+    #[test]
+    fn test_concurrent_inserts() {
+        let bpm = Arc::new(BufferPoolManager::new(8, 2));
+        let heap = Arc::new(TableHeap::new(Arc::clone(&bpm)).unwrap());
+        let handles = (0..4u64)
+            .map(|t| {
+                let heap = Arc::clone(&heap);
+                std::thread::spawn(move || {
+                    (0..200u64)
+                        .map(|i| {
+                            let id = t * 1000 + i;
+                            let rid = heap.insert_tuple(&tuple(id, "row")).unwrap();
+                            assert_eq!(heap.get_tuple(rid).unwrap().get_id(), id);
+                            rid
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect::<Vec<_>>();
+        let mut rids = handles
+            .into_iter()
+            .flat_map(|h| h.join().unwrap())
+            .collect::<Vec<_>>();
+        rids.sort();
+        rids.dedup();
+        assert_eq!(rids.len(), 800);
+        assert_eq!(heap.iter().count(), 800);
+    }
+
+    #[test]
+    fn test_iterator_pins_one_page_at_a_time() {
+        // far more pages than frames
+        let bpm = Arc::new(BufferPoolManager::new(3, 2));
+        let heap = TableHeap::new(Arc::clone(&bpm)).unwrap();
+        let rids = (0..2000u64)
+            .map(|i| heap.insert_tuple(&tuple(i, "row")).unwrap())
+            .collect::<Vec<_>>();
+        assert!(rids.last().unwrap().page_id > 3);
+        for rid in rids.iter().step_by(3) {
+            assert!(heap.mark_delete(*rid));
+        }
+        let mut iter = heap.iter();
+        let mut seen = 0;
+        for (rid, tuple) in iter.by_ref() {
+            let i = tuple.get_id() as usize;
+            assert_eq!(rids[i], rid);
+            assert!(!i.is_multiple_of(3));
+            // nothing stays pinned between calls
+            assert_eq!(bpm.get_pin_count(rid.page_id).unwrap_or(0), 0);
+            seen += 1;
+        }
+        assert_eq!(seen, 2000 - 667);
+        assert!(iter.next().is_none());
+    }
+}
@@ -1,29 +1,208 @@
-use std::sync::atomic::AtomicU64;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
 
-#[allow(dead_code)]
+use crate::types::RecordId;
+
+pub type TxnId = u64;
+pub type Timestamp = u64;
+
+// Transaction ids start far above any commit timestamp, so a value stored where either can be
+// found tells which it is.
+pub const TXN_START_ID: TxnId = 1 << 62;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum TransactionState {
     RUNNING,
+    // hit a conflict, it can only be aborted
     TAINTED,
     COMMITTED,
     ABORTED,
 }
-#[allow(dead_code)]
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
 pub enum IsolationLevel {
     READUNCOMMITTED,
+    // every statement reads as of when it began
+    READCOMMITTED,
+    #[default]
     SNAPSHOTISOLATION,
     SERIALIZABLE,
 }
-#[allow(dead_code)]
+
+/// A transaction handed out by the transaction manager. It reads the database as of its read
+/// timestamp, and what it wrote becomes visible to others at its commit timestamp.
+#[derive(Debug)]
 pub struct Transaction {
-    id: AtomicU64,
-    finished: bool,
+    id: TxnId,
+    isolation_level: IsolationLevel,
+    // moves forward with every statement under READ COMMITTED
+    read_ts: AtomicU64,
+    // reads as of an earlier time and cannot write
+    read_only: bool,
+    // how long a statement waits for a lock, in milliseconds, zero waits as long as it takes
+    lock_timeout: AtomicU64,
+    // zero until committed
+    commit_ts: AtomicU64,
+    state: Mutex<TransactionState>,
+    // the rows written, by table id
+    write_set: Mutex<HashMap<u32, HashSet<RecordId>>>,
+    // the rows read and the tables scanned, kept by serializable transactions only. A scan reads
+    // through a predicate matching every row of the table, rows inserted later included.
+    read_set: Mutex<HashMap<u32, HashSet<RecordId>>>,
+    scanned: Mutex<HashSet<u32>>,
 }
 
 impl Transaction {
-    pub fn new() -> Self {
+    pub fn new(id: TxnId, isolation_level: IsolationLevel, read_ts: Timestamp) -> Self {
+        Self {
+            id,
+            isolation_level,
+            read_ts: AtomicU64::new(read_ts),
+            read_only: false,
+            lock_timeout: AtomicU64::new(0),
+            commit_ts: AtomicU64::new(0),
+            state: Mutex::new(TransactionState::RUNNING),
+            write_set: Mutex::new(HashMap::new()),
+            read_set: Mutex::new(HashMap::new()),
+            scanned: Mutex::new(HashSet::new()),
+        }
+    }
+
+    pub fn new_read_only(id: TxnId, isolation_level: IsolationLevel, read_ts: Timestamp) -> Self {
         Self {
-            id: AtomicU64::new(0),
-            finished: false,
+            read_only: true,
+            ..Self::new(id, isolation_level, read_ts)
+        }
+    }
+
+    pub fn get_id(&self) -> TxnId {
+        self.id
+    }
+
+    pub fn get_isolation_level(&self) -> IsolationLevel {
+        self.isolation_level
+    }
+
+    pub fn get_read_ts(&self) -> Timestamp {
+        self.read_ts.load(Ordering::Acquire)
+    }
+
+    // Only for the transaction manager, which keeps the watermark.
+    pub fn set_read_ts(&self, read_ts: Timestamp) {
+        self.read_ts.store(read_ts, Ordering::Release);
+    }
+
+    pub fn get_commit_ts(&self) -> Option<Timestamp> {
+        match self.commit_ts.load(Ordering::Acquire) {
+            0 => None,
+            ts => Some(ts),
+        }
+    }
+
+    pub fn get_state(&self) -> TransactionState {
+        *self.state.lock().unwrap()
+    }
+
+    // Only for the transaction manager, which checks the transitions.
+    pub fn set_state(&self, state: TransactionState) {
+        *self.state.lock().unwrap() = state;
+    }
+
+    pub fn set_commit_ts(&self, commit_ts: Timestamp) {
+        self.commit_ts.store(commit_ts, Ordering::Release);
+    }
+
+    /// Marks the transaction as having hit a conflict.
+    pub fn set_tainted(&self) {
+        let mut state = self.state.lock().unwrap();
+        if *state == TransactionState::RUNNING {
+            *state = TransactionState::TAINTED;
+        }
+    }
+
+    /// Fails unless the transaction can still read and write.
+    pub fn check_running(&self) -> Result<(), String> {
+        match self.get_state() {
+            TransactionState::RUNNING => Ok(()),
+            TransactionState::TAINTED => Err(format!(
+                "transaction {} hit a conflict and can only be aborted",
+                self.id
+            )),
+            state => Err(format!("transaction {} is {:?}", self.id, state)),
+        }
+    }
+
+    /// Fails unless the transaction can still write.
+    pub fn check_writable(&self) -> Result<(), String> {
+        self.check_running()?;
+        if self.read_only {
+            return Err(format!(
+                "transaction {} reads as of an earlier time and cannot write",
+                self.id
+            ));
         }
+        Ok(())
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    pub fn get_lock_timeout(&self) -> Option<Duration> {
+        match self.lock_timeout.load(Ordering::Relaxed) {
+            0 => None,
+            millis => Some(Duration::from_millis(millis)),
+        }
+    }
+
+    /// Makes waiting for a lock fail after `lock_timeout`, None waits as long as it takes.
+    pub fn set_lock_timeout(&self, lock_timeout: Option<Duration>) {
+        let millis = lock_timeout.map_or(0, |timeout| timeout.as_millis().max(1) as u64);
+        self.lock_timeout.store(millis, Ordering::Relaxed);
+    }
+
+    pub fn append_write_set(&self, table_id: u32, rid: RecordId) {
+        self.write_set
+            .lock()
+            .unwrap()
+            .entry(table_id)
+            .or_default()
+            .insert(rid);
+    }
+
+    pub fn get_write_set(&self) -> HashMap<u32, HashSet<RecordId>> {
+        self.write_set.lock().unwrap().clone()
+    }
+
+    pub fn append_read_set(&self, table_id: u32, rid: RecordId) {
+        if self.isolation_level == IsolationLevel::SERIALIZABLE {
+            self.read_set
+                .lock()
+                .unwrap()
+                .entry(table_id)
+                .or_default()
+                .insert(rid);
+        }
+    }
+
+    pub fn append_scan(&self, table_id: u32) {
+        if self.isolation_level == IsolationLevel::SERIALIZABLE {
+            self.scanned.lock().unwrap().insert(table_id);
+        }
+    }
+
+    /// Whether the transaction read one of the rows in `write_set`, or scanned a table with a
+    /// row in it.
+    pub fn read_any(&self, write_set: &HashMap<u32, HashSet<RecordId>>) -> bool {
+        let scanned = self.scanned.lock().unwrap();
+        let read_set = self.read_set.lock().unwrap();
+        write_set.iter().any(|(table_id, rids)| {
+            scanned.contains(table_id)
+                || read_set
+                    .get(table_id)
+                    .is_some_and(|read| rids.iter().any(|rid| read.contains(rid)))
+        })
     }
 }
@@ -1,4 +1,3 @@
-
 #[derive(Clone, Debug, PartialEq)]
 pub enum TxnResult<T, E> {
     Ok(T),
@@ -6,3 +5,30 @@ pub enum TxnResult<T, E> {
 }
 
 pub type PageId = usize;
+
+// Location of a tuple in a table heap. It stays valid for the tuple's whole life, an update that
+// moves the tuple leaves a forwarding pointer behind.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct RecordId {
+    pub page_id: PageId,
+    pub slot: u16,
+}
+
+impl RecordId {
+    pub fn new(page_id: PageId, slot: u16) -> Self {
+        Self { page_id, slot }
+    }
+}
+
+// Indexes store record ids as (page_id, offset) pairs.
+impl From<RecordId> for (u64, usize) {
+    fn from(rid: RecordId) -> Self {
+        (rid.page_id as u64, rid.slot as usize)
+    }
+}
+
+impl From<(u64, usize)> for RecordId {
+    fn from((page_id, slot): (u64, usize)) -> Self {
+        Self::new(page_id as PageId, slot as u16)
+    }
+}
@@ -0,0 +1,134 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use common::transaction::TxnId;
+
+use crate::lock_manager::LockManager;
+
+/// How often the deadlock detector looks for deadlocks unless told otherwise.
+pub const DEFAULT_DEADLOCK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Looks for deadlocks among the transactions waiting for locks every `interval` on a thread of
+/// its own, until dropped.
+#[derive(Debug)]
+pub struct DeadlockDetector {
+    interval: Duration,
+    // dropped to stop the thread
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl DeadlockDetector {
+    pub fn start(lock_manager: Arc<LockManager>, interval: Duration) -> Self {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let thread = thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                lock_manager.detect_deadlocks();
+            }
+        });
+        Self {
+            interval,
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+
+    pub fn get_interval(&self) -> Duration {
+        self.interval
+    }
+}
+
+impl Drop for DeadlockDetector {
+    fn drop(&mut self) {
+        drop(self.stop.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+// The transactions to abort so the waits-for graph has no cycle left: the youngest, with the
+// largest id, of each cycle, found in id order so the same graph always gives the same victims.
+pub(crate) fn find_victims(graph: &BTreeMap<TxnId, BTreeSet<TxnId>>) -> Vec<TxnId> {
+    let mut victims = BTreeSet::new();
+    while let Some(cycle) = find_cycle(graph, &victims) {
+        victims.insert(*cycle.iter().max().unwrap());
+    }
+    victims.into_iter().collect()
+}
+
+fn find_cycle(
+    graph: &BTreeMap<TxnId, BTreeSet<TxnId>>,
+    removed: &BTreeSet<TxnId>,
+) -> Option<Vec<TxnId>> {
+    let mut done = BTreeSet::new();
+    for start in graph.keys() {
+        let mut path = vec![];
+        if let Some(cycle) = visit(graph, removed, *start, &mut path, &mut done) {
+            return Some(cycle);
+        }
+    }
+    None
+}
+
+// Depth-first from `txn_id`, `path` holds the transactions on the way to it. Returns the cycle
+// closed by an edge back into the path, if one is found.
+fn visit(
+    graph: &BTreeMap<TxnId, BTreeSet<TxnId>>,
+    removed: &BTreeSet<TxnId>,
+    txn_id: TxnId,
+    path: &mut Vec<TxnId>,
+    done: &mut BTreeSet<TxnId>,
+) -> Option<Vec<TxnId>> {
+    if let Some(at) = path.iter().position(|t| *t == txn_id) {
+        return Some(path[at..].to_vec());
+    }
+    if removed.contains(&txn_id) || done.contains(&txn_id) {
+        return None;
+    }
+    path.push(txn_id);
+    for next in graph.get(&txn_id).into_iter().flatten() {
+        if let Some(cycle) = visit(graph, removed, *next, path, done) {
+            return Some(cycle);
+        }
+    }
+    path.pop();
+    done.insert(txn_id);
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn graph(edges: &[(TxnId, TxnId)]) -> BTreeMap<TxnId, BTreeSet<TxnId>> {
+        let mut graph: BTreeMap<TxnId, BTreeSet<TxnId>> = BTreeMap::new();
+        for (from, to) in edges {
+            graph.entry(*from).or_default().insert(*to);
+        }
+        graph
+    }
+
+    #[test]
+    fn test_the_youngest_transaction_of_each_cycle_is_picked() {
+        assert!(find_victims(&graph(&[(1, 2), (2, 3), (4, 3)])).is_empty());
+        assert_eq!(find_victims(&graph(&[(1, 2), (2, 1)])), vec![2]);
+        // two cycles sharing 3, aborting 3 breaks both
+        assert_eq!(
+            find_victims(&graph(&[(1, 3), (3, 1), (2, 3), (3, 2)])),
+            vec![3]
+        );
+        // the cycles share 1, the youngest of neither
+        assert_eq!(
+            find_victims(&graph(&[(1, 5), (5, 6), (6, 1), (1, 2), (2, 1), (7, 1)])),
+            vec![2, 6]
+        );
+        assert_eq!(
+            find_victims(&graph(&[(1, 2), (2, 1), (3, 4), (4, 3)])),
+            vec![2, 4]
+        );
+    }
+}
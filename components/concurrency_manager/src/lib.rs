@@ -1,3 +1,6 @@
+pub mod deadlock_detector;
+pub mod lock_manager;
+
 pub fn add(left: u64, right: u64) -> u64 {
     left + right
 }
@@ -0,0 +1,842 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Instant;
+
+use common::transaction::{Transaction, TxnId};
+use common::types::RecordId;
+
+use crate::deadlock_detector;
+
+pub type TableId = u32;
+pub type IndexId = u32;
+
+// Hierarchical two-phase locking. Tables are locked in any of the five modes, rows in S or X
+// only, and a row lock needs an intention lock on its table first: IS or stronger for S, IX or
+// stronger for X. A transaction keeps its locks until it commits or aborts, when `unlock_all`
+// releases them together, which makes the locking strict.
+//
+// Each locked table or row has a queue of requests, the granted ones first, then the waiting
+// ones in the order they came. A waiting request is granted once it is compatible with every
+// granted one and nothing waits ahead of it, so a stream of compatible requests cannot starve
+// an incompatible one. A transaction asking for a stronger lock than it holds upgrades it: the
+// upgrade waits right behind the granted requests, ahead of every other waiter.
+//
+// The gaps between the entries of an ordered index are locked too, against phantoms. A gap is
+// named by the entry right above it, or by none past the last entry, and is locked in S mode by
+// range scans, under an IS lock on the table. Inserting an entry into a gap locked by another
+// transaction has to wait until it is released: the insert enters the gap in IX mode, under an
+// IX lock on the table, but does not keep the lock, so inserts never block each other.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum LockMode {
+    IntentionShared,
+    IntentionExclusive,
+    Shared,
+    SharedIntentionExclusive,
+    Exclusive,
+}
+
+impl LockMode {
+    pub fn is_compatible(self, other: LockMode) -> bool {
+        use LockMode::*;
+        match (self, other) {
+            (Exclusive, _) | (_, Exclusive) => false,
+            (IntentionShared, _) | (_, IntentionShared) => true,
+            (IntentionExclusive, IntentionExclusive) | (Shared, Shared) => true,
+            _ => false,
+        }
+    }
+
+    // Whether holding the lock in this mode gives all that `other` does.
+    pub fn covers(self, other: LockMode) -> bool {
+        use LockMode::*;
+        match (self, other) {
+            (Exclusive, _) => true,
+            (SharedIntentionExclusive, mode) => mode != Exclusive,
+            (Shared, IntentionShared | Shared) => true,
+            (IntentionExclusive, IntentionShared | IntentionExclusive) => true,
+            (mode, other) => mode == other,
+        }
+    }
+
+    // The weakest mode covering both.
+    fn combine(self, other: LockMode) -> LockMode {
+        if self.covers(other) {
+            self
+        } else if other.covers(self) {
+            other
+        } else {
+            // S and IX
+            LockMode::SharedIntentionExclusive
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum LockTarget {
+    // the catalog as a whole, locked in X mode by transactions running DDL
+    Catalog,
+    Table(TableId),
+    Row(TableId, RecordId),
+    // the gap of the index below the stored key, or above its last entry
+    Gap(TableId, IndexId, Option<Vec<u8>>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum LockError {
+    Invalid(String),
+    // The lock could not be granted without waiting, or within the transaction's lock timeout.
+    NotAvailable(String),
+    // Waiting for the lock would never end, the transaction can only be aborted.
+    Deadlock(String),
+}
+
+impl Display for LockError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LockError::Invalid(msg) | LockError::NotAvailable(msg) | LockError::Deadlock(msg) => {
+                write!(f, "{}", msg)
+            }
+        }
+    }
+}
+
+impl From<String> for LockError {
+    fn from(msg: String) -> Self {
+        LockError::Invalid(msg)
+    }
+}
+
+#[derive(Debug)]
+struct LockRequest {
+    txn_id: TxnId,
+    mode: LockMode,
+    granted: bool,
+}
+
+#[derive(Debug, Default)]
+struct LockQueue {
+    // an upgrade is a second, waiting request of a transaction holding a granted one
+    requests: Vec<LockRequest>,
+    // woken whenever a request of the queue is granted or leaves it
+    waiters: Arc<Condvar>,
+}
+
+impl LockQueue {
+    fn granted_mode(&self, txn_id: TxnId) -> Option<LockMode> {
+        self.requests
+            .iter()
+            .find(|r| r.txn_id == txn_id && r.granted)
+            .map(|r| r.mode)
+    }
+
+    fn waiting(&self, txn_id: TxnId) -> Option<usize> {
+        self.requests
+            .iter()
+            .position(|r| r.txn_id == txn_id && !r.granted)
+    }
+
+    // Whether the waiting request at `idx` can be granted: nothing waits ahead of it and it is
+    // compatible with the locks granted to the other transactions.
+    fn grantable(&self, idx: usize) -> bool {
+        let request = &self.requests[idx];
+        self.requests[..idx].iter().all(|r| r.granted)
+            && self
+                .requests
+                .iter()
+                .filter(|r| r.granted && r.txn_id != request.txn_id)
+                .all(|r| r.mode.is_compatible(request.mode))
+    }
+
+    fn grant(&mut self, idx: usize) {
+        let txn_id = self.requests[idx].txn_id;
+        self.requests[idx].granted = true;
+        // an upgrade replaces the lock held before
+        if let Some(held) = self
+            .requests
+            .iter()
+            .position(|r| r.txn_id == txn_id && r.granted)
+            .filter(|held| *held != idx)
+        {
+            self.requests.remove(held);
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct LockTable {
+    queues: HashMap<LockTarget, LockQueue>,
+    // the targets each transaction holds or waits for a lock on
+    locked: HashMap<TxnId, HashSet<LockTarget>>,
+    // waiting transactions picked to break a deadlock, until they wake up
+    victims: HashSet<TxnId>,
+}
+
+impl LockTable {
+    // Drops the waiting request of `txn_id` for `target`.
+    fn withdraw(&mut self, txn_id: TxnId, target: &LockTarget) {
+        let queue = self.queues.get_mut(target).unwrap();
+        if let Some(idx) = queue.waiting(txn_id) {
+            queue.requests.remove(idx);
+        }
+        // the requests behind it may be grantable now
+        queue.waiters.notify_all();
+        if queue.granted_mode(txn_id).is_none() {
+            if let Some(targets) = self.locked.get_mut(&txn_id) {
+                targets.remove(target);
+            }
+        }
+        if queue.requests.is_empty() {
+            self.queues.remove(target);
+        }
+    }
+
+    // Which transactions each waiting transaction waits for: those holding a lock it is not
+    // compatible with, and those waiting ahead of it.
+    fn waits_for(&self) -> BTreeMap<TxnId, BTreeSet<TxnId>> {
+        let mut graph: BTreeMap<TxnId, BTreeSet<TxnId>> = BTreeMap::new();
+        for queue in self.queues.values() {
+            for (idx, request) in queue.requests.iter().enumerate() {
+                if request.granted || self.victims.contains(&request.txn_id) {
+                    continue;
+                }
+                let ahead = queue.requests[..idx]
+                    .iter()
+                    .filter(|r| r.txn_id != request.txn_id)
+                    .filter(|r| !r.granted || !r.mode.is_compatible(request.mode))
+                    .map(|r| r.txn_id);
+                graph.entry(request.txn_id).or_default().extend(ahead);
+            }
+        }
+        graph
+    }
+}
+
+/// Grants table, row and gap locks to transactions, see above.
+#[derive(Debug, Default)]
+pub struct LockManager {
+    // one latch over all queues, waiters wait on their queue's condition variable with it
+    lock_table: Mutex<LockTable>,
+}
+
+impl LockManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Locks the table in `mode`, waiting until the lock can be granted, or failing once the
+    /// transaction's lock timeout passed.
+    pub fn lock_table(
+        &self,
+        txn: &Transaction,
+        mode: LockMode,
+        table_id: TableId,
+    ) -> Result<(), LockError> {
+        self.lock(txn, LockTarget::Table(table_id), mode, true, true)
+    }
+
+    /// Like `lock_table`, but fails instead of waiting if the lock cannot be granted right away.
+    pub fn try_lock_table(
+        &self,
+        txn: &Transaction,
+        mode: LockMode,
+        table_id: TableId,
+    ) -> Result<(), LockError> {
+        self.lock(txn, LockTarget::Table(table_id), mode, false, true)
+    }
+
+    /// Locks the catalog in X mode, waiting as `lock_table` does. Transactions running DDL hold
+    /// it until they end, the DDL of one is committed or undone before another's begins.
+    pub fn lock_catalog(&self, txn: &Transaction) -> Result<(), LockError> {
+        self.lock(txn, LockTarget::Catalog, LockMode::Exclusive, true, true)
+    }
+
+    /// Locks the row in `mode`, S or X, waiting as `lock_table` does. The table has to be locked
+    /// first.
+    pub fn lock_row(
+        &self,
+        txn: &Transaction,
+        mode: LockMode,
+        table_id: TableId,
+        rid: RecordId,
+    ) -> Result<(), LockError> {
+        self.lock(txn, LockTarget::Row(table_id, rid), mode, true, true)
+    }
+
+    /// Like `lock_row`, but fails instead of waiting if the lock cannot be granted right away.
+    pub fn try_lock_row(
+        &self,
+        txn: &Transaction,
+        mode: LockMode,
+        table_id: TableId,
+        rid: RecordId,
+    ) -> Result<(), LockError> {
+        self.lock(txn, LockTarget::Row(table_id, rid), mode, false, true)
+    }
+
+    /// Locks the gap of the index below `next_key` in S mode, waiting as `lock_table` does. The
+    /// table has to be locked in IS mode or stronger first.
+    pub fn lock_gap(
+        &self,
+        txn: &Transaction,
+        table_id: TableId,
+        index_id: IndexId,
+        next_key: Option<Vec<u8>>,
+    ) -> Result<(), LockError> {
+        let target = LockTarget::Gap(table_id, index_id, next_key);
+        self.lock(txn, target, LockMode::Shared, true, true)
+    }
+
+    /// Like `lock_gap`, but fails instead of waiting if the lock cannot be granted right away.
+    pub fn try_lock_gap(
+        &self,
+        txn: &Transaction,
+        table_id: TableId,
+        index_id: IndexId,
+        next_key: Option<Vec<u8>>,
+    ) -> Result<(), LockError> {
+        let target = LockTarget::Gap(table_id, index_id, next_key);
+        self.lock(txn, target, LockMode::Shared, false, true)
+    }
+
+    /// Waits until no other transaction holds the gap of the index below `next_key`, before
+    /// inserting an entry into it. Nothing stays locked. The table has to be locked in IX mode
+    /// or stronger first.
+    pub fn enter_gap(
+        &self,
+        txn: &Transaction,
+        table_id: TableId,
+        index_id: IndexId,
+        next_key: Option<Vec<u8>>,
+    ) -> Result<(), LockError> {
+        let target = LockTarget::Gap(table_id, index_id, next_key);
+        self.lock(txn, target, LockMode::IntentionExclusive, true, false)
+    }
+
+    /// Like `enter_gap`, but fails instead of waiting if another transaction holds the gap.
+    pub fn try_enter_gap(
+        &self,
+        txn: &Transaction,
+        table_id: TableId,
+        index_id: IndexId,
+        next_key: Option<Vec<u8>>,
+    ) -> Result<(), LockError> {
+        let target = LockTarget::Gap(table_id, index_id, next_key);
+        self.lock(txn, target, LockMode::IntentionExclusive, false, false)
+    }
+
+    /// The mode `txn` holds a lock on `target` in, if it holds one.
+    pub fn held_mode(&self, txn: &Transaction, target: LockTarget) -> Option<LockMode> {
+        let lock_table = self.lock_table.lock().unwrap();
+        lock_table.queues.get(&target)?.granted_mode(txn.get_id())
+    }
+
+    // Without `hold`, the request leaves the queue as soon as it could be granted.
+    fn lock(
+        &self,
+        txn: &Transaction,
+        target: LockTarget,
+        mode: LockMode,
+        wait: bool,
+        hold: bool,
+    ) -> Result<(), LockError> {
+        txn.check_running()?;
+        let txn_id = txn.get_id();
+        let mut lock_table = self.lock_table.lock().unwrap();
+        let table_id = match target {
+            LockTarget::Catalog | LockTarget::Table(_) => None,
+            LockTarget::Row(table_id, _) | LockTarget::Gap(table_id, _, _) => Some(table_id),
+        };
+        if let Some(table_id) = table_id {
+            let needed = match (&target, mode) {
+                (LockTarget::Row(..), LockMode::Shared) => LockMode::IntentionShared,
+                (LockTarget::Row(..), LockMode::Exclusive) => LockMode::IntentionExclusive,
+                (LockTarget::Gap(..), LockMode::Shared) => LockMode::IntentionShared,
+                (LockTarget::Gap(..), LockMode::IntentionExclusive) => LockMode::IntentionExclusive,
+                (LockTarget::Row(..), _) => {
+                    return Err(LockError::Invalid(
+                        "rows are locked in S or X mode only".to_string(),
+                    ))
+                }
+                _ => {
+                    return Err(LockError::Invalid(
+                        "gaps are locked in S or IX mode only".to_string(),
+                    ))
+                }
+            };
+            let table_mode = lock_table
+                .queues
+                .get(&LockTarget::Table(table_id))
+                .and_then(|queue| queue.granted_mode(txn_id));
+            if !table_mode.is_some_and(|table_mode| table_mode.covers(needed)) {
+                return Err(LockError::Invalid(format!(
+                    "locking {:?} in {:?} mode needs a {:?} lock on table {}",
+                    target, mode, needed, table_id
+                )));
+            }
+        }
+        let queue = lock_table.queues.entry(target.clone()).or_default();
+        match queue.granted_mode(txn_id).filter(|_| hold) {
+            Some(held) if held.covers(mode) => return Ok(()),
+            Some(held) => {
+                // two upgrades would wait for each other's locks forever
+                if queue
+                    .requests
+                    .iter()
+                    .any(|r| !r.granted && queue.granted_mode(r.txn_id).is_some())
+                {
+                    txn.set_tainted();
+                    return Err(LockError::Deadlock(format!(
+                        "transaction {} cannot upgrade its lock on {:?}, another transaction is upgrading its own",
+                        txn_id, target
+                    )));
+                }
+                let granted = queue.requests.iter().filter(|r| r.granted).count();
+                queue.requests.insert(
+                    granted,
+                    LockRequest {
+                        txn_id,
+                        mode: held.combine(mode),
+                        granted: false,
+                    },
+                );
+            }
+            None => queue.requests.push(LockRequest {
+                txn_id,
+                mode,
+                granted: false,
+            }),
+        }
+        let waiters = Arc::clone(&queue.waiters);
+        lock_table
+            .locked
+            .entry(txn_id)
+            .or_default()
+            .insert(target.clone());
+        let deadline = txn
+            .get_lock_timeout()
+            .map(|timeout| (timeout, Instant::now() + timeout));
+        loop {
+            if lock_table.victims.remove(&txn_id) {
+                lock_table.withdraw(txn_id, &target);
+                txn.set_tainted();
+                return Err(LockError::Deadlock(format!(
+                    "transaction {} was aborted to break a deadlock while waiting to lock {:?}",
+                    txn_id, target
+                )));
+            }
+            let queue = lock_table.queues.get_mut(&target).unwrap();
+            let idx = queue.waiting(txn_id).unwrap();
+            if queue.grantable(idx) && !hold {
+                lock_table.withdraw(txn_id, &target);
+                return Ok(());
+            }
+            if queue.grantable(idx) {
+                queue.grant(idx);
+                // the next waiter may be compatible as well
+                waiters.notify_all();
+                return Ok(());
+            }
+            if !wait {
+                lock_table.withdraw(txn_id, &target);
+                return Err(LockError::NotAvailable(format!(
+                    "could not lock {:?} in {:?} mode without waiting",
+                    target, mode
+                )));
+            }
+            lock_table = match deadline {
+                Some((timeout, deadline)) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        lock_table.withdraw(txn_id, &target);
+                        return Err(LockError::NotAvailable(format!(
+                            "could not lock {:?} in {:?} mode within {:?}",
+                            target, mode, timeout
+                        )));
+                    }
+                    waiters.wait_timeout(lock_table, deadline - now).unwrap().0
+                }
+                None => waiters.wait(lock_table).unwrap(),
+            };
+        }
+    }
+
+    /// Breaks the deadlocks among the waiting transactions: the youngest transaction of each
+    /// cycle in the waits-for graph stops waiting and fails with a deadlock error. Returns the
+    /// ids of the transactions picked.
+    pub fn detect_deadlocks(&self) -> Vec<TxnId> {
+        let mut lock_table = self.lock_table.lock().unwrap();
+        let victims = deadlock_detector::find_victims(&lock_table.waits_for());
+        for txn_id in victims.iter() {
+            lock_table.victims.insert(*txn_id);
+            // a transaction waits for one lock at a time, wake the queue it waits in
+            for target in lock_table.locked[txn_id].iter() {
+                let queue = &lock_table.queues[target];
+                if queue.waiting(*txn_id).is_some() {
+                    queue.waiters.notify_all();
+                }
+            }
+        }
+        victims
+    }
+
+    /// Releases every lock `txn` holds, once it committed or aborted.
+    pub fn unlock_all(&self, txn: &Transaction) {
+        let mut lock_table = self.lock_table.lock().unwrap();
+        lock_table.victims.remove(&txn.get_id());
+        let Some(targets) = lock_table.locked.remove(&txn.get_id()) else {
+            return;
+        };
+        for target in targets {
+            let queue = lock_table.queues.get_mut(&target).unwrap();
+            queue.requests.retain(|r| r.txn_id != txn.get_id());
+            queue.waiters.notify_all();
+            if queue.requests.is_empty() {
+                lock_table.queues.remove(&target);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    use common::transaction::IsolationLevel;
+
+    use super::*;
+    use crate::deadlock_detector::DeadlockDetector;
+
+    fn txn(id: TxnId) -> Arc<Transaction> {
+        Arc::new(Transaction::new(id, IsolationLevel::SERIALIZABLE, 0))
+    }
+
+    #[test]
+    fn test_lock_modes_follow_the_compatibility_matrix() {
+        use LockMode::*;
+        let modes = [
+            IntentionShared,
+            IntentionExclusive,
+            Shared,
+            SharedIntentionExclusive,
+            Exclusive,
+        ];
+        let matrix = [
+            [true, true, true, true, false],
+            [true, true, false, false, false],
+            [true, false, true, false, false],
+            [true, false, false, false, false],
+            [false, false, false, false, false],
+        ];
+        for (i, a) in modes.iter().enumerate() {
+            for (j, b) in modes.iter().enumerate() {
+                assert_eq!(a.is_compatible(*b), matrix[i][j], "{:?} and {:?}", a, b);
+            }
+        }
+        assert_eq!(Shared.combine(IntentionExclusive), SharedIntentionExclusive);
+        assert_eq!(IntentionShared.combine(Exclusive), Exclusive);
+        assert!(SharedIntentionExclusive.covers(Shared));
+        assert!(!Shared.covers(IntentionExclusive));
+    }
+
+    #[test]
+    fn test_row_locks_need_a_table_intention_lock() {
+        let lockm = LockManager::new();
+        let t1 = txn(1);
+        let rid = RecordId::new(1, 0);
+        assert!(lockm.lock_row(&t1, LockMode::Shared, 7, rid).is_err());
+        assert!(lockm
+            .lock_row(&t1, LockMode::IntentionShared, 7, rid)
+            .is_err());
+
+        lockm.lock_table(&t1, LockMode::IntentionShared, 7).unwrap();
+        lockm.lock_row(&t1, LockMode::Shared, 7, rid).unwrap();
+        assert!(lockm.lock_row(&t1, LockMode::Exclusive, 7, rid).is_err());
+
+        lockm
+            .lock_table(&t1, LockMode::IntentionExclusive, 7)
+            .unwrap();
+        lockm.lock_row(&t1, LockMode::Exclusive, 7, rid).unwrap();
+        assert_eq!(
+            lockm.held_mode(&t1, LockTarget::Table(7)),
+            Some(LockMode::IntentionExclusive)
+        );
+        assert_eq!(
+            lockm.held_mode(&t1, LockTarget::Row(7, rid)),
+            Some(LockMode::Exclusive)
+        );
+
+        lockm.unlock_all(&t1);
+        assert_eq!(lockm.held_mode(&t1, LockTarget::Table(7)), None);
+        assert!(lockm.lock_table.lock().unwrap().queues.is_empty());
+    }
+
+    #[test]
+    fn test_conflicting_locks_wait_until_released() {
+        let lockm = Arc::new(LockManager::new());
+        let (t1, t2) = (txn(1), txn(2));
+        let rid = RecordId::new(1, 0);
+        lockm
+            .lock_table(&t1, LockMode::IntentionExclusive, 7)
+            .unwrap();
+        lockm.lock_row(&t1, LockMode::Exclusive, 7, rid).unwrap();
+        lockm
+            .lock_table(&t2, LockMode::IntentionExclusive, 7)
+            .unwrap();
+        assert!(lockm.try_lock_row(&t2, LockMode::Shared, 7, rid).is_err());
+
+        let (sender, receiver) = mpsc::channel();
+        let waiter = {
+            let (lockm, t2) = (Arc::clone(&lockm), Arc::clone(&t2));
+            thread::spawn(move || {
+                lockm.lock_row(&t2, LockMode::Exclusive, 7, rid).unwrap();
+                sender.send(()).unwrap();
+            })
+        };
+        assert!(receiver.recv_timeout(Duration::from_millis(100)).is_err());
+        lockm.unlock_all(&t1);
+        receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        waiter.join().unwrap();
+        assert_eq!(
+            lockm.held_mode(&t2, LockTarget::Row(7, rid)),
+            Some(LockMode::Exclusive)
+        );
+    }
+
+    #[test]
+    fn test_waiters_are_granted_in_order() {
+        let lockm = Arc::new(LockManager::new());
+        let (t1, t2, t3) = (txn(1), txn(2), txn(3));
+        lockm.lock_table(&t1, LockMode::Shared, 7).unwrap();
+
+        let (sender, receiver) = mpsc::channel();
+        let spawn = |t: Arc<Transaction>, mode| {
+            let (lockm, sender) = (Arc::clone(&lockm), sender.clone());
+            thread::spawn(move || {
+                lockm.lock_table(&t, mode, 7).unwrap();
+                sender.send(t.get_id()).unwrap();
+            })
+        };
+        let writer = spawn(Arc::clone(&t2), LockMode::Exclusive);
+        thread::sleep(Duration::from_millis(50));
+        // compatible with the granted S lock, but queued behind the X request
+        let reader = spawn(Arc::clone(&t3), LockMode::Shared);
+        assert!(receiver.recv_timeout(Duration::from_millis(100)).is_err());
+
+        lockm.unlock_all(&t1);
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(5)), Ok(2));
+        assert!(receiver.recv_timeout(Duration::from_millis(100)).is_err());
+        lockm.unlock_all(&t2);
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(5)), Ok(3));
+        writer.join().unwrap();
+        reader.join().unwrap();
+    }
+
+    #[test]
+    fn test_upgrades_wait_ahead_of_other_requests() {
+        let lockm = Arc::new(LockManager::new());
+        let (t1, t2, t3) = (txn(1), txn(2), txn(3));
+        lockm.lock_table(&t1, LockMode::Shared, 7).unwrap();
+        lockm.lock_table(&t2, LockMode::Shared, 7).unwrap();
+        // S covers IS already
+        lockm.lock_table(&t1, LockMode::IntentionShared, 7).unwrap();
+        assert_eq!(
+            lockm.held_mode(&t1, LockTarget::Table(7)),
+            Some(LockMode::Shared)
+        );
+
+        let (sender, receiver) = mpsc::channel();
+        let spawn = |t: Arc<Transaction>, mode| {
+            let (lockm, sender) = (Arc::clone(&lockm), sender.clone());
+            thread::spawn(move || {
+                let result = lockm.lock_table(&t, mode, 7);
+                sender.send((t.get_id(), result.is_ok())).unwrap();
+            })
+        };
+        let other = spawn(Arc::clone(&t3), LockMode::IntentionShared);
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(5)), Ok((3, true)));
+        other.join().unwrap();
+        lockm.unlock_all(&t3);
+
+        let writer = spawn(Arc::clone(&t3), LockMode::Exclusive);
+        thread::sleep(Duration::from_millis(50));
+        let upgrade = spawn(Arc::clone(&t1), LockMode::IntentionExclusive);
+        assert!(receiver.recv_timeout(Duration::from_millis(100)).is_err());
+
+        // a second upgrade would wait for the first forever
+        assert!(lockm.lock_table(&t2, LockMode::Exclusive, 7).is_err());
+        lockm.unlock_all(&t2);
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(5)), Ok((1, true)));
+        assert_eq!(
+            lockm.held_mode(&t1, LockTarget::Table(7)),
+            Some(LockMode::SharedIntentionExclusive)
+        );
+        lockm.unlock_all(&t1);
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(5)), Ok((3, true)));
+        upgrade.join().unwrap();
+        writer.join().unwrap();
+    }
+
+    #[test]
+    fn test_waiting_for_a_lock_times_out() {
+        let lockm = LockManager::new();
+        let (t1, t2, t3) = (txn(1), txn(2), txn(3));
+        lockm.lock_table(&t1, LockMode::Exclusive, 7).unwrap();
+        t2.set_lock_timeout(Some(Duration::from_millis(50)));
+        assert_eq!(t2.get_lock_timeout(), Some(Duration::from_millis(50)));
+        let started = Instant::now();
+        assert!(matches!(
+            lockm.lock_table(&t2, LockMode::IntentionShared, 7),
+            Err(LockError::NotAvailable(_))
+        ));
+        assert!(started.elapsed() >= Duration::from_millis(50));
+        // the request left the queue, and the transaction can go on
+        assert!(t2.check_running().is_ok());
+        lockm.lock_table(&t2, LockMode::IntentionShared, 8).unwrap();
+        lockm.unlock_all(&t1);
+        lockm.lock_table(&t3, LockMode::Exclusive, 7).unwrap();
+        assert_eq!(lockm.held_mode(&t2, LockTarget::Table(7)), None);
+    }
+
+    #[test]
+    fn test_gap_locks_hold_off_inserts_into_the_gap() {
+        let lockm = Arc::new(LockManager::new());
+        let (t1, t2, t3, t4) = (txn(1), txn(2), txn(3), txn(4));
+        let key = Some(b"k".to_vec());
+        assert!(lockm.lock_gap(&t4, 7, 1, key.clone()).is_err());
+        lockm.lock_table(&t4, LockMode::IntentionShared, 7).unwrap();
+        assert!(matches!(
+            lockm.try_enter_gap(&t4, 7, 1, key.clone()),
+            Err(LockError::Invalid(_))
+        ));
+
+        // scans share a gap
+        for t in [&t1, &t3] {
+            lockm.lock_table(t, LockMode::IntentionShared, 7).unwrap();
+            lockm.lock_gap(t, 7, 1, key.clone()).unwrap();
+        }
+        lockm
+            .lock_table(&t2, LockMode::IntentionExclusive, 7)
+            .unwrap();
+        assert!(matches!(
+            lockm.try_enter_gap(&t2, 7, 1, key.clone()),
+            Err(LockError::NotAvailable(_))
+        ));
+        // other gaps, and other indexes, are free
+        lockm.try_enter_gap(&t2, 7, 1, None).unwrap();
+        lockm.try_enter_gap(&t2, 7, 2, key.clone()).unwrap();
+        assert_eq!(lockm.held_mode(&t2, LockTarget::Gap(7, 1, None)), None);
+
+        let (sender, receiver) = mpsc::channel();
+        let waiter = {
+            let (lockm, t2, key) = (Arc::clone(&lockm), Arc::clone(&t2), key.clone());
+            thread::spawn(move || {
+                lockm.enter_gap(&t2, 7, 1, key).unwrap();
+                sender.send(()).unwrap();
+            })
+        };
+        assert!(receiver.recv_timeout(Duration::from_millis(100)).is_err());
+        lockm.unlock_all(&t1);
+        assert!(receiver.recv_timeout(Duration::from_millis(100)).is_err());
+        lockm.unlock_all(&t3);
+        receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        waiter.join().unwrap();
+        assert_eq!(
+            lockm.held_mode(&t2, LockTarget::Gap(7, 1, key.clone())),
+            None
+        );
+
+        // a transaction does not wait for its own gap lock
+        lockm
+            .lock_table(&t4, LockMode::IntentionExclusive, 7)
+            .unwrap();
+        lockm.lock_gap(&t4, 7, 1, key.clone()).unwrap();
+        lockm.try_enter_gap(&t4, 7, 1, key.clone()).unwrap();
+        assert_eq!(
+            lockm.held_mode(&t4, LockTarget::Gap(7, 1, key.clone())),
+            Some(LockMode::Shared)
+        );
+        lockm.unlock_all(&t2);
+        lockm.unlock_all(&t4);
+        assert!(lockm.lock_table.lock().unwrap().queues.is_empty());
+    }
+
+    #[test]
+    fn test_deadlocked_waiters_are_aborted() {
+        let lockm = Arc::new(LockManager::new());
+        let (t1, t2, t3) = (txn(1), txn(2), txn(3));
+        let (r1, r2) = (RecordId::new(1, 0), RecordId::new(1, 1));
+        for (t, rid) in [(&t1, r1), (&t2, r2)] {
+            lockm
+                .lock_table(t, LockMode::IntentionExclusive, 7)
+                .unwrap();
+            lockm.lock_row(t, LockMode::Exclusive, 7, rid).unwrap();
+        }
+        lockm
+            .lock_table(&t3, LockMode::IntentionExclusive, 7)
+            .unwrap();
+
+        let (sender, receiver) = mpsc::channel();
+        let spawn = |t: &Arc<Transaction>, rid| {
+            let (lockm, t, sender) = (Arc::clone(&lockm), Arc::clone(t), sender.clone());
+            thread::spawn(move || {
+                let result = lockm.lock_row(&t, LockMode::Exclusive, 7, rid);
+                sender.send((t.get_id(), result)).unwrap();
+            })
+        };
+        let waiting = |n| {
+            while lockm.lock_table.lock().unwrap().waits_for().len() < n {
+                thread::sleep(Duration::from_millis(10));
+            }
+        };
+        // 3 waits behind 1 without being part of the cycle
+        let mut waiters = vec![];
+        for (n, (t, rid)) in [(&t1, r2), (&t3, r2), (&t2, r1)].into_iter().enumerate() {
+            waiters.push(spawn(t, rid));
+            waiting(n + 1);
+        }
+        assert_eq!(lockm.detect_deadlocks(), vec![2]);
+        let (txn_id, result) = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(txn_id, 2);
+        assert!(matches!(result, Err(LockError::Deadlock(_))));
+        assert!(t2.check_running().is_err());
+        assert!(lockm.detect_deadlocks().is_empty());
+
+        lockm.unlock_all(&t2);
+        assert_eq!(
+            receiver.recv_timeout(Duration::from_secs(5)),
+            Ok((1, Ok(())))
+        );
+        lockm.unlock_all(&t1);
+        assert_eq!(
+            receiver.recv_timeout(Duration::from_secs(5)),
+            Ok((3, Ok(())))
+        );
+        for waiter in waiters {
+            waiter.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_the_detector_breaks_deadlocks_in_the_background() {
+        let lockm = Arc::new(LockManager::new());
+        let detector = DeadlockDetector::start(Arc::clone(&lockm), Duration::from_millis(10));
+        let (t1, t2) = (txn(1), txn(2));
+        lockm.lock_table(&t1, LockMode::Shared, 7).unwrap();
+        lockm.lock_table(&t2, LockMode::Shared, 8).unwrap();
+        let waiter = {
+            let (lockm, t1) = (Arc::clone(&lockm), Arc::clone(&t1));
+            thread::spawn(move || lockm.lock_table(&t1, LockMode::Exclusive, 8))
+        };
+        assert!(matches!(
+            lockm.lock_table(&t2, LockMode::Exclusive, 7),
+            Err(LockError::Deadlock(_))
+        ));
+        lockm.unlock_all(&t2);
+        assert_eq!(waiter.join().unwrap(), Ok(()));
+        drop(detector);
+    }
+}
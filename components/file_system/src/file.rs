@@ -1,12 +1,12 @@
 use super::settings::get_io_type;
 use crate::io_rate_limiter::{IoOp, IoRateLimiter};
 use std::env;
-use std::path::PathBuf;
 use std::fmt::{self, Debug, Formatter};
 use std::fs;
 #[allow(unused)]
 use std::io::{Read, Result, Seek, SeekFrom, Write};
 use std::path::Path;
+use std::path::PathBuf;
 use std::sync::Arc;
 
 use super::io_rate_limiter::get_io_rate_limiter;
@@ -50,6 +50,21 @@ impl File {
         })
     }
 
+    // Opens the file for reading and writing, creating it if needed. Unlike `create` the
+    // existing contents are kept, e.g. for a database file that is reopened on startup.
+    pub fn open_or_create<P: AsRef<Path>>(path: P) -> Result<File> {
+        let inner = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(env::temp_dir().join(path))?;
+        Ok(File {
+            inner,
+            limiter: get_io_rate_limiter(),
+        })
+    }
+
     #[cfg(test)]
     pub fn create_with_limiter<P: AsRef<Path>>(
         path: P,
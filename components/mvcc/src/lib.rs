@@ -1,5 +1,5 @@
-mod transaction_manager;
-mod types;
+pub mod transaction_manager;
+pub mod types;
 
 #[cfg(test)]
 mod tests {
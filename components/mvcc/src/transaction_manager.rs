@@ -1,17 +1,772 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
 
-use std::{collections::HashMap, sync::atomic::AtomicUsize};
-use common::types::PageId;
-use std::cell::RefCell;
+use common::transaction::{
+    IsolationLevel, Timestamp, Transaction, TransactionState, TxnId, TXN_START_ID,
+};
+use common::types::{PageId, RecordId};
 
-use crate::types::{PageVersionInfo, Watermark};
+use crate::types::{PageVersionInfo, UndoLink, UndoLog, VersionLink, Watermark};
 
+// Hands out transactions and their timestamps. A transaction reads as of the last commit
+// timestamp when it began, or when its statement began under READ COMMITTED; commits are
+// numbered one after the other under the commit latch, so a transaction beginning after a
+// commit returned sees all of it.
+//
+// It also keeps the versions of the tuples of type T, see `types`. Writers install a tuple's
+// version link before they change the tuple in place, and readers read the tuple before its
+// version link, so a reader never takes a newer tuple for the version the link describes. An
+// aborted transaction puts its tuples back as they were but leaves their version links, which
+// keep sending readers to the undo logs until the tuples are written again.
+//
+// Versions older than the newest one at or below the watermark are read by no transaction,
+// `garbage_collect` drops them. Those committed inside the retention window are kept as well,
+// for transactions going back in time with `begin_as_of`.
+//
+// Serializable transactions are checked when they commit, see `check_serializable`.
 
+pub struct TransactionManager<T> {
+    next_txn_id: AtomicU64,
+    last_commit_ts: AtomicU64,
+    // held while a commit timestamp is handed out and the transaction marked committed
+    commit_latch: Mutex<()>,
+    // transactions that did not finish yet
+    txn_map: RwLock<HashMap<TxnId, Arc<Transaction>>>,
+    // taken under the commit latch when a transaction begins or commits
+    watermark: Mutex<Watermark>,
+    // undo logs by the transaction that wrote them
+    undo_logs: RwLock<HashMap<TxnId, Vec<UndoLog<T>>>>,
+    version_info: RwLock<HashMap<PageId, PageVersionInfo>>,
+    // committed transactions that ran alongside one still running, taken under the commit latch
+    committed: Mutex<Vec<CommittedTxn>>,
+    // when the commit timestamps were handed out, back to the oldest one that can be read as of
+    commit_times: Mutex<VecDeque<(Timestamp, SystemTime)>>,
+    retention: Mutex<Duration>,
+    // versions of commits at or below it may be dropped, taken under the watermark lock
+    collected: AtomicU64,
+}
 
+struct CommittedTxn {
+    txn: Arc<Transaction>,
+    commit_ts: Timestamp,
+    write_set: HashMap<u32, HashSet<RecordId>>,
+    // it has an rw-antidependency on a transaction that committed before it
+    out_conflict: bool,
+}
 
-#[allow(dead_code)]
-struct TransactionManager {
-    version_info: HashMap<PageId, RefCell<PageVersionInfo>>,
-    running_txns: Watermark,
-    last_commit_ts: AtomicUsize,
-}   
+impl<T: Clone> Default for TransactionManager<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
+impl<T: Clone> TransactionManager<T> {
+    pub fn new() -> Self {
+        Self {
+            next_txn_id: AtomicU64::new(TXN_START_ID),
+            last_commit_ts: AtomicU64::new(0),
+            commit_latch: Mutex::new(()),
+            txn_map: RwLock::new(HashMap::new()),
+            watermark: Mutex::new(Watermark::default()),
+            undo_logs: RwLock::new(HashMap::new()),
+            version_info: RwLock::new(HashMap::new()),
+            committed: Mutex::new(vec![]),
+            // the tuples as they were at startup have commit timestamp 0
+            commit_times: Mutex::new(VecDeque::from([(0, SystemTime::now())])),
+            retention: Mutex::new(Duration::ZERO),
+            collected: AtomicU64::new(0),
+        }
+    }
+
+    pub fn begin(&self, isolation_level: IsolationLevel) -> Arc<Transaction> {
+        let txn_id = self.next_txn_id.fetch_add(1, Ordering::SeqCst);
+        // a commit in progress is not seen until its timestamp is the last one
+        let _commit = self.commit_latch.lock().unwrap();
+        let read_ts = self.last_commit_ts.load(Ordering::SeqCst);
+        let txn = Arc::new(Transaction::new(txn_id, isolation_level, read_ts));
+        self.watermark.lock().unwrap().add_txn(read_ts);
+        self.txn_map
+            .write()
+            .unwrap()
+            .insert(txn_id, Arc::clone(&txn));
+        txn
+    }
+
+    /// Begins a read-only transaction reading the database as it was at commit timestamp
+    /// `read_ts`. Fails if the versions it needs may have been garbage-collected, see
+    /// `set_retention`.
+    pub fn begin_as_of(&self, read_ts: Timestamp) -> Result<Arc<Transaction>, String> {
+        let txn_id = self.next_txn_id.fetch_add(1, Ordering::SeqCst);
+        let mut watermark = self.watermark.lock().unwrap();
+        if read_ts > self.get_last_commit_ts() {
+            return Err(format!(
+                "commit timestamp {} was not handed out yet",
+                read_ts
+            ));
+        }
+        if read_ts < self.collected.load(Ordering::SeqCst) {
+            return Err(format!(
+                "commit timestamp {} is older than the versions kept",
+                read_ts
+            ));
+        }
+        let txn = Arc::new(Transaction::new_read_only(
+            txn_id,
+            IsolationLevel::SNAPSHOTISOLATION,
+            read_ts,
+        ));
+        watermark.add_txn(read_ts);
+        self.txn_map
+            .write()
+            .unwrap()
+            .insert(txn_id, Arc::clone(&txn));
+        Ok(txn)
+    }
+
+    /// The last commit timestamp handed out at or before `time`.
+    pub fn get_commit_ts_at(&self, time: SystemTime) -> Result<Timestamp, String> {
+        let commit_times = self.commit_times.lock().unwrap();
+        commit_times
+            .iter()
+            .rev()
+            .find(|(_, committed)| *committed <= time)
+            .map(|(commit_ts, _)| *commit_ts)
+            .ok_or_else(|| "the time is older than the versions kept".to_string())
+    }
+
+    /// Keeps the versions committed within `retention` of now from being garbage-collected,
+    /// so they can be read with `begin_as_of`.
+    pub fn set_retention(&self, retention: Duration) {
+        *self.retention.lock().unwrap() = retention;
+    }
+
+    pub fn get_retention(&self) -> Duration {
+        *self.retention.lock().unwrap()
+    }
+
+    /// The commit timestamp the versions are kept back to: the watermark, or the timestamp
+    /// current at the start of the retention window if that is older. Transactions cannot go
+    /// back further once this was called.
+    pub fn get_gc_horizon(&self) -> Timestamp {
+        let since = SystemTime::now().checked_sub(self.get_retention());
+        let retained = {
+            let commit_times = self.commit_times.lock().unwrap();
+            commit_times
+                .iter()
+                .rev()
+                .find(|(_, committed)| since.is_some_and(|since| *committed <= since))
+                .or(commit_times.front())
+                .map_or(0, |(commit_ts, _)| *commit_ts)
+        };
+        let watermark = self.watermark.lock().unwrap();
+        let horizon = watermark.get_watermark().min(retained);
+        self.collected.fetch_max(horizon, Ordering::SeqCst);
+        horizon
+    }
+
+    /// Takes a new snapshot for the next statement of a READ COMMITTED transaction, which then
+    /// reads what committed before it began. Other transactions keep theirs.
+    pub fn begin_statement(&self, txn: &Transaction) {
+        if txn.get_isolation_level() != IsolationLevel::READCOMMITTED {
+            return;
+        }
+        let _commit = self.commit_latch.lock().unwrap();
+        let read_ts = self.last_commit_ts.load(Ordering::SeqCst);
+        let mut watermark = self.watermark.lock().unwrap();
+        watermark.remove_txn(txn.get_read_ts());
+        watermark.add_txn(read_ts);
+        txn.set_read_ts(read_ts);
+    }
+
+    /// Commits the transaction. A serializable transaction that cannot be serialized with the
+    /// transactions that committed while it ran fails and is tainted, it can be retried.
+    pub fn commit(&self, txn: &Transaction) -> Result<Timestamp, String> {
+        let _commit = self.commit_latch.lock().unwrap();
+        txn.check_running()?;
+        let write_set = txn.get_write_set();
+        let mut committed = self.committed.lock().unwrap();
+        let out_conflict = match txn.get_isolation_level() {
+            IsolationLevel::SERIALIZABLE => Self::check_serializable(&committed, txn, &write_set)?,
+            _ => false,
+        };
+        let commit_ts = self.last_commit_ts.load(Ordering::SeqCst) + 1;
+        {
+            let mut version_info = self.version_info.write().unwrap();
+            for rid in write_set.values().flatten() {
+                if let Some(link) = Self::link_mut(&mut version_info, *rid) {
+                    if link.ts == txn.get_id() {
+                        link.ts = commit_ts;
+                    }
+                }
+            }
+        }
+        txn.set_commit_ts(commit_ts);
+        txn.set_state(TransactionState::COMMITTED);
+        self.commit_times
+            .lock()
+            .unwrap()
+            .push_back((commit_ts, SystemTime::now()));
+        self.last_commit_ts.store(commit_ts, Ordering::SeqCst);
+        let removed = self.txn_map.write().unwrap().remove(&txn.get_id());
+        let mut watermark = self.watermark.lock().unwrap();
+        watermark.remove_txn(txn.get_read_ts());
+        watermark.update_commit_ts(commit_ts);
+        // only transactions that began before a commit can conflict with it
+        committed.retain(|other| other.commit_ts > watermark.get_watermark());
+        if let Some(removed) = removed.filter(|_| {
+            !write_set.is_empty() || txn.get_isolation_level() == IsolationLevel::SERIALIZABLE
+        }) {
+            committed.push(CommittedTxn {
+                txn: removed,
+                commit_ts,
+                write_set,
+                out_conflict,
+            });
+        }
+        Ok(commit_ts)
+    }
+
+    // Serializable snapshot isolation. A transaction reading a row that a concurrent one writes
+    // reads the version before, so it has to come first in any serial order: it has an
+    // rw-antidependency on the writer. Every cycle of dependencies among transactions running
+    // under snapshot isolation has a pivot, a transaction with an rw-antidependency on one that
+    // committed first and another one having an rw-antidependency on it.
+    //
+    // `txn` fails to commit if it would be such a pivot, or if it has an rw-antidependency on a
+    // committed pivot. Returns whether it has an rw-antidependency on a committed transaction.
+    fn check_serializable(
+        committed: &[CommittedTxn],
+        txn: &Transaction,
+        write_set: &HashMap<u32, HashSet<RecordId>>,
+    ) -> Result<bool, String> {
+        // in commit order
+        let mut first_out = None;
+        let mut last_in = None;
+        for other in committed
+            .iter()
+            .filter(|other| other.commit_ts > txn.get_read_ts())
+        {
+            if txn.read_any(&other.write_set) {
+                if other.out_conflict {
+                    return Err(Self::serialization_failure(txn));
+                }
+                first_out.get_or_insert(other.commit_ts);
+            }
+            if other.txn.read_any(write_set) {
+                last_in = Some(other.commit_ts);
+            }
+        }
+        match (first_out, last_in) {
+            // the transaction `txn` depends on committed before the one depending on it, or is it
+            (Some(first_out), Some(last_in)) if first_out <= last_in => {
+                Err(Self::serialization_failure(txn))
+            }
+            _ => Ok(first_out.is_some()),
+        }
+    }
+
+    fn serialization_failure(txn: &Transaction) -> String {
+        txn.set_tainted();
+        format!(
+            "could not serialize transaction {} with the transactions that committed while it ran, it can be retried",
+            txn.get_id()
+        )
+    }
+
+    pub fn abort(&self, txn: &Transaction) -> Result<(), String> {
+        match txn.get_state() {
+            TransactionState::RUNNING | TransactionState::TAINTED => {}
+            state => {
+                return Err(format!(
+                    "transaction {} is {:?} and cannot be aborted",
+                    txn.get_id(),
+                    state
+                ))
+            }
+        }
+        txn.set_state(TransactionState::ABORTED);
+        self.txn_map.write().unwrap().remove(&txn.get_id());
+        self.watermark.lock().unwrap().remove_txn(txn.get_read_ts());
+        Ok(())
+    }
+
+    /// The running or tainted transaction with the id.
+    pub fn get_transaction(&self, txn_id: TxnId) -> Option<Arc<Transaction>> {
+        self.txn_map.read().unwrap().get(&txn_id).cloned()
+    }
+
+    pub fn get_last_commit_ts(&self) -> Timestamp {
+        self.last_commit_ts.load(Ordering::SeqCst)
+    }
+
+    pub fn get_watermark(&self) -> Timestamp {
+        self.watermark.lock().unwrap().get_watermark()
+    }
+
+    /// Drops the undo logs no transaction can read any more, along with the version links of
+    /// tuples whose newest version every transaction sees. Those are read as they are from then
+    /// on, like tuples written before startup.
+    pub fn garbage_collect(&self) {
+        let watermark = self.get_gc_horizon();
+        {
+            // one at or below the horizon is kept, times after it map to it
+            let mut commit_times = self.commit_times.lock().unwrap();
+            while commit_times.get(1).is_some_and(|(ts, _)| *ts <= watermark) {
+                commit_times.pop_front();
+            }
+        }
+        let mut version_info = self.version_info.write().unwrap();
+        let mut undo_logs = self.undo_logs.write().unwrap();
+        let txn_map = self.txn_map.read().unwrap();
+        let get_log = |undo_logs: &HashMap<TxnId, Vec<UndoLog<T>>>, link: UndoLink| {
+            undo_logs
+                .get(&link.prev_txn)
+                .and_then(|logs| logs.get(link.prev_log_idx))
+                .map(|log| (log.ts, log.prev_version))
+        };
+        let mut reachable = HashSet::new();
+        for page in version_info.values_mut() {
+            page.prev_link.retain(|_, link| {
+                let settled = match link.ts {
+                    ts if ts < TXN_START_ID => ts <= watermark,
+                    txn_id if txn_map.contains_key(&txn_id) => false,
+                    // aborted, the tuple is as the undo log has it, or gone if it was inserted
+                    _ => get_log(&undo_logs, link.prev).is_none_or(|(ts, _)| ts <= watermark),
+                };
+                if settled {
+                    return false;
+                }
+                // the chain is read up to the first version at or below the watermark
+                let mut prev = link.prev;
+                while let Some((ts, prev_version)) = get_log(&undo_logs, prev) {
+                    reachable.insert(prev.prev_txn);
+                    if ts <= watermark {
+                        let logs = undo_logs.get_mut(&prev.prev_txn).unwrap();
+                        logs[prev.prev_log_idx].prev_version = UndoLink::INVALID;
+                        break;
+                    }
+                    prev = prev_version;
+                }
+                true
+            });
+        }
+        version_info.retain(|_, page| !page.prev_link.is_empty());
+        undo_logs.retain(|txn_id, _| txn_map.contains_key(txn_id) || reachable.contains(txn_id));
+    }
+
+    fn link_mut(
+        version_info: &mut HashMap<PageId, PageVersionInfo>,
+        rid: RecordId,
+    ) -> Option<&mut VersionLink> {
+        version_info
+            .get_mut(&rid.page_id)?
+            .prev_link
+            .get_mut(&rid.slot)
+    }
+
+    pub fn get_version_link(&self, rid: RecordId) -> Option<VersionLink> {
+        let version_info = self.version_info.read().unwrap();
+        version_info
+            .get(&rid.page_id)?
+            .prev_link
+            .get(&rid.slot)
+            .copied()
+    }
+
+    pub fn get_undo_log(&self, link: UndoLink) -> Option<UndoLog<T>> {
+        let undo_logs = self.undo_logs.read().unwrap();
+        undo_logs
+            .get(&link.prev_txn)?
+            .get(link.prev_log_idx)
+            .cloned()
+    }
+
+    // The timestamp and undo link of the version `txn` writes over when it writes the tuple,
+    // None if `txn` wrote it before. Taints `txn` on a write-write conflict. Under READ
+    // COMMITTED a transaction writes over the newest committed version, seen or not.
+    fn written_over(
+        &self,
+        version_info: &HashMap<PageId, PageVersionInfo>,
+        txn: &Transaction,
+        rid: RecordId,
+    ) -> Result<Option<(Timestamp, UndoLink)>, String> {
+        let link = version_info
+            .get(&rid.page_id)
+            .and_then(|page| page.prev_link.get(&rid.slot))
+            .copied();
+        let (ts, prev) = match link {
+            None => (0, UndoLink::INVALID),
+            Some(link) if link.ts == txn.get_id() => return Ok(None),
+            Some(link) if link.ts < TXN_START_ID => (link.ts, link.prev),
+            // not committed yet, transaction ids are above every read timestamp
+            Some(link) if self.get_transaction(link.ts).is_some() => (link.ts, link.prev),
+            // written by a transaction that aborted, the tuple is as it was before
+            Some(link) => match self.get_undo_log(link.prev) {
+                Some(log) => (log.ts, log.prev_version),
+                None => (0, UndoLink::INVALID),
+            },
+        };
+        let committed = ts < TXN_START_ID;
+        if ts > txn.get_read_ts()
+            && !(committed && txn.get_isolation_level() == IsolationLevel::READCOMMITTED)
+        {
+            txn.set_tainted();
+            return Err(format!(
+                "write-write conflict on {:?}, it was changed by a concurrent transaction",
+                rid
+            ));
+        }
+        Ok(Some((ts, prev)))
+    }
+
+    /// Fails with a write-write conflict, tainting `txn`, if writing the tuple at `rid` would
+    /// write over a version `txn` cannot see.
+    pub fn check_write(&self, txn: &Transaction, rid: RecordId) -> Result<(), String> {
+        let version_info = self.version_info.read().unwrap();
+        self.written_over(&version_info, txn, rid).map(|_| ())
+    }
+
+    /// Called before `txn` writes the tuple at `rid`, whose newest version is `current`, or
+    /// before it makes an inserted tuple visible, with `current` None. The first write of a
+    /// tuple saves `current` to an undo log. Returns the version the tuple had before `txn`
+    /// wrote it, None if `txn` inserted it.
+    ///
+    /// Writing over a version `txn` cannot see is a write-write conflict: the transaction is
+    /// tainted and can only be aborted.
+    pub fn begin_write(
+        &self,
+        txn: &Transaction,
+        rid: RecordId,
+        current: Option<&T>,
+    ) -> Result<Option<T>, String> {
+        let mut version_info = self.version_info.write().unwrap();
+        let Some((ts, prev)) = self.written_over(&version_info, txn, rid)? else {
+            let link = version_info[&rid.page_id].prev_link[&rid.slot];
+            return Ok(self.get_undo_log(link.prev).map(|log| log.tuple));
+        };
+        let prev = match current {
+            Some(tuple) => {
+                let mut undo_logs = self.undo_logs.write().unwrap();
+                let logs = undo_logs.entry(txn.get_id()).or_default();
+                logs.push(UndoLog {
+                    tuple: tuple.clone(),
+                    ts,
+                    prev_version: prev,
+                });
+                UndoLink {
+                    prev_txn: txn.get_id(),
+                    prev_log_idx: logs.len() - 1,
+                }
+            }
+            None => UndoLink::INVALID,
+        };
+        version_info
+            .entry(rid.page_id)
+            .or_default()
+            .prev_link
+            .insert(
+                rid.slot,
+                VersionLink {
+                    ts: txn.get_id(),
+                    prev,
+                },
+            );
+        Ok(current.cloned())
+    }
+
+    /// The version of the tuple at `rid` that `txn` sees, given the newest version and whether
+    /// it is marked deleted. The newest version has to be read before this is called.
+    pub fn visible_version(
+        &self,
+        txn: &Transaction,
+        rid: RecordId,
+        current: T,
+        deleted: bool,
+    ) -> Option<T> {
+        let Some(link) = self.get_version_link(rid) else {
+            return (!deleted).then_some(current);
+        };
+        if link.ts == txn.get_id() || (link.ts < TXN_START_ID && link.ts <= txn.get_read_ts()) {
+            return (!deleted).then_some(current);
+        }
+        let mut prev = link.prev;
+        while let Some(log) = self.get_undo_log(prev) {
+            if log.ts <= txn.get_read_ts() {
+                return Some(log.tuple);
+            }
+            prev = log.prev_version;
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use common::types::RecordId;
+
+    #[test]
+    fn test_begin_commit_and_abort() {
+        let txn_manager = TransactionManager::<String>::new();
+        let first = txn_manager.begin(IsolationLevel::default());
+        let second = txn_manager.begin(IsolationLevel::SERIALIZABLE);
+        assert_ne!(first.get_id(), second.get_id());
+        assert_eq!(second.get_isolation_level(), IsolationLevel::SERIALIZABLE);
+        assert_eq!((first.get_read_ts(), second.get_read_ts()), (0, 0));
+        assert_eq!(first.get_state(), TransactionState::RUNNING);
+
+        first.append_write_set(1, RecordId::new(3, 0));
+        first.append_write_set(1, RecordId::new(3, 0));
+        assert_eq!(first.get_write_set()[&1].len(), 1);
+        assert_eq!(txn_manager.commit(&first), Ok(1));
+        assert_eq!(first.get_commit_ts(), Some(1));
+        assert_eq!(first.get_state(), TransactionState::COMMITTED);
+        assert!(txn_manager.get_transaction(first.get_id()).is_none());
+
+        // later transactions read what committed before they began
+        let third = txn_manager.begin(IsolationLevel::default());
+        assert_eq!(third.get_read_ts(), 1);
+        assert!(txn_manager.get_transaction(third.get_id()).is_some());
+
+        second.set_tainted();
+        assert!(txn_manager.commit(&second).is_err());
+        txn_manager.abort(&second).unwrap();
+        assert_eq!(second.get_state(), TransactionState::ABORTED);
+        assert_eq!(second.get_commit_ts(), None);
+        assert!(txn_manager.abort(&first).is_err());
+        assert!(txn_manager.commit(&second).is_err());
+        assert_eq!(txn_manager.commit(&third), Ok(2));
+    }
+
+    #[test]
+    fn test_versions_and_write_conflicts() {
+        let txn_manager = TransactionManager::<String>::new();
+        let rid = RecordId::new(3, 1);
+        let read = |txn: &Transaction, current: &str, deleted: bool| {
+            txn_manager.visible_version(txn, rid, current.to_string(), deleted)
+        };
+
+        // a tuple from before startup is seen by everyone, an insert only by its writer
+        let writer = txn_manager.begin(IsolationLevel::default());
+        let reader = txn_manager.begin(IsolationLevel::default());
+        assert_eq!(read(&reader, "a", false), Some("a".to_string()));
+        assert_eq!(txn_manager.begin_write(&writer, rid, None), Ok(None));
+        writer.append_write_set(1, rid);
+        assert_eq!(read(&writer, "a", false), Some("a".to_string()));
+        assert_eq!(read(&reader, "a", false), None);
+        txn_manager.commit(&writer).unwrap();
+        assert_eq!(read(&reader, "a", false), None);
+        let reader = txn_manager.begin(IsolationLevel::default());
+        assert_eq!(read(&reader, "a", false), Some("a".to_string()));
+
+        // an update keeps the old version for those who began before it committed
+        let writer = txn_manager.begin(IsolationLevel::default());
+        let a = "a".to_string();
+        assert_eq!(txn_manager.begin_write(&writer, rid, Some(&a)), Ok(Some(a)));
+        writer.append_write_set(1, rid);
+        assert_eq!(read(&writer, "b", false), Some("b".to_string()));
+        assert_eq!(read(&reader, "b", false), Some("a".to_string()));
+        // a second write of the same tuple still reports the version before the transaction
+        let b = "b".to_string();
+        assert_eq!(
+            txn_manager.begin_write(&writer, rid, Some(&b)),
+            Ok(Some("a".to_string()))
+        );
+        assert_eq!(read(&writer, "c", true), None);
+        assert_eq!(read(&reader, "c", true), Some("a".to_string()));
+
+        // writing a tuple another transaction wrote and did not commit is a conflict
+        assert!(txn_manager.begin_write(&reader, rid, Some(&b)).is_err());
+        assert_eq!(reader.get_state(), TransactionState::TAINTED);
+        txn_manager.abort(&reader).unwrap();
+        txn_manager.commit(&writer).unwrap();
+
+        // and so is writing a version committed after the transaction began
+        let late = txn_manager.begin(IsolationLevel::default());
+        let writer = txn_manager.begin(IsolationLevel::default());
+        assert_eq!(read(&late, "c", true), None);
+        let c = "c".to_string();
+        txn_manager.begin_write(&writer, rid, Some(&c)).unwrap();
+        writer.append_write_set(1, rid);
+        txn_manager.commit(&writer).unwrap();
+        assert!(txn_manager.begin_write(&late, rid, Some(&c)).is_err());
+
+        // the writes of an aborted transaction are passed over
+        let aborted = txn_manager.begin(IsolationLevel::default());
+        txn_manager.begin_write(&aborted, rid, Some(&c)).unwrap();
+        txn_manager.abort(&aborted).unwrap();
+        let reader = txn_manager.begin(IsolationLevel::default());
+        assert_eq!(read(&reader, "c", false), Some("c".to_string()));
+        assert_eq!(
+            txn_manager.begin_write(&reader, rid, Some(&c)),
+            Ok(Some("c".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_garbage_collection_keeps_what_running_transactions_read() {
+        let txn_manager = TransactionManager::<String>::new();
+        let rid = RecordId::new(3, 1);
+        let write = |current: Option<&str>| {
+            let writer = txn_manager.begin(IsolationLevel::default());
+            let current = current.map(|c| c.to_string());
+            txn_manager
+                .begin_write(&writer, rid, current.as_ref())
+                .unwrap();
+            writer.append_write_set(1, rid);
+            writer
+        };
+        let first = write(None);
+        txn_manager.commit(&first).unwrap();
+        let reader = txn_manager.begin(IsolationLevel::default());
+        for current in ["a", "b"] {
+            let writer = write(Some(current));
+            txn_manager.commit(&writer).unwrap();
+        }
+        assert_eq!(txn_manager.get_watermark(), reader.get_read_ts());
+
+        // the reader still needs the oldest version
+        txn_manager.garbage_collect();
+        let read =
+            |txn: &Transaction| txn_manager.visible_version(txn, rid, "c".to_string(), false);
+        assert_eq!(read(&reader), Some("a".to_string()));
+        txn_manager.commit(&reader).unwrap();
+        assert_eq!(
+            txn_manager.get_watermark(),
+            txn_manager.get_last_commit_ts()
+        );
+        let link = txn_manager.get_version_link(rid).unwrap();
+        txn_manager.garbage_collect();
+        assert_eq!(txn_manager.get_version_link(rid), None);
+        assert_eq!(txn_manager.get_undo_log(link.prev), None);
+
+        // an aborted write is dropped once the version it wrote over is old enough
+        let aborted = write(Some("c"));
+        let link = txn_manager.get_version_link(rid).unwrap();
+        txn_manager.abort(&aborted).unwrap();
+        let reader = txn_manager.begin(IsolationLevel::default());
+        assert_eq!(read(&reader), Some("c".to_string()));
+        txn_manager.garbage_collect();
+        assert_eq!(txn_manager.get_version_link(rid), None);
+        assert_eq!(txn_manager.get_undo_log(link.prev), None);
+        assert_eq!(read(&reader), Some("c".to_string()));
+    }
+
+    #[test]
+    fn test_serializable_transactions_fail_on_dangerous_structures() {
+        let txn_manager = TransactionManager::<String>::new();
+        let (x, y) = (RecordId::new(1, 0), RecordId::new(1, 1));
+        let read_and_write = |level, read: RecordId, write: RecordId| {
+            let txn = txn_manager.begin(level);
+            txn.append_read_set(1, read);
+            txn.append_write_set(1, write);
+            txn
+        };
+
+        // write skew: each reads what the other writes, snapshot isolation lets both commit
+        let first = read_and_write(IsolationLevel::SNAPSHOTISOLATION, x, y);
+        let second = read_and_write(IsolationLevel::SNAPSHOTISOLATION, y, x);
+        txn_manager.commit(&first).unwrap();
+        txn_manager.commit(&second).unwrap();
+
+        let first = read_and_write(IsolationLevel::SERIALIZABLE, x, y);
+        let second = read_and_write(IsolationLevel::SERIALIZABLE, y, x);
+        txn_manager.commit(&first).unwrap();
+        assert!(txn_manager.commit(&second).is_err());
+        assert_eq!(second.get_state(), TransactionState::TAINTED);
+        txn_manager.abort(&second).unwrap();
+        let retried = read_and_write(IsolationLevel::SERIALIZABLE, y, x);
+        txn_manager.commit(&retried).unwrap();
+
+        // the pivot commits after the transaction it depends on, the one depending on it fails
+        let pivot = read_and_write(IsolationLevel::SERIALIZABLE, x, y);
+        let reader = txn_manager.begin(IsolationLevel::SERIALIZABLE);
+        let writer = txn_manager.begin(IsolationLevel::SNAPSHOTISOLATION);
+        writer.append_write_set(1, x);
+        txn_manager.commit(&writer).unwrap();
+        txn_manager.commit(&pivot).unwrap();
+        reader.append_read_set(1, y);
+        assert!(txn_manager.commit(&reader).is_err());
+
+        // reading nothing written concurrently commits
+        let reader = txn_manager.begin(IsolationLevel::SERIALIZABLE);
+        let writer = read_and_write(IsolationLevel::SERIALIZABLE, y, x);
+        reader.append_read_set(1, y);
+        txn_manager.commit(&writer).unwrap();
+        txn_manager.commit(&reader).unwrap();
+    }
+
+    #[test]
+    fn test_read_committed_takes_a_snapshot_per_statement() {
+        let txn_manager = TransactionManager::<String>::new();
+        let rid = RecordId::new(1, 0);
+        let read_committed = txn_manager.begin(IsolationLevel::READCOMMITTED);
+        let snapshot = txn_manager.begin(IsolationLevel::SNAPSHOTISOLATION);
+        let writer = txn_manager.begin(IsolationLevel::default());
+        txn_manager.begin_write(&writer, rid, None).unwrap();
+        writer.append_write_set(1, rid);
+        txn_manager.commit(&writer).unwrap();
+
+        let read =
+            |txn: &Transaction| txn_manager.visible_version(txn, rid, "a".to_string(), false);
+        assert_eq!(read(&read_committed), None);
+        txn_manager.begin_statement(&read_committed);
+        txn_manager.begin_statement(&snapshot);
+        assert_eq!(read(&read_committed), Some("a".to_string()));
+        assert_eq!(read(&snapshot), None);
+        assert_eq!(txn_manager.get_watermark(), snapshot.get_read_ts());
+
+        // the newest committed version is written over, an uncommitted one is a conflict
+        assert!(txn_manager.check_write(&snapshot, rid).is_err());
+        let next = txn_manager.begin(IsolationLevel::READCOMMITTED);
+        let writer = txn_manager.begin(IsolationLevel::default());
+        txn_manager.commit(&writer).unwrap();
+        let current = "a".to_string();
+        txn_manager.begin_write(&next, rid, Some(&current)).unwrap();
+        assert!(txn_manager.check_write(&read_committed, rid).is_err());
+        assert_eq!(read_committed.get_state(), TransactionState::TAINTED);
+    }
+
+    #[test]
+    fn test_reading_as_of_an_earlier_commit() {
+        let txn_manager = TransactionManager::<String>::new();
+        let rid = RecordId::new(1, 0);
+        let write = |current: Option<&str>| {
+            let writer = txn_manager.begin(IsolationLevel::default());
+            txn_manager
+                .begin_write(&writer, rid, current.map(|c| c.to_string()).as_ref())
+                .unwrap();
+            writer.append_write_set(1, rid);
+            txn_manager.commit(&writer).unwrap()
+        };
+        let read = |txn: &Transaction, current: &str| {
+            txn_manager.visible_version(txn, rid, current.to_string(), false)
+        };
+        let inserted = write(None);
+        write(Some("a"));
+        let as_of = txn_manager.begin_as_of(inserted).unwrap();
+        assert_eq!(read(&as_of, "b"), Some("a".to_string()));
+        assert!(as_of.check_writable().is_err());
+        assert!(txn_manager
+            .begin_as_of(txn_manager.get_last_commit_ts() + 1)
+            .is_err());
+        assert_eq!(
+            txn_manager.get_commit_ts_at(SystemTime::now()),
+            Ok(txn_manager.get_last_commit_ts())
+        );
+        txn_manager.commit(&as_of).unwrap();
+
+        // nothing is kept for going back without a retention window
+        txn_manager.garbage_collect();
+        assert!(txn_manager.begin_as_of(inserted).is_err());
+        txn_manager.set_retention(Duration::from_secs(3600));
+        let updated = write(Some("b"));
+        write(Some("c"));
+        txn_manager.garbage_collect();
+        let as_of = txn_manager.begin_as_of(updated).unwrap();
+        assert_eq!(read(&as_of, "d"), Some("c".to_string()));
+        txn_manager.commit(&as_of).unwrap();
+    }
+}
@@ -1,24 +1,120 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
-// TODO: move these types to a config package
-#[allow(dead_code)]
-type TimeStamp = u64;
-type SlottedOffset = u64;
-type TxnId = i64;
+use common::transaction::{Timestamp, TxnId};
 
-#[allow(dead_code)]
-pub struct PageVersionInfo {
-    prev_link: HashMap<SlottedOffset, UndoLink>,
-}
-#[allow(dead_code)]
-pub struct Watermark {}
-#[allow(dead_code)]
-struct UndoLink {
-    prev_txn: TxnId,
+// Every tuple written since startup has a version link: the timestamp of its newest version and
+// the undo log holding the version before it. The timestamp is the writer's transaction id until
+// the writer commits, then its commit timestamp. Each undo log links to the version before its
+// own, so older and older versions are found by following the chain. Tuples without a version
+// link were last written before startup, every transaction sees them as they are.
+
+type SlottedOffset = u16;
+
+// transaction ids start at TXN_START_ID, so no transaction has this one
+pub const INVALID_TXN_ID: TxnId = 0;
+
+/// Where an undo log is kept: the transaction that wrote it and its position among that
+/// transaction's undo logs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UndoLink {
+    pub prev_txn: TxnId,
+    pub prev_log_idx: usize,
 }
-#[allow(dead_code)]
+
 impl UndoLink {
+    pub const INVALID: UndoLink = UndoLink {
+        prev_txn: INVALID_TXN_ID,
+        prev_log_idx: 0,
+    };
+
     pub fn is_valid(&self) -> bool {
-        self.prev_txn != -1 // TODO: make a INVALID_TXN_ID var for this -1 value
+        self.prev_txn != INVALID_TXN_ID
+    }
+}
+
+/// A version of a tuple that was written over, as of commit timestamp `ts`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct UndoLog<T> {
+    pub tuple: T,
+    pub ts: Timestamp,
+    pub prev_version: UndoLink,
+}
+
+/// The newest version of a tuple: its timestamp and the undo log of the version before it,
+/// invalid if the tuple was inserted by that version.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VersionLink {
+    pub ts: Timestamp,
+    pub prev: UndoLink,
+}
+
+/// The version links of the tuples of a page, by slot.
+#[derive(Debug, Default)]
+pub struct PageVersionInfo {
+    pub prev_link: HashMap<SlottedOffset, VersionLink>,
+}
+
+/// The lowest read timestamp of the running transactions, or the last commit timestamp while
+/// none is running. No transaction reads a version older than the newest one at or below it.
+#[derive(Debug, Default)]
+pub struct Watermark {
+    commit_ts: Timestamp,
+    // read timestamps of the running transactions, with how many read at each
+    current_reads: BTreeMap<Timestamp, usize>,
+}
+
+impl Watermark {
+    pub fn new(commit_ts: Timestamp) -> Self {
+        Self {
+            commit_ts,
+            current_reads: BTreeMap::new(),
+        }
+    }
+
+    pub fn add_txn(&mut self, read_ts: Timestamp) {
+        *self.current_reads.entry(read_ts).or_default() += 1;
+    }
+
+    pub fn remove_txn(&mut self, read_ts: Timestamp) {
+        if let Some(count) = self.current_reads.get_mut(&read_ts) {
+            *count -= 1;
+            if *count == 0 {
+                self.current_reads.remove(&read_ts);
+            }
+        }
+    }
+
+    pub fn update_commit_ts(&mut self, commit_ts: Timestamp) {
+        self.commit_ts = commit_ts;
+    }
+
+    pub fn get_watermark(&self) -> Timestamp {
+        match self.current_reads.first_key_value() {
+            Some((read_ts, _)) => *read_ts,
+            None => self.commit_ts,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_watermark_is_the_lowest_read_timestamp() {
+        let mut watermark = Watermark::new(3);
+        assert_eq!(watermark.get_watermark(), 3);
+        watermark.add_txn(3);
+        watermark.add_txn(3);
+        watermark.update_commit_ts(4);
+        watermark.add_txn(4);
+        assert_eq!(watermark.get_watermark(), 3);
+        watermark.remove_txn(3);
+        assert_eq!(watermark.get_watermark(), 3);
+        watermark.remove_txn(3);
+        assert_eq!(watermark.get_watermark(), 4);
+        watermark.update_commit_ts(5);
+        watermark.remove_txn(4);
+        assert_eq!(watermark.get_watermark(), 5);
     }
 }
@@ -1,291 +1,324 @@
-// use common::page::{Page, PageId};
-
-// use crate::IoTDevice;
-// use std::cmp;
-// use std::collections::HashMap;
-// use std::mem;
-// type Tree = Box<Node>;
-// type KeyType = u64;
-
-// type Data = (Option<IoTDevice>, Option<Tree>);
-
-// #[derive(Clone, PartialEq, Debug)]
-// enum NodeType {
-//     Leaf,
-//     Regular,
-// }
-
-// #[derive(Clone, PartialEq)]
-// enum Direction {
-//     Left,
-//     Right(usize),
-// }
-
-// #[derive(Clone)]
-// struct Node {
-//     devices: Vec<Option<IoTDevice>>,
-//     children: Vec<Option<Tree>>,
-//     left_child: Option<Tree>,
-//     pub node_type: NodeType,
-// }
-
-// impl Node {
-//     pub fn new_leaf() -> Tree {
-//         Node::new(NodeType::Leaf)
-//     }
-
-//     pub fn new_regular() -> Tree {
-//         Node::new(NodeType::Regular)
-//     }
-
-//     fn new(node_type: NodeType) -> Tree {
-//         Box::new(Node {
-//             left_child: None,
-//             devices: vec![],
-//             children: vec![],
-//             node_type: node_type,
-//         })
-//     }
-
-//     pub fn len(&self) -> usize {
-//         self.children.len() + 1
-//     }
-
-//     pub fn split(&mut self) -> (IoTDevice, Tree) {
-//         let mut sibling = Node::new(self.node_type.clone());
-
-//         let no_of_devices = self.devices.len();
-//         let split_at = no_of_devices / 2usize;
-
-//         let dev = self.devices.remove(split_at);
-//         let node = self.children.remove(split_at);
-
-//         for _ in split_at..self.devices.len() {
-//             let device = self.devices.pop().unwrap();
-//             let child = self.children.pop().unwrap();
-//             sibling.add_key(device.as_ref().unwrap().numerical_id, (device, child));
-//         }
-
-//         sibling.add_left_child(node);
-//         (dev.unwrap(), sibling)
-//     }
-
-//     pub fn add_left_child(&mut self, tree: Option<Tree>) {
-//         self.left_child = tree;
-//     }
-
-//     pub fn add_key(&mut self, key: KeyType, value: Data) -> bool {
-//         let pos = match self.find_closest_index(key) {
-//             Direction::Left => 0,
-//             Direction::Right(p) => p + 1,
-//         };
-//         let (dev, tree) = value;
-
-//         if pos >= self.devices.len() {
-//             self.devices.push(dev);
-//             self.children.push(tree);
-//         } else {
-//             self.devices.insert(pos, dev);
-//             self.children.insert(pos, tree);
-//         }
-//         true
-//     }
-
-//     pub fn remove_key(&mut self, id: KeyType) -> Option<(KeyType, Data)> {
-//         match self.find_closest_index(id) {
-//             Direction::Left => {
-//                 let tree = mem::replace(&mut self.left_child, None);
-//                 Some((id, (None, tree)))
-//             }
-//             Direction::Right(index) => {
-//                 let dev = self.devices.remove(index);
-//                 let tree = self.children.remove(index);
-//                 Some((dev.as_ref().unwrap().numerical_id, (dev, tree)))
-//             }
-//         }
-//     }
-
-//     pub fn find_closest_index(&self, key: KeyType) -> Direction {
-//         let mut index = Direction::Left;
-//         for (i, pair) in self.devices.iter().enumerate() {
-//             if let Some(dev) = pair {
-//                 if dev.numerical_id <= key {
-//                     index = Direction::Right(i);
-//                 } else {
-//                     break;
-//                 }
-//             }
-//         }
-//         index
-//     }
-
-//     pub fn get_device(&self, key: KeyType) -> Option<&IoTDevice> {
-//         let mut result = None;
-//         for d in self.devices.iter() {
-//             if let Some(device) = d {
-//                 if device.numerical_id == key {
-//                     result = Some(device);
-//                     break;
-//                 }
-//             }
-//         }
-//         result
-//     }
-
-//     pub fn get_child(&self, key: KeyType) -> Option<&Tree> {
-//         match self.find_closest_index(key) {
-//             Direction::Left => self.left_child.as_ref(),
-//             Direction::Right(i) => self.children[i].as_ref(),
-//         }
-//     }
-// }
-
-// pub struct DeviceDatabase {
-//     root: Option<Tree>,
-//     order: usize,
-//     pub length: u64,
-// }
-
-// impl DeviceDatabase {
-//     pub fn new_empty(order: usize) -> DeviceDatabase {
-//         DeviceDatabase {
-//             root: None,
-//             length: 0,
-//             order: order,
-//         }
-//     }
-
-//     pub fn add(&mut self, device: IoTDevice) {
-//         let node = if self.root.is_some() {
-//             mem::replace(&mut self.root, None).unwrap()
-//         } else {
-//             Node::new_leaf()
-//         };
-
-//         let (root, _) = self.add_r(node, device, true);
-
-//         self.root = Some(root);
-//     }
-
-//     fn add_r(&mut self, node: Tree, device: IoTDevice, is_root: bool) -> (Tree, Option<Data>) {
-//         let mut node = node;
-//         let id = device.numerical_id;
-
-//         match node.node_type {
-//             NodeType::Leaf => {
-//                 if node.add_key(id, (Some(device), None)) {
-//                     self.length += 1;
-//                 }
-//             }
-//             NodeType::Regular => {
-//                 let (key, (dev, tree)) = node.remove_key(id).unwrap();
-//                 let new = self.add_r(tree.unwrap(), device, false);
-//                 if dev.is_none() {
-//                     node.add_left_child(Some(new.0));
-//                 } else {
-//                     node.add_key(key, (dev, Some(new.0)));
-//                 }
-//                 if let Some(split_result) = new.1 {
-//                     let new_id = &split_result.0.clone().unwrap();
-//                     node.add_key(new_id.numerical_id, split_result);
-//                 }
-//             }
-//         }
-
-//         if node.len() > self.order {
-//             let (new_parent, sibling) = node.split();
-
-//             // Check if the root node is "full" and add a new level
-//             if is_root {
-//                 let mut parent = Node::new_regular();
-//                 // Add the former root to the left
-//                 parent.add_left_child(Some(node));
-//                 // Add the new right part as well
-//                 parent.add_key(new_parent.numerical_id, (Some(new_parent), Some(sibling)));
-//                 (parent, None)
-//             } else {
-//                 (node, Some((Some(new_parent), Some(sibling))))
-//             }
-//         } else {
-//             (node, None)
-//         }
-//     }
-
-//     pub fn is_a_valid_btree(&self) -> bool {
-//         if let Some(tree) = self.root.as_ref() {
-//             let total = self.validate(tree, 0);
-//             total.0 && total.1 == total.2
-//         } else {
-//             false // there is no tree
-//         }
-//     }
-
-//     fn validate(&self, node: &Tree, level: usize) -> (bool, usize, usize) {
-//         //node.print(format!("Level: {}", level));
-//         match node.node_type {
-//             NodeType::Leaf => (node.len() <= self.order, level, level),
-//             NodeType::Regular => {
-//                 // Root node only requires two children, every other node at least half the
-//                 // order
-//                 let min_children = if level > 0 { self.order / 2usize } else { 2 };
-//                 let key_rules = node.len() <= self.order && node.len() >= min_children;
-
-//                 let mut total = (key_rules, usize::max_value(), level);
-//                 for n in node.children.iter().chain(vec![&node.left_child]) {
-//                     if let Some(ref tree) = n {
-//                         let stats = self.validate(tree, level + 1);
-//                         total = (
-//                             total.0 && stats.0,
-//                             cmp::min(stats.1, total.1),
-//                             cmp::max(stats.2, total.2),
-//                         );
-//                     }
-//                 }
-//                 total
-//             }
-//         }
-//     }
-
-//     pub fn find(&self, id: KeyType) -> Option<IoTDevice> {
-//         match self.root.as_ref() {
-//             Some(tree) => self.find_r(tree, id),
-//             _ => None,
-//         }
-//     }
-
-//     fn find_r(&self, node: &Tree, id: KeyType) -> Option<IoTDevice> {
-//         match node.get_device(id) {
-//             Some(device) => Some(device.clone()),
-//             None if node.node_type != NodeType::Leaf => {
-//                 if let Some(tree) = node.get_child(id) {
-//                     self.find_r(tree, id)
-//                 } else {
-//                     None
-//                 }
-//             }
-//             _ => None,
-//         }
-//     }
-
-//     pub fn walk(&self, callback: impl Fn(&IoTDevice) -> ()) {
-//         if let Some(ref root) = self.root {
-//             self.walk_in_order(root, &callback);
-//         }
-//     }
-
-//     fn walk_in_order(&self, node: &Tree, callback: &impl Fn(&IoTDevice) -> ()) {
-//         if let Some(ref left) = node.left_child {
-//             self.walk_in_order(left, callback);
-//         }
-
-//         for i in 0..node.devices.len() {
-//             if let Some(ref k) = node.devices[i] {
-//                 callback(k);
-//             }
-
-//             if let Some(ref c) = node.children[i] {
-//                 self.walk_in_order(&c, callback);
-//             }
-//         }
-//     }
-// }
+use std::cmp;
+use std::ops::Bound;
+
+// In-memory B+tree. Nodes live in an arena and refer to each other by index, which keeps the
+// leaf sibling links (used by range scans) simple. Values are only stored in the leaves;
+// regular nodes hold separator keys where children[i] covers keys < keys[i] and
+// children[i + 1] covers keys >= keys[i].
+
+type NodeId = usize;
+
+#[derive(Clone, PartialEq, Debug)]
+enum NodeType {
+    Leaf,
+    Regular,
+}
+
+#[derive(Clone, Debug)]
+struct Node<K, V> {
+    node_type: NodeType,
+    keys: Vec<K>,
+    values: Vec<V>,
+    children: Vec<NodeId>,
+    next: Option<NodeId>,
+}
+
+impl<K, V> Node<K, V> {
+    fn new(node_type: NodeType) -> Self {
+        Self {
+            node_type,
+            keys: vec![],
+            values: vec![],
+            children: vec![],
+            next: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct BPlusTree<K, V> {
+    nodes: Vec<Node<K, V>>,
+    root: NodeId,
+    order: usize,
+    pub length: u64,
+}
+
+impl<K: Ord + Clone, V: Clone> BPlusTree<K, V> {
+    /// `order` is the maximum number of keys a node holds before it is split.
+    pub fn new_empty(order: usize) -> Self {
+        assert!(order >= 3, "a B+tree needs an order of at least 3");
+        Self {
+            nodes: vec![Node::new(NodeType::Leaf)],
+            root: 0,
+            order,
+            length: 0,
+        }
+    }
+
+    /// Inserts the key, replacing and returning the previous value if the key was present.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let (old, split) = self.insert_r(self.root, key, value);
+        if let Some((separator, sibling)) = split {
+            // the root was full, add a new level
+            let mut parent = Node::new(NodeType::Regular);
+            parent.keys.push(separator);
+            parent.children.push(self.root);
+            parent.children.push(sibling);
+            self.root = self.push_node(parent);
+        }
+        if old.is_none() {
+            self.length += 1;
+        }
+        old
+    }
+
+    fn insert_r(&mut self, node_id: NodeId, key: K, value: V) -> (Option<V>, Option<(K, NodeId)>) {
+        if self.nodes[node_id].node_type == NodeType::Leaf {
+            let node = &mut self.nodes[node_id];
+            match node.keys.binary_search(&key) {
+                Ok(pos) => return (Some(std::mem::replace(&mut node.values[pos], value)), None),
+                Err(pos) => {
+                    node.keys.insert(pos, key);
+                    node.values.insert(pos, value);
+                }
+            }
+            if node.keys.len() <= self.order {
+                return (None, None);
+            }
+            return (None, Some(self.split_leaf(node_id)));
+        }
+
+        let pos = self.nodes[node_id].keys.partition_point(|k| *k <= key);
+        let child = self.nodes[node_id].children[pos];
+        let (old, split) = self.insert_r(child, key, value);
+        if let Some((separator, sibling)) = split {
+            let node = &mut self.nodes[node_id];
+            node.keys.insert(pos, separator);
+            node.children.insert(pos + 1, sibling);
+            if node.keys.len() > self.order {
+                return (old, Some(self.split_regular(node_id)));
+            }
+        }
+        (old, None)
+    }
+
+    fn split_leaf(&mut self, node_id: NodeId) -> (K, NodeId) {
+        let node = &mut self.nodes[node_id];
+        let split_at = node.keys.len() / 2;
+        let mut sibling = Node::new(NodeType::Leaf);
+        sibling.keys = node.keys.split_off(split_at);
+        sibling.values = node.values.split_off(split_at);
+        sibling.next = node.next;
+        let separator = sibling.keys[0].clone();
+        let sibling_id = self.push_node(sibling);
+        self.nodes[node_id].next = Some(sibling_id);
+        (separator, sibling_id)
+    }
+
+    fn split_regular(&mut self, node_id: NodeId) -> (K, NodeId) {
+        let node = &mut self.nodes[node_id];
+        let split_at = node.keys.len() / 2;
+        let mut sibling = Node::new(NodeType::Regular);
+        sibling.keys = node.keys.split_off(split_at + 1);
+        sibling.children = node.children.split_off(split_at + 1);
+        // the middle key moves up to the parent
+        let separator = node.keys.pop().unwrap();
+        (separator, self.push_node(sibling))
+    }
+
+    fn push_node(&mut self, node: Node<K, V>) -> NodeId {
+        self.nodes.push(node);
+        self.nodes.len() - 1
+    }
+
+    fn find_leaf(&self, key: &K) -> NodeId {
+        let mut node_id = self.root;
+        while self.nodes[node_id].node_type == NodeType::Regular {
+            let node = &self.nodes[node_id];
+            node_id = node.children[node.keys.partition_point(|k| k <= key)];
+        }
+        node_id
+    }
+
+    fn first_leaf(&self) -> NodeId {
+        let mut node_id = self.root;
+        while self.nodes[node_id].node_type == NodeType::Regular {
+            node_id = self.nodes[node_id].children[0];
+        }
+        node_id
+    }
+
+    pub fn find(&self, key: &K) -> Option<&V> {
+        let leaf = &self.nodes[self.find_leaf(key)];
+        leaf.keys
+            .binary_search(key)
+            .ok()
+            .map(|pos| &leaf.values[pos])
+    }
+
+    /// Removes the key from its leaf. Underfull leaves are not merged back into their siblings,
+    /// the tree only shrinks when it is rebuilt; lookups and scans simply skip empty leaves.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let leaf_id = self.find_leaf(key);
+        let leaf = &mut self.nodes[leaf_id];
+        let pos = leaf.keys.binary_search(key).ok()?;
+        leaf.keys.remove(pos);
+        self.length -= 1;
+        Some(leaf.values.remove(pos))
+    }
+
+    /// Iterates the entries within the bounds in key order by walking the leaf level.
+    pub fn range(&self, start: Bound<&K>, end: Bound<&K>) -> Range<'_, K, V> {
+        let (leaf, pos) = match start {
+            Bound::Unbounded => (self.first_leaf(), 0),
+            Bound::Included(key) => {
+                let leaf = self.find_leaf(key);
+                (leaf, self.nodes[leaf].keys.partition_point(|k| k < key))
+            }
+            Bound::Excluded(key) => {
+                let leaf = self.find_leaf(key);
+                (leaf, self.nodes[leaf].keys.partition_point(|k| k <= key))
+            }
+        };
+        Range {
+            tree: self,
+            leaf: Some(leaf),
+            pos,
+            end: end.cloned(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    pub fn is_a_valid_btree(&self) -> bool {
+        let (valid, min_depth, max_depth) = self.validate(self.root, 0);
+        valid && min_depth == max_depth
+    }
+
+    fn validate(&self, node_id: NodeId, level: usize) -> (bool, usize, usize) {
+        let node = &self.nodes[node_id];
+        let sorted = node.keys.windows(2).all(|w| w[0] < w[1]);
+        match node.node_type {
+            NodeType::Leaf => (
+                sorted && node.keys.len() <= self.order && node.keys.len() == node.values.len(),
+                level,
+                level,
+            ),
+            NodeType::Regular => {
+                let mut total = (
+                    sorted
+                        && node.keys.len() <= self.order
+                        && node.children.len() == node.keys.len() + 1,
+                    usize::MAX,
+                    level,
+                );
+                for child in node.children.iter() {
+                    let stats = self.validate(*child, level + 1);
+                    total = (
+                        total.0 && stats.0,
+                        cmp::min(stats.1, total.1),
+                        cmp::max(stats.2, total.2),
+                    );
+                }
+                total
+            }
+        }
+    }
+}
+
+pub struct Range<'a, K, V> {
+    tree: &'a BPlusTree<K, V>,
+    leaf: Option<NodeId>,
+    pos: usize,
+    end: Bound<K>,
+}
+
+impl<'a, K: Ord, V> Iterator for Range<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let node = &self.tree.nodes[self.leaf?];
+            if self.pos >= node.keys.len() {
+                self.leaf = node.next;
+                self.pos = 0;
+                continue;
+            }
+            let key = &node.keys[self.pos];
+            let in_range = match &self.end {
+                Bound::Unbounded => true,
+                Bound::Included(end) => key <= end,
+                Bound::Excluded(end) => key < end,
+            };
+            if !in_range {
+                self.leaf = None;
+                return None;
+            }
+            self.pos += 1;
+            return Some((key, &node.values[self.pos - 1]));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_insert_and_find() {
+        let mut tree = BPlusTree::new_empty(3);
+        for i in (0..200u64).rev() {
+            assert_eq!(tree.insert(i, i * 10), None);
+        }
+        assert!(tree.is_a_valid_btree());
+        assert_eq!(tree.length, 200);
+        for i in 0..200u64 {
+            assert_eq!(tree.find(&i), Some(&(i * 10)));
+        }
+        assert_eq!(tree.find(&500), None);
+        assert_eq!(tree.insert(7, 0), Some(70));
+        assert_eq!(tree.length, 200);
+    }
+
+    #[test]
+    fn test_range_scan_crosses_leaves() {
+        let mut tree = BPlusTree::new_empty(4);
+        for i in 0..100u64 {
+            tree.insert(i * 2, i);
+        }
+        let keys: Vec<u64> = tree
+            .range(Bound::Included(&10), Bound::Excluded(&20))
+            .map(|(k, _)| *k)
+            .collect();
+        assert_eq!(keys, vec![10, 12, 14, 16, 18]);
+        let keys: Vec<u64> = tree
+            .range(Bound::Excluded(&193), Bound::Unbounded)
+            .map(|(k, _)| *k)
+            .collect();
+        assert_eq!(keys, vec![194, 196, 198]);
+        assert_eq!(tree.range(Bound::Unbounded, Bound::Unbounded).count(), 100);
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut tree = BPlusTree::new_empty(3);
+        for i in 0..50u64 {
+            tree.insert(i, i);
+        }
+        for i in 0..40u64 {
+            assert_eq!(tree.remove(&i), Some(i));
+        }
+        assert_eq!(tree.remove(&3), None);
+        assert!(tree.is_a_valid_btree());
+        assert_eq!(tree.length, 10);
+        let keys: Vec<u64> = tree
+            .range(Bound::Unbounded, Bound::Unbounded)
+            .map(|(k, _)| *k)
+            .collect();
+        assert_eq!(keys, (40..50).collect::<Vec<u64>>());
+        tree.insert(5, 5);
+        assert_eq!(tree.find(&5), Some(&5));
+    }
+}
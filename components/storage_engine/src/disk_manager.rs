@@ -1,9 +1,11 @@
 use common::types::PageId;
 use common::types::TxnResult;
 use file_system::file::File;
-use std::io::{Read, Result, Write};
+use std::io::{Read, Result, Seek, SeekFrom, Write};
 use std::sync::{Arc, Mutex};
 
+pub const PAGE_SIZE: usize = 4096;
+
 #[allow(dead_code)]
 pub struct DiskManager {
     file: File, // FIXME : check if we should own or reference
@@ -21,19 +23,33 @@ impl DiskManager {
         }
     }
 
-    // FIXME: must read at pageid
-    pub fn read_page(&mut self, _pageid: PageId, page_data: &mut [u8]) -> Result<usize> {
-        let mut buffer = [0; 64];
-        let n = self.file.read(&mut buffer[..])?;
-        page_data[..n].copy_from_slice(&buffer[..n]);
+    // Pages live at page_id * PAGE_SIZE in the file. A page that was never written reads as
+    // zeroes.
+    pub fn read_page(&mut self, pageid: PageId, page_data: &mut [u8]) -> Result<usize> {
+        self.file
+            .seek(SeekFrom::Start((pageid * PAGE_SIZE) as u64))?;
+        let mut n = 0;
+        while n < page_data.len() {
+            let read = self.file.read(&mut page_data[n..])?;
+            if read == 0 {
+                break;
+            }
+            n += read;
+        }
+        page_data[n..].fill(0);
         Ok(n)
     }
 
-    // FIXME: must write at pageid
-    pub fn write_page(&mut self, _pageid: PageId, page_data: &mut [u8]) -> TxnResult<usize, ()> {
-        let buffer = [0; 64];
-        page_data[..].copy_from_slice(&buffer[..]);
-        TxnResult::Ok(self.file.write(&buffer[..]).unwrap())
+    pub fn write_page(&mut self, pageid: PageId, page_data: &[u8]) -> TxnResult<usize, ()> {
+        let written = self
+            .file
+            .seek(SeekFrom::Start((pageid * PAGE_SIZE) as u64))
+            .and_then(|_| self.file.write_all(page_data))
+            .and_then(|_| self.file.flush());
+        match written {
+            Ok(()) => TxnResult::Ok(page_data.len()),
+            Err(_) => TxnResult::Err(()),
+        }
     }
 
     pub fn increase_disk_space(&mut self, pages: usize) {
@@ -50,3 +66,27 @@ impl DiskManager {
 
     pub fn shutdown(&self) {}
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_pages_are_written_at_their_offset() {
+        let path = format!("disk_manager_test_{}.dat", std::process::id());
+        let mut disk_manager = DiskManager::new(File::open_or_create(&path).unwrap());
+        disk_manager.write_page(3, &[3; PAGE_SIZE]);
+        disk_manager.write_page(1, &[1; PAGE_SIZE]);
+        let mut page = [9; PAGE_SIZE];
+        disk_manager.read_page(3, &mut page).unwrap();
+        assert_eq!(page, [3; PAGE_SIZE]);
+        disk_manager.read_page(1, &mut page).unwrap();
+        assert_eq!(page, [1; PAGE_SIZE]);
+        // the gap before page 1 and the space past the end read as empty pages
+        disk_manager.read_page(0, &mut page).unwrap();
+        assert_eq!(page, [0; PAGE_SIZE]);
+        assert_eq!(disk_manager.read_page(7, &mut page).unwrap(), 0);
+        assert_eq!(page, [0; PAGE_SIZE]);
+        std::fs::remove_file(std::env::temp_dir().join(path)).unwrap();
+    }
+}
@@ -17,7 +17,7 @@
 // This should not return until the DiskScheduler's destructor is called.
 
 use crate::disk_manager::DiskManager;
-use common::types::PageId;
+use common::types::{PageId, TxnResult};
 use std::fmt::{Debug, Formatter, Result};
 use std::sync::{Arc, Condvar, Mutex};
 use std::{collections::VecDeque, thread};
@@ -64,8 +64,7 @@ impl Debug for DiskRequest {
 }
 
 pub struct DiskScheduler {
-    #[allow(dead_code)]
-    disk_manager: DiskManager,
+    disk_manager: Mutex<DiskManager>,
     channel: Arc<(Mutex<VecDeque<DiskRequest>>, Condvar)>, // Channel to coordinate threads
 }
 
@@ -73,11 +72,26 @@ impl DiskScheduler {
     #[allow(dead_code)]
     pub fn new(disk_manager: DiskManager) -> Self {
         Self {
-            disk_manager,
+            disk_manager: Mutex::new(disk_manager),
             channel: Arc::new((Mutex::new(VecDeque::new()), Condvar::new())), // Initialize channel
         }
     }
 
+    // Synchronous requests, the buffer pool waits for its page I/O to finish anyway.
+    pub fn read_page(&self, page_id: PageId, page_data: &mut [u8]) -> std::io::Result<usize> {
+        self.disk_manager
+            .lock()
+            .unwrap()
+            .read_page(page_id, page_data)
+    }
+
+    pub fn write_page(&self, page_id: PageId, page_data: &[u8]) -> TxnResult<usize, ()> {
+        self.disk_manager
+            .lock()
+            .unwrap()
+            .write_page(page_id, page_data)
+    }
+
     // Start a worker thread
     #[allow(dead_code)]
     pub fn start_worker_thread(&self) {
@@ -1,4 +1,4 @@
-mod bplustree;
+pub mod bplustree;
 pub mod disk_manager;
 pub mod disk_scheduler;
 mod page;
@@ -47,7 +47,7 @@ impl Page {
         self.pin_count.load(Ordering::Acquire)
     }
 
-    pub fn lock_data(&self) -> MutexGuard<Vec<u8>> {
+    pub fn lock_data(&self) -> MutexGuard<'_, Vec<u8>> {
         self.data.lock().expect("Mutex lock failed")
     }
 }
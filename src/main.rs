@@ -1,14 +1,19 @@
-use buffer::bufferpoolmanager::BufferPoolManager;
-use buffer::catalog::Catalog;
-use buffer::query_types::{
-    get_demo_schema, get_demo_table_heap_with_n_page_m_tuples_each, TablePage,
+use buffer::catalog::{
+    AlterTable, AsOf, Catalog, IndexType, ReferentialAction, RowLockWait, TableConstraint,
 };
-use common::transaction::Transaction;
-use std::sync::{Arc, Mutex};
+use buffer::expression::Expr;
+use buffer::index_key::SortOrder;
+use buffer::query_types::{get_demo_schema, get_demo_tuple, Column, Identity, TypeId, Value};
+use buffer::sequence::SequenceOptions;
+use buffer::session::Session;
+use buffer::statistics::AutoAnalyze;
+use common::transaction::{IsolationLevel, Transaction, TransactionState};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use std::io::{self, Write};
 
-#[allow(dead_code)]
+#[allow(dead_code, clippy::upper_case_acronyms)]
 enum Statements {
     SELECT(String),
     CREATE(String),
@@ -83,15 +88,19 @@ fn main() {
     print!("start-up");
     make_kestreldb_logo();
 
-    println!("Enter a command (SELECT, CREATE, or EXIT to quit):");
+    println!("Enter a command (SELECT, CREATE, INSERT, ALTER, DROP, TRUNCATE, ANALYZE, SET, SHOW, BEGIN, COMMIT, ROLLBACK, or EXIT to quit):");
 
-    let mut bpm = BufferPoolManager::new(10, 2);
-    bpm.table_heap = Arc::new(Mutex::new(get_demo_table_heap_with_n_page_m_tuples_each(
-        10, 10,
-    )));
-    #[allow(unused)]
-    let mut catalog = Arc::new(Mutex::new(Catalog::new()));
-    catalog.lock().unwrap().bpm = bpm;
+    let path = std::env::current_dir().unwrap().join("sparrowdb.db");
+    let catalog = match Catalog::open(&path) {
+        Ok(catalog) => Arc::new(catalog),
+        Err(e) => {
+            println!("Could not open {}: {}", path.display(), e);
+            return;
+        }
+    };
+    create_demo_table(&catalog);
+    catalog.set_auto_analyze(Some(AutoAnalyze::default()));
+    let mut session = Session::default();
 
     loop {
         print!(" > ");
@@ -99,55 +108,968 @@ fn main() {
 
         let mut input = String::new();
         io::stdin().read_line(&mut input).unwrap();
+        let raw = input.trim().to_string();
         let upper = input.to_uppercase();
         let input = upper.split_whitespace().collect::<Vec<&str>>();
 
         let fake = Arc::clone(&catalog);
+        // statements run in the transaction begun with BEGIN, or each in one of its own
+        let explicit = session.get_transaction();
+        let txn = explicit
+            .clone()
+            .unwrap_or_else(|| catalog.begin(session.get_isolation_level()));
+        catalog.begin_statement(&txn);
+        txn.set_lock_timeout(session.get_lock_timeout());
 
-        match input[0] {
+        match input.first().copied().unwrap_or_default() {
+            "" => {}
             "/DT" => show_table(fake),
-            "SELECT" => handle_select(fake, input.clone()),
-            "CREATE" => handle_create(fake, input.clone()),
+            "BEGIN" => handle_begin(fake, &mut session, input.clone()),
+            "COMMIT" => handle_commit(fake, &mut session),
+            "ROLLBACK" => handle_rollback(fake, &mut session),
+            "SELECT" => handle_select(fake, &session, &txn, input.clone(), &raw),
+            "CREATE" => handle_create(fake, &session, &txn, input.clone()),
+            "DROP" => handle_drop(fake, &session, &txn, input.clone()),
+            "ALTER" => handle_alter(fake, &session, &txn, &raw),
+            "TRUNCATE" => handle_truncate(fake, &session, &txn, input.clone()),
+            "INSERT" => handle_insert(fake, &session, &txn, &raw),
+            "ANALYZE" => handle_analyze(fake, &session, &txn, input.clone()),
+            "SET" => handle_set(fake, &mut session, &raw),
+            "SHOW" => handle_show(fake, &session, &txn, input.clone()),
             "EXIT" => {
+                if let Some(txn) = session.get_transaction() {
+                    let _ = catalog.abort(&txn);
+                }
                 print_goodbye();
                 break;
             }
-            _ => println!("Unknown command. Try SELECT, CREATE, or EXIT."),
+            _ => println!(
+                "Unknown command. Try SELECT, CREATE, INSERT, ALTER, DROP, TRUNCATE, ANALYZE, SET, SHOW, BEGIN, COMMIT, ROLLBACK, or EXIT."
+            ),
         }
+        match explicit {
+            // one that hit a conflict can only be aborted
+            Some(txn) if txn.get_state() == TransactionState::TAINTED => {
+                let _ = catalog.abort(&txn);
+                session.set_transaction(None);
+                println!("ROLLBACK, the transaction hit a conflict");
+            }
+            Some(_) => {}
+            None => {
+                if catalog.commit(&txn).is_err() {
+                    let _ = catalog.abort(&txn);
+                }
+            }
+        }
+    }
+}
+
+// A DEMO table with some random people to select from, created on first start.
+fn create_demo_table(catalog: &Arc<Catalog>) {
+    if catalog.get_table_heap("DEMO").is_some() {
+        return;
+    }
+    let txn = catalog.begin(IsolationLevel::default());
+    catalog
+        .create_table(&txn, "DEMO".to_string(), get_demo_schema(), true)
+        .unwrap();
+    for _ in 0..100 {
+        let values = get_demo_tuple().get_values().to_vec();
+        catalog.insert_tuple(&txn, "DEMO", values).unwrap();
+    }
+    catalog.commit(&txn).unwrap();
+}
+
+fn show_table(catalog: Arc<Catalog>) {
+    println!("{:?}", catalog.get_table_names());
+}
+
+// BEGIN [ISOLATION LEVEL <level>]
+fn handle_begin(catalog: Arc<Catalog>, session: &mut Session, input: Vec<&str>) {
+    if session.get_transaction().is_some() {
+        return println!("Error: a transaction is already in progress");
+    }
+    let isolation_level = match input[1..] {
+        [] => Some(session.get_isolation_level()),
+        ["ISOLATION", "LEVEL", ref level @ ..] => parse_isolation_level(level),
+        _ => None,
+    };
+    let Some(isolation_level) = isolation_level else {
+        return println!(
+            "Usage: BEGIN [ISOLATION LEVEL <level>], the level is one of {}",
+            ISOLATION_LEVELS
+        );
+    };
+    session.set_transaction(Some(catalog.begin(isolation_level)));
+    println!("BEGIN");
+}
+
+const ISOLATION_LEVELS: &str =
+    "READ UNCOMMITTED, READ COMMITTED, REPEATABLE READ, SNAPSHOT or SERIALIZABLE";
+
+// REPEATABLE READ is snapshot isolation, as in PostgreSQL.
+fn parse_isolation_level(words: &[&str]) -> Option<IsolationLevel> {
+    match words {
+        ["READ", "UNCOMMITTED"] => Some(IsolationLevel::READUNCOMMITTED),
+        ["READ", "COMMITTED"] => Some(IsolationLevel::READCOMMITTED),
+        ["REPEATABLE", "READ"] | ["SNAPSHOT"] => Some(IsolationLevel::SNAPSHOTISOLATION),
+        ["SERIALIZABLE"] => Some(IsolationLevel::SERIALIZABLE),
+        _ => None,
     }
 }
 
-fn show_table(catalog: Arc<Mutex<Catalog>>) {
-    let guard = catalog.lock().unwrap();
-    println!("{:?}", guard.get_table(None));
+fn isolation_level_name(isolation_level: IsolationLevel) -> &'static str {
+    match isolation_level {
+        IsolationLevel::READUNCOMMITTED => "READ UNCOMMITTED",
+        IsolationLevel::READCOMMITTED => "READ COMMITTED",
+        IsolationLevel::SNAPSHOTISOLATION => "SNAPSHOT",
+        IsolationLevel::SERIALIZABLE => "SERIALIZABLE",
+    }
 }
 
-fn handle_select(catalog: Arc<Mutex<Catalog>>, input: Vec<&str>) {
-    let guard = catalog.lock().unwrap();
+// COMMIT
+fn handle_commit(catalog: Arc<Catalog>, session: &mut Session) {
+    let Some(txn) = session.get_transaction() else {
+        return println!("Error: no transaction in progress");
+    };
+    session.set_transaction(None);
+    match catalog.commit(&txn) {
+        Ok(_) => println!("COMMIT"),
+        Err(e) => {
+            let _ = catalog.abort(&txn);
+            println!("Error: {}", e);
+            println!("ROLLBACK");
+        }
+    }
+}
 
-    if input[2].to_string() != "FROM" {
+// ROLLBACK
+fn handle_rollback(catalog: Arc<Catalog>, session: &mut Session) {
+    let Some(txn) = session.get_transaction() else {
+        return println!("Error: no transaction in progress");
+    };
+    session.set_transaction(None);
+    match catalog.abort(&txn) {
+        Ok(()) => println!("ROLLBACK"),
+        Err(e) => println!("Error: {}", e),
+    }
+}
+
+// SELECT * FROM <table> [AS OF TIMESTAMP <expr> | AS OF COMMIT <commit timestamp>]
+// SELECT * FROM <table> [LIMIT <n>] [FOR UPDATE [NOWAIT | SKIP LOCKED]]
+// SELECT <expr>, e.g. SELECT nextval('<sequence>')
+fn handle_select(
+    catalog: Arc<Catalog>,
+    session: &Session,
+    txn: &Transaction,
+    input: Vec<&str>,
+    raw: &str,
+) {
+    if input.get(2) != Some(&"FROM") {
+        let value = Expr::parse(skip_words(raw, 1))
+            .and_then(|expr| expr.eval_constant_with(&session.functions(&catalog)));
+        match value {
+            Ok(value) => println!("  {}", value),
+            Err(e) => println!("Error: {}", e),
+        }
         return;
     }
-    let _table = guard.get_table(Some(input[3].to_string()));
-    println!(
-        "{:?}",
-        guard
-            .bpm
-            .table_heap
-            .lock()
-            .unwrap()
-            .data
-            .iter()
-            .map(|table| table.clone())
-            .collect::<Vec<TablePage>>()
-    )
+    let usage = "Usage: SELECT * FROM <table> [AS OF TIMESTAMP <expr> | AS OF COMMIT <commit timestamp>] | SELECT * FROM <table> [LIMIT <n>] [FOR UPDATE [NOWAIT | SKIP LOCKED]]";
+    let Some(table_name) = input.get(3).copied() else {
+        return println!("{}", usage);
+    };
+    let (limit, rest) = match input.get(4..) {
+        Some(["LIMIT", limit, rest @ ..]) => match limit.parse::<usize>() {
+            Ok(limit) => (Some(limit), rest),
+            Err(_) => return println!("Error: LIMIT {} is not a number of rows", limit),
+        },
+        Some(rest) => (None, rest),
+        None => (None, &[][..]),
+    };
+    let for_update = match rest {
+        ["FOR", "UPDATE"] => Some(RowLockWait::Wait),
+        ["FOR", "UPDATE", "NOWAIT"] => Some(RowLockWait::NoWait),
+        ["FOR", "UPDATE", "SKIP", "LOCKED"] => Some(RowLockWait::SkipLocked),
+        _ => None,
+    };
+    if let Some(wait) = for_update {
+        // the rows stay locked until the transaction ends
+        let rows = session
+            .resolve_table(&catalog, table_name)
+            .and_then(|table_name| {
+                catalog
+                    .select_for_update(txn, &table_name, limit, wait)
+                    .map_err(|e| e.to_string())
+            });
+        match rows {
+            Ok(rows) => {
+                for (rid, tuple) in rows.iter() {
+                    println!("  ({}, {}): {}", rid.page_id, rid.slot, tuple);
+                }
+                println!("({} rows)", rows.len());
+            }
+            Err(e) => println!("Error: {}", e),
+        }
+        return;
+    }
+    // going back in time reads in a transaction of its own
+    let as_of = match rest {
+        [] => None,
+        [_, ..] if limit.is_some() => return println!("{}", usage),
+        ["AS", "OF", "TIMESTAMP", ..] => {
+            let time = Expr::parse(skip_words(raw, 7))
+                .and_then(|expr| expr.eval_constant_with(&session.functions(&catalog)))
+                .and_then(|value| parse_time(&value));
+            match time.and_then(|time| catalog.begin_as_of(AsOf::Time(time))) {
+                Ok(txn) => Some(txn),
+                Err(e) => return println!("Error: {}", e),
+            }
+        }
+        ["AS", "OF", "COMMIT", commit_ts] => {
+            let Ok(commit_ts) = commit_ts.parse::<u64>() else {
+                return println!("Error: {} is not a commit timestamp", commit_ts);
+            };
+            match catalog.begin_as_of(AsOf::CommitTs(commit_ts)) {
+                Ok(txn) => Some(txn),
+                Err(e) => return println!("Error: {}", e),
+            }
+        }
+        _ => return println!("{}", usage),
+    };
+    let rows = session
+        .resolve_table(&catalog, table_name)
+        .and_then(|table_name| {
+            let txn = as_of.as_deref().unwrap_or(txn);
+            catalog.scan(txn, &table_name).map_err(|e| e.to_string())
+        });
+    match rows {
+        Ok(rows) => {
+            // rows are printed as the scan reaches them, one page in memory at a time
+            let mut count = 0;
+            let printed = rows.take(limit.unwrap_or(usize::MAX)).try_for_each(|row| {
+                let (rid, tuple) = row.map_err(|e| e.to_string())?;
+                println!("  ({}, {}): {}", rid.page_id, rid.slot, tuple);
+                count += 1;
+                Ok::<_, String>(())
+            });
+            match printed {
+                Ok(()) => println!("({} rows)", count),
+                Err(e) => println!("Error: {}", e),
+            }
+        }
+        Err(e) => println!("Error: {}", e),
+    }
+    if let Some(as_of) = &as_of {
+        let _ = catalog.commit(as_of);
+    }
+}
+
+// A timestamp is microseconds since the epoch, or text such as '2025-01-31 12:00:00' in UTC.
+fn parse_time(value: &Value) -> Result<SystemTime, String> {
+    let micros = match value {
+        Value::Timestamp(micros) | Value::BigInt(micros) => Some(*micros),
+        Value::Integer(micros) => Some(*micros as i64),
+        Value::Varchar(text) => parse_utc(text),
+        _ => None,
+    };
+    micros
+        .and_then(|micros| u64::try_from(micros).ok())
+        .map(|micros| UNIX_EPOCH + Duration::from_micros(micros))
+        .ok_or_else(|| format!("{} is not a timestamp", value))
+}
+
+// YYYY-MM-DD [HH:MM[:SS[.ffffff]]]
+fn parse_utc(text: &str) -> Option<i64> {
+    let (date, time) = text
+        .trim()
+        .split_once([' ', 'T'])
+        .unwrap_or((text.trim(), "00:00"));
+    let date = date
+        .splitn(3, '-')
+        .map(|part| part.parse::<i64>().ok())
+        .collect::<Option<Vec<i64>>>()?;
+    let [year, month, day] = date[..] else {
+        return None;
+    };
+    let (time, fraction) = time.split_once('.').unwrap_or((time, "0"));
+    let time = time
+        .split(':')
+        .map(|part| part.parse::<i64>().ok())
+        .collect::<Option<Vec<i64>>>()?;
+    let (hour, minute, second) = match time[..] {
+        [hour, minute] => (hour, minute, 0),
+        [hour, minute, second] => (hour, minute, second),
+        _ => return None,
+    };
+    if !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 60
+    {
+        return None;
+    }
+    let micros = format!("{:0<6}", fraction).get(..6)?.parse::<i64>().ok()?;
+    // days since the epoch of the proleptic Gregorian date, years starting in March
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
+    Some(((days * 24 + hour) * 60 + minute) * 60_000_000 + second * 1_000_000 + micros)
 }
 
-fn handle_create(catalog: Arc<Mutex<Catalog>>, input: Vec<&str>) {
-    let transaction = Transaction::new();
-    let table_name = input[1].to_string();
+// CREATE <table> [PRIMARY KEY (<column>, ...)]
+// CREATE SCHEMA <schema> [AUTHORIZATION <owner>]
+// CREATE SEQUENCE <sequence> [INCREMENT [BY] <n>] [MINVALUE <n>] [MAXVALUE <n>]
+//     [START [WITH] <n>] [CACHE <n>] [[NO] CYCLE]
+fn handle_create(catalog: Arc<Catalog>, session: &Session, txn: &Transaction, input: Vec<&str>) {
+    let sequence_usage = "Usage: CREATE SEQUENCE <sequence> [INCREMENT [BY] <n>] [MINVALUE <n>] [MAXVALUE <n>] [START [WITH] <n>] [CACHE <n>] [[NO] CYCLE]";
+    let schema_usage = "Usage: CREATE SCHEMA <schema> [AUTHORIZATION <owner>]";
+    match &input[1..] {
+        [] => return println!("Usage: CREATE <table> [PRIMARY KEY (<column>, ...)] | CREATE [UNIQUE] INDEX ... | CREATE SEQUENCE ... | CREATE SCHEMA ..."),
+        ["SEQUENCE"] => return println!("{}", sequence_usage),
+        ["SEQUENCE", sequence_name, options @ ..] => {
+            let Some(options) = parse_sequence_options(options) else {
+                return println!("{}", sequence_usage);
+            };
+            let result = session
+                .qualify_new_table(&catalog, sequence_name)
+                .and_then(|sequence_name| catalog.create_sequence(txn, &sequence_name, options));
+            match result {
+                Ok(sequence_info) => println!("{:?}", sequence_info),
+                Err(e) => println!("Error: {}", e),
+            }
+            return;
+        }
+        ["INDEX", rest @ ..] => return handle_create_index(catalog, session, txn, rest, false),
+        ["UNIQUE", "INDEX", rest @ ..] => {
+            return handle_create_index(catalog, session, txn, rest, true)
+        }
+        ["SCHEMA"] => return println!("{}", schema_usage),
+        ["SCHEMA", schema_name, rest @ ..] => {
+            let owner = match rest {
+                [] => session.get_user().to_lowercase(),
+                ["AUTHORIZATION", owner] => owner.to_lowercase(),
+                _ => return println!("{}", schema_usage),
+            };
+            match catalog.create_schema(txn, schema_name, &owner) {
+                Ok(schema_info) => println!("{:?}", schema_info),
+                Err(e) => println!("Error: {}", e),
+            }
+            return;
+        }
+        _ => {}
+    }
+    let table_name = match session.qualify_new_table(&catalog, input[1]) {
+        Ok(table_name) => table_name,
+        Err(e) => return println!("Error: {}", e),
+    };
     let schema = get_demo_schema();
-    let mut guard = catalog.lock().unwrap();
-    let table_info = guard.create_table(transaction, table_name, schema, true);
-    println!("{:?}", table_info);
+    match catalog.create_table(txn, table_name.clone(), schema, true) {
+        Ok(table_info) => println!("{:?}", table_info),
+        Err(e) => return println!("Error: {}", e),
+    }
+    if input.get(2..4) == Some(&["PRIMARY", "KEY"]) {
+        let (key_columns, _) = parse_key_columns(&input[4..].join(" "));
+        if let Err(e) = catalog.create_index(
+            txn,
+            format!("{}_PKEY", table_name),
+            table_name,
+            key_columns,
+            IndexType::BPlusTreeIndex,
+            true,
+            true,
+        ) {
+            println!("Error: {}", e);
+        }
+    }
+}
+
+// The options of CREATE SEQUENCE, in any order.
+fn parse_sequence_options(mut input: &[&str]) -> Option<SequenceOptions> {
+    let (mut increment, mut min_value, mut max_value, mut start, mut cache) =
+        (1, None, None, None, None);
+    let mut cycle = false;
+    while !input.is_empty() {
+        let (target, rest) = match input {
+            ["INCREMENT", "BY", rest @ ..] | ["INCREMENT", rest @ ..] => (&mut increment, rest),
+            ["START", "WITH", rest @ ..] | ["START", rest @ ..] => (start.insert(0), rest),
+            ["MINVALUE", rest @ ..] => (min_value.insert(0), rest),
+            ["MAXVALUE", rest @ ..] => (max_value.insert(0), rest),
+            ["CACHE", rest @ ..] => (cache.insert(0), rest),
+            ["CYCLE", rest @ ..] | ["NO", "CYCLE", rest @ ..] => {
+                cycle = input[0] == "CYCLE";
+                input = rest;
+                continue;
+            }
+            _ => return None,
+        };
+        *target = rest.first()?.parse().ok()?;
+        input = &rest[1..];
+    }
+    let mut options = SequenceOptions::new(increment).with_cycle(cycle);
+    if let Some(min_value) = min_value {
+        options = options.with_min_value(min_value);
+    }
+    if let Some(max_value) = max_value {
+        options = options.with_max_value(max_value);
+    }
+    if let Some(start) = start {
+        options = options.with_start(start);
+    }
+    if let Some(cache) = cache {
+        options = options.with_cache(cache);
+    }
+    Some(options)
+}
+
+// Parses "(<column> [ASC|DESC], ...) <rest>" into the key columns and what follows.
+fn parse_key_columns(text: &str) -> (Vec<(String, SortOrder)>, &str) {
+    let rest = text.split_once('(').map_or(text, |(_, rest)| rest);
+    let (columns, rest) = rest.split_once(')').unwrap_or((rest, ""));
+    let key_columns = columns
+        .split(',')
+        .filter_map(|c| {
+            let mut parts = c.split_whitespace();
+            let name = parts.next()?.to_string();
+            let order = match parts.next() {
+                Some("DESC") => SortOrder::Desc,
+                _ => SortOrder::Asc,
+            };
+            Some((name, order))
+        })
+        .collect::<Vec<(String, SortOrder)>>();
+    (key_columns, rest)
+}
+
+// CREATE [UNIQUE] INDEX <name> ON <table> (<column> [ASC|DESC], ...) [USING BTREE|SKIPLIST|HASH]
+fn handle_create_index(
+    catalog: Arc<Catalog>,
+    session: &Session,
+    txn: &Transaction,
+    input: &[&str],
+    is_unique: bool,
+) {
+    if input.len() < 3 || input[1] != "ON" {
+        println!("Usage: CREATE [UNIQUE] INDEX <name> ON <table> (<column> [ASC|DESC], ...) [USING BTREE|SKIPLIST|HASH]");
+        return;
+    }
+    let index_name = input[0].to_string();
+    let rest = input[2..].join(" ");
+    let table_name =
+        match session.resolve_table(&catalog, rest.split('(').next().unwrap_or_default().trim()) {
+            Ok(table_name) => table_name,
+            Err(e) => return println!("Error: {}", e),
+        };
+    let (key_columns, using) = parse_key_columns(&rest);
+    let index_type = match using.split_whitespace().collect::<Vec<&str>>()[..] {
+        ["USING", "BTREE"] => IndexType::BPlusTreeIndex,
+        ["USING", "HASH"] => IndexType::ExtendibleHashIndex,
+        _ => IndexType::SkipListIndex,
+    };
+    match catalog.create_index(
+        txn,
+        index_name,
+        table_name,
+        key_columns,
+        index_type,
+        is_unique,
+        false,
+    ) {
+        Ok(index_info) => println!("{:?}", index_info),
+        Err(e) => println!("Error: {}", e),
+    }
+}
+
+// DROP TABLE <table> [CASCADE]
+// DROP INDEX <name> ON <table>
+// DROP SCHEMA <schema> [CASCADE]
+// DROP SEQUENCE <sequence>
+fn handle_drop(catalog: Arc<Catalog>, session: &Session, txn: &Transaction, input: Vec<&str>) {
+    let resolve = |table_name| session.resolve_table(&catalog, table_name);
+    let result = match input[1..] {
+        ["TABLE", table_name] => {
+            resolve(table_name).and_then(|table_name| catalog.drop_table(txn, &table_name, false))
+        }
+        ["TABLE", table_name, "CASCADE"] => {
+            resolve(table_name).and_then(|table_name| catalog.drop_table(txn, &table_name, true))
+        }
+        ["INDEX", index_name, "ON", table_name] => resolve(table_name)
+            .and_then(|table_name| catalog.drop_index(txn, &table_name, index_name)),
+        ["SCHEMA", schema_name] => catalog.drop_schema(txn, schema_name, false),
+        ["SCHEMA", schema_name, "CASCADE"] => catalog.drop_schema(txn, schema_name, true),
+        ["SEQUENCE", sequence_name] => {
+            session
+                .resolve_sequence(&catalog, sequence_name)
+                .and_then(|sequence_info| {
+                    catalog.drop_sequence(txn, &sequence_info.get_qualified_name())
+                })
+        }
+        _ => {
+            println!("Usage: DROP TABLE <table> [CASCADE] | DROP INDEX <name> ON <table> | DROP SCHEMA <schema> [CASCADE] | DROP SEQUENCE <sequence>");
+            return;
+        }
+    };
+    match result {
+        Ok(()) => println!("DROP {}", input[1]),
+        Err(e) => println!("Error: {}", e),
+    }
+}
+
+// The integer type of SMALLSERIAL, SERIAL and BIGSERIAL columns, which are BY DEFAULT identity
+// columns.
+fn parse_serial(text: &str) -> Option<TypeId> {
+    match text {
+        "SMALLSERIAL" => Some(TypeId::SMALLINT),
+        "SERIAL" => Some(TypeId::INTEGER),
+        "BIGSERIAL" => Some(TypeId::BIGINT),
+        _ => None,
+    }
+}
+
+// INTEGER, BIGINT, VARCHAR(<length>), ...
+fn parse_type(text: &str) -> Option<(TypeId, u32)> {
+    let (name, length) = match text.split_once('(') {
+        Some((name, length)) => (name, length.trim_end_matches(')').parse().ok()?),
+        None => (text, 20),
+    };
+    let type_id = match name {
+        "BOOLEAN" => TypeId::BOOLEAN,
+        "TINYINT" => TypeId::TINYINT,
+        "SMALLINT" => TypeId::SMALLINT,
+        "INTEGER" | "INT" => TypeId::INTEGER,
+        "BIGINT" => TypeId::BIGINT,
+        "DECIMAL" => TypeId::DECIMAL,
+        "VARCHAR" => TypeId::VARCHAR,
+        "TIMESTAMP" => TypeId::TIMESTAMP,
+        _ => return None,
+    };
+    Some((type_id, length))
+}
+
+// ALTER TABLE <table> ADD [COLUMN] <column> <type> [NOT NULL] [DEFAULT <expr>]
+// ALTER TABLE <table> ADD [COLUMN] <column> <type> GENERATED ALWAYS|BY DEFAULT AS IDENTITY
+// ALTER TABLE <table> ADD [COLUMN] <column> SMALLSERIAL|SERIAL|BIGSERIAL
+// ALTER TABLE <table> DROP [COLUMN] <column>
+// ALTER TABLE <table> RENAME [COLUMN] <column> TO <name>
+// ALTER TABLE <table> RENAME TO <name>
+// ALTER TABLE <table> ALTER [COLUMN] <column> TYPE <type>
+// ALTER TABLE <table> ALTER [COLUMN] <column> SET NOT NULL | DROP NOT NULL
+// ALTER TABLE <table> ALTER [COLUMN] <column> SET DEFAULT <expr> | DROP DEFAULT
+// ALTER TABLE <table> ADD CONSTRAINT <name> CHECK (<expr>)
+// ALTER TABLE <table> ADD CONSTRAINT <name> FOREIGN KEY (<column>, ...)
+//     REFERENCES <table> (<column>, ...) [ON DELETE RESTRICT|CASCADE|SET NULL]
+// ALTER TABLE <table> DROP CONSTRAINT <name>
+// Expressions keep the case they were typed in.
+fn handle_alter(catalog: Arc<Catalog>, session: &Session, txn: &Transaction, raw: &str) {
+    let upper = raw.to_uppercase();
+    let mut words = upper.split_whitespace().collect::<Vec<&str>>();
+    let mut skipped = 0;
+    if words.get(4) == Some(&"COLUMN") {
+        words.remove(4);
+        skipped = 1;
+    }
+    // the expression starting at word `idx` of `words`, as it was typed
+    let expr_from = |idx: usize| Expr::parse(skip_words(raw, idx + skipped));
+    let action = match words.get(1..) {
+        Some(["TABLE", _, "ADD", "CONSTRAINT", name, "CHECK", ..]) => expr_from(7).map(|expr| {
+            AlterTable::AddConstraint(TableConstraint::Check {
+                name: name.to_string(),
+                expr,
+            })
+        }),
+        Some(["TABLE", _, "ADD", "CONSTRAINT", name, "FOREIGN", ..]) => {
+            match parse_foreign_key(skip_words(&upper, 6 + skipped)) {
+                Some((columns, ref_table, ref_columns, on_delete)) => session
+                    .resolve_table(&catalog, &ref_table)
+                    .map(|ref_table| {
+                        AlterTable::AddConstraint(TableConstraint::ForeignKey {
+                            name: name.to_string(),
+                            columns,
+                            ref_table,
+                            ref_columns,
+                            on_delete,
+                        })
+                    }),
+                None => Err(String::new()),
+            }
+        }
+        Some(["TABLE", _, "DROP", "CONSTRAINT", name]) => {
+            Ok(AlterTable::DropConstraint(name.to_string()))
+        }
+        Some(["TABLE", _, "ADD", column, column_type, rest @ ..]) => {
+            let not_null = rest.starts_with(&["NOT", "NULL"]);
+            let rest = if not_null { &rest[2..] } else { rest };
+            let serial = parse_serial(column_type).map(|type_id| (type_id, 0));
+            let column = serial
+                .or_else(|| parse_type(column_type))
+                .map(|(type_id, length)| {
+                    let column =
+                        Column::new(column.to_string(), type_id, length).with_not_null(not_null);
+                    match serial {
+                        Some(_) => column.with_identity(Identity::ByDefault),
+                        None => column,
+                    }
+                })
+                .ok_or_else(String::new);
+            match rest {
+                [] => column,
+                ["GENERATED", "ALWAYS", "AS", "IDENTITY"] => {
+                    column.map(|column| column.with_identity(Identity::Always))
+                }
+                ["GENERATED", "BY", "DEFAULT", "AS", "IDENTITY"] => {
+                    column.map(|column| column.with_identity(Identity::ByDefault))
+                }
+                ["DEFAULT", ..] => {
+                    let default = expr_from(words.len() - rest.len() + 1);
+                    column.and_then(|column| default.map(|default| column.with_default(default)))
+                }
+                _ => Err(String::new()),
+            }
+            .map(AlterTable::AddColumn)
+        }
+        Some(["TABLE", _, "DROP", column]) => Ok(AlterTable::DropColumn(column.to_string())),
+        Some(["TABLE", _, "RENAME", "TO", new_name]) => {
+            Ok(AlterTable::RenameTo(new_name.to_string()))
+        }
+        Some(["TABLE", _, "RENAME", column, "TO", new_name]) => Ok(AlterTable::RenameColumn {
+            column: column.to_string(),
+            new_name: new_name.to_string(),
+        }),
+        Some(["TABLE", _, "ALTER", column, "TYPE", column_type]) => parse_type(column_type)
+            .map(|(type_id, length)| AlterTable::AlterColumnType {
+                column: column.to_string(),
+                type_id,
+                length,
+            })
+            .ok_or_else(String::new),
+        Some(["TABLE", _, "ALTER", column, "SET", "NOT", "NULL"]) => {
+            Ok(AlterTable::SetNotNull(column.to_string()))
+        }
+        Some(["TABLE", _, "ALTER", column, "DROP", "NOT", "NULL"]) => {
+            Ok(AlterTable::DropNotNull(column.to_string()))
+        }
+        Some(["TABLE", _, "ALTER", column, "SET", "DEFAULT", ..]) => {
+            expr_from(7).map(|default| AlterTable::SetDefault {
+                column: column.to_string(),
+                default: Some(default),
+            })
+        }
+        Some(["TABLE", _, "ALTER", column, "DROP", "DEFAULT"]) => Ok(AlterTable::SetDefault {
+            column: column.to_string(),
+            default: None,
+        }),
+        _ => Err(String::new()),
+    };
+    let action = match action {
+        Ok(action) => action,
+        Err(e) if !e.is_empty() => return println!("Error: {}", e),
+        Err(_) => {
+            println!("Usage: ALTER TABLE <table> ADD <column> <type> [NOT NULL] [DEFAULT <expr>] | ADD <column> <type> GENERATED ALWAYS|BY DEFAULT AS IDENTITY | ADD <column> SERIAL | DROP <column> | RENAME <column> TO <name> | RENAME TO <name> | ALTER <column> TYPE <type> | ALTER <column> SET|DROP NOT NULL | ALTER <column> SET DEFAULT <expr> | ALTER <column> DROP DEFAULT | ADD CONSTRAINT <name> CHECK (<expr>) | ADD CONSTRAINT <name> FOREIGN KEY (<column>, ...) REFERENCES <table> (<column>, ...) [ON DELETE RESTRICT|CASCADE|SET NULL] | DROP CONSTRAINT <name>");
+            return;
+        }
+    };
+    let result = session
+        .resolve_table(&catalog, words[2])
+        .and_then(|table_name| catalog.alter_table(txn, &table_name, action));
+    match result {
+        Ok(()) => println!("ALTER TABLE"),
+        Err(e) => println!("Error: {}", e),
+    }
+}
+
+// The text after the first `n` words.
+fn skip_words(text: &str, n: usize) -> &str {
+    let mut rest = text.trim_start();
+    for _ in 0..n {
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        rest = rest[end..].trim_start();
+    }
+    rest
+}
+
+// FOREIGN KEY (<column>, ...) REFERENCES <table> (<column>, ...) [ON DELETE <action>]
+fn parse_foreign_key(text: &str) -> Option<(Vec<String>, String, Vec<String>, ReferentialAction)> {
+    let names = |key_columns: Vec<(String, SortOrder)>| {
+        key_columns
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect::<Vec<String>>()
+    };
+    let rest = skip_words(text, 2);
+    let (columns, rest) = parse_key_columns(rest);
+    let (ref_table, _) = skip_words(rest, 1).split_once('(')?;
+    let (ref_columns, rest) = parse_key_columns(skip_words(rest, 1));
+    let on_delete = match rest.split_whitespace().collect::<Vec<&str>>()[..] {
+        [] | ["ON", "DELETE", "RESTRICT"] | ["ON", "DELETE", "NO", "ACTION"] => {
+            ReferentialAction::Restrict
+        }
+        ["ON", "DELETE", "CASCADE"] => ReferentialAction::Cascade,
+        ["ON", "DELETE", "SET", "NULL"] => ReferentialAction::SetNull,
+        _ => return None,
+    };
+    Some((
+        names(columns),
+        ref_table.trim().to_string(),
+        names(ref_columns),
+        on_delete,
+    ))
+}
+
+// TRUNCATE [TABLE] <table>
+fn handle_truncate(catalog: Arc<Catalog>, session: &Session, txn: &Transaction, input: Vec<&str>) {
+    let table_name = match input[1..] {
+        [table_name] | ["TABLE", table_name] => table_name,
+        _ => {
+            println!("Usage: TRUNCATE [TABLE] <table>");
+            return;
+        }
+    };
+    let result = session
+        .resolve_table(&catalog, table_name)
+        .and_then(|table_name| catalog.truncate_table(txn, &table_name));
+    match result {
+        Ok(()) => println!("TRUNCATE TABLE"),
+        Err(e) => println!("Error: {}", e),
+    }
+}
+
+// ANALYZE [<table>]
+fn handle_analyze(catalog: Arc<Catalog>, session: &Session, txn: &Transaction, input: Vec<&str>) {
+    let result = match input[1..] {
+        [] => catalog.analyze(txn, None),
+        [table_name] => session
+            .resolve_table(&catalog, table_name)
+            .and_then(|table_name| catalog.analyze(txn, Some(&table_name))),
+        _ => {
+            println!("Usage: ANALYZE [<table>]");
+            return;
+        }
+    };
+    match result {
+        Ok(()) => println!("ANALYZE"),
+        Err(e) => println!("Error: {}", e),
+    }
+}
+
+// INSERT INTO <table> [(<column>, ...)] VALUES (<value>, ...)
+// Values keep the case they were typed in; NULL and DEFAULT are the only keywords. Columns left
+// out of the column list or given DEFAULT get their default.
+fn handle_insert(catalog: Arc<Catalog>, session: &Session, txn: &Transaction, raw: &str) {
+    let words = raw.split_whitespace().collect::<Vec<&str>>();
+    let values_at = raw.to_uppercase().find("VALUES");
+    let values_start = values_at.and_then(|at| raw[at..].find('(').map(|start| at + start));
+    if words.len() < 4 || !words[1].eq_ignore_ascii_case("INTO") || values_start.is_none() {
+        println!("Usage: INSERT INTO <table> [(<column>, ...)] VALUES (<value>, ...)");
+        return;
+    }
+    let table_name = words[2]
+        .split('(')
+        .next()
+        .unwrap_or_default()
+        .to_uppercase();
+    let table_name = match session.resolve_table(&catalog, &table_name) {
+        Ok(table_name) => table_name,
+        Err(e) => return println!("Error: {}", e),
+    };
+    let columns = raw[..values_at.unwrap()]
+        .split_once('(')
+        .map(|(_, columns)| {
+            let (key_columns, _) = parse_key_columns(&columns.to_uppercase());
+            key_columns
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect::<Vec<String>>()
+        });
+    let columns = columns.unwrap_or_else(|| {
+        catalog
+            .get_table(&table_name)
+            .map(|table_info| {
+                let columns = table_info.get_schema().get_columns();
+                columns.iter().map(|c| c.get_name().to_string()).collect()
+            })
+            .unwrap_or_default()
+    });
+    let values_end = raw.rfind(')').unwrap_or(raw.len());
+    let values = raw[values_start.unwrap() + 1..values_end]
+        .split(',')
+        .map(|v| {
+            let v = v.trim();
+            if v.eq_ignore_ascii_case("DEFAULT") {
+                return None;
+            }
+            let v = v.trim_matches('\'');
+            if v.eq_ignore_ascii_case("NULL") {
+                Some(Value::Null)
+            } else {
+                Some(Value::Varchar(v.to_string()))
+            }
+        })
+        .collect::<Vec<Option<Value>>>();
+    // a column given DEFAULT is left out, unless the counts do not match and the insert fails
+    let (columns, values): (Vec<String>, Vec<Value>) = if columns.len() == values.len() {
+        columns
+            .into_iter()
+            .zip(values)
+            .filter_map(|(column, value)| Some((column, value?)))
+            .unzip()
+    } else {
+        (columns, values.into_iter().flatten().collect())
+    };
+    let result = catalog.insert_values(
+        txn,
+        &table_name,
+        &columns,
+        values,
+        &session.functions(&catalog),
+    );
+    match result {
+        Ok(location) => println!("INSERT 1 at {:?}", location),
+        Err(e) => println!("Error: {}", e),
+    }
+}
+
+// SET SEARCH_PATH TO <schema>, ...
+// SET SEARCH_PATH = <schema>, ...
+// SET TRANSACTION ISOLATION LEVEL <level>
+// SET VERSION_RETENTION TO <seconds>
+// SET LOCK_TIMEOUT TO <milliseconds>
+fn handle_set(catalog: Arc<Catalog>, session: &mut Session, raw: &str) {
+    let upper = raw.to_uppercase();
+    if let Some(rest) = upper.strip_prefix("SET LOCK_TIMEOUT") {
+        let rest = rest.trim_start();
+        let millis = rest
+            .strip_prefix("TO")
+            .or_else(|| rest.strip_prefix('='))
+            .and_then(|millis| millis.trim().parse::<u64>().ok());
+        match millis {
+            // zero waits as long as it takes
+            Some(millis) => {
+                session.set_lock_timeout((millis > 0).then(|| Duration::from_millis(millis)));
+                println!("SET");
+            }
+            None => println!(
+                "Usage: SET LOCK_TIMEOUT TO <milliseconds>, 0 waits for locks as long as it takes"
+            ),
+        }
+        return;
+    }
+    if let Some(rest) = upper.strip_prefix("SET VERSION_RETENTION") {
+        let rest = rest.trim_start();
+        let seconds = rest
+            .strip_prefix("TO")
+            .or_else(|| rest.strip_prefix('='))
+            .and_then(|seconds| seconds.trim().parse::<u64>().ok());
+        match seconds {
+            Some(seconds) => {
+                catalog.set_version_retention(Duration::from_secs(seconds));
+                println!("SET");
+            }
+            None => println!("Usage: SET VERSION_RETENTION TO <seconds>"),
+        }
+        return;
+    }
+    if let Some(level) = upper.strip_prefix("SET TRANSACTION ISOLATION LEVEL") {
+        // the transactions the session begins from now on run at the level
+        if session.get_transaction().is_some() {
+            return println!("Error: the isolation level cannot change while a transaction is in progress, use BEGIN ISOLATION LEVEL <level>");
+        }
+        match parse_isolation_level(&level.split_whitespace().collect::<Vec<&str>>()) {
+            Some(isolation_level) => {
+                session.set_isolation_level(isolation_level);
+                println!("SET");
+            }
+            None => println!(
+                "Usage: SET TRANSACTION ISOLATION LEVEL <level>, the level is one of {}",
+                ISOLATION_LEVELS
+            ),
+        }
+        return;
+    }
+    let Some(schemas) = upper
+        .strip_prefix("SET SEARCH_PATH")
+        .map(|rest| rest.trim_start())
+        .and_then(|rest| rest.strip_prefix("TO").or_else(|| rest.strip_prefix('=')))
+    else {
+        println!(
+            "Usage: SET SEARCH_PATH TO <schema>, ... | SET TRANSACTION ISOLATION LEVEL <level> | SET VERSION_RETENTION TO <seconds> | SET LOCK_TIMEOUT TO <milliseconds>"
+        );
+        return;
+    };
+    session.set_search_path(
+        schemas
+            .split(',')
+            .map(|schema_name| schema_name.trim().to_string())
+            .filter(|schema_name| !schema_name.is_empty())
+            .collect(),
+    );
+    println!("SET");
+}
+
+// SHOW SEARCH_PATH
+// SHOW STATISTICS <table>
+// SHOW TRANSACTION ISOLATION LEVEL
+// SHOW VERSION_RETENTION
+// SHOW LOCK_TIMEOUT
+// SHOW COMMIT_TS
+fn handle_show(catalog: Arc<Catalog>, session: &Session, txn: &Transaction, input: Vec<&str>) {
+    match input[1..] {
+        ["SEARCH_PATH"] => println!("{}", session.get_search_path().join(", ")),
+        ["STATISTICS", table_name] => show_statistics(&catalog, session, table_name),
+        ["TRANSACTION", "ISOLATION", "LEVEL"] => {
+            println!("{}", isolation_level_name(txn.get_isolation_level()))
+        }
+        ["VERSION_RETENTION"] => println!("{}s", catalog.get_version_retention().as_secs()),
+        ["LOCK_TIMEOUT"] => println!(
+            "{}ms",
+            session.get_lock_timeout().unwrap_or_default().as_millis()
+        ),
+        // what AS OF COMMIT goes back to
+        ["COMMIT_TS"] => println!("{}", catalog.get_last_commit_ts()),
+        _ => println!(
+            "Usage: SHOW SEARCH_PATH | SHOW STATISTICS <table> | SHOW TRANSACTION ISOLATION LEVEL | SHOW VERSION_RETENTION | SHOW LOCK_TIMEOUT | SHOW COMMIT_TS"
+        ),
+    }
+}
+
+fn show_statistics(catalog: &Catalog, session: &Session, table_name: &str) {
+    let table_name = match session.resolve_table(catalog, table_name) {
+        Ok(table_name) => table_name,
+        Err(e) => return println!("Error: {}", e),
+    };
+    let (Some(table_info), Some(statistics)) = (
+        catalog.get_table(&table_name),
+        catalog.get_statistics(&table_name),
+    ) else {
+        return println!("{} has not been analyzed", table_name);
+    };
+    println!(
+        "{}: {} rows in {} pages",
+        table_name, statistics.row_count, statistics.page_count
+    );
+    for column in table_info.get_schema().get_columns() {
+        let Some(column_statistics) = statistics.columns.get(&column.get_column_id()) else {
+            continue;
+        };
+        let most_common = column_statistics
+            .most_common
+            .iter()
+            .map(|(value, frequency)| format!("{} ({:.3})", value, frequency))
+            .collect::<Vec<String>>();
+        let histogram = column_statistics
+            .histogram
+            .iter()
+            .map(|value| value.to_string())
+            .collect::<Vec<String>>();
+        println!(
+            "  {}: null fraction {:.3}, {} distinct, most common [{}], histogram [{}]",
+            column.get_name(),
+            column_statistics.null_fraction,
+            column_statistics.n_distinct,
+            most_common.join(", "),
+            histogram.join(", ")
+        );
+    }
 }